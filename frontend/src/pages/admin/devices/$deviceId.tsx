@@ -13,6 +13,7 @@ import { UAVData } from '@/utils/uav/manager'
 import RealtimeDataPanel from '@/components/features/devices/RealtimeDataPanel'
 import HistoryDataPanel from '@/components/features/devices/HistoryDataPanel'
 import * as deviceService from '@/utils/api/device'
+import { wsAuthProtocols } from '@/utils/api/request'
 import { formatToLocal } from '@/utils/time'
 
 type Device = deviceService.Device
@@ -186,7 +187,7 @@ const DeviceDetail: React.FC = () => {
 
     // 添加延迟确保代理服务器已启动
     const connectTimeout = setTimeout(() => {
-      ws = new WebSocket(wsUrl)
+      ws = new WebSocket(wsUrl, wsAuthProtocols())
 
       ws.onopen = () => {
         const msg = `Connected to device ${deviceId} WebSocket proxy`
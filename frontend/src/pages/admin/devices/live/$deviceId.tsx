@@ -21,6 +21,7 @@ import {
 } from '@ant-design/icons'
 import { UAVData } from '@/utils/uav/manager'
 import * as deviceService from '@/utils/api/device'
+import { wsAuthProtocols } from '@/utils/api/request'
 import AMapComponent from '@/components/features/map/AMapComponent'
 import RTMPPlayer from '@/components/features/video/RTMPPlayer'
 
@@ -249,7 +250,7 @@ const DeviceLive: React.FC = () => {
     const proxyPort = 2333 + device.id
     const wsUrl = `ws://127.0.0.1:${proxyPort}/${deviceId}`
 
-    const ws = new WebSocket(wsUrl)
+    const ws = new WebSocket(wsUrl, wsAuthProtocols())
     wsRef.current = ws
 
     ws.onopen = () => {
@@ -14,6 +14,10 @@ export interface Device {
   is_active: boolean
   mqtt_port?: number
   mqtt_enabled: boolean
+  mavlink_system_id?: number
+  nmea_port?: number
+  collection_mode: boolean
+  device_group?: string
   created_at: string
   updated_at: string
 }
@@ -28,6 +32,10 @@ export interface CreateDeviceParams {
   drone_brand?: string
   mqtt_port?: number
   mqtt_enabled?: boolean
+  mavlink_system_id?: number
+  nmea_port?: number
+  collection_mode?: boolean
+  device_group?: string
 }
 
 export interface UpdateDeviceParams {
@@ -42,6 +50,10 @@ export interface UpdateDeviceParams {
   is_active?: boolean
   mqtt_port?: number
   mqtt_enabled?: boolean
+  mavlink_system_id?: number
+  nmea_port?: number
+  collection_mode?: boolean
+  device_group?: string
 }
 
 // 获取设备列表
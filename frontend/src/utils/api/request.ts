@@ -69,5 +69,11 @@ request.interceptors.response.use(
   }
 )
 
+// WebSocket 无法设置请求头，通过 Sec-WebSocket-Protocol 传递 token
+export const wsAuthProtocols = (): string[] => {
+  const token = localStorage.getItem('auth_token')
+  return token ? ['bearer', token] : []
+}
+
 export default request
 export { request }
@@ -1,3 +1,5 @@
+import { wsAuthProtocols } from '@/utils/api/request'
+
 export interface RealtimeMessage {
   type: 'realtime_data' | 'welcome' | 'pong'
   device_id?: number
@@ -48,7 +50,7 @@ export class RealtimeManager {
         // 连接到后端统一WebSocket接口，接收所有设备的数据
         const wsUrl = `ws://localhost:8086/api/realtime/ws`
         console.log(`Connecting to backend unified WebSocket: ${wsUrl}`)
-        this.socket = new WebSocket(wsUrl)
+        this.socket = new WebSocket(wsUrl, wsAuthProtocols())
 
         this.socket.onopen = () => {
           console.log('Connected to realtime data stream')
@@ -247,6 +249,7 @@ export class RealtimeManager {
         method: 'POST',
         headers: {
           'Content-Type': 'application/json',
+          Authorization: `Bearer ${localStorage.getItem('auth_token')}`,
         },
         body: JSON.stringify(command),
       })
@@ -267,7 +270,11 @@ export class RealtimeManager {
    */
   async getDeviceHistory(deviceId: number, limit = 100, offset = 0): Promise<any[]> {
     try {
-      const response = await fetch(`/api/realtime/devices/${deviceId}/history?limit=${limit}&offset=${offset}`)
+      const response = await fetch(`/api/realtime/devices/${deviceId}/history?limit=${limit}&offset=${offset}`, {
+        headers: {
+          Authorization: `Bearer ${localStorage.getItem('auth_token')}`,
+        },
+      })
       
       if (response.ok) {
         const result = await response.json()
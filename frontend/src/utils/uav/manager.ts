@@ -3,6 +3,8 @@
  * 支持多设备连接和操作
  */
 
+import { wsAuthProtocols } from '@/utils/api/request'
+
 export interface UAVData {
   battery?: string
   camera?: string
@@ -114,7 +116,7 @@ export class UAVManager {
 
         // 连接到后端WebSocket代理，使用设备专用端口
         const proxyUrl = `ws://127.0.0.1:${device.websocketPort}/${deviceId}`
-        const socket = new WebSocket(proxyUrl)
+        const socket = new WebSocket(proxyUrl, wsAuthProtocols())
 
         socket.onopen = () => {
           console.log(`Connected to device ${deviceId} via proxy port ${device.websocketPort}`)
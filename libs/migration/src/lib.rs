@@ -25,6 +25,19 @@ mod m20250821_000001_add_device_mqtt_and_realtime_data;
 mod m20250821_000001_update_device_mqtt_fields;
 mod m20250827_000001_change_websocket_url_to_port;
 mod m20250901_000001_rename_rtmp_to_easynvr;
+mod m20250910_000001_add_device_mavlink_system_id;
+mod m20250912_000001_add_device_nmea_fields;
+mod m20250915_000001_create_device_telemetry_rollup;
+mod m20250918_000001_create_flight;
+mod m20250920_000001_add_device_group;
+mod m20250925_000001_add_task_progress;
+mod m20250927_000001_create_task_schedule;
+mod m20250928_000001_create_task_event;
+mod m20251001_000001_create_task_template;
+mod m20251003_000001_add_task_simulation;
+mod m20251005_000001_create_geofence;
+mod m20251008_000001_create_fleet_mission;
+mod m20251010_000001_drop_geofence_region;
 pub struct Migrator;
 
 #[async_trait::async_trait]
@@ -51,6 +64,19 @@ impl MigratorTrait for Migrator {
             Box::new(m20250821_000001_update_device_mqtt_fields::Migration),
             Box::new(m20250827_000001_change_websocket_url_to_port::Migration),
             Box::new(m20250901_000001_rename_rtmp_to_easynvr::Migration),
+            Box::new(m20250910_000001_add_device_mavlink_system_id::Migration),
+            Box::new(m20250912_000001_add_device_nmea_fields::Migration),
+            Box::new(m20250915_000001_create_device_telemetry_rollup::Migration),
+            Box::new(m20250918_000001_create_flight::Migration),
+            Box::new(m20250920_000001_add_device_group::Migration),
+            Box::new(m20250925_000001_add_task_progress::Migration),
+            Box::new(m20250927_000001_create_task_schedule::Migration),
+            Box::new(m20250928_000001_create_task_event::Migration),
+            Box::new(m20251001_000001_create_task_template::Migration),
+            Box::new(m20251003_000001_add_task_simulation::Migration),
+            Box::new(m20251005_000001_create_geofence::Migration),
+            Box::new(m20251008_000001_create_fleet_mission::Migration),
+            Box::new(m20251010_000001_drop_geofence_region::Migration),
         ]
     }
 }
@@ -0,0 +1,60 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // 添加 MAVLink 系统ID字段（用于将飞控 sysid 映射到设备）
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Device::Table)
+                    .add_column(integer_null(Device::MavlinkSystemId))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_device_mavlink_system_id")
+                    .table(Device::Table)
+                    .col(Device::MavlinkSystemId)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_device_mavlink_system_id")
+                    .table(Device::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Device::Table)
+                    .drop_column(Device::MavlinkSystemId)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Device {
+    Table,
+    MavlinkSystemId,
+}
@@ -0,0 +1,39 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // 添加 NMEA TCP 端口和采集模式字段
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Device::Table)
+                    .add_column(integer_null(Device::NmeaPort))
+                    .add_column(boolean(Device::CollectionMode).default(false))
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Device::Table)
+                    .drop_column(Device::NmeaPort)
+                    .drop_column(Device::CollectionMode)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Device {
+    Table,
+    NmeaPort,
+    CollectionMode,
+}
@@ -0,0 +1,91 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // 创建遥测聚合表（1s / 1m / 1h 三种粒度）
+        manager
+            .create_table(
+                Table::create()
+                    .table(DeviceTelemetryRollup::Table)
+                    .col(
+                        big_integer(DeviceTelemetryRollup::Id)
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(integer(DeviceTelemetryRollup::DeviceId).not_null())
+                    .col(string_len(DeviceTelemetryRollup::Resolution, 8).not_null())
+                    .col(timestamp_with_time_zone(DeviceTelemetryRollup::BucketStart).not_null())
+                    .col(big_integer(DeviceTelemetryRollup::SampleCount).not_null())
+                    .col(json(DeviceTelemetryRollup::Fields).not_null())
+                    .col(timestamp_with_time_zone(DeviceTelemetryRollup::CreatedAt).not_null())
+                    .col(timestamp_with_time_zone(DeviceTelemetryRollup::UpdatedAt).not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_device_telemetry_rollup_device_id")
+                            .from(
+                                DeviceTelemetryRollup::Table,
+                                DeviceTelemetryRollup::DeviceId,
+                            )
+                            .to(Device::Table, Device::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // 按设备、粒度、时间查询
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_device_telemetry_rollup_bucket")
+                    .table(DeviceTelemetryRollup::Table)
+                    .col(DeviceTelemetryRollup::DeviceId)
+                    .col(DeviceTelemetryRollup::Resolution)
+                    .col(DeviceTelemetryRollup::BucketStart)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_device_telemetry_rollup_bucket")
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(DeviceTelemetryRollup::Table).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Device {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum DeviceTelemetryRollup {
+    Table,
+    Id,
+    DeviceId,
+    Resolution,
+    BucketStart,
+    SampleCount,
+    Fields,
+    CreatedAt,
+    UpdatedAt,
+}
@@ -0,0 +1,129 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // 创建飞行架次表
+        manager
+            .create_table(
+                Table::create()
+                    .table(Flight::Table)
+                    .col(pk_auto(Flight::Id))
+                    .col(uuid_uniq(Flight::Uuid))
+                    .col(integer(Flight::DeviceId).not_null())
+                    .col(integer_null(Flight::TaskId))
+                    .col(string_len(Flight::Status, 20).not_null())
+                    .col(string_len(Flight::StartReason, 20).not_null())
+                    .col(string_len_null(Flight::EndReason, 20))
+                    .col(timestamp_with_time_zone(Flight::StartTime).not_null())
+                    .col(timestamp_with_time_zone_null(Flight::EndTime))
+                    .col(double(Flight::DurationSeconds).default(0.0))
+                    .col(double(Flight::Distance).default(0.0))
+                    .col(double_null(Flight::MaxAltitude))
+                    .col(double_null(Flight::MaxSpeed))
+                    .col(double_null(Flight::BatteryStart))
+                    .col(double_null(Flight::BatteryEnd))
+                    .col(double_null(Flight::BatteryConsumed))
+                    .col(timestamp_with_time_zone(Flight::CreatedAt).not_null())
+                    .col(timestamp_with_time_zone(Flight::UpdatedAt).not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_flight_device_id")
+                            .from(Flight::Table, Flight::DeviceId)
+                            .to(Device::Table, Device::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_flight_task_id")
+                            .from(Flight::Table, Flight::TaskId)
+                            .to(Task::Table, Task::Id)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // 按设备和起飞时间查询
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_flight_device_start_time")
+                    .table(Flight::Table)
+                    .col(Flight::DeviceId)
+                    .col(Flight::StartTime)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_flight_task_id")
+                    .table(Flight::Table)
+                    .col(Flight::TaskId)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(Index::drop().name("idx_flight_task_id").to_owned())
+            .await?;
+
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_flight_device_start_time")
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(Flight::Table).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Device {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Task {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Flight {
+    Table,
+    Id,
+    Uuid,
+    DeviceId,
+    TaskId,
+    Status,
+    StartReason,
+    EndReason,
+    StartTime,
+    EndTime,
+    DurationSeconds,
+    Distance,
+    MaxAltitude,
+    MaxSpeed,
+    BatteryStart,
+    BatteryEnd,
+    BatteryConsumed,
+    CreatedAt,
+    UpdatedAt,
+}
@@ -0,0 +1,96 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // 创建任务调度表，每个任务最多一条调度
+        manager
+            .create_table(
+                Table::create()
+                    .table(TaskSchedule::Table)
+                    .col(pk_auto(TaskSchedule::Id))
+                    .col(integer_uniq(TaskSchedule::TaskId))
+                    .col(string_len_null(TaskSchedule::Cron, 100))
+                    .col(timestamp_with_time_zone_null(TaskSchedule::RunAt))
+                    .col(string_len(TaskSchedule::Timezone, 64).not_null())
+                    .col(string_len(TaskSchedule::MisfirePolicy, 20).not_null())
+                    .col(integer(TaskSchedule::MisfireGraceSecs).default(60))
+                    .col(boolean(TaskSchedule::Enabled).default(true))
+                    .col(timestamp_with_time_zone_null(TaskSchedule::NextRunAt))
+                    .col(timestamp_with_time_zone_null(TaskSchedule::LastRunAt))
+                    .col(integer_null(TaskSchedule::LastTaskId))
+                    .col(string_len_null(TaskSchedule::LastStatus, 20))
+                    .col(text_null(TaskSchedule::LastError))
+                    .col(timestamp_with_time_zone(TaskSchedule::CreatedAt).not_null())
+                    .col(timestamp_with_time_zone(TaskSchedule::UpdatedAt).not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_task_schedule_task_id")
+                            .from(TaskSchedule::Table, TaskSchedule::TaskId)
+                            .to(Task::Table, Task::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // 调度器按下次执行时间扫描到期的调度
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_task_schedule_next_run_at")
+                    .table(TaskSchedule::Table)
+                    .col(TaskSchedule::Enabled)
+                    .col(TaskSchedule::NextRunAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_task_schedule_next_run_at")
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(TaskSchedule::Table).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Task {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum TaskSchedule {
+    Table,
+    Id,
+    TaskId,
+    Cron,
+    RunAt,
+    Timezone,
+    MisfirePolicy,
+    MisfireGraceSecs,
+    Enabled,
+    NextRunAt,
+    LastRunAt,
+    LastTaskId,
+    LastStatus,
+    LastError,
+    CreatedAt,
+    UpdatedAt,
+}
@@ -0,0 +1,95 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // 创建任务状态变更记录表
+        manager
+            .create_table(
+                Table::create()
+                    .table(TaskEvent::Table)
+                    .col(pk_auto(TaskEvent::Id))
+                    .col(integer(TaskEvent::TaskId).not_null())
+                    .col(string_len_null(TaskEvent::FromStatus, 20))
+                    .col(string_len(TaskEvent::ToStatus, 20).not_null())
+                    .col(string_len(TaskEvent::Actor, 20).not_null())
+                    .col(integer_null(TaskEvent::UserId))
+                    .col(text_null(TaskEvent::Reason))
+                    .col(timestamp_with_time_zone(TaskEvent::CreatedAt).not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_task_event_task_id")
+                            .from(TaskEvent::Table, TaskEvent::TaskId)
+                            .to(Task::Table, Task::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_task_event_user_id")
+                            .from(TaskEvent::Table, TaskEvent::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // 按任务查询时间线
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_task_event_task_id_created_at")
+                    .table(TaskEvent::Table)
+                    .col(TaskEvent::TaskId)
+                    .col(TaskEvent::CreatedAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_task_event_task_id_created_at")
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(TaskEvent::Table).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Task {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum TaskEvent {
+    Table,
+    Id,
+    TaskId,
+    FromStatus,
+    ToStatus,
+    Actor,
+    UserId,
+    Reason,
+    CreatedAt,
+}
@@ -0,0 +1,259 @@
+use sea_orm_migration::{prelude::*, schema::*, sea_orm::JsonValue};
+use uuid::Uuid;
+
+/// 一键任务的步骤，原先硬编码在 `TaskParameters::one_click_task()` 中
+const ONE_CLICK_PARAMETERS: &str = r#"{
+    "steps": [
+        {"step_type": "takeoff", "parameters": {}, "timeout": 30},
+        {"step_type": "wait", "parameters": {"duration": 10}, "timeout": null},
+        {"step_type": "move_to_heading", "parameters": {"heading": 0}, "timeout": 30},
+        {"step_type": "wait", "parameters": {"duration": 10}, "timeout": null},
+        {"step_type": "move_to_height", "parameters": {"height": 1.5}, "timeout": 30},
+        {"step_type": "wait", "parameters": {"duration": 10}, "timeout": null},
+        {"step_type": "move_to_height", "parameters": {"height": 2.0}, "timeout": 30},
+        {"step_type": "move_to_heading", "parameters": {"heading": 0}, "timeout": 30},
+        {"step_type": "wait", "parameters": {"duration": 10}, "timeout": null},
+        {"step_type": "landing", "parameters": {}, "timeout": 60}
+    ],
+    "timeout": 300,
+    "retry_count": 1
+}"#;
+const ONE_CLICK_DESCRIPTION: &str =
+    "起飞-悬停10秒-朝北-悬停10秒-飞到1.5米-悬停10秒-飞到2米-朝北-悬停10秒-降落";
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // 创建任务模板表，保存每个模板的当前版本
+        manager
+            .create_table(
+                Table::create()
+                    .table(TaskTemplate::Table)
+                    .col(pk_auto(TaskTemplate::Id))
+                    .col(uuid_uniq(TaskTemplate::Uuid))
+                    .col(string_len(TaskTemplate::Key, 64).not_null())
+                    .col(string_len(TaskTemplate::Name, 255).not_null())
+                    .col(text_null(TaskTemplate::Description))
+                    .col(json_null(TaskTemplate::DroneModels))
+                    .col(json(TaskTemplate::Parameters).not_null())
+                    .col(integer(TaskTemplate::Version).default(1))
+                    .col(boolean(TaskTemplate::IsActive).default(true))
+                    .col(integer_null(TaskTemplate::CreatedBy))
+                    .col(integer_null(TaskTemplate::UpdatedBy))
+                    .col(timestamp_with_time_zone(TaskTemplate::CreatedAt).not_null())
+                    .col(timestamp_with_time_zone(TaskTemplate::UpdatedAt).not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_task_template_created_by")
+                            .from(TaskTemplate::Table, TaskTemplate::CreatedBy)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_task_template_updated_by")
+                            .from(TaskTemplate::Table, TaskTemplate::UpdatedBy)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // 按标识查找适用于设备机型的模板
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_task_template_key")
+                    .table(TaskTemplate::Table)
+                    .col(TaskTemplate::Key)
+                    .to_owned(),
+            )
+            .await?;
+
+        // 创建任务模板版本表，每次修改模板内容保存一个快照
+        manager
+            .create_table(
+                Table::create()
+                    .table(TaskTemplateVersion::Table)
+                    .col(pk_auto(TaskTemplateVersion::Id))
+                    .col(integer(TaskTemplateVersion::TemplateId).not_null())
+                    .col(integer(TaskTemplateVersion::Version).not_null())
+                    .col(string_len(TaskTemplateVersion::Name, 255).not_null())
+                    .col(text_null(TaskTemplateVersion::Description))
+                    .col(json_null(TaskTemplateVersion::DroneModels))
+                    .col(json(TaskTemplateVersion::Parameters).not_null())
+                    .col(integer_null(TaskTemplateVersion::CreatedBy))
+                    .col(timestamp_with_time_zone(TaskTemplateVersion::CreatedAt).not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_task_template_version_template_id")
+                            .from(TaskTemplateVersion::Table, TaskTemplateVersion::TemplateId)
+                            .to(TaskTemplate::Table, TaskTemplate::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_task_template_version_created_by")
+                            .from(TaskTemplateVersion::Table, TaskTemplateVersion::CreatedBy)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_task_template_version_template_id_version")
+                    .table(TaskTemplateVersion::Table)
+                    .col(TaskTemplateVersion::TemplateId)
+                    .col(TaskTemplateVersion::Version)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        // 记录任务由哪个模板的哪个版本创建
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Task::Table)
+                    .add_column(integer_null(Task::TemplateId))
+                    .add_column(integer_null(Task::TemplateVersion))
+                    .to_owned(),
+            )
+            .await?;
+
+        // 预置一键任务模板
+        let parameters: JsonValue = ONE_CLICK_PARAMETERS
+            .parse()
+            .map_err(|e| DbErr::Custom(format!("Invalid one-click parameters: {}", e)))?;
+        let template_uuid = Uuid::new_v4();
+        let insert_template = Query::insert()
+            .into_table(TaskTemplate::Table)
+            .columns([
+                TaskTemplate::Uuid,
+                TaskTemplate::Key,
+                TaskTemplate::Name,
+                TaskTemplate::Description,
+                TaskTemplate::Parameters,
+                TaskTemplate::CreatedAt,
+                TaskTemplate::UpdatedAt,
+            ])
+            .values_panic([
+                template_uuid.into(),
+                "one_click".into(),
+                "一键任务".into(),
+                ONE_CLICK_DESCRIPTION.into(),
+                parameters.clone().into(),
+                Expr::current_timestamp().into(),
+                Expr::current_timestamp().into(),
+            ])
+            .to_owned();
+        manager.exec_stmt(insert_template).await?;
+
+        let insert_version = Query::insert()
+            .into_table(TaskTemplateVersion::Table)
+            .columns([
+                TaskTemplateVersion::TemplateId,
+                TaskTemplateVersion::Version,
+                TaskTemplateVersion::Name,
+                TaskTemplateVersion::Description,
+                TaskTemplateVersion::Parameters,
+                TaskTemplateVersion::CreatedAt,
+            ])
+            .select_from(
+                Query::select()
+                    .column(TaskTemplate::Id)
+                    .expr(Expr::val(1))
+                    .expr(Expr::val("一键任务"))
+                    .expr(Expr::val(ONE_CLICK_DESCRIPTION))
+                    .expr(Expr::val(parameters))
+                    .expr(Expr::current_timestamp())
+                    .from(TaskTemplate::Table)
+                    .and_where(Expr::col(TaskTemplate::Uuid).eq(template_uuid))
+                    .to_owned(),
+            )
+            .map_err(|e| DbErr::Custom(e.to_string()))?
+            .to_owned();
+        manager.exec_stmt(insert_version).await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Task::Table)
+                    .drop_column(Task::TemplateId)
+                    .drop_column(Task::TemplateVersion)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(TaskTemplateVersion::Table).to_owned())
+            .await?;
+
+        manager
+            .drop_index(Index::drop().name("idx_task_template_key").to_owned())
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(TaskTemplate::Table).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Task {
+    Table,
+    TemplateId,
+    TemplateVersion,
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum TaskTemplate {
+    Table,
+    Id,
+    Uuid,
+    Key,
+    Name,
+    Description,
+    DroneModels,
+    Parameters,
+    Version,
+    IsActive,
+    CreatedBy,
+    UpdatedBy,
+    CreatedAt,
+    UpdatedAt,
+}
+
+#[derive(DeriveIden)]
+enum TaskTemplateVersion {
+    Table,
+    Id,
+    TemplateId,
+    Version,
+    Name,
+    Description,
+    DroneModels,
+    Parameters,
+    CreatedBy,
+    CreatedAt,
+}
@@ -0,0 +1,111 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // 创建电子围栏表，围栏可指定给设备、用户或区域，都不指定时对所有设备生效
+        manager
+            .create_table(
+                Table::create()
+                    .table(Geofence::Table)
+                    .col(pk_auto(Geofence::Id))
+                    .col(uuid_uniq(Geofence::Uuid))
+                    .col(string_len(Geofence::Name, 255).not_null())
+                    .col(text_null(Geofence::Description))
+                    .col(string_len(Geofence::Kind, 20).not_null())
+                    .col(json(Geofence::Shape).not_null())
+                    .col(double_null(Geofence::MinAltitude))
+                    .col(double_null(Geofence::MaxAltitude))
+                    .col(integer_null(Geofence::DeviceId))
+                    .col(integer_null(Geofence::UserId))
+                    .col(integer_null(Geofence::RegionId))
+                    .col(boolean(Geofence::RtlOnBreach).default(false))
+                    .col(boolean(Geofence::IsActive).default(true))
+                    .col(integer_null(Geofence::CreatedBy))
+                    .col(timestamp_with_time_zone(Geofence::CreatedAt).not_null())
+                    .col(timestamp_with_time_zone(Geofence::UpdatedAt).not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_geofence_device_id")
+                            .from(Geofence::Table, Geofence::DeviceId)
+                            .to(Device::Table, Device::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_geofence_user_id")
+                            .from(Geofence::Table, Geofence::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_geofence_region_id")
+                            .from(Geofence::Table, Geofence::RegionId)
+                            .to(Region::Table, Region::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_geofence_created_by")
+                            .from(Geofence::Table, Geofence::CreatedBy)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Geofence::Table).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Device {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Region {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Geofence {
+    Table,
+    Id,
+    Uuid,
+    Name,
+    Description,
+    Kind,
+    Shape,
+    MinAltitude,
+    MaxAltitude,
+    DeviceId,
+    UserId,
+    RegionId,
+    RtlOnBreach,
+    IsActive,
+    CreatedBy,
+    CreatedAt,
+    UpdatedAt,
+}
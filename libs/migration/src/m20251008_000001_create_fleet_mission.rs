@@ -0,0 +1,108 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // 创建编队任务表，一个编队任务拆分为多台设备各自执行的子任务
+        manager
+            .create_table(
+                Table::create()
+                    .table(FleetMission::Table)
+                    .col(pk_auto(FleetMission::Id))
+                    .col(uuid_uniq(FleetMission::Uuid))
+                    .col(string_len(FleetMission::Name, 255).not_null())
+                    .col(text_null(FleetMission::Description))
+                    .col(integer(FleetMission::UserId).not_null())
+                    .col(string_len(FleetMission::Status, 20).not_null())
+                    .col(string_len(FleetMission::FailurePolicy, 20).not_null())
+                    .col(text_null(FleetMission::Error))
+                    .col(timestamp_with_time_zone_null(FleetMission::StartTime))
+                    .col(timestamp_with_time_zone_null(FleetMission::EndTime))
+                    .col(timestamp_with_time_zone(FleetMission::CreatedAt).not_null())
+                    .col(timestamp_with_time_zone(FleetMission::UpdatedAt).not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_fleet_mission_user_id")
+                            .from(FleetMission::Table, FleetMission::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // 子任务所属的编队任务，以及接管了哪个失败子任务的剩余航线
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Task::Table)
+                    .add_column(integer_null(Task::FleetMissionId))
+                    .add_column(integer_null(Task::ReassignedFrom))
+                    .add_foreign_key(
+                        TableForeignKey::new()
+                            .name("fk_task_fleet_mission_id")
+                            .from_tbl(Task::Table)
+                            .from_col(Task::FleetMissionId)
+                            .to_tbl(FleetMission::Table)
+                            .to_col(FleetMission::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Task::Table)
+                    .drop_foreign_key(Alias::new("fk_task_fleet_mission_id"))
+                    .drop_column(Task::FleetMissionId)
+                    .drop_column(Task::ReassignedFrom)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(FleetMission::Table).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Task {
+    Table,
+    FleetMissionId,
+    ReassignedFrom,
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum FleetMission {
+    Table,
+    Id,
+    Uuid,
+    Name,
+    Description,
+    UserId,
+    Status,
+    FailurePolicy,
+    Error,
+    StartTime,
+    EndTime,
+    CreatedAt,
+    UpdatedAt,
+}
@@ -0,0 +1,52 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // 设备和用户没有所属区域，区域围栏无法约束任何设备，移除围栏的区域字段
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Geofence::Table)
+                    .drop_foreign_key(Alias::new("fk_geofence_region_id"))
+                    .drop_column(Geofence::RegionId)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Geofence::Table)
+                    .add_column(integer_null(Geofence::RegionId))
+                    .add_foreign_key(
+                        TableForeignKey::new()
+                            .name("fk_geofence_region_id")
+                            .from_tbl(Geofence::Table)
+                            .from_col(Geofence::RegionId)
+                            .to_tbl(Region::Table)
+                            .to_col(Region::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Region {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Geofence {
+    Table,
+    RegionId,
+}
@@ -13,7 +13,7 @@ use loco_rs::{
 };
 use migration::Migrator;
 
-use crate::{controllers, services, tasks};
+use crate::{controllers, services, settings, tasks};
 
 pub struct App;
 #[async_trait]
@@ -47,6 +47,10 @@ impl Hooks for App {
             .add_route(controllers::user::routes())
             .add_route(controllers::device::routes())
             .add_route(controllers::task::routes())
+            .add_route(controllers::task_schedule::routes())
+            .add_route(controllers::task_template::routes())
+            .add_route(controllers::fleet_mission::routes())
+            .add_route(controllers::geofence::routes())
             .add_route(controllers::admin::routes())
             .add_route(controllers::rbac::routes())
             .add_route(controllers::graphql::routes())
@@ -57,6 +61,7 @@ impl Hooks for App {
             .add_route(controllers::upload::routes())
             .add_route(controllers::element_type::routes())
             .add_route(controllers::realtime::routes())
+            .add_route(controllers::flight::routes())
     }
 
     async fn connect_workers(_ctx: &AppContext, _queue: &Queue) -> Result<()> {
@@ -66,8 +71,11 @@ impl Hooks for App {
     async fn after_routes(router: axum::Router, ctx: &AppContext) -> Result<axum::Router> {
         // 初始化服务管理器
         let db = std::sync::Arc::new(ctx.db.clone());
-        let service_manager =
-            std::sync::Arc::new(services::service_manager::ServiceManager::new(db).await);
+        let settings = settings::Settings::from_config(&ctx.config);
+        let jwt_secret = ctx.config.get_jwt_config().ok().map(|jwt| jwt.secret.clone());
+        let service_manager = std::sync::Arc::new(
+            services::service_manager::ServiceManager::new(db, settings, jwt_secret).await,
+        );
 
         // 启动所有服务
         if let Err(e) = service_manager.start().await {
@@ -88,6 +96,8 @@ impl Hooks for App {
 
     fn register_tasks(tasks: &mut Tasks) {
         tasks.register(tasks::seed::SeedData);
+        tasks.register(tasks::retention::EnforceRetention);
+        tasks.register(tasks::restore_archive::RestoreArchive);
     }
 
     async fn truncate(_ctx: &AppContext) -> Result<()> {
@@ -6,6 +6,7 @@ use serde_json::json;
 use uuid::Uuid;
 
 use crate::models::{device, user};
+use crate::services::app_state;
 
 /// 获取所有设备（无需认证）
 #[debug_handler]
@@ -84,6 +85,10 @@ async fn create_device(
         mqtt_port: params.mqtt_port,
         mqtt_enabled: params.mqtt_enabled.unwrap_or(false),
         is_connected: false,
+        mavlink_system_id: params.mavlink_system_id,
+        nmea_port: params.nmea_port,
+        collection_mode: params.collection_mode.unwrap_or(false),
+        device_group: params.device_group.clone(),
         created_at: chrono::Utc::now().naive_utc(),
         updated_at: chrono::Utc::now().naive_utc(),
     };
@@ -112,10 +117,28 @@ async fn create_device(
         mqtt_port: Set(params.mqtt_port),
         mqtt_enabled: Set(params.mqtt_enabled.unwrap_or(false)),
         is_connected: Set(false),
+        mavlink_system_id: Set(params.mavlink_system_id),
+        nmea_port: Set(params.nmea_port),
+        collection_mode: Set(params.collection_mode.unwrap_or(false)),
+        device_group: Set(params.device_group),
         ..Default::default()
     };
 
     let device_model = new_device.insert(&ctx.db).await?;
+
+    if let Some(service_manager) = app_state::get_service_manager() {
+        // 刷新MAVLink系统ID映射
+        if device_model.mavlink_system_id.is_some() {
+            service_manager.reload_mavlink_mappings().await;
+        }
+        // 启动NMEA监听
+        service_manager.sync_device_nmea(&device_model).await;
+        // 刷新实时推送的设备归属和分组
+        service_manager.reload_broadcast_devices().await;
+        // 新设备纳入电子围栏检查
+        service_manager.reload_geofences();
+    }
+
     let response = device::DeviceResponse::from(device_model);
 
     format::json(response)
@@ -195,6 +218,20 @@ async fn update_device(
         }
     }
 
+    // 验证MAVLink系统ID
+    if let Some(Some(system_id)) = params.mavlink_system_id {
+        if !(1..=255).contains(&system_id) {
+            return bad_request("MAVLink system ID must be between 1 and 255");
+        }
+    }
+
+    // 验证NMEA端口
+    if let Some(Some(port)) = params.nmea_port {
+        if !(1..=65535).contains(&port) {
+            return bad_request("NMEA port must be between 1 and 65535");
+        }
+    }
+
     // 如果设置为默认设备，需要先取消其他设备的默认状态
     if params.is_default == Some(true) {
         // 取消用户的其他默认设备
@@ -245,8 +282,37 @@ async fn update_device(
     if let Some(is_connected) = params.is_connected {
         active_device.is_connected = Set(is_connected);
     }
+    let mavlink_changed = params.mavlink_system_id.is_some();
+    let group_changed = params.device_group.is_some();
+    if let Some(mavlink_system_id) = params.mavlink_system_id {
+        active_device.mavlink_system_id = Set(mavlink_system_id);
+    }
+    if let Some(nmea_port) = params.nmea_port {
+        active_device.nmea_port = Set(nmea_port);
+    }
+    if let Some(collection_mode) = params.collection_mode {
+        active_device.collection_mode = Set(collection_mode);
+    }
+    if let Some(device_group) = params.device_group {
+        // 空字符串表示移出分组
+        active_device.device_group =
+            Set(Some(device_group).filter(|group| !group.trim().is_empty()));
+    }
 
     let updated_device = active_device.update(&ctx.db).await?;
+
+    if let Some(service_manager) = app_state::get_service_manager() {
+        // 刷新MAVLink系统ID映射
+        if mavlink_changed {
+            service_manager.reload_mavlink_mappings().await;
+        }
+        // 同步NMEA监听和采集模式
+        service_manager.sync_device_nmea(&updated_device).await;
+        // 刷新实时推送的设备分组
+        if group_changed {
+            service_manager.reload_broadcast_devices().await;
+        }
+    }
     let response = device::DeviceResponse::from(updated_device);
 
     format::json(response)
@@ -281,8 +347,21 @@ async fn delete_device(
     };
 
     // 删除设备
+    let device_id = device_model.id;
+    let had_mavlink = device_model.mavlink_system_id.is_some();
     device_model.delete(&ctx.db).await?;
 
+    if let Some(service_manager) = app_state::get_service_manager() {
+        // 刷新MAVLink系统ID映射
+        if had_mavlink {
+            service_manager.reload_mavlink_mappings().await;
+        }
+        // 停止NMEA监听
+        service_manager.remove_device_nmea(device_id).await;
+        // 刷新实时推送的设备归属和分组
+        service_manager.reload_broadcast_devices().await;
+    }
+
     format::json(json!({
         "message": "Device deleted successfully"
     }))
@@ -0,0 +1,551 @@
+use crate::models::fleet_mission::{self, FailurePolicy};
+use crate::models::task::{self, TaskParameters, TaskProgress, TaskStatus, STEP_COMPLETED};
+use crate::models::{device, user};
+use crate::services::app_state;
+use crate::services::fleet_mission::split_parameters;
+use crate::services::task_executor::parse_parameters;
+use crate::services::task_state::{self, Actor};
+use axum::body::Bytes;
+use axum::Json;
+use loco_rs::controller::middleware::auth::JWT;
+use loco_rs::prelude::*;
+use sea_orm::{QueryOrder, TransactionTrait};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use uuid::Uuid;
+
+/// 为单台设备指定的子任务
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SubTaskParams {
+    pub device_id: i32,
+    pub name: Option<String>,
+    pub parameters: serde_json::Value,
+}
+
+/// 创建编队任务：`sub_tasks` 为每台设备分别指定参数，或 `device_ids` 加 `parameters` 将航线拆分给各设备
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CreateFleetMissionParams {
+    pub name: String,
+    pub description: Option<String>,
+    pub failure_policy: Option<FailurePolicy>, // 默认 continue
+    pub sub_tasks: Option<Vec<SubTaskParams>>,
+    pub device_ids: Option<Vec<i32>>,
+    pub parameters: Option<serde_json::Value>,
+}
+
+/// 子任务进度
+#[derive(Debug, Serialize)]
+pub struct SubTaskResponse {
+    pub id: i32,
+    pub uuid: Uuid,
+    pub name: String,
+    pub status: String,
+    pub device_id: i32,
+    pub device_name: Option<String>,
+    pub completed_steps: usize,
+    pub total_steps: usize,
+    pub current_step: Option<usize>,
+    pub current_step_type: Option<String>,
+    pub error: Option<String>,
+    pub reassigned_from: Option<i32>, // 接管的失败子任务ID
+    pub latitude: Option<f64>,        // 设备最近上报的位置
+    pub longitude: Option<f64>,
+    pub altitude: Option<f64>, // 相对起飞点高度
+    pub battery: Option<f64>,  // 剩余电量（百分比）
+    pub start_time: Option<DateTimeWithTimeZone>,
+    pub end_time: Option<DateTimeWithTimeZone>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FleetMissionResponse {
+    pub id: i32,
+    pub uuid: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    pub user_id: i32,
+    pub status: String,
+    pub failure_policy: String,
+    pub error: Option<String>,
+    pub completed_steps: usize,
+    pub total_steps: usize,
+    pub progress: f64, // 完成百分比
+    pub sub_tasks: Vec<SubTaskResponse>,
+    pub start_time: Option<DateTimeWithTimeZone>,
+    pub end_time: Option<DateTimeWithTimeZone>,
+    pub created_at: DateTimeWithTimeZone,
+    pub updated_at: DateTimeWithTimeZone,
+}
+
+/// 汇总编队任务及各子任务的进度
+async fn mission_response(
+    db: &DatabaseConnection,
+    mission: fleet_mission::Model,
+) -> Result<FleetMissionResponse> {
+    let tasks = task::Entity::find()
+        .filter(task::Column::FleetMissionId.eq(mission.id))
+        .find_also_related(device::Entity)
+        .order_by_asc(task::Column::Id)
+        .all(db)
+        .await?;
+    let service_manager = app_state::get_service_manager();
+
+    let mut sub_tasks = Vec::new();
+    for (task, device) in &tasks {
+        let progress: Option<TaskProgress> = task
+            .progress
+            .clone()
+            .and_then(|value| serde_json::from_value(value).ok());
+        let (completed_steps, total_steps, current_step) = match &progress {
+            Some(progress) if task.status != TaskStatus::Pending.to_string() => (
+                progress
+                    .steps
+                    .iter()
+                    .filter(|step| step.status == STEP_COMPLETED)
+                    .count(),
+                progress.steps.len(),
+                progress.current_step,
+            ),
+            _ => (
+                0,
+                parse_parameters(task.parameters.as_ref())
+                    .map(|(_, steps)| steps.len())
+                    .unwrap_or(0),
+                None,
+            ),
+        };
+        let telemetry = match &service_manager {
+            Some(service_manager) => service_manager.get_telemetry(task.device_id).await,
+            None => None,
+        };
+        let position = telemetry
+            .as_ref()
+            .and_then(|telemetry| telemetry.position.as_ref());
+
+        sub_tasks.push(SubTaskResponse {
+            id: task.id,
+            uuid: task.uuid,
+            name: task.name.clone(),
+            status: task.status.clone(),
+            device_id: task.device_id,
+            device_name: device.as_ref().map(|d| d.name.clone()),
+            completed_steps,
+            total_steps,
+            current_step,
+            current_step_type: current_step
+                .and_then(|index| progress.as_ref()?.steps.get(index))
+                .map(|step| step.step_type.clone()),
+            error: progress.and_then(|progress| progress.error),
+            reassigned_from: task.reassigned_from,
+            latitude: position.map(|position| position.value.latitude),
+            longitude: position.map(|position| position.value.longitude),
+            altitude: telemetry
+                .as_ref()
+                .and_then(|telemetry| telemetry.altitude.as_ref()?.value.relative),
+            battery: telemetry
+                .as_ref()
+                .and_then(|telemetry| telemetry.battery.as_ref()?.value.remaining),
+            start_time: task.start_time,
+            end_time: task.end_time,
+        });
+    }
+
+    // 未完成的航点已交给其它设备的子任务只计入已完成的步骤，避免重复计算
+    let reassigned: HashSet<i32> = tasks
+        .iter()
+        .filter_map(|(task, _)| task.reassigned_from)
+        .collect();
+    let completed_steps: usize = sub_tasks.iter().map(|task| task.completed_steps).sum();
+    let total_steps: usize = sub_tasks
+        .iter()
+        .map(|task| {
+            if reassigned.contains(&task.id) {
+                task.completed_steps
+            } else {
+                task.total_steps
+            }
+        })
+        .sum();
+    let progress = if total_steps == 0 {
+        0.0
+    } else {
+        (completed_steps as f64 * 1000.0 / total_steps as f64).round() / 10.0
+    };
+
+    Ok(FleetMissionResponse {
+        id: mission.id,
+        uuid: mission.uuid,
+        name: mission.name,
+        description: mission.description,
+        user_id: mission.user_id,
+        status: mission.status,
+        failure_policy: mission.failure_policy,
+        error: mission.error,
+        completed_steps,
+        total_steps,
+        progress,
+        sub_tasks,
+        start_time: mission.start_time,
+        end_time: mission.end_time,
+        created_at: mission.created_at,
+        updated_at: mission.updated_at,
+    })
+}
+
+/// 获取编队任务列表
+pub async fn list(auth: JWT, State(ctx): State<AppContext>) -> Result<Response> {
+    // 查找用户 (JWT中的pid实际上是email)
+    let user = user::Entity::find()
+        .filter(user::Column::Email.eq(&auth.claims.pid))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(user) = user else {
+        return unauthorized("用户未找到");
+    };
+
+    let missions = fleet_mission::Entity::find()
+        .filter(fleet_mission::Column::UserId.eq(user.id))
+        .order_by_desc(fleet_mission::Column::CreatedAt)
+        .all(&ctx.db)
+        .await?;
+
+    let mut responses = Vec::new();
+    for mission in missions {
+        responses.push(mission_response(&ctx.db, mission).await?);
+    }
+
+    format::json(responses)
+}
+
+/// 创建编队任务，为每台设备生成待执行的子任务
+pub async fn create(
+    auth: JWT,
+    State(ctx): State<AppContext>,
+    Json(params): Json<CreateFleetMissionParams>,
+) -> Result<Response> {
+    // 查找用户 (JWT中的pid实际上是email)
+    let user = user::Entity::find()
+        .filter(user::Column::Email.eq(&auth.claims.pid))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(user) = user else {
+        return unauthorized("用户未找到");
+    };
+
+    // 每台设备的子任务名称（可选）和参数
+    let parts: Vec<(i32, Option<String>, serde_json::Value)> =
+        match (params.sub_tasks, params.device_ids, params.parameters) {
+            (Some(sub_tasks), None, None) => sub_tasks
+                .into_iter()
+                .map(|sub_task| (sub_task.device_id, sub_task.name, sub_task.parameters))
+                .collect(),
+            (None, Some(device_ids), Some(parameters)) => {
+                let parameters: TaskParameters = match serde_json::from_value(parameters) {
+                    Ok(parameters) => parameters,
+                    Err(e) => return bad_request(format!("任务参数无效: {}", e)),
+                };
+                let split = match split_parameters(&parameters, device_ids.len()) {
+                    Ok(split) => split,
+                    Err(e) => return bad_request(format!("航线无法拆分: {}", e)),
+                };
+                device_ids
+                    .into_iter()
+                    .zip(split)
+                    .map(|(device_id, parameters)| {
+                        (
+                            device_id,
+                            None,
+                            serde_json::to_value(parameters).unwrap_or_default(),
+                        )
+                    })
+                    .collect()
+            }
+            _ => return bad_request("请指定 sub_tasks，或同时指定 device_ids 和 parameters"),
+        };
+
+    if parts.is_empty() {
+        return bad_request("编队任务至少需要一台设备");
+    }
+    let mut device_ids = HashSet::new();
+    if !parts
+        .iter()
+        .all(|(device_id, _, _)| device_ids.insert(*device_id))
+    {
+        return bad_request("每台设备只能分配一个子任务");
+    }
+
+    // 验证设备是否属于该用户，并按设备校验各自的航线
+    let mut devices = Vec::new();
+    for (device_id, _, parameters) in &parts {
+        let device = device::Entity::find()
+            .filter(device::Column::Id.eq(*device_id))
+            .filter(device::Column::UserId.eq(user.id))
+            .one(&ctx.db)
+            .await?;
+
+        let Some(device) = device else {
+            return bad_request(format!("设备 {} 未找到或无权限", device_id));
+        };
+
+        if let Some(message) = super::task::validate_plan(device.id, parameters).await {
+            return bad_request(format!("设备 {}: {}", device.name, message));
+        }
+        devices.push(device);
+    }
+
+    let now: DateTimeWithTimeZone = chrono::Utc::now().into();
+    let txn = ctx.db.begin().await?;
+    let mission = fleet_mission::ActiveModel {
+        uuid: Set(Uuid::new_v4()),
+        name: Set(params.name.clone()),
+        description: Set(params.description),
+        user_id: Set(user.id),
+        status: Set(TaskStatus::Pending.to_string()),
+        failure_policy: Set(params
+            .failure_policy
+            .unwrap_or(FailurePolicy::Continue)
+            .as_str()
+            .to_string()),
+        created_at: Set(now),
+        updated_at: Set(now),
+        ..Default::default()
+    }
+    .insert(&txn)
+    .await?;
+
+    for ((_, name, parameters), device) in parts.into_iter().zip(&devices) {
+        let task = task::ActiveModel {
+            uuid: Set(Uuid::new_v4()),
+            name: Set(name.unwrap_or_else(|| format!("{} - {}", params.name, device.name))),
+            description: Set(TaskParameters::deserialize(&parameters)
+                .ok()
+                .map(|parameters| parameters.describe())),
+            task_type: Set(task::TaskType::Manual.to_string()),
+            status: Set(TaskStatus::Pending.to_string()),
+            device_id: Set(device.id),
+            user_id: Set(user.id),
+            parameters: Set(Some(parameters)),
+            fleet_mission_id: Set(Some(mission.id)),
+            ..Default::default()
+        };
+        let task = task::Entity::insert(task).exec_with_returning(&txn).await?;
+        task_state::record_created(&txn, &task, Actor::User(user.id), None).await?;
+    }
+    txn.commit().await?;
+
+    format::json(mission_response(&ctx.db, mission).await?)
+}
+
+/// 获取编队任务及各子任务的进度
+pub async fn get_one(
+    auth: JWT,
+    Path(mission_uuid): Path<Uuid>,
+    State(ctx): State<AppContext>,
+) -> Result<Response> {
+    // 查找用户 (JWT中的pid实际上是email)
+    let user = user::Entity::find()
+        .filter(user::Column::Email.eq(&auth.claims.pid))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(user) = user else {
+        return unauthorized("用户未找到");
+    };
+
+    let mission = fleet_mission::Entity::find()
+        .filter(fleet_mission::Column::Uuid.eq(mission_uuid))
+        .filter(fleet_mission::Column::UserId.eq(user.id))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(mission) = mission else {
+        return not_found();
+    };
+
+    format::json(mission_response(&ctx.db, mission).await?)
+}
+
+/// 删除编队任务及其子任务
+pub async fn delete_mission(
+    auth: JWT,
+    Path(mission_uuid): Path<Uuid>,
+    State(ctx): State<AppContext>,
+) -> Result<Response> {
+    // 查找用户 (JWT中的pid实际上是email)
+    let user = user::Entity::find()
+        .filter(user::Column::Email.eq(&auth.claims.pid))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(user) = user else {
+        return unauthorized("用户未找到");
+    };
+
+    let mission = fleet_mission::Entity::find()
+        .filter(fleet_mission::Column::Uuid.eq(mission_uuid))
+        .filter(fleet_mission::Column::UserId.eq(user.id))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(mission) = mission else {
+        return not_found();
+    };
+
+    if mission.status == TaskStatus::Running.to_string() {
+        return bad_request("编队任务执行中，请先取消");
+    }
+    // 中止其它设备后编队任务已结束，但子任务可能仍在停止中
+    if let Some(service_manager) = app_state::get_service_manager() {
+        let tasks = task::Entity::find()
+            .filter(task::Column::FleetMissionId.eq(mission.id))
+            .all(&ctx.db)
+            .await?;
+        for task in tasks {
+            if service_manager.task_executor.is_running(task.id).await {
+                return bad_request("子任务执行中，请稍后再试");
+            }
+        }
+    }
+
+    fleet_mission::Entity::delete_by_id(mission.id)
+        .exec(&ctx.db)
+        .await?;
+
+    format::json(serde_json::json!({
+        "message": "编队任务删除成功"
+    }))
+}
+
+/// 同时开始所有子任务
+pub async fn start(
+    auth: JWT,
+    Path(mission_uuid): Path<Uuid>,
+    State(ctx): State<AppContext>,
+) -> Result<Response> {
+    // 查找用户 (JWT中的pid实际上是email)
+    let user = user::Entity::find()
+        .filter(user::Column::Email.eq(&auth.claims.pid))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(user) = user else {
+        return unauthorized("用户未找到");
+    };
+
+    let mission = fleet_mission::Entity::find()
+        .filter(fleet_mission::Column::Uuid.eq(mission_uuid))
+        .filter(fleet_mission::Column::UserId.eq(user.id))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(mission) = mission else {
+        return not_found();
+    };
+
+    if mission.status != TaskStatus::Pending.to_string() {
+        return bad_request("只能执行待执行的编队任务");
+    }
+
+    let Some(service_manager) = app_state::get_service_manager() else {
+        return bad_request("服务管理器未初始化");
+    };
+
+    if let Err(e) = service_manager
+        .fleet_coordinator
+        .start_mission(mission.id, Actor::User(user.id))
+        .await
+    {
+        return bad_request(e);
+    }
+
+    format::json(serde_json::json!({
+        "message": "编队任务已开始执行",
+        "uuid": mission.uuid
+    }))
+}
+
+/// 取消编队任务：停止执行中的子任务，取消待执行的子任务
+pub async fn cancel(
+    auth: JWT,
+    Path(mission_uuid): Path<Uuid>,
+    State(ctx): State<AppContext>,
+    body: Bytes,
+) -> Result<Response> {
+    stop(auth, mission_uuid, ctx, body, TaskStatus::Cancelled).await
+}
+
+/// 中止执行中的编队任务，所有设备停止执行并降落
+pub async fn abort(
+    auth: JWT,
+    Path(mission_uuid): Path<Uuid>,
+    State(ctx): State<AppContext>,
+    body: Bytes,
+) -> Result<Response> {
+    stop(auth, mission_uuid, ctx, body, TaskStatus::Aborted).await
+}
+
+async fn stop(
+    auth: JWT,
+    mission_uuid: Uuid,
+    ctx: AppContext,
+    body: Bytes,
+    status: TaskStatus,
+) -> Result<Response> {
+    let Ok(reason) = super::task::parse_reason(&body) else {
+        return bad_request("请求体格式错误");
+    };
+
+    // 查找用户 (JWT中的pid实际上是email)
+    let user = user::Entity::find()
+        .filter(user::Column::Email.eq(&auth.claims.pid))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(user) = user else {
+        return unauthorized("用户未找到");
+    };
+
+    let mission = fleet_mission::Entity::find()
+        .filter(fleet_mission::Column::Uuid.eq(mission_uuid))
+        .filter(fleet_mission::Column::UserId.eq(user.id))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(mission) = mission else {
+        return not_found();
+    };
+
+    if status == TaskStatus::Aborted && mission.status != TaskStatus::Running.to_string() {
+        return bad_request("编队任务未在执行");
+    }
+
+    let Some(service_manager) = app_state::get_service_manager() else {
+        return bad_request("服务管理器未初始化");
+    };
+
+    if let Err(e) = service_manager
+        .fleet_coordinator
+        .stop_mission(mission.id, status, Actor::User(user.id), reason)
+        .await
+    {
+        return bad_request(e);
+    }
+
+    format::json(serde_json::json!({
+        "message": if status == TaskStatus::Aborted { "编队任务已中止" } else { "编队任务已取消" },
+        "uuid": mission.uuid
+    }))
+}
+
+pub fn routes() -> Routes {
+    Routes::new()
+        .prefix("fleet-missions")
+        .add("/", get(list))
+        .add("/", post(create))
+        .add("/{mission_uuid}", get(get_one))
+        .add("/{mission_uuid}", delete(delete_mission))
+        .add("/{mission_uuid}/start", post(start))
+        .add("/{mission_uuid}/cancel", post(cancel))
+        .add("/{mission_uuid}/abort", post(abort))
+}
@@ -0,0 +1,352 @@
+use crate::models::{device, flight, task};
+use crate::services::access::{self, Authenticated};
+use crate::services::mission_file::{self, MissionFormat};
+use crate::services::{app_state, replay};
+use axum::{
+    extract::{ws::WebSocketUpgrade, Path, Query, RawQuery, State},
+    http::HeaderMap,
+    response::Response,
+};
+use loco_rs::prelude::*;
+use sea_orm::{PaginatorTrait, QueryOrder, QuerySelect};
+use serde::Deserialize;
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize)]
+pub struct FlightQuery {
+    pub limit: Option<u64>,
+    pub offset: Option<u64>,
+    pub status: Option<String>, // in_progress, completed
+    pub from: Option<String>,   // RFC3339，按起飞时间过滤
+    pub to: Option<String>,     // RFC3339
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReplayQuery {
+    pub speed: Option<f64>, // 初始倍速，默认 1
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MissionExportQuery {
+    pub format: Option<String>, // plan（默认）、kml、kmz
+}
+
+/// 通过UUID查找设备，失败时返回错误响应
+async fn find_device(
+    db: &DatabaseConnection,
+    device_uuid: &str,
+) -> std::result::Result<device::Model, serde_json::Value> {
+    let uuid = Uuid::parse_str(device_uuid).map_err(|_| {
+        serde_json::json!({
+            "error": "Invalid UUID format",
+            "device_uuid": device_uuid
+        })
+    })?;
+
+    match device::Entity::find()
+        .filter(device::Column::Uuid.eq(uuid))
+        .one(db)
+        .await
+    {
+        Ok(Some(device)) => Ok(device),
+        Ok(None) => Err(serde_json::json!({
+            "error": "Device not found",
+            "device_uuid": device_uuid
+        })),
+        Err(e) => Err(serde_json::json!({
+            "error": format!("Database error: {}", e),
+            "device_uuid": device_uuid
+        })),
+    }
+}
+
+fn flight_json(flight: &flight::Model, task: Option<&task::Model>) -> serde_json::Value {
+    serde_json::json!({
+        "id": flight.id,
+        "uuid": flight.uuid,
+        "device_id": flight.device_id,
+        "task_id": flight.task_id,
+        "task": task.map(|task| serde_json::json!({
+            "id": task.id,
+            "uuid": task.uuid,
+            "name": task.name,
+            "status": task.status
+        })),
+        "status": flight.status,
+        "start_reason": flight.start_reason,
+        "end_reason": flight.end_reason,
+        "start_time": flight.start_time.to_rfc3339(),
+        "end_time": flight.end_time.map(|time| time.to_rfc3339()),
+        "duration_seconds": flight.duration_seconds,
+        "distance": flight.distance,
+        "max_altitude": flight.max_altitude,
+        "max_speed": flight.max_speed,
+        "battery_start": flight.battery_start,
+        "battery_end": flight.battery_end,
+        "battery_consumed": flight.battery_consumed,
+        "updated_at": flight.updated_at.to_rfc3339()
+    })
+}
+
+/// 获取设备飞行架次列表，按起飞时间倒序
+pub async fn list_device_flights(
+    Authenticated(principal): Authenticated,
+    Path(device_uuid): Path<String>,
+    Query(params): Query<FlightQuery>,
+    State(ctx): State<AppContext>,
+) -> Result<Response> {
+    let device = match find_device(&ctx.db, &device_uuid).await {
+        Ok(device) => device,
+        Err(error) => return format::json(error),
+    };
+    if !principal.can_access(&device) {
+        return unauthorized("无权访问该设备");
+    }
+
+    let mut query = flight::Entity::find().filter(flight::Column::DeviceId.eq(device.id));
+    if let Some(status) = params.status.as_deref().filter(|s| !s.is_empty()) {
+        query = query.filter(flight::Column::Status.eq(status));
+    }
+    for (value, is_from) in [(&params.from, true), (&params.to, false)] {
+        let Some(value) = value else {
+            continue;
+        };
+        let Ok(time) = chrono::DateTime::parse_from_rfc3339(value) else {
+            return format::json(serde_json::json!({
+                "error": format!("Invalid RFC3339 time: {}", value)
+            }));
+        };
+        query = if is_from {
+            query.filter(flight::Column::StartTime.gte(time))
+        } else {
+            query.filter(flight::Column::StartTime.lte(time))
+        };
+    }
+
+    let limit = params.limit.unwrap_or(20).min(100); // 最大100条
+    let offset = params.offset.unwrap_or(0);
+
+    let total = query.clone().count(&ctx.db).await?;
+    let flights = query
+        .order_by_desc(flight::Column::StartTime)
+        .order_by_desc(flight::Column::Id)
+        .limit(limit)
+        .offset(offset)
+        .find_also_related(task::Entity)
+        .all(&ctx.db)
+        .await?;
+
+    let data: Vec<_> = flights
+        .iter()
+        .map(|(flight, task)| flight_json(flight, task.as_ref()))
+        .collect();
+
+    format::json(serde_json::json!({
+        "device_id": device.id,
+        "data": data,
+        "limit": limit,
+        "offset": offset,
+        "total": total
+    }))
+}
+
+/// 获取单个飞行架次详情
+pub async fn get_device_flight(
+    Authenticated(principal): Authenticated,
+    Path((device_uuid, flight_uuid)): Path<(String, String)>,
+    State(ctx): State<AppContext>,
+) -> Result<Response> {
+    let device = match find_device(&ctx.db, &device_uuid).await {
+        Ok(device) => device,
+        Err(error) => return format::json(error),
+    };
+    if !principal.can_access(&device) {
+        return unauthorized("无权访问该设备");
+    }
+    let Ok(flight_uuid) = Uuid::parse_str(&flight_uuid) else {
+        return format::json(serde_json::json!({
+            "error": "Invalid UUID format",
+            "flight_uuid": flight_uuid
+        }));
+    };
+
+    let found = flight::Entity::find()
+        .filter(flight::Column::DeviceId.eq(device.id))
+        .filter(flight::Column::Uuid.eq(flight_uuid))
+        .find_also_related(task::Entity)
+        .one(&ctx.db)
+        .await?;
+
+    match found {
+        Some((flight, task)) => format::json(flight_json(&flight, task.as_ref())),
+        None => format::json(serde_json::json!({
+            "error": "Flight not found",
+            "flight_uuid": flight_uuid
+        })),
+    }
+}
+
+/// 飞行回放 WebSocket 端点
+///
+/// 升级前校验 JWT 或 API Key（传递方式见 `services::access`），只能回放有权访问的设备。
+/// 连接建立后立即按 `speed` 倍速播放，控制消息见 `services::replay`。
+pub async fn replay_device_flight(
+    ws: WebSocketUpgrade,
+    headers: HeaderMap,
+    RawQuery(query): RawQuery,
+    Path((device_uuid, flight_uuid)): Path<(String, String)>,
+    Query(params): Query<ReplayQuery>,
+    State(ctx): State<AppContext>,
+) -> Result<Response> {
+    let Some(service_manager) = app_state::get_service_manager() else {
+        return format::json(serde_json::json!({ "error": "Service manager not initialized" }));
+    };
+    let Some(token) = access::request_token(query.as_deref(), &headers) else {
+        return unauthorized("缺少访问凭证");
+    };
+    let principal = match service_manager.access.authenticate(&token).await {
+        Ok(principal) => principal,
+        Err(e) => return unauthorized(e),
+    };
+
+    let device = match find_device(&ctx.db, &device_uuid).await {
+        Ok(device) => device,
+        Err(error) => return format::json(error),
+    };
+    if !principal.can_access(&device) {
+        return unauthorized("无权访问该设备");
+    }
+    let Ok(flight_uuid) = Uuid::parse_str(&flight_uuid) else {
+        return format::json(serde_json::json!({
+            "error": "Invalid UUID format",
+            "flight_uuid": flight_uuid
+        }));
+    };
+    let speed = match replay::validate_speed(params.speed.unwrap_or(1.0)) {
+        Ok(speed) => speed,
+        Err(e) => return format::json(serde_json::json!({ "error": e })),
+    };
+
+    let Some(flight) = flight::Entity::find()
+        .filter(flight::Column::DeviceId.eq(device.id))
+        .filter(flight::Column::Uuid.eq(flight_uuid))
+        .one(&ctx.db)
+        .await?
+    else {
+        return format::json(serde_json::json!({
+            "error": "Flight not found",
+            "flight_uuid": flight_uuid
+        }));
+    };
+
+    let range = replay::ReplayRange::from_flight(&flight);
+    let db = ctx.db.clone();
+    let ws = if access::uses_bearer_protocol(&headers) {
+        ws.protocols([access::BEARER_PROTOCOL])
+    } else {
+        ws
+    };
+    Ok(ws.on_upgrade(move |socket| replay::run(db, socket, range, speed)))
+}
+
+/// 导出飞行架次所属任务的航线，随飞行记录归档
+pub async fn export_flight_mission(
+    Authenticated(principal): Authenticated,
+    Path((device_uuid, flight_uuid)): Path<(String, String)>,
+    Query(params): Query<MissionExportQuery>,
+    State(ctx): State<AppContext>,
+) -> Result<Response> {
+    let device = match find_device(&ctx.db, &device_uuid).await {
+        Ok(device) => device,
+        Err(error) => return format::json(error),
+    };
+    if !principal.can_access(&device) {
+        return unauthorized("无权访问该设备");
+    }
+    let Ok(flight_uuid) = Uuid::parse_str(&flight_uuid) else {
+        return format::json(serde_json::json!({
+            "error": "Invalid UUID format",
+            "flight_uuid": flight_uuid
+        }));
+    };
+    let Some(mission_format) = MissionFormat::parse(params.format.as_deref().unwrap_or("plan"))
+    else {
+        return format::json(serde_json::json!({
+            "error": "Unsupported format, expected plan, kml or kmz"
+        }));
+    };
+
+    let found = flight::Entity::find()
+        .filter(flight::Column::DeviceId.eq(device.id))
+        .filter(flight::Column::Uuid.eq(flight_uuid))
+        .find_also_related(task::Entity)
+        .one(&ctx.db)
+        .await?;
+    let task = match found {
+        Some((_, Some(task))) => task,
+        Some((_, None)) => {
+            return format::json(serde_json::json!({
+                "error": "Flight has no task",
+                "flight_uuid": flight_uuid
+            }));
+        }
+        None => {
+            return format::json(serde_json::json!({
+                "error": "Flight not found",
+                "flight_uuid": flight_uuid
+            }));
+        }
+    };
+
+    let Some(service_manager) = app_state::get_service_manager() else {
+        return format::json(serde_json::json!({ "error": "Service manager not initialized" }));
+    };
+    let plan = match service_manager
+        .task_executor
+        .plan(task.device_id, task.parameters.as_ref())
+        .await
+    {
+        Ok(plan) => plan,
+        Err(e) => return format::json(serde_json::json!({ "error": e })),
+    };
+    // 导出的起飞点取航线第一个航点，而不是设备当前位置
+    let content = match mission_file::export(mission_format, &task.name, &plan.steps, None) {
+        Ok(content) => content,
+        Err(e) => return format::json(serde_json::json!({ "error": e })),
+    };
+
+    Response::builder()
+        .header(
+            axum::http::header::CONTENT_TYPE,
+            mission_format.content_type(),
+        )
+        .header(
+            axum::http::header::CONTENT_DISPOSITION,
+            format!(
+                "attachment; filename=\"flight-{}-task-{}.{}\"",
+                flight_uuid,
+                task.id,
+                mission_format.extension()
+            ),
+        )
+        .body(axum::body::Body::from(content))
+        .map_err(|e| Error::string(&format!("Failed to build export response: {}", e)))
+}
+
+pub fn routes() -> Routes {
+    Routes::new()
+        .prefix("realtime")
+        .add("/devices/{device_id}/flights", get(list_device_flights))
+        .add(
+            "/devices/{device_id}/flights/{flight_id}",
+            get(get_device_flight),
+        )
+        .add(
+            "/devices/{device_id}/flights/{flight_id}/replay",
+            get(replay_device_flight),
+        )
+        .add(
+            "/devices/{device_id}/flights/{flight_id}/mission",
+            get(export_flight_mission),
+        )
+}
@@ -0,0 +1,376 @@
+use crate::middleware::rbac::check_user_permission;
+use crate::models::{device, geofence, user};
+use crate::services::app_state;
+use crate::services::geofence::{Geofence, GeofenceKind, GeofenceShape};
+use axum::extract::Query;
+use axum::Json;
+use loco_rs::controller::middleware::auth::JWT;
+use loco_rs::prelude::*;
+use sea_orm::{DatabaseConnection, QueryOrder};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// 围栏定义，更新时整体替换，未传的高度限制表示不限制
+#[derive(Debug, Deserialize, Serialize)]
+pub struct GeofenceParams {
+    pub name: String,
+    pub description: Option<String>,
+    pub kind: GeofenceKind,
+    pub shape: GeofenceShape,
+    pub min_altitude: Option<f64>, // 相对起飞点，米
+    pub max_altitude: Option<f64>,
+    pub rtl_on_breach: Option<bool>, // 越界时自动返航
+    pub is_active: Option<bool>,
+}
+
+/// 设备、用户最多指定一个，都不指定时为全局围栏；创建后不可修改
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CreateGeofenceParams {
+    #[serde(flatten)]
+    pub geofence: GeofenceParams,
+    pub device_id: Option<i32>,
+    pub user_id: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListGeofencesQuery {
+    pub device_id: Option<i32>, // 只返回约束该设备的围栏
+    pub include_inactive: Option<bool>,
+}
+
+/// 校验围栏定义
+fn validate_params(params: &GeofenceParams) -> Option<String> {
+    if params.name.trim().is_empty() {
+        return Some("围栏名称不能为空".to_string());
+    }
+    Geofence {
+        name: params.name.clone(),
+        kind: params.kind,
+        shape: params.shape.clone(),
+        min_altitude: params.min_altitude,
+        max_altitude: params.max_altitude,
+    }
+    .validate()
+    .err()
+    .map(|e| format!("电子围栏无效: {}", e))
+}
+
+/// 检查用户能否管理指定对象的围栏，不能时返回提示信息：
+/// 设备围栏需要拥有该设备，用户围栏只能指定自己（有 users.write 权限时可指定他人），
+/// 全局围栏需要 system.admin 权限
+async fn check_scope(
+    db: &DatabaseConnection,
+    auth: &JWT,
+    user: &user::Model,
+    device_id: Option<i32>,
+    user_id: Option<i32>,
+) -> Result<Option<&'static str>> {
+    let is_admin = check_user_permission(db, &auth.claims.pid, "system.admin").await?;
+    match (device_id, user_id) {
+        (Some(device_id), None) => {
+            let device = device::Entity::find_by_id(device_id).one(db).await?;
+            Ok(match device {
+                Some(device) if is_admin || device.user_id == user.id => None,
+                _ => Some("设备未找到或无权限"),
+            })
+        }
+        (None, Some(user_id)) => {
+            if user_id != user.id
+                && !is_admin
+                && !check_user_permission(db, &auth.claims.pid, "users.write").await?
+            {
+                return Ok(Some("只能为自己设置用户围栏"));
+            }
+            let target = user::Entity::find_by_id(user_id).one(db).await?;
+            Ok(target.is_none().then_some("用户不存在"))
+        }
+        (None, None) => Ok((!is_admin).then_some("全局围栏需要系统管理员权限")),
+        (Some(_), Some(_)) => Ok(Some("围栏只能指定设备或用户中的一个")),
+    }
+}
+
+/// 非管理员只能查看约束自己设备的围栏
+fn is_visible(fence: &geofence::Model, user: &user::Model, device_ids: &[i32]) -> bool {
+    match (fence.device_id, fence.user_id) {
+        (Some(device_id), _) => device_ids.contains(&device_id),
+        (None, Some(user_id)) => user_id == user.id,
+        (None, None) => true,
+    }
+}
+
+/// 围栏变更后立即重新加载实时检查使用的围栏
+fn reload_geofences() {
+    if let Some(service_manager) = app_state::get_service_manager() {
+        service_manager.reload_geofences();
+    }
+}
+
+/// 获取电子围栏列表
+pub async fn list(
+    auth: JWT,
+    Query(query): Query<ListGeofencesQuery>,
+    State(ctx): State<AppContext>,
+) -> Result<Response> {
+    // 查找用户 (JWT中的pid实际上是email)
+    let user = user::Entity::find()
+        .filter(user::Column::Email.eq(&auth.claims.pid))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(user) = user else {
+        return unauthorized("用户未找到");
+    };
+
+    let is_admin = check_user_permission(&ctx.db, &auth.claims.pid, "system.admin").await?;
+
+    let mut select = geofence::Entity::find();
+    if !query.include_inactive.unwrap_or(false) {
+        select = select.filter(geofence::Column::IsActive.eq(true));
+    }
+    let fences = select
+        .order_by_asc(geofence::Column::Id)
+        .all(&ctx.db)
+        .await?;
+
+    let fences: Vec<geofence::Model> = match query.device_id {
+        Some(device_id) => {
+            let device = device::Entity::find_by_id(device_id).one(&ctx.db).await?;
+            let Some(device) = device.filter(|device| is_admin || device.user_id == user.id) else {
+                return bad_request("设备未找到或无权限");
+            };
+            fences
+                .into_iter()
+                .filter(|fence| fence.applies_to(device.id, device.user_id))
+                .collect()
+        }
+        None if is_admin => fences,
+        None => {
+            let device_ids: Vec<i32> = device::Entity::find()
+                .filter(device::Column::UserId.eq(user.id))
+                .all(&ctx.db)
+                .await?
+                .into_iter()
+                .map(|device| device.id)
+                .collect();
+            fences
+                .into_iter()
+                .filter(|fence| is_visible(fence, &user, &device_ids))
+                .collect()
+        }
+    };
+
+    format::json(fences)
+}
+
+/// 创建电子围栏
+pub async fn create(
+    auth: JWT,
+    State(ctx): State<AppContext>,
+    Json(params): Json<CreateGeofenceParams>,
+) -> Result<Response> {
+    // 查找用户 (JWT中的pid实际上是email)
+    let user = user::Entity::find()
+        .filter(user::Column::Email.eq(&auth.claims.pid))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(user) = user else {
+        return unauthorized("用户未找到");
+    };
+
+    if !check_user_permission(&ctx.db, &auth.claims.pid, "devices.write").await? {
+        return unauthorized("权限不足");
+    }
+
+    if let Some(message) = validate_params(&params.geofence) {
+        return bad_request(message);
+    }
+    if let Some(message) =
+        check_scope(&ctx.db, &auth, &user, params.device_id, params.user_id).await?
+    {
+        return bad_request(message);
+    }
+
+    let now: DateTimeWithTimeZone = chrono::Utc::now().into();
+    let definition = params.geofence;
+    let fence_model = geofence::ActiveModel {
+        uuid: Set(Uuid::new_v4()),
+        name: Set(definition.name.trim().to_string()),
+        description: Set(definition.description),
+        kind: Set(definition.kind.as_str().to_string()),
+        shape: Set(serde_json::to_value(&definition.shape)?),
+        min_altitude: Set(definition.min_altitude),
+        max_altitude: Set(definition.max_altitude),
+        device_id: Set(params.device_id),
+        user_id: Set(params.user_id),
+        rtl_on_breach: Set(definition.rtl_on_breach.unwrap_or(false)),
+        is_active: Set(definition.is_active.unwrap_or(true)),
+        created_by: Set(Some(user.id)),
+        created_at: Set(now),
+        updated_at: Set(now),
+        ..Default::default()
+    };
+
+    let fence = geofence::Entity::insert(fence_model)
+        .exec_with_returning(&ctx.db)
+        .await?;
+    reload_geofences();
+
+    format::json(fence)
+}
+
+/// 获取单个电子围栏
+pub async fn get_one(
+    auth: JWT,
+    Path(geofence_uuid): Path<Uuid>,
+    State(ctx): State<AppContext>,
+) -> Result<Response> {
+    // 查找用户 (JWT中的pid实际上是email)
+    let user = user::Entity::find()
+        .filter(user::Column::Email.eq(&auth.claims.pid))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(user) = user else {
+        return unauthorized("用户未找到");
+    };
+
+    let fence = geofence::Entity::find()
+        .filter(geofence::Column::Uuid.eq(geofence_uuid))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(fence) = fence else {
+        return not_found();
+    };
+
+    if !check_user_permission(&ctx.db, &auth.claims.pid, "system.admin").await? {
+        let device_ids: Vec<i32> = device::Entity::find()
+            .filter(device::Column::UserId.eq(user.id))
+            .all(&ctx.db)
+            .await?
+            .into_iter()
+            .map(|device| device.id)
+            .collect();
+        if !is_visible(&fence, &user, &device_ids) {
+            return not_found();
+        }
+    }
+
+    format::json(fence)
+}
+
+/// 更新电子围栏定义
+pub async fn update(
+    auth: JWT,
+    Path(geofence_uuid): Path<Uuid>,
+    State(ctx): State<AppContext>,
+    Json(params): Json<GeofenceParams>,
+) -> Result<Response> {
+    // 查找用户 (JWT中的pid实际上是email)
+    let user = user::Entity::find()
+        .filter(user::Column::Email.eq(&auth.claims.pid))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(user) = user else {
+        return unauthorized("用户未找到");
+    };
+
+    if !check_user_permission(&ctx.db, &auth.claims.pid, "devices.write").await? {
+        return unauthorized("权限不足");
+    }
+
+    let fence = geofence::Entity::find()
+        .filter(geofence::Column::Uuid.eq(geofence_uuid))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(fence) = fence else {
+        return not_found();
+    };
+
+    if let Some(message) =
+        check_scope(&ctx.db, &auth, &user, fence.device_id, fence.user_id).await?
+    {
+        return bad_request(message);
+    }
+    if let Some(message) = validate_params(&params) {
+        return bad_request(message);
+    }
+
+    let shape = serde_json::to_value(&params.shape)?;
+    let rtl_on_breach = params.rtl_on_breach.unwrap_or(fence.rtl_on_breach);
+    let is_active = params.is_active.unwrap_or(fence.is_active);
+
+    let mut fence_active: geofence::ActiveModel = fence.into();
+    fence_active.name = Set(params.name.trim().to_string());
+    fence_active.description = Set(params.description);
+    fence_active.kind = Set(params.kind.as_str().to_string());
+    fence_active.shape = Set(shape);
+    fence_active.min_altitude = Set(params.min_altitude);
+    fence_active.max_altitude = Set(params.max_altitude);
+    fence_active.rtl_on_breach = Set(rtl_on_breach);
+    fence_active.is_active = Set(is_active);
+    fence_active.updated_at = Set(chrono::Utc::now().into());
+
+    let fence = fence_active.update(&ctx.db).await?;
+    reload_geofences();
+
+    format::json(fence)
+}
+
+/// 删除电子围栏
+pub async fn delete_geofence(
+    auth: JWT,
+    Path(geofence_uuid): Path<Uuid>,
+    State(ctx): State<AppContext>,
+) -> Result<Response> {
+    // 查找用户 (JWT中的pid实际上是email)
+    let user = user::Entity::find()
+        .filter(user::Column::Email.eq(&auth.claims.pid))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(user) = user else {
+        return unauthorized("用户未找到");
+    };
+
+    if !check_user_permission(&ctx.db, &auth.claims.pid, "devices.delete").await? {
+        return unauthorized("权限不足");
+    }
+
+    let fence = geofence::Entity::find()
+        .filter(geofence::Column::Uuid.eq(geofence_uuid))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(fence) = fence else {
+        return not_found();
+    };
+
+    if let Some(message) =
+        check_scope(&ctx.db, &auth, &user, fence.device_id, fence.user_id).await?
+    {
+        return bad_request(message);
+    }
+
+    geofence::Entity::delete_by_id(fence.id)
+        .exec(&ctx.db)
+        .await?;
+    reload_geofences();
+
+    format::json(serde_json::json!({
+        "message": "电子围栏删除成功"
+    }))
+}
+
+pub fn routes() -> Routes {
+    Routes::new()
+        .prefix("geofences")
+        .add("/", get(list))
+        .add("/", post(create))
+        .add("/{geofence_uuid}", get(get_one))
+        .add("/{geofence_uuid}", put(update))
+        .add("/{geofence_uuid}", delete(delete_geofence))
+}
@@ -4,11 +4,16 @@ pub mod collection_data;
 pub mod common;
 pub mod device;
 pub mod element_type;
+pub mod fleet_mission;
+pub mod flight;
+pub mod geofence;
 pub mod graphql;
 pub mod prediction;
 pub mod rbac;
 pub mod realtime;
 pub mod task;
+pub mod task_schedule;
+pub mod task_template;
 pub mod upload;
 pub mod user;
 pub mod warn;
@@ -2,36 +2,85 @@ use crate::models::device;
 use axum::{
     extract::{
         ws::{WebSocket, WebSocketUpgrade},
-        Path, Query, State,
+        Path, Query, RawQuery, State,
+    },
+    http::HeaderMap,
+    response::{
+        sse::{KeepAlive, Sse},
+        IntoResponse, Response,
     },
-    response::Response,
 };
 use loco_rs::prelude::*;
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use uuid::Uuid;
 
-use crate::services::app_state;
+use crate::services::access::{self, Authenticated, DeviceScope};
+use crate::services::{app_state, export, history, mavlink_service::MavlinkCommand, rollup, sse};
 
 #[derive(Debug, Deserialize)]
 pub struct WebSocketQuery {
     pub device_id: Option<i32>,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct SseQuery {
+    pub devices: Option<String>, // 逗号分隔的设备ID，为空时推送全部可访问设备
+    pub last_event_id: Option<u64>, // 续传位置，重连时浏览器会自动发送 Last-Event-ID 头
+}
+
 /// WebSocket连接端点
+///
+/// 升级前校验 JWT 或 API Key（传递方式见 `services::access`），只推送用户有权访问的设备。
 pub async fn websocket_handler(
     ws: WebSocketUpgrade,
+    headers: HeaderMap,
+    RawQuery(query): RawQuery,
     Query(params): Query<WebSocketQuery>,
-    State(ctx): State<AppContext>,
-) -> Response {
-    let client_id = Uuid::new_v4().to_string();
+    State(_ctx): State<AppContext>,
+) -> Result<Response> {
+    let Some(service_manager) = app_state::get_service_manager() else {
+        return format::json(serde_json::json!({
+            "error": "Service manager not initialized"
+        }));
+    };
 
-    // 从应用上下文获取广播服务
-    // 注意：这里需要在应用启动时将BroadcastService添加到AppContext中
-    ws.on_upgrade(move |socket| handle_websocket_connection(socket, client_id, params.device_id))
+    let Some(token) = access::request_token(query.as_deref(), &headers) else {
+        return unauthorized("缺少访问凭证");
+    };
+    let principal = match service_manager.access.authenticate(&token).await {
+        Ok(principal) => principal,
+        Err(e) => return unauthorized(e),
+    };
+    if let Some(device_id) = params.device_id {
+        if !service_manager
+            .access
+            .can_access_device(&principal, device_id)
+            .await?
+        {
+            return unauthorized("无权访问该设备");
+        }
+    }
+
+    let client_id = Uuid::new_v4().to_string();
+    let scope = principal.scope;
+    let ws = if access::uses_bearer_protocol(&headers) {
+        ws.protocols([access::BEARER_PROTOCOL])
+    } else {
+        ws
+    };
+    Ok(ws.on_upgrade(move |socket| {
+        handle_websocket_connection(socket, client_id, params.device_id, scope)
+    }))
 }
 
 /// 处理WebSocket连接
-async fn handle_websocket_connection(socket: WebSocket, client_id: String, device_id: Option<i32>) {
+async fn handle_websocket_connection(
+    socket: WebSocket,
+    client_id: String,
+    device_id: Option<i32>,
+    scope: DeviceScope,
+) {
     tracing::info!(
         "WebSocket connection established: client_id={}, device_id={:?}",
         client_id,
@@ -41,15 +90,87 @@ async fn handle_websocket_connection(socket: WebSocket, client_id: String, devic
     // 获取服务管理器
     if let Some(service_manager) = app_state::get_service_manager() {
         service_manager
-            .handle_websocket_connection(socket, client_id, device_id)
+            .handle_websocket_connection(socket, client_id, device_id, scope)
             .await;
     } else {
         tracing::error!("Service manager not initialized");
     }
 }
 
+/// 实时数据 SSE 端点
+///
+/// 凭证传递方式与 WebSocket 相同，EventSource 无法设置请求头时使用 `token` 查询参数。
+/// 事件格式和续传规则见 `services::sse`。
+pub async fn sse_handler(
+    headers: HeaderMap,
+    RawQuery(query): RawQuery,
+    Query(params): Query<SseQuery>,
+    State(ctx): State<AppContext>,
+) -> Result<Response> {
+    let Some(service_manager) = app_state::get_service_manager() else {
+        return format::json(serde_json::json!({
+            "error": "Service manager not initialized"
+        }));
+    };
+
+    let Some(token) = access::request_token(query.as_deref(), &headers) else {
+        return unauthorized("缺少访问凭证");
+    };
+    let principal = match service_manager.access.authenticate(&token).await {
+        Ok(principal) => principal,
+        Err(e) => return unauthorized(e),
+    };
+
+    let requested = params
+        .devices
+        .as_deref()
+        .map(str::trim)
+        .filter(|devices| !devices.is_empty());
+    let devices = match requested {
+        Some(requested) => {
+            let mut devices = HashSet::new();
+            for value in requested.split(',').map(str::trim) {
+                let Ok(device_id) = value.parse::<i32>() else {
+                    return format::json(serde_json::json!({
+                        "error": format!("Invalid device id: {}", value)
+                    }));
+                };
+                if !service_manager
+                    .access
+                    .can_access_device(&principal, device_id)
+                    .await?
+                {
+                    return unauthorized("无权访问该设备");
+                }
+                devices.insert(device_id);
+            }
+            Some(devices)
+        }
+        None => principal.device_ids(&ctx.db).await?,
+    };
+
+    let last_event_id = headers
+        .get("last-event-id")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok())
+        .or(params.last_event_id);
+
+    let stream = sse::event_stream(
+        service_manager.get_event_log().await,
+        devices,
+        last_event_id,
+    );
+    // 关闭反向代理缓冲，保证事件及时送达
+    Ok((
+        [("x-accel-buffering", "no")],
+        Sse::new(stream).keep_alive(KeepAlive::default()),
+    )
+        .into_response())
+}
+
 /// 获取设备实时状态
 pub async fn get_device_status(
+    Authenticated(principal): Authenticated,
     Path(device_uuid): Path<String>,
     State(ctx): State<AppContext>,
 ) -> Result<Response> {
@@ -70,7 +191,8 @@ pub async fn get_device_status(
         .one(&ctx.db)
         .await
     {
-        Ok(Some(device)) => device.id,
+        Ok(Some(device)) if principal.can_access(&device) => device.id,
+        Ok(Some(_)) => return unauthorized("无权访问该设备"),
         Ok(None) => {
             return format::json(serde_json::json!({
                 "error": "Device not found",
@@ -91,8 +213,10 @@ pub async fn get_device_status(
         "status": "disconnected",
         "last_update": chrono::Utc::now().to_rfc3339(),
         "data": {},
+        "telemetry": null,
         "websocket_connected": false,
-        "mqtt_running": false
+        "mqtt_running": false,
+        "mavlink_online": false
     });
 
     // 从服务管理器获取设备状态
@@ -107,8 +231,12 @@ pub async fn get_device_status(
         let mqtt_running = service_manager.is_mqtt_running(device_id).await;
         status["mqtt_running"] = serde_json::json!(mqtt_running);
 
-        // 如果WebSocket连接或MQTT运行，则认为设备已连接
-        if ws_connected || mqtt_running {
+        // 检查MAVLink在线状态
+        let mavlink_online = service_manager.is_mavlink_online(device_id).await;
+        status["mavlink_online"] = serde_json::json!(mavlink_online);
+
+        // 如果WebSocket连接、MQTT运行或MAVLink在线，则认为设备已连接
+        if ws_connected || mqtt_running || mavlink_online {
             status["status"] = serde_json::json!("connected");
         }
 
@@ -116,22 +244,39 @@ pub async fn get_device_status(
         if let Some(device_data) = service_manager.get_device_state(device_id).await {
             status["data"] = device_data;
         }
+
+        // 获取类型化遥测快照
+        if let Some(snapshot) = service_manager.get_telemetry(device_id).await {
+            status["telemetry"] = serde_json::json!(snapshot);
+        }
     }
 
     format::json(status)
 }
 
-/// 获取所有设备状态
-pub async fn get_all_device_status(State(ctx): State<AppContext>) -> Result<Response> {
+/// 获取当前用户可访问设备的状态
+pub async fn get_all_device_status(
+    Authenticated(principal): Authenticated,
+    State(ctx): State<AppContext>,
+) -> Result<Response> {
+    let allowed = principal.device_ids(&ctx.db).await?;
     let mut devices = Vec::new();
 
     // 从服务管理器获取所有设备状态
     if let Some(service_manager) = app_state::get_service_manager() {
         let all_states = service_manager.get_all_device_states().await;
+        let mut all_telemetry = service_manager.get_all_telemetry().await;
         for (device_id, data) in all_states {
+            if allowed
+                .as_ref()
+                .is_some_and(|allowed| !allowed.contains(&device_id))
+            {
+                continue;
+            }
             devices.push(serde_json::json!({
                 "device_id": device_id,
                 "data": data,
+                "telemetry": all_telemetry.remove(&device_id),
                 "status": "connected"
             }));
         }
@@ -146,8 +291,37 @@ pub async fn get_all_device_status(State(ctx): State<AppContext>) -> Result<Resp
     format::json(status)
 }
 
+/// 获取WebSocket推送指标，包括各客户端的队列深度和丢弃计数（仅管理员）
+pub async fn get_broadcast_metrics(Authenticated(principal): Authenticated) -> Result<Response> {
+    if principal.scope != DeviceScope::All {
+        return unauthorized("权限不足");
+    }
+
+    match app_state::get_service_manager() {
+        Some(service_manager) => format::json(service_manager.get_broadcast_metrics().await),
+        None => format::json(serde_json::json!({
+            "error": "Service manager not initialized"
+        })),
+    }
+}
+
+/// 获取实时数据写入管道指标（仅管理员）
+pub async fn get_ingest_metrics(Authenticated(principal): Authenticated) -> Result<Response> {
+    if principal.scope != DeviceScope::All {
+        return unauthorized("权限不足");
+    }
+
+    match app_state::get_service_manager() {
+        Some(service_manager) => format::json(service_manager.get_ingest_metrics()),
+        None => format::json(serde_json::json!({
+            "error": "Service manager not initialized"
+        })),
+    }
+}
+
 /// 向设备发送命令
 pub async fn send_device_command(
+    Authenticated(principal): Authenticated,
     Path(device_uuid): Path<String>,
     State(ctx): State<AppContext>,
     Json(command): Json<serde_json::Value>,
@@ -169,7 +343,8 @@ pub async fn send_device_command(
         .one(&ctx.db)
         .await
     {
-        Ok(Some(device)) => device.id,
+        Ok(Some(device)) if principal.can_access(&device) => device.id,
+        Ok(Some(_)) => return unauthorized("无权访问该设备"),
         Ok(None) => {
             return format::json(serde_json::json!({
                 "error": "Device not found",
@@ -192,13 +367,43 @@ pub async fn send_device_command(
     );
 
     let mut command_sent = false;
+    let mut transport = "websocket";
+    let mut ack = None;
+    let mut error = None;
 
-    // 通过设备WebSocket代理发送命令
     if let Some(service_manager) = app_state::get_service_manager() {
-        if let Some(command_str) = command.as_str() {
-            if let Ok(_) = service_manager
+        let mavlink_command = if service_manager.is_mavlink_online(device_id).await {
+            MavlinkCommand::from_json(&command)
+        } else {
+            None
+        };
+
+        if let Some(mavlink_command) = mavlink_command {
+            // MAVLink设备下发COMMAND_LONG并等待应答
+            transport = "mavlink";
+            match service_manager
+                .send_mavlink_command(device_id, &mavlink_command)
+                .await
+            {
+                Ok(result) => {
+                    command_sent = result.accepted;
+                    ack = Some(result);
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        "Failed to send MAVLink command to device {}: {}",
+                        device_id,
+                        e
+                    );
+                    error = Some(e.to_string());
+                }
+            }
+        } else if let Some(command_str) = command.as_str() {
+            // 通过设备WebSocket代理发送命令
+            if service_manager
                 .send_device_websocket_command(device_id, command_str.to_string())
                 .await
+                .is_ok()
             {
                 command_sent = true;
             }
@@ -208,6 +413,9 @@ pub async fn send_device_command(
     let response = serde_json::json!({
         "device_id": device_id,
         "command_sent": command_sent,
+        "transport": transport,
+        "ack": ack,
+        "error": error,
         "timestamp": chrono::Utc::now().to_rfc3339()
     });
 
@@ -216,6 +424,7 @@ pub async fn send_device_command(
 
 /// 获取设备历史数据
 pub async fn get_device_history(
+    Authenticated(principal): Authenticated,
     Path(device_uuid): Path<String>,
     Query(params): Query<HistoryQuery>,
     State(ctx): State<AppContext>,
@@ -237,7 +446,8 @@ pub async fn get_device_history(
         .one(&ctx.db)
         .await
     {
-        Ok(Some(device)) => device.id,
+        Ok(Some(device)) if principal.can_access(&device) => device.id,
+        Ok(Some(_)) => return unauthorized("无权访问该设备"),
         Ok(None) => {
             return format::json(serde_json::json!({
                 "error": "Device not found",
@@ -251,21 +461,30 @@ pub async fn get_device_history(
             }));
         }
     };
-    use crate::models::device_realtime_data;
-    use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, QueryOrder, QuerySelect};
+    let filter = match build_history_filter(&params) {
+        Ok(filter) => filter,
+        Err(e) => return format::json(serde_json::json!({ "error": e })),
+    };
+    let cursor = match params.cursor.as_deref() {
+        Some(value) => match history::Cursor::decode(value) {
+            Some(cursor) => Some(cursor),
+            None => {
+                return format::json(serde_json::json!({
+                    "error": "Invalid cursor"
+                }));
+            }
+        },
+        None => None,
+    };
 
     let limit = params.limit.unwrap_or(100).min(1000); // 最大1000条
     let offset = params.offset.unwrap_or(0);
 
-    let history_data = device_realtime_data::Entity::find()
-        .filter(device_realtime_data::Column::DeviceId.eq(device_id))
-        .order_by_desc(device_realtime_data::Column::ReceivedAt)
-        .limit(limit)
-        .offset(offset)
-        .all(&ctx.db)
-        .await?;
+    let page =
+        history::query_history(&ctx.db, device_id, &filter, cursor.as_ref(), limit, offset).await?;
 
-    let response_data: Vec<_> = history_data
+    let response_data: Vec<_> = page
+        .rows
         .into_iter()
         .map(|data| {
             serde_json::json!({
@@ -283,18 +502,292 @@ pub async fn get_device_history(
         "device_id": device_id,
         "data": response_data,
         "limit": limit,
-        "offset": offset,
-        "total": response_data.len()
+        "offset": if cursor.is_some() { 0 } else { offset },
+        "next_cursor": page.next_cursor.map(|cursor| cursor.encode()),
+        "total": page.total,
+        "total_exact": page.total_exact
     });
 
     format::json(response)
 }
 
+/// 获取设备遥测聚合曲线
+///
+/// `from`/`to` 为 RFC3339 时间，默认最近一小时；`bucket` 为时间桶长度（如 `10s`、`5m`），
+/// 缺省或为 `auto` 时自动选择；`fields` 为逗号分隔的字段名。
+pub async fn get_device_series(
+    Authenticated(principal): Authenticated,
+    Path(device_uuid): Path<String>,
+    Query(params): Query<SeriesQuery>,
+    State(ctx): State<AppContext>,
+) -> Result<Response> {
+    // 解析UUID字符串
+    let uuid = match Uuid::parse_str(&device_uuid) {
+        Ok(uuid) => uuid,
+        Err(_) => {
+            return format::json(serde_json::json!({
+                "error": "Invalid UUID format",
+                "device_uuid": device_uuid
+            }));
+        }
+    };
+
+    // 通过UUID查找设备ID
+    let device_id = match device::Entity::find()
+        .filter(device::Column::Uuid.eq(uuid))
+        .one(&ctx.db)
+        .await
+    {
+        Ok(Some(device)) if principal.can_access(&device) => device.id,
+        Ok(Some(_)) => return unauthorized("无权访问该设备"),
+        Ok(None) => {
+            return format::json(serde_json::json!({
+                "error": "Device not found",
+                "device_uuid": device_uuid
+            }));
+        }
+        Err(e) => {
+            return format::json(serde_json::json!({
+                "error": format!("Database error: {}", e),
+                "device_uuid": device_uuid
+            }));
+        }
+    };
+
+    let parse_time = |value: &Option<String>| match value {
+        Some(value) => chrono::DateTime::parse_from_rfc3339(value)
+            .map(Some)
+            .map_err(|_| format!("Invalid RFC3339 time: {}", value)),
+        None => Ok(None),
+    };
+    let (from, to) = match (parse_time(&params.from), parse_time(&params.to)) {
+        (Ok(from), Ok(to)) => {
+            let to = to.unwrap_or_else(|| {
+                chrono::Utc::now().with_timezone(&chrono::FixedOffset::east_opt(8 * 3600).unwrap())
+            });
+            (from.unwrap_or(to - chrono::Duration::hours(1)), to)
+        }
+        (Err(e), _) | (_, Err(e)) => {
+            return format::json(serde_json::json!({ "error": e }));
+        }
+    };
+
+    let bucket = match params.bucket.as_deref().map(str::trim) {
+        None | Some("") | Some("auto") => None,
+        Some(value) => match rollup::parse_bucket(value) {
+            Some(seconds) => Some(seconds),
+            None => {
+                return format::json(serde_json::json!({
+                    "error": format!("Invalid bucket: {}", value)
+                }));
+            }
+        },
+    };
+    let (resolution, bucket_seconds) = match rollup::choose_bucket(from, to, bucket) {
+        Ok(chosen) => chosen,
+        Err(e) => return format::json(serde_json::json!({ "error": e })),
+    };
+
+    let fields: Option<std::collections::HashSet<String>> = params.fields.as_ref().map(|fields| {
+        fields
+            .split(',')
+            .map(str::trim)
+            .filter(|field| !field.is_empty())
+            .map(str::to_string)
+            .collect()
+    });
+
+    let series = rollup::query_series(
+        &ctx.db,
+        device_id,
+        from,
+        to,
+        resolution,
+        bucket_seconds,
+        fields.as_ref(),
+    )
+    .await?;
+
+    format::json(series)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SeriesQuery {
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub bucket: Option<String>, // "auto"、"10s"、"5m"、"1h"...
+    pub fields: Option<String>, // 逗号分隔
+}
+
+/// 导出设备遥测数据
+///
+/// `format` 为 csv、ndjson、gpx 或 kml；时间范围默认最近 24 小时，过滤参数与历史查询相同（不分页）。
+/// CSV 可通过 `encoding`（如 gbk）指定编码，通过 `fields` 指定列。
+pub async fn export_device_data(
+    Authenticated(principal): Authenticated,
+    Path(device_uuid): Path<String>,
+    Query(params): Query<ExportQuery>,
+    Query(history_params): Query<HistoryQuery>,
+    State(ctx): State<AppContext>,
+) -> Result<Response> {
+    // 解析UUID字符串
+    let uuid = match Uuid::parse_str(&device_uuid) {
+        Ok(uuid) => uuid,
+        Err(_) => {
+            return format::json(serde_json::json!({
+                "error": "Invalid UUID format",
+                "device_uuid": device_uuid
+            }));
+        }
+    };
+
+    // 通过UUID查找设备
+    let device = match device::Entity::find()
+        .filter(device::Column::Uuid.eq(uuid))
+        .one(&ctx.db)
+        .await
+    {
+        Ok(Some(device)) if principal.can_access(&device) => device,
+        Ok(Some(_)) => return unauthorized("无权访问该设备"),
+        Ok(None) => {
+            return format::json(serde_json::json!({
+                "error": "Device not found",
+                "device_uuid": device_uuid
+            }));
+        }
+        Err(e) => {
+            return format::json(serde_json::json!({
+                "error": format!("Database error: {}", e),
+                "device_uuid": device_uuid
+            }));
+        }
+    };
+
+    let Some(export_format) =
+        export::ExportFormat::parse(params.format.as_deref().unwrap_or("csv"))
+    else {
+        return format::json(serde_json::json!({
+            "error": "Unsupported format, expected csv, ndjson, gpx or kml"
+        }));
+    };
+    let encoding = match params.encoding.as_deref() {
+        Some(label) => match encoding_rs::Encoding::for_label(label.trim().as_bytes()) {
+            // UTF-16 等只能解码的编码会映射为 UTF-8
+            Some(encoding) => encoding.output_encoding(),
+            None => {
+                return format::json(serde_json::json!({
+                    "error": format!("Unknown encoding: {}", label)
+                }));
+            }
+        },
+        None => encoding_rs::UTF_8,
+    };
+
+    let mut filter = match build_history_filter(&history_params) {
+        Ok(filter) => filter,
+        Err(e) => return format::json(serde_json::json!({ "error": e })),
+    };
+    let to = filter.to.unwrap_or_else(|| {
+        chrono::Utc::now().with_timezone(&chrono::FixedOffset::east_opt(8 * 3600).unwrap())
+    });
+    let from = filter.from.unwrap_or(to - chrono::Duration::hours(24));
+    filter.from = Some(from);
+    filter.to = Some(to);
+
+    let fields = params
+        .fields
+        .as_deref()
+        .unwrap_or("")
+        .split(',')
+        .map(str::trim)
+        .filter(|field| !field.is_empty())
+        .map(str::to_string)
+        .collect();
+    let options = export::ExportOptions {
+        format: export_format,
+        encoding,
+        fields,
+        name: device.name.clone(),
+    };
+
+    let filename = format!(
+        "device-{}-{}.{}",
+        device.id,
+        from.format("%Y%m%d%H%M%S"),
+        export_format.extension()
+    );
+    let receiver = export::spawn_export(ctx.db.clone(), device.id, filter, options.clone());
+    let stream = futures_util::stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|chunk| (chunk, receiver))
+    });
+
+    Response::builder()
+        .header(
+            axum::http::header::CONTENT_TYPE,
+            export_format.content_type(options.encoding),
+        )
+        .header(
+            axum::http::header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", filename),
+        )
+        .body(axum::body::Body::from_stream(stream))
+        .map_err(|e| Error::string(&format!("Failed to build export response: {}", e)))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    pub format: Option<String>,   // csv、ndjson、gpx、kml
+    pub encoding: Option<String>, // 仅 CSV，如 "gbk"，默认 UTF-8
+    pub fields: Option<String>,   // 仅 CSV，逗号分隔的列
+}
+
 #[derive(Debug, Deserialize)]
 pub struct HistoryQuery {
     pub limit: Option<u64>,
     pub offset: Option<u64>,
-    pub data_type: Option<String>, // "mqtt" 或 "websocket"
+    pub data_type: Option<String>, // 逗号分隔，如 "mqtt,mavlink"
+    pub from: Option<String>,      // RFC3339
+    pub to: Option<String>,        // RFC3339
+    pub has: Option<String>,       // 逗号分隔的必须存在的字段，支持 a.b 路径
+    #[serde(rename = "where")]
+    pub predicates: Option<String>, // 逗号分隔的比较条件，如 "battery<20,altitude>=100"
+    pub cursor: Option<String>,    // 上一页返回的 next_cursor
+}
+
+/// 将查询参数转换为历史数据过滤条件
+fn build_history_filter(
+    params: &HistoryQuery,
+) -> std::result::Result<history::HistoryFilter, String> {
+    let split = |value: &Option<String>| -> Vec<String> {
+        value
+            .as_deref()
+            .unwrap_or("")
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(str::to_string)
+            .collect()
+    };
+    let parse_time = |value: &Option<String>| match value {
+        Some(value) => chrono::DateTime::parse_from_rfc3339(value)
+            .map(Some)
+            .map_err(|_| format!("Invalid RFC3339 time: {}", value)),
+        None => Ok(None),
+    };
+
+    Ok(history::HistoryFilter {
+        from: parse_time(&params.from)?,
+        to: parse_time(&params.to)?,
+        data_types: split(&params.data_type),
+        has_fields: split(&params.has)
+            .iter()
+            .map(|path| history::parse_path(path))
+            .collect::<std::result::Result<_, _>>()?,
+        predicates: split(&params.predicates)
+            .iter()
+            .map(|predicate| history::Predicate::parse(predicate))
+            .collect::<std::result::Result<_, _>>()?,
+    })
 }
 
 #[derive(Debug, Deserialize)]
@@ -323,6 +816,7 @@ pub struct DeviceConnectResponse {
 
 /// 连接设备MQTT
 pub async fn connect_device_mqtt(
+    Authenticated(principal): Authenticated,
     Path(device_uuid): Path<String>,
     State(ctx): State<AppContext>,
     Json(request): Json<MqttConnectRequest>,
@@ -344,7 +838,8 @@ pub async fn connect_device_mqtt(
         .one(&ctx.db)
         .await
     {
-        Ok(Some(device)) => device.id,
+        Ok(Some(device)) if principal.can_access(&device) => device.id,
+        Ok(Some(_)) => return unauthorized("无权访问该设备"),
         Ok(None) => {
             return format::json(MqttConnectResponse {
                 success: false,
@@ -397,6 +892,7 @@ pub async fn connect_device_mqtt(
 
 /// 连接设备WebSocket
 pub async fn connect_device_websocket(
+    Authenticated(principal): Authenticated,
     Path(device_uuid): Path<String>,
     State(ctx): State<AppContext>,
     Json(request): Json<DeviceConnectRequest>,
@@ -419,7 +915,8 @@ pub async fn connect_device_websocket(
         .one(&ctx.db)
         .await
     {
-        Ok(Some(device)) => device,
+        Ok(Some(device)) if principal.can_access(&device) => device,
+        Ok(Some(_)) => return unauthorized("无权访问该设备"),
         Ok(None) => {
             return format::json(DeviceConnectResponse {
                 success: false,
@@ -488,6 +985,7 @@ pub async fn connect_device_websocket(
 
 /// 断开设备WebSocket连接
 pub async fn disconnect_device_websocket(
+    Authenticated(principal): Authenticated,
     Path(device_uuid): Path<String>,
     State(ctx): State<AppContext>,
 ) -> Result<Response> {
@@ -509,7 +1007,8 @@ pub async fn disconnect_device_websocket(
         .one(&ctx.db)
         .await
     {
-        Ok(Some(device)) => device,
+        Ok(Some(device)) if principal.can_access(&device) => device,
+        Ok(Some(_)) => return unauthorized("无权访问该设备"),
         Ok(None) => {
             return format::json(DeviceConnectResponse {
                 success: false,
@@ -558,6 +1057,7 @@ pub async fn disconnect_device_websocket(
 
 /// 断开设备MQTT
 pub async fn disconnect_device_mqtt(
+    Authenticated(principal): Authenticated,
     Path(device_uuid): Path<String>,
     State(ctx): State<AppContext>,
 ) -> Result<Response> {
@@ -567,7 +1067,8 @@ pub async fn disconnect_device_mqtt(
         .one(&ctx.db)
         .await
     {
-        Ok(Some(device)) => device.id,
+        Ok(Some(device)) if principal.can_access(&device) => device.id,
+        Ok(Some(_)) => return unauthorized("无权访问该设备"),
         Ok(None) => {
             return format::json(MqttConnectResponse {
                 success: false,
@@ -620,6 +1121,7 @@ pub struct UpdateHistoryRequest {
 
 /// 批量删除历史记录
 pub async fn batch_delete_history(
+    Authenticated(principal): Authenticated,
     Path(device_uuid): Path<String>,
     State(ctx): State<AppContext>,
     Json(payload): Json<BatchDeleteRequest>,
@@ -642,7 +1144,8 @@ pub async fn batch_delete_history(
         .one(&ctx.db)
         .await
     {
-        Ok(Some(device)) => device.id,
+        Ok(Some(device)) if principal.can_access(&device) => device.id,
+        Ok(Some(_)) => return unauthorized("无权访问该设备"),
         Ok(None) => {
             return format::json(serde_json::json!({
                 "error": "Device not found"
@@ -675,6 +1178,7 @@ pub async fn batch_delete_history(
 
 /// 更新历史记录
 pub async fn update_history_record(
+    Authenticated(principal): Authenticated,
     Path((device_uuid, record_id)): Path<(String, i64)>,
     State(ctx): State<AppContext>,
     Json(payload): Json<UpdateHistoryRequest>,
@@ -697,7 +1201,8 @@ pub async fn update_history_record(
         .one(&ctx.db)
         .await
     {
-        Ok(Some(device)) => device.id,
+        Ok(Some(device)) if principal.can_access(&device) => device.id,
+        Ok(Some(_)) => return unauthorized("无权访问该设备"),
         Ok(None) => {
             return format::json(serde_json::json!({
                 "error": "Device not found"
@@ -748,10 +1253,18 @@ pub fn routes() -> Routes {
     Routes::new()
         .prefix("realtime")
         .add("/ws", get(websocket_handler))
+        .add("/sse", get(sse_handler))
         .add("/devices", get(get_all_device_status))
+        .add("/metrics/ingest", get(get_ingest_metrics))
+        .add("/metrics/broadcast", get(get_broadcast_metrics))
         .add("/devices/{device_id}/status", get(get_device_status))
         .add("/devices/{device_id}/command", post(send_device_command))
         .add("/devices/{device_id}/history", get(get_device_history))
+        .add("/devices/{device_id}/export", get(export_device_data))
+        .add(
+            "/devices/{device_id}/history/series",
+            get(get_device_series),
+        )
         .add(
             "/devices/{device_id}/history/batch",
             delete(batch_delete_history),
@@ -1,4 +1,15 @@
-use crate::models::{device, task, user};
+use crate::models::{device, fleet_mission, task, task_event, task_template, user};
+use crate::services::app_state;
+use crate::services::fleet_mission::is_terminal;
+use crate::services::geofence::Geofence;
+use crate::services::mission::{MissionEstimate, Waypoint};
+use crate::services::mission_file::{self, KmlImportOptions, MissionFormat};
+use crate::services::simulation::Simulation;
+use crate::services::task_executor::{MissionPlan, StepAction};
+use crate::services::task_state::{self, Actor, TransitionError};
+use axum::body::{Body, Bytes};
+use axum::extract::Query;
+use axum::http::header;
 use axum::Json;
 use loco_rs::controller::middleware::auth::JWT;
 use loco_rs::prelude::*;
@@ -23,6 +34,104 @@ pub struct UpdateTaskParams {
     pub parameters: Option<serde_json::Value>,
     pub start_time: Option<DateTimeWithTimeZone>,
     pub end_time: Option<DateTimeWithTimeZone>,
+    pub reason: Option<String>, // 状态变更原因
+}
+
+/// 暂停、恢复、取消、中止的可选请求体
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct TransitionParams {
+    pub reason: Option<String>,
+}
+
+/// 预览航线的请求体
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PlanTaskParams {
+    pub device_id: i32,
+    pub parameters: serde_json::Value,
+}
+
+/// 导入航线文件的查询参数，请求体为文件内容
+#[derive(Debug, Deserialize)]
+pub struct ImportTaskQuery {
+    pub device_id: i32,
+    pub format: Option<String>, // plan、kml、kmz，默认按文件内容识别
+    pub name: Option<String>,   // 默认使用文件中的名称
+    pub task_type: Option<String>,
+    pub altitude: Option<f64>, // 仅 KML/KMZ，统一的相对起飞点高度（米）
+    pub speed: Option<f64>,    // 仅 KML/KMZ，航点水平速度（米/秒）
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportTaskQuery {
+    pub format: Option<String>, // plan（默认）、kml、kmz
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Position {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+/// 模拟的起点和额外检查的电子围栏
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct SimulationOptions {
+    pub origin: Option<Position>, // 默认为设备最近上报的位置
+    #[serde(default)]
+    pub geofences: Vec<Geofence>,
+}
+
+/// 模拟未保存任务的请求体
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SimulateTaskParams {
+    pub device_id: i32,
+    pub parameters: serde_json::Value,
+    #[serde(flatten)]
+    pub options: SimulationOptions,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PlannedStepResponse {
+    pub source_step: usize, // 所属任务参数步骤的序号
+    pub step_type: String,
+    pub waypoint: Option<Waypoint>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MissionPlanResponse {
+    pub steps: Vec<PlannedStepResponse>,
+    pub estimate: MissionEstimate,
+}
+
+impl From<MissionPlan> for MissionPlanResponse {
+    fn from(plan: MissionPlan) -> Self {
+        Self {
+            steps: plan
+                .steps
+                .into_iter()
+                .map(|step| PlannedStepResponse {
+                    source_step: step.source_step,
+                    step_type: step.step_type,
+                    waypoint: match step.action {
+                        StepAction::Waypoint(waypoint) => Some(waypoint),
+                        _ => None,
+                    },
+                })
+                .collect(),
+            estimate: plan.estimate,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct TaskEventResponse {
+    pub id: i32,
+    pub from_status: Option<String>,
+    pub to_status: String,
+    pub actor: String,
+    pub user_id: Option<i32>,
+    pub user_name: Option<String>,
+    pub reason: Option<String>,
+    pub created_at: DateTimeWithTimeZone,
 }
 
 #[derive(Debug, Serialize)]
@@ -36,6 +145,11 @@ pub struct TaskResponse {
     pub device_id: i32,
     pub user_id: i32,
     pub parameters: Option<serde_json::Value>,
+    pub progress: Option<serde_json::Value>,
+    pub template_id: Option<i32>,
+    pub template_version: Option<i32>,
+    pub fleet_mission_id: Option<i32>,
+    pub reassigned_from: Option<i32>,
     pub start_time: Option<DateTimeWithTimeZone>,
     pub end_time: Option<DateTimeWithTimeZone>,
     pub created_at: DateTimeWithTimeZone,
@@ -55,6 +169,11 @@ impl From<(task::Model, Option<device::Model>)> for TaskResponse {
             device_id: task.device_id,
             user_id: task.user_id,
             parameters: task.parameters,
+            progress: task.progress,
+            template_id: task.template_id,
+            template_version: task.template_version,
+            fleet_mission_id: task.fleet_mission_id,
+            reassigned_from: task.reassigned_from,
             start_time: task.start_time,
             end_time: task.end_time,
             created_at: task.created_at,
@@ -115,6 +234,12 @@ pub async fn create(
         return bad_request("设备未找到或无权限");
     };
 
+    if let Some(parameters) = &params.parameters {
+        if let Some(message) = validate_plan(device.id, parameters).await {
+            return bad_request(message);
+        }
+    }
+
     let task_model = task::ActiveModel {
         uuid: Set(Uuid::new_v4()),
         name: Set(params.name),
@@ -130,6 +255,7 @@ pub async fn create(
     let task = task::Entity::insert(task_model)
         .exec_with_returning(&ctx.db)
         .await?;
+    task_state::record_created(&ctx.db, &task, Actor::User(user.id), None).await?;
 
     let response = TaskResponse::from((task, Some(device)));
     format::json(response)
@@ -193,30 +319,82 @@ pub async fn update(
         return not_found();
     };
 
-    let mut task_active: task::ActiveModel = task.into();
+    let status = match params.status.as_deref().map(str::parse::<task::TaskStatus>) {
+        Some(Ok(status)) => Some(status),
+        Some(Err(_)) => return bad_request("无效的任务状态"),
+        None => None,
+    };
+
+    // 执行中的任务由执行服务维护状态
+    if status.is_some() && is_running(task.id).await {
+        return bad_request("任务执行中，请通过暂停、恢复、取消或中止接口变更状态");
+    }
+    // 执行、暂停、完成、失败和中止只能由执行服务变更，这里只允许取消和重新排队
+    if let Some(status) = status.filter(|status| status.to_string() != task.status) {
+        if !matches!(
+            status,
+            task::TaskStatus::Cancelled | task::TaskStatus::Pending
+        ) {
+            return bad_request("只能将任务状态变更为 cancelled 或 pending，执行请使用开始接口");
+        }
+    }
+    let is_active = [task::TaskStatus::Running, task::TaskStatus::Paused]
+        .iter()
+        .any(|active| active.to_string() == task.status);
+    if params.parameters.is_some() && is_active {
+        return bad_request("任务执行中或已暂停，不能修改任务步骤");
+    }
+    if (status.is_some() || params.parameters.is_some()) && in_fleet(&ctx.db, &task).await? {
+        return bad_request("编队子任务由编队任务统一管理，不能单独修改状态或步骤");
+    }
+
+    let mut changes = task::ActiveModel {
+        ..Default::default()
+    };
 
     if let Some(name) = params.name {
-        task_active.name = Set(name);
+        changes.name = Set(name);
     }
     if let Some(description) = params.description {
-        task_active.description = Set(Some(description));
-    }
-    if let Some(status) = params.status {
-        task_active.status = Set(status);
+        changes.description = Set(Some(description));
     }
     if let Some(parameters) = params.parameters {
-        task_active.parameters = Set(Some(parameters));
+        if let Some(message) = validate_plan(task.device_id, &parameters).await {
+            return bad_request(message);
+        }
+        changes.parameters = Set(Some(parameters));
+        // 步骤变化后之前的模拟结果不再有效
+        changes.simulation = Set(None);
     }
     if let Some(start_time) = params.start_time {
-        task_active.start_time = Set(Some(start_time));
+        changes.start_time = Set(Some(start_time));
     }
     if let Some(end_time) = params.end_time {
-        task_active.end_time = Set(Some(end_time));
+        changes.end_time = Set(Some(end_time));
     }
 
-    task_active.updated_at = Set(chrono::Utc::now().into());
-
-    let updated_task = task_active.update(&ctx.db).await?;
+    let updated_task = match status {
+        Some(status) if status.to_string() != task.status => {
+            match task_state::transition(
+                &ctx.db,
+                task.id,
+                status,
+                Actor::User(user.id),
+                params.reason,
+                changes,
+            )
+            .await
+            {
+                Ok(task) => task,
+                Err(e) => return Err(transition_error(e)),
+            }
+        }
+        _ => {
+            changes.id = Set(task.id);
+            changes.updated_at = Set(chrono::Utc::now().into());
+            changes.update(&ctx.db).await?
+        }
+    };
 
     // 获取关联的设备信息
     let device = device::Entity::find()
@@ -254,6 +432,13 @@ pub async fn delete_task(
         return not_found();
     };
 
+    if is_running(task.id).await {
+        return bad_request("任务执行中，请先取消");
+    }
+    if in_fleet(&ctx.db, &task).await? {
+        return bad_request("编队子任务由编队任务统一管理，编队任务结束前不能删除");
+    }
+
     task::Entity::delete_by_id(task.id).exec(&ctx.db).await?;
 
     format::json(serde_json::json!({
@@ -261,18 +446,195 @@ pub async fn delete_task(
     }))
 }
 
-/// 创建一键任务
-pub async fn create_one_click_task(
+/// 任务是否正在执行
+async fn is_running(task_id: i32) -> bool {
+    match app_state::get_service_manager() {
+        Some(service_manager) => service_manager.task_executor.is_running(task_id).await,
+        None => false,
+    }
+}
+
+/// 任务是否属于尚未结束的编队任务
+async fn in_fleet(db: &DatabaseConnection, task: &task::Model) -> Result<bool> {
+    let Some(mission_id) = task.fleet_mission_id else {
+        return Ok(false);
+    };
+    let mission = fleet_mission::Entity::find_by_id(mission_id)
+        .one(db)
+        .await?;
+    Ok(mission.is_some_and(|mission| !is_terminal(&mission.status)))
+}
+
+/// 状态变更失败时的错误
+fn transition_error(e: TransitionError) -> Error {
+    match e {
+        TransitionError::NotFound => Error::NotFound,
+        TransitionError::Illegal { from, to } => {
+            Error::BadRequest(format!("任务状态不能从 {} 变更为 {}", from, to.to_string()))
+        }
+        TransitionError::Conflict => Error::BadRequest("任务状态已被修改，请重试".to_string()),
+        TransitionError::Db(e) => e.into(),
+    }
+}
+
+/// 解析可选的状态变更原因
+pub(crate) fn parse_reason(body: &Bytes) -> serde_json::Result<Option<String>> {
+    if body.iter().all(u8::is_ascii_whitespace) {
+        return Ok(None);
+    }
+    serde_json::from_slice::<TransitionParams>(body).map(|params| params.reason)
+}
+
+/// 解析可选的模拟参数
+fn parse_simulation_options(body: &Bytes) -> serde_json::Result<SimulationOptions> {
+    if body.iter().all(u8::is_ascii_whitespace) {
+        return Ok(SimulationOptions::default());
+    }
+    serde_json::from_slice(body)
+}
+
+/// 按设备机型模拟任务，参数无效时返回提示信息
+async fn run_simulation(
+    device_id: i32,
+    parameters: Option<&serde_json::Value>,
+    options: SimulationOptions,
+) -> std::result::Result<Simulation, String> {
+    if let Some(origin) = &options.origin {
+        if !(-90.0..=90.0).contains(&origin.latitude)
+            || !(-180.0..=180.0).contains(&origin.longitude)
+        {
+            return Err("起点坐标无效".to_string());
+        }
+    }
+    for geofence in &options.geofences {
+        geofence
+            .validate()
+            .map_err(|e| format!("电子围栏无效: {}", e))?;
+    }
+
+    let service_manager = app_state::get_service_manager().ok_or("服务管理器未初始化")?;
+    service_manager
+        .task_executor
+        .simulate(
+            device_id,
+            parameters,
+            options
+                .origin
+                .map(|origin| (origin.latitude, origin.longitude)),
+            &options.geofences,
+        )
+        .await
+        .map_err(|e| format!("任务参数无效: {}", e))
+}
+
+/// 校验任务步骤，按设备机型的续航估算航线并检查设备的电子围栏，不通过时返回提示信息
+pub(crate) async fn validate_plan(
+    device_id: i32,
+    parameters: &serde_json::Value,
+) -> Option<String> {
+    let service_manager = app_state::get_service_manager()?;
+    let executor = &service_manager.task_executor;
+    let plan = match executor.plan(device_id, Some(parameters)).await {
+        Ok(plan) => plan,
+        Err(e) => return Some(format!("任务参数无效: {}", e)),
+    };
+    if let Err(e) = plan.estimate.check() {
+        return Some(format!("航线超出电池续航: {}", e));
+    }
+    executor
+        .check_geofences(device_id, &plan)
+        .await
+        .err()
+        .map(|e| format!("航线违反电子围栏: {}", e))
+}
+
+/// 预览航线：展开航点并估算距离、飞行时间和续航
+pub async fn preview_plan(
     auth: JWT,
     State(ctx): State<AppContext>,
-    Json(device_id): Json<serde_json::Value>,
+    Json(params): Json<PlanTaskParams>,
 ) -> Result<Response> {
-    let device_id: i32 = device_id
-        .get("device_id")
-        .and_then(|v| v.as_i64())
-        .map(|v| v as i32)
-        .ok_or_else(|| Error::BadRequest("device_id is required".to_string()))?;
+    // 查找用户 (JWT中的pid实际上是email)
+    let user = user::Entity::find()
+        .filter(user::Column::Email.eq(&auth.claims.pid))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(user) = user else {
+        return unauthorized("用户未找到");
+    };
+
+    // 验证设备是否属于该用户
+    let device = device::Entity::find()
+        .filter(device::Column::Id.eq(params.device_id))
+        .filter(device::Column::UserId.eq(user.id))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(device) = device else {
+        return bad_request("设备未找到或无权限");
+    };
+
+    let Some(service_manager) = app_state::get_service_manager() else {
+        return bad_request("服务管理器未初始化");
+    };
+
+    match service_manager
+        .task_executor
+        .plan(device.id, Some(&params.parameters))
+        .await
+    {
+        Ok(plan) => format::json(MissionPlanResponse::from(plan)),
+        Err(e) => bad_request(format!("任务参数无效: {}", e)),
+    }
+}
 
+/// 获取任务的航线
+pub async fn plan(
+    auth: JWT,
+    Path(task_uuid): Path<Uuid>,
+    State(ctx): State<AppContext>,
+) -> Result<Response> {
+    // 查找用户 (JWT中的pid实际上是email)
+    let user = user::Entity::find()
+        .filter(user::Column::Email.eq(&auth.claims.pid))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(user) = user else {
+        return unauthorized("用户未找到");
+    };
+
+    let task = task::Entity::find()
+        .filter(task::Column::Uuid.eq(task_uuid))
+        .filter(task::Column::UserId.eq(user.id))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(task) = task else {
+        return not_found();
+    };
+
+    let Some(service_manager) = app_state::get_service_manager() else {
+        return bad_request("服务管理器未初始化");
+    };
+
+    match service_manager
+        .task_executor
+        .plan(task.device_id, task.parameters.as_ref())
+        .await
+    {
+        Ok(plan) => format::json(MissionPlanResponse::from(plan)),
+        Err(e) => bad_request(format!("任务参数无效: {}", e)),
+    }
+}
+
+/// 模拟任务：按设备机型推演轨迹、每步预计完成时间、电量、最大高度和违规，不保存
+pub async fn preview_simulation(
+    auth: JWT,
+    State(ctx): State<AppContext>,
+    Json(params): Json<SimulateTaskParams>,
+) -> Result<Response> {
     // 查找用户 (JWT中的pid实际上是email)
     let user = user::Entity::find()
         .filter(user::Column::Email.eq(&auth.claims.pid))
@@ -285,7 +647,7 @@ pub async fn create_one_click_task(
 
     // 验证设备是否属于该用户
     let device = device::Entity::find()
-        .filter(device::Column::Id.eq(device_id))
+        .filter(device::Column::Id.eq(params.device_id))
         .filter(device::Column::UserId.eq(user.id))
         .one(&ctx.db)
         .await?;
@@ -294,38 +656,560 @@ pub async fn create_one_click_task(
         return bad_request("设备未找到或无权限");
     };
 
-    let one_click_params = task::TaskParameters::one_click_task();
+    match run_simulation(device.id, Some(&params.parameters), params.options).await {
+        Ok(simulation) => format::json(simulation),
+        Err(message) => bad_request(message),
+    }
+}
+
+/// 模拟任务并保存结果
+pub async fn simulate(
+    auth: JWT,
+    Path(task_uuid): Path<Uuid>,
+    State(ctx): State<AppContext>,
+    body: Bytes,
+) -> Result<Response> {
+    // 查找用户 (JWT中的pid实际上是email)
+    let user = user::Entity::find()
+        .filter(user::Column::Email.eq(&auth.claims.pid))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(user) = user else {
+        return unauthorized("用户未找到");
+    };
+
+    let task = task::Entity::find()
+        .filter(task::Column::Uuid.eq(task_uuid))
+        .filter(task::Column::UserId.eq(user.id))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(task) = task else {
+        return not_found();
+    };
+
+    let Ok(options) = parse_simulation_options(&body) else {
+        return bad_request("无效的请求体");
+    };
+    let simulation = match run_simulation(task.device_id, task.parameters.as_ref(), options).await {
+        Ok(simulation) => simulation,
+        Err(message) => return bad_request(message),
+    };
+
+    let simulation = serde_json::to_value(&simulation)?;
+    let changes = task::ActiveModel {
+        id: Set(task.id),
+        simulation: Set(Some(simulation.clone())),
+        ..Default::default()
+    };
+    changes.update(&ctx.db).await?;
+
+    format::json(simulation)
+}
+
+/// 获取任务最近一次的模拟结果
+pub async fn simulation(
+    auth: JWT,
+    Path(task_uuid): Path<Uuid>,
+    State(ctx): State<AppContext>,
+) -> Result<Response> {
+    // 查找用户 (JWT中的pid实际上是email)
+    let user = user::Entity::find()
+        .filter(user::Column::Email.eq(&auth.claims.pid))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(user) = user else {
+        return unauthorized("用户未找到");
+    };
+
+    let task = task::Entity::find()
+        .filter(task::Column::Uuid.eq(task_uuid))
+        .filter(task::Column::UserId.eq(user.id))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(simulation) = task.and_then(|task| task.simulation) else {
+        return not_found();
+    };
+
+    format::json(simulation)
+}
+
+/// 导入 QGroundControl `.plan` 或 KML/KMZ 航线文件，创建待执行任务
+pub async fn import(
+    auth: JWT,
+    State(ctx): State<AppContext>,
+    Query(params): Query<ImportTaskQuery>,
+    body: Bytes,
+) -> Result<Response> {
+    // 查找用户 (JWT中的pid实际上是email)
+    let user = user::Entity::find()
+        .filter(user::Column::Email.eq(&auth.claims.pid))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(user) = user else {
+        return unauthorized("用户未找到");
+    };
+
+    // 验证设备是否属于该用户
+    let device = device::Entity::find()
+        .filter(device::Column::Id.eq(params.device_id))
+        .filter(device::Column::UserId.eq(user.id))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(device) = device else {
+        return bad_request("设备未找到或无权限");
+    };
+
+    let mission_format = match params.format.as_deref() {
+        Some(format) => match MissionFormat::parse(format) {
+            Some(format) => format,
+            None => return bad_request("不支持的航线格式，可选 plan、kml、kmz"),
+        },
+        None => match MissionFormat::detect(&body) {
+            Some(format) => format,
+            None => return bad_request("无法识别航线文件格式，请指定 format"),
+        },
+    };
+    let options = KmlImportOptions {
+        altitude: params.altitude,
+        speed: params.speed,
+    };
+    let imported = match mission_file::import(mission_format, &body, &options) {
+        Ok(imported) => imported,
+        Err(e) => return bad_request(format!("航线文件无效: {}", e)),
+    };
+
+    let parameters = serde_json::to_value(&imported.parameters)?;
+    if let Some(message) = validate_plan(device.id, &parameters).await {
+        return bad_request(message);
+    }
 
     let task_model = task::ActiveModel {
         uuid: Set(Uuid::new_v4()),
-        name: Set("一键任务".to_string()),
-        description: Set(Some(
-            "起飞-悬停-朝北-静止10秒-飞到1.5米-悬停-朝北-静止10秒-飞到2米-悬停-朝北-静止10秒-降落"
-                .to_string(),
-        )),
-        task_type: Set("auto".to_string()),
+        name: Set(params
+            .name
+            .or(imported.name)
+            .unwrap_or_else(|| "导入任务".to_string())),
+        description: Set(Some(format!("从 {} 文件导入", mission_format.extension()))),
+        task_type: Set(params.task_type.unwrap_or_else(|| "auto".to_string())),
         status: Set("pending".to_string()),
-        device_id: Set(device_id),
+        device_id: Set(device.id),
         user_id: Set(user.id),
-        parameters: Set(Some(serde_json::to_value(one_click_params).unwrap())),
+        parameters: Set(Some(parameters)),
         ..Default::default()
     };
 
     let task = task::Entity::insert(task_model)
         .exec_with_returning(&ctx.db)
         .await?;
+    task_state::record_created(
+        &ctx.db,
+        &task,
+        Actor::User(user.id),
+        Some(format!("Imported from {} file", mission_format.extension())),
+    )
+    .await?;
 
     let response = TaskResponse::from((task, Some(device)));
     format::json(response)
 }
 
+/// 导出任务航线为 QGroundControl `.plan` 或 KML/KMZ 文件
+pub async fn export(
+    auth: JWT,
+    Path(task_uuid): Path<Uuid>,
+    State(ctx): State<AppContext>,
+    Query(params): Query<ExportTaskQuery>,
+) -> Result<Response> {
+    // 查找用户 (JWT中的pid实际上是email)
+    let user = user::Entity::find()
+        .filter(user::Column::Email.eq(&auth.claims.pid))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(user) = user else {
+        return unauthorized("用户未找到");
+    };
+
+    let task = task::Entity::find()
+        .filter(task::Column::Uuid.eq(task_uuid))
+        .filter(task::Column::UserId.eq(user.id))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(task) = task else {
+        return not_found();
+    };
+
+    let Some(mission_format) = MissionFormat::parse(params.format.as_deref().unwrap_or("plan"))
+    else {
+        return bad_request("不支持的航线格式，可选 plan、kml、kmz");
+    };
+
+    let Some(service_manager) = app_state::get_service_manager() else {
+        return bad_request("服务管理器未初始化");
+    };
+
+    let plan = match service_manager
+        .task_executor
+        .plan(task.device_id, task.parameters.as_ref())
+        .await
+    {
+        Ok(plan) => plan,
+        Err(e) => return bad_request(format!("任务参数无效: {}", e)),
+    };
+    let content = match mission_file::export(mission_format, &task.name, &plan.steps, plan.origin) {
+        Ok(content) => content,
+        Err(e) => return bad_request(format!("无法导出航线: {}", e)),
+    };
+
+    Response::builder()
+        .header(header::CONTENT_TYPE, mission_format.content_type())
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!(
+                "attachment; filename=\"task-{}.{}\"",
+                task.id,
+                mission_format.extension()
+            ),
+        )
+        .body(Body::from(content))
+        .map_err(|e| Error::string(&format!("Failed to build export response: {}", e)))
+}
+
+/// 开始执行任务
+pub async fn start(
+    auth: JWT,
+    Path(task_uuid): Path<Uuid>,
+    State(ctx): State<AppContext>,
+) -> Result<Response> {
+    // 查找用户 (JWT中的pid实际上是email)
+    let user = user::Entity::find()
+        .filter(user::Column::Email.eq(&auth.claims.pid))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(user) = user else {
+        return unauthorized("用户未找到");
+    };
+
+    let task = task::Entity::find()
+        .filter(task::Column::Uuid.eq(task_uuid))
+        .filter(task::Column::UserId.eq(user.id))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(task) = task else {
+        return not_found();
+    };
+
+    if task.status != task::TaskStatus::Pending.to_string() {
+        return bad_request("只能执行待执行的任务");
+    }
+    if task.fleet_mission_id.is_some() {
+        return bad_request("编队子任务需要通过编队任务统一开始");
+    }
+
+    let Some(service_manager) = app_state::get_service_manager() else {
+        return bad_request("服务管理器未初始化");
+    };
+
+    if let Err(e) = service_manager
+        .task_executor
+        .start(&task, Actor::User(user.id))
+        .await
+    {
+        return bad_request(e);
+    }
+
+    format::json(serde_json::json!({
+        "message": "任务已开始执行",
+        "uuid": task.uuid
+    }))
+}
+
+/// 取消任务：执行中的任务停止执行，待执行的任务直接取消
+pub async fn cancel(
+    auth: JWT,
+    Path(task_uuid): Path<Uuid>,
+    State(ctx): State<AppContext>,
+    body: Bytes,
+) -> Result<Response> {
+    stop(auth, task_uuid, ctx, body, task::TaskStatus::Cancelled).await
+}
+
+/// 中止执行中的任务，停止执行并下发降落命令
+pub async fn abort(
+    auth: JWT,
+    Path(task_uuid): Path<Uuid>,
+    State(ctx): State<AppContext>,
+    body: Bytes,
+) -> Result<Response> {
+    stop(auth, task_uuid, ctx, body, task::TaskStatus::Aborted).await
+}
+
+async fn stop(
+    auth: JWT,
+    task_uuid: Uuid,
+    ctx: AppContext,
+    body: Bytes,
+    status: task::TaskStatus,
+) -> Result<Response> {
+    let Ok(reason) = parse_reason(&body) else {
+        return bad_request("请求体格式错误");
+    };
+
+    // 查找用户 (JWT中的pid实际上是email)
+    let user = user::Entity::find()
+        .filter(user::Column::Email.eq(&auth.claims.pid))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(user) = user else {
+        return unauthorized("用户未找到");
+    };
+
+    let task = task::Entity::find()
+        .filter(task::Column::Uuid.eq(task_uuid))
+        .filter(task::Column::UserId.eq(user.id))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(task) = task else {
+        return not_found();
+    };
+
+    let stopped = match app_state::get_service_manager() {
+        Some(service_manager) => {
+            service_manager
+                .task_executor
+                .stop(task.id, status, Actor::User(user.id), reason.clone())
+                .await
+        }
+        None => false,
+    };
+
+    if !stopped {
+        if status == task::TaskStatus::Aborted {
+            return bad_request("任务未在执行");
+        }
+        if let Err(e) = task_state::transition(
+            &ctx.db,
+            task.id,
+            status,
+            Actor::User(user.id),
+            reason,
+            task::ActiveModel {
+                end_time: Set(Some(chrono::Utc::now().into())),
+                ..Default::default()
+            },
+        )
+        .await
+        {
+            return Err(transition_error(e));
+        }
+    }
+
+    format::json(serde_json::json!({
+        "message": if status == task::TaskStatus::Aborted { "任务已中止" } else { "任务已取消" },
+        "uuid": task.uuid
+    }))
+}
+
+/// 暂停执行中的任务
+pub async fn pause(
+    auth: JWT,
+    Path(task_uuid): Path<Uuid>,
+    State(ctx): State<AppContext>,
+    body: Bytes,
+) -> Result<Response> {
+    set_paused(auth, task_uuid, ctx, body, true).await
+}
+
+/// 恢复已暂停的任务
+pub async fn resume(
+    auth: JWT,
+    Path(task_uuid): Path<Uuid>,
+    State(ctx): State<AppContext>,
+    body: Bytes,
+) -> Result<Response> {
+    set_paused(auth, task_uuid, ctx, body, false).await
+}
+
+async fn set_paused(
+    auth: JWT,
+    task_uuid: Uuid,
+    ctx: AppContext,
+    body: Bytes,
+    paused: bool,
+) -> Result<Response> {
+    let Ok(reason) = parse_reason(&body) else {
+        return bad_request("请求体格式错误");
+    };
+
+    // 查找用户 (JWT中的pid实际上是email)
+    let user = user::Entity::find()
+        .filter(user::Column::Email.eq(&auth.claims.pid))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(user) = user else {
+        return unauthorized("用户未找到");
+    };
+
+    let task = task::Entity::find()
+        .filter(task::Column::Uuid.eq(task_uuid))
+        .filter(task::Column::UserId.eq(user.id))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(task) = task else {
+        return not_found();
+    };
+
+    let Some(service_manager) = app_state::get_service_manager() else {
+        return bad_request("服务管理器未初始化");
+    };
+
+    let executor = &service_manager.task_executor;
+    let result = if paused {
+        executor.pause(task.id, Actor::User(user.id), reason).await
+    } else {
+        executor.resume(task.id, Actor::User(user.id), reason).await
+    };
+    if let Err(e) = result {
+        return bad_request(e);
+    }
+
+    format::json(serde_json::json!({
+        "message": if paused { "任务已暂停" } else { "任务已恢复" },
+        "uuid": task.uuid
+    }))
+}
+
+/// 获取任务状态变更时间线
+pub async fn events(
+    auth: JWT,
+    Path(task_uuid): Path<Uuid>,
+    State(ctx): State<AppContext>,
+) -> Result<Response> {
+    // 查找用户 (JWT中的pid实际上是email)
+    let user = user::Entity::find()
+        .filter(user::Column::Email.eq(&auth.claims.pid))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(user) = user else {
+        return unauthorized("用户未找到");
+    };
+
+    let task = task::Entity::find()
+        .filter(task::Column::Uuid.eq(task_uuid))
+        .filter(task::Column::UserId.eq(user.id))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(task) = task else {
+        return not_found();
+    };
+
+    let events = task_event::Entity::find()
+        .filter(task_event::Column::TaskId.eq(task.id))
+        .find_also_related(user::Entity)
+        .order_by_asc(task_event::Column::CreatedAt)
+        .order_by_asc(task_event::Column::Id)
+        .all(&ctx.db)
+        .await?;
+
+    let events: Vec<TaskEventResponse> = events
+        .into_iter()
+        .map(|(event, user)| TaskEventResponse {
+            id: event.id,
+            from_status: event.from_status,
+            to_status: event.to_status,
+            actor: event.actor,
+            user_id: event.user_id,
+            user_name: user.map(|user| user.name),
+            reason: event.reason,
+            created_at: event.created_at,
+        })
+        .collect();
+
+    format::json(events)
+}
+
+/// 创建一键任务：按设备机型实例化一键任务模板
+pub async fn create_one_click_task(
+    auth: JWT,
+    State(ctx): State<AppContext>,
+    Json(device_id): Json<serde_json::Value>,
+) -> Result<Response> {
+    let device_id: i32 = device_id
+        .get("device_id")
+        .and_then(|v| v.as_i64())
+        .map(|v| v as i32)
+        .ok_or_else(|| Error::BadRequest("device_id is required".to_string()))?;
+
+    // 查找用户 (JWT中的pid实际上是email)
+    let user = user::Entity::find()
+        .filter(user::Column::Email.eq(&auth.claims.pid))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(user) = user else {
+        return unauthorized("用户未找到");
+    };
+
+    // 验证设备是否属于该用户
+    let device = device::Entity::find()
+        .filter(device::Column::Id.eq(device_id))
+        .filter(device::Column::UserId.eq(user.id))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(device) = device else {
+        return bad_request("设备未找到或无权限");
+    };
+
+    let template = super::task_template::find_for_device(
+        &ctx.db,
+        task_template::ONE_CLICK_KEY,
+        device.drone_model.as_deref(),
+    )
+    .await?;
+
+    let Some(template) = template else {
+        return bad_request("没有适用于该设备机型的一键任务模板");
+    };
+
+    super::task_template::instantiate(&ctx.db, &user, device, &template, None, None, None).await
+}
+
 pub fn routes() -> Routes {
     Routes::new()
         .prefix("tasks")
         .add("/", get(list))
         .add("/", post(create))
         .add("/one-click", post(create_one_click_task))
+        .add("/plan", post(preview_plan))
+        .add("/simulate", post(preview_simulation))
+        .add("/import", post(import))
         .add("/{task_uuid}", get(get_one))
         .add("/{task_uuid}", put(update))
         .add("/{task_uuid}", delete(delete_task))
+        .add("/{task_uuid}/start", post(start))
+        .add("/{task_uuid}/cancel", post(cancel))
+        .add("/{task_uuid}/abort", post(abort))
+        .add("/{task_uuid}/pause", post(pause))
+        .add("/{task_uuid}/resume", post(resume))
+        .add("/{task_uuid}/events", get(events))
+        .add("/{task_uuid}/plan", get(plan))
+        .add("/{task_uuid}/simulate", post(simulate))
+        .add("/{task_uuid}/simulation", get(simulation))
+        .add("/{task_uuid}/export", get(export))
 }
@@ -0,0 +1,351 @@
+use crate::models::{task, task_schedule, user};
+use crate::services::task_executor::parse_parameters;
+use crate::services::task_scheduler::ScheduleSpec;
+use axum::extract::Query;
+use axum::Json;
+use loco_rs::controller::middleware::auth::JWT;
+use loco_rs::prelude::*;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// 详情中返回的后续执行次数
+const SCHEDULE_UPCOMING_COUNT: usize = 5;
+const DEFAULT_UPCOMING_LIMIT: usize = 20;
+const MAX_UPCOMING_LIMIT: usize = 100;
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ScheduleParams {
+    pub cron: Option<String>,                 // 为空时为单次调度
+    pub run_at: Option<DateTimeWithTimeZone>, // 单次调度的执行时间，默认为任务的 start_time
+    pub timezone: Option<String>,
+    pub misfire_policy: Option<String>, // skip, run_once
+    pub misfire_grace_secs: Option<i32>,
+    pub enabled: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpcomingQuery {
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ScheduleResponse {
+    pub id: i32,
+    pub task_uuid: Uuid,
+    pub cron: Option<String>,
+    pub run_at: Option<DateTimeWithTimeZone>,
+    pub timezone: String,
+    pub misfire_policy: String,
+    pub misfire_grace_secs: i32,
+    pub enabled: bool,
+    pub next_run_at: Option<DateTimeWithTimeZone>,
+    pub upcoming: Vec<DateTimeWithTimeZone>,
+    pub last_run_at: Option<DateTimeWithTimeZone>,
+    pub last_task_id: Option<i32>,
+    pub last_status: Option<String>,
+    pub last_error: Option<String>,
+    pub created_at: DateTimeWithTimeZone,
+    pub updated_at: DateTimeWithTimeZone,
+}
+
+impl ScheduleResponse {
+    fn new(schedule: task_schedule::Model, task_uuid: Uuid) -> Self {
+        // 时间统一以调度时区表示
+        let spec = ScheduleSpec::from_model(&schedule).ok();
+        let localize = |at: DateTimeWithTimeZone| match &spec {
+            Some(spec) => spec.localize(at),
+            None => at,
+        };
+        let upcoming = match (&spec, schedule.next_run_at) {
+            (Some(spec), Some(next_run_at)) if schedule.enabled => {
+                spec.upcoming(next_run_at, SCHEDULE_UPCOMING_COUNT)
+            }
+            _ => Vec::new(),
+        };
+
+        Self {
+            id: schedule.id,
+            task_uuid,
+            cron: schedule.cron,
+            run_at: schedule.run_at.map(localize),
+            timezone: schedule.timezone,
+            misfire_policy: schedule.misfire_policy,
+            misfire_grace_secs: schedule.misfire_grace_secs,
+            enabled: schedule.enabled,
+            next_run_at: schedule.next_run_at.map(localize),
+            upcoming,
+            last_run_at: schedule.last_run_at.map(localize),
+            last_task_id: schedule.last_task_id,
+            last_status: schedule.last_status,
+            last_error: schedule.last_error,
+            created_at: localize(schedule.created_at),
+            updated_at: localize(schedule.updated_at),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct UpcomingRun {
+    pub schedule_id: i32,
+    pub task_uuid: Uuid,
+    pub task_name: String,
+    pub device_id: i32,
+    pub run_at: DateTimeWithTimeZone,
+    pub timezone: String,
+    pub recurring: bool,
+}
+
+/// 获取任务的调度
+pub async fn get_schedule(
+    auth: JWT,
+    Path(task_uuid): Path<Uuid>,
+    State(ctx): State<AppContext>,
+) -> Result<Response> {
+    // 查找用户 (JWT中的pid实际上是email)
+    let user = user::Entity::find()
+        .filter(user::Column::Email.eq(&auth.claims.pid))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(user) = user else {
+        return unauthorized("用户未找到");
+    };
+
+    let task = task::Entity::find()
+        .filter(task::Column::Uuid.eq(task_uuid))
+        .filter(task::Column::UserId.eq(user.id))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(task) = task else {
+        return not_found();
+    };
+
+    let schedule = task_schedule::Entity::find()
+        .filter(task_schedule::Column::TaskId.eq(task.id))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(schedule) = schedule else {
+        return not_found();
+    };
+
+    format::json(ScheduleResponse::new(schedule, task.uuid))
+}
+
+/// 创建或替换任务的调度
+pub async fn set_schedule(
+    auth: JWT,
+    Path(task_uuid): Path<Uuid>,
+    State(ctx): State<AppContext>,
+    Json(params): Json<ScheduleParams>,
+) -> Result<Response> {
+    // 查找用户 (JWT中的pid实际上是email)
+    let user = user::Entity::find()
+        .filter(user::Column::Email.eq(&auth.claims.pid))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(user) = user else {
+        return unauthorized("用户未找到");
+    };
+
+    let task = task::Entity::find()
+        .filter(task::Column::Uuid.eq(task_uuid))
+        .filter(task::Column::UserId.eq(user.id))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(task) = task else {
+        return not_found();
+    };
+
+    let cron = params
+        .cron
+        .map(|cron| cron.trim().to_string())
+        .filter(|cron| !cron.is_empty());
+    let run_at = if cron.is_some() {
+        None
+    } else {
+        params.run_at.or(task.start_time)
+    };
+    let timezone = params
+        .timezone
+        .unwrap_or_else(|| task_schedule::DEFAULT_TIMEZONE.to_string());
+    let misfire_policy = params
+        .misfire_policy
+        .unwrap_or_else(|| task_schedule::MISFIRE_SKIP.to_string());
+    let misfire_grace_secs = params
+        .misfire_grace_secs
+        .unwrap_or(task_schedule::DEFAULT_MISFIRE_GRACE_SECS);
+    let enabled = params.enabled.unwrap_or(true);
+
+    if misfire_policy != task_schedule::MISFIRE_SKIP
+        && misfire_policy != task_schedule::MISFIRE_RUN_ONCE
+    {
+        return bad_request("错过策略只能为 skip 或 run_once");
+    }
+    if misfire_grace_secs < 0 {
+        return bad_request("宽限时间不能为负数");
+    }
+
+    let spec = match ScheduleSpec::parse(cron.as_deref(), run_at, &timezone) {
+        Ok(spec) => spec,
+        Err(e) => return bad_request(format!("调度规则无效: {}", e)),
+    };
+    if let Err(e) = parse_parameters(task.parameters.as_ref()) {
+        return bad_request(format!("任务参数无效: {}", e));
+    }
+    if !spec.is_recurring() && task.status != task::TaskStatus::Pending.to_string() {
+        return bad_request("单次调度只能用于待执行的任务");
+    }
+
+    let now: DateTimeWithTimeZone = chrono::Utc::now().into();
+    let next_run_at = spec.next_after(now);
+    if enabled && next_run_at.is_none() {
+        return bad_request("执行时间已过");
+    }
+
+    let existing = task_schedule::Entity::find()
+        .filter(task_schedule::Column::TaskId.eq(task.id))
+        .one(&ctx.db)
+        .await?;
+
+    let is_new = existing.is_none();
+    let mut schedule = match existing {
+        Some(existing) => existing.into(),
+        None => task_schedule::ActiveModel {
+            task_id: Set(task.id),
+            created_at: Set(now),
+            ..Default::default()
+        },
+    };
+    schedule.cron = Set(cron);
+    schedule.run_at = Set(run_at);
+    schedule.timezone = Set(timezone);
+    schedule.misfire_policy = Set(misfire_policy);
+    schedule.misfire_grace_secs = Set(misfire_grace_secs);
+    schedule.enabled = Set(enabled);
+    schedule.next_run_at = Set(next_run_at.filter(|_| enabled));
+    schedule.last_error = Set(None);
+    schedule.updated_at = Set(now);
+    let schedule = if is_new {
+        schedule.insert(&ctx.db).await?
+    } else {
+        schedule.update(&ctx.db).await?
+    };
+
+    // 设置调度后任务即为定时任务
+    if task.task_type != task::TaskType::Scheduled.to_string() {
+        let mut task_active: task::ActiveModel = task.clone().into();
+        task_active.task_type = Set(task::TaskType::Scheduled.to_string());
+        task_active.updated_at = Set(now);
+        task_active.update(&ctx.db).await?;
+    }
+
+    format::json(ScheduleResponse::new(schedule, task.uuid))
+}
+
+/// 删除任务的调度
+pub async fn delete_schedule(
+    auth: JWT,
+    Path(task_uuid): Path<Uuid>,
+    State(ctx): State<AppContext>,
+) -> Result<Response> {
+    // 查找用户 (JWT中的pid实际上是email)
+    let user = user::Entity::find()
+        .filter(user::Column::Email.eq(&auth.claims.pid))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(user) = user else {
+        return unauthorized("用户未找到");
+    };
+
+    let task = task::Entity::find()
+        .filter(task::Column::Uuid.eq(task_uuid))
+        .filter(task::Column::UserId.eq(user.id))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(task) = task else {
+        return not_found();
+    };
+
+    let result = task_schedule::Entity::delete_many()
+        .filter(task_schedule::Column::TaskId.eq(task.id))
+        .exec(&ctx.db)
+        .await?;
+
+    if result.rows_affected == 0 {
+        return not_found();
+    }
+
+    format::json(serde_json::json!({
+        "message": "调度删除成功"
+    }))
+}
+
+/// 列出当前用户所有调度的后续执行时间
+pub async fn upcoming(
+    auth: JWT,
+    Query(query): Query<UpcomingQuery>,
+    State(ctx): State<AppContext>,
+) -> Result<Response> {
+    // 查找用户 (JWT中的pid实际上是email)
+    let user = user::Entity::find()
+        .filter(user::Column::Email.eq(&auth.claims.pid))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(user) = user else {
+        return unauthorized("用户未找到");
+    };
+
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_UPCOMING_LIMIT)
+        .clamp(1, MAX_UPCOMING_LIMIT);
+
+    let schedules = task_schedule::Entity::find()
+        .filter(task_schedule::Column::Enabled.eq(true))
+        .filter(task_schedule::Column::NextRunAt.is_not_null())
+        .find_also_related(task::Entity)
+        .filter(task::Column::UserId.eq(user.id))
+        .all(&ctx.db)
+        .await?;
+
+    let mut runs: Vec<UpcomingRun> = Vec::new();
+    for (schedule, task) in schedules {
+        let (Some(task), Some(next_run_at)) = (task, schedule.next_run_at) else {
+            continue;
+        };
+        let Ok(spec) = ScheduleSpec::from_model(&schedule) else {
+            continue;
+        };
+        for run_at in spec.upcoming(next_run_at, limit) {
+            runs.push(UpcomingRun {
+                schedule_id: schedule.id,
+                task_uuid: task.uuid,
+                task_name: task.name.clone(),
+                device_id: task.device_id,
+                run_at,
+                timezone: schedule.timezone.clone(),
+                recurring: spec.is_recurring(),
+            });
+        }
+    }
+    runs.sort_by_key(|run| run.run_at);
+    runs.truncate(limit);
+
+    format::json(runs)
+}
+
+pub fn routes() -> Routes {
+    Routes::new()
+        .prefix("tasks")
+        .add("/schedules/upcoming", get(upcoming))
+        .add("/{task_uuid}/schedule", get(get_schedule))
+        .add("/{task_uuid}/schedule", put(set_schedule))
+        .add("/{task_uuid}/schedule", delete(delete_schedule))
+}
@@ -0,0 +1,644 @@
+use super::task::{validate_plan, TaskResponse};
+use crate::middleware::rbac::check_user_permission;
+use crate::models::{device, task, task_template, task_template_version, user};
+use crate::services::task_executor::parse_parameters;
+use crate::services::task_state::{self, Actor};
+use axum::extract::Query;
+use axum::Json;
+use loco_rs::controller::middleware::auth::JWT;
+use loco_rs::prelude::*;
+use sea_orm::{DatabaseConnection, QueryOrder, TransactionTrait};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+const MAX_KEY_LEN: usize = 64;
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CreateTemplateParams {
+    pub key: String,
+    pub name: String,
+    pub description: Option<String>,       // 默认按步骤生成
+    pub drone_models: Option<Vec<String>>, // 为空时适用于所有机型
+    pub parameters: serde_json::Value,
+    pub is_active: Option<bool>,
+}
+
+/// 修改名称、描述、适用机型或参数时生成新版本
+#[derive(Debug, Deserialize, Serialize)]
+pub struct UpdateTemplateParams {
+    pub key: Option<String>,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub drone_models: Option<Vec<String>>, // 传空列表表示适用于所有机型
+    pub parameters: Option<serde_json::Value>,
+    pub is_active: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListTemplatesQuery {
+    pub key: Option<String>,
+    pub drone_model: Option<String>, // 只返回适用于该机型的模板
+    pub include_inactive: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct InstantiateParams {
+    pub device_id: i32,
+    pub version: Option<i32>, // 默认为当前版本
+    pub name: Option<String>, // 默认为模板名称
+    pub task_type: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TemplateResponse {
+    pub id: i32,
+    pub uuid: Uuid,
+    pub key: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub drone_models: Option<Vec<String>>,
+    pub parameters: serde_json::Value,
+    pub version: i32,
+    pub is_active: bool,
+    pub created_by: Option<i32>,
+    pub updated_by: Option<i32>,
+    pub created_at: DateTimeWithTimeZone,
+    pub updated_at: DateTimeWithTimeZone,
+}
+
+impl From<task_template::Model> for TemplateResponse {
+    fn from(template: task_template::Model) -> Self {
+        Self {
+            drone_models: template.drone_models(),
+            id: template.id,
+            uuid: template.uuid,
+            key: template.key,
+            name: template.name,
+            description: template.description,
+            parameters: template.parameters,
+            version: template.version,
+            is_active: template.is_active,
+            created_by: template.created_by,
+            updated_by: template.updated_by,
+            created_at: template.created_at,
+            updated_at: template.updated_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct TemplateVersionResponse {
+    pub version: i32,
+    pub name: String,
+    pub description: Option<String>,
+    pub drone_models: Option<Vec<String>>,
+    pub parameters: serde_json::Value,
+    pub created_by: Option<i32>,
+    pub created_at: DateTimeWithTimeZone,
+}
+
+impl From<task_template_version::Model> for TemplateVersionResponse {
+    fn from(version: task_template_version::Model) -> Self {
+        Self {
+            drone_models: task_template::drone_models(version.drone_models.as_ref()),
+            version: version.version,
+            name: version.name,
+            description: version.description,
+            parameters: version.parameters,
+            created_by: version.created_by,
+            created_at: version.created_at,
+        }
+    }
+}
+
+/// 去掉空白和重复的机型，空列表表示适用于所有机型
+fn normalize_drone_models(models: Vec<String>) -> Option<Vec<String>> {
+    let mut normalized: Vec<String> = Vec::new();
+    for model in models {
+        let model = model.trim().to_string();
+        if !model.is_empty()
+            && !normalized
+                .iter()
+                .any(|existing| existing.eq_ignore_ascii_case(&model))
+        {
+            normalized.push(model);
+        }
+    }
+    if normalized.is_empty() {
+        None
+    } else {
+        Some(normalized)
+    }
+}
+
+/// 校验模板标识：小写字母、数字和下划线
+fn validate_key(key: &str) -> Option<&'static str> {
+    if key.is_empty() || key.len() > MAX_KEY_LEN {
+        return Some("模板标识长度应为1-64个字符");
+    }
+    if !key
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+    {
+        return Some("模板标识只能包含小写字母、数字和下划线");
+    }
+    None
+}
+
+/// 同一标识下启用的模板适用机型不能重叠，否则无法确定设备使用哪个模板
+async fn find_conflict(
+    db: &DatabaseConnection,
+    key: &str,
+    drone_models: Option<&[String]>,
+    exclude_id: Option<i32>,
+) -> Result<Option<task_template::Model>> {
+    let mut query = task_template::Entity::find()
+        .filter(task_template::Column::Key.eq(key))
+        .filter(task_template::Column::IsActive.eq(true));
+    if let Some(exclude_id) = exclude_id {
+        query = query.filter(task_template::Column::Id.ne(exclude_id));
+    }
+    let templates = query.all(db).await?;
+
+    Ok(templates
+        .into_iter()
+        .find(|template| match (template.drone_models(), drone_models) {
+            (None, None) => true,
+            (Some(existing), Some(models)) => models
+                .iter()
+                .any(|model| task_template::applies_to(Some(&existing), Some(model))),
+            _ => false,
+        }))
+}
+
+/// 查找设备机型可用的模板，优先使用指定了该机型的模板
+pub(crate) async fn find_for_device(
+    db: &DatabaseConnection,
+    key: &str,
+    drone_model: Option<&str>,
+) -> Result<Option<task_template::Model>> {
+    let templates = task_template::Entity::find()
+        .filter(task_template::Column::Key.eq(key))
+        .filter(task_template::Column::IsActive.eq(true))
+        .order_by_asc(task_template::Column::Id)
+        .all(db)
+        .await?;
+
+    let (specific, generic): (Vec<_>, Vec<_>) = templates
+        .into_iter()
+        .filter(|template| template.applies_to(drone_model))
+        .partition(|template| template.drone_models().is_some());
+
+    Ok(specific.into_iter().chain(generic).next())
+}
+
+/// 按模板版本为设备创建待执行任务
+pub(crate) async fn instantiate(
+    db: &DatabaseConnection,
+    user: &user::Model,
+    device: device::Model,
+    template: &task_template::Model,
+    version: Option<i32>,
+    name: Option<String>,
+    task_type: Option<String>,
+) -> Result<Response> {
+    if !template.is_active {
+        return bad_request("模板已停用");
+    }
+
+    let snapshot = task_template_version::Entity::find()
+        .filter(task_template_version::Column::TemplateId.eq(template.id))
+        .filter(task_template_version::Column::Version.eq(version.unwrap_or(template.version)))
+        .one(db)
+        .await?;
+
+    let Some(snapshot) = snapshot else {
+        return bad_request("模板版本不存在");
+    };
+
+    let drone_models = task_template::drone_models(snapshot.drone_models.as_ref());
+    if !task_template::applies_to(drone_models.as_deref(), device.drone_model.as_deref()) {
+        return bad_request(format!(
+            "模板不适用于该设备机型: {}",
+            device.drone_model.as_deref().unwrap_or("未设置")
+        ));
+    }
+    if let Some(message) = validate_plan(device.id, &snapshot.parameters).await {
+        return bad_request(message);
+    }
+
+    let task_model = task::ActiveModel {
+        uuid: Set(Uuid::new_v4()),
+        name: Set(name.unwrap_or_else(|| snapshot.name.clone())),
+        description: Set(snapshot.description.clone()),
+        task_type: Set(task_type.unwrap_or_else(|| task::TaskType::Auto.to_string())),
+        status: Set(task::TaskStatus::Pending.to_string()),
+        device_id: Set(device.id),
+        user_id: Set(user.id),
+        parameters: Set(Some(snapshot.parameters.clone())),
+        template_id: Set(Some(template.id)),
+        template_version: Set(Some(snapshot.version)),
+        ..Default::default()
+    };
+
+    let task = task::Entity::insert(task_model)
+        .exec_with_returning(db)
+        .await?;
+    task_state::record_created(
+        db,
+        &task,
+        Actor::User(user.id),
+        Some(format!(
+            "Created from template {} v{}",
+            template.key, snapshot.version
+        )),
+    )
+    .await?;
+
+    format::json(TaskResponse::from((task, Some(device))))
+}
+
+/// 获取任务模板列表
+pub async fn list(
+    auth: JWT,
+    Query(query): Query<ListTemplatesQuery>,
+    State(ctx): State<AppContext>,
+) -> Result<Response> {
+    // 查找用户 (JWT中的pid实际上是email)
+    let user = user::Entity::find()
+        .filter(user::Column::Email.eq(&auth.claims.pid))
+        .one(&ctx.db)
+        .await?;
+
+    if user.is_none() {
+        return unauthorized("用户未找到");
+    }
+
+    let mut select = task_template::Entity::find();
+    if let Some(key) = &query.key {
+        select = select.filter(task_template::Column::Key.eq(key));
+    }
+    if !query.include_inactive.unwrap_or(false) {
+        select = select.filter(task_template::Column::IsActive.eq(true));
+    }
+    let templates = select
+        .order_by_asc(task_template::Column::Key)
+        .order_by_asc(task_template::Column::Id)
+        .all(&ctx.db)
+        .await?;
+
+    let responses: Vec<TemplateResponse> = templates
+        .into_iter()
+        .filter(|template| match &query.drone_model {
+            Some(drone_model) => template.applies_to(Some(drone_model)),
+            None => true,
+        })
+        .map(TemplateResponse::from)
+        .collect();
+
+    format::json(responses)
+}
+
+/// 创建任务模板
+pub async fn create(
+    auth: JWT,
+    State(ctx): State<AppContext>,
+    Json(params): Json<CreateTemplateParams>,
+) -> Result<Response> {
+    // 查找用户 (JWT中的pid实际上是email)
+    let user = user::Entity::find()
+        .filter(user::Column::Email.eq(&auth.claims.pid))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(user) = user else {
+        return unauthorized("用户未找到");
+    };
+
+    if !check_user_permission(&ctx.db, &auth.claims.pid, "tasks.write").await? {
+        return unauthorized("权限不足");
+    }
+
+    let key = params.key.trim().to_string();
+    if let Some(message) = validate_key(&key) {
+        return bad_request(message);
+    }
+    if params.name.trim().is_empty() {
+        return bad_request("模板名称不能为空");
+    }
+    let parameters = match parse_parameters(Some(&params.parameters)) {
+        Ok((parameters, _)) => parameters,
+        Err(e) => return bad_request(format!("任务参数无效: {}", e)),
+    };
+    let drone_models = params.drone_models.and_then(normalize_drone_models);
+    let is_active = params.is_active.unwrap_or(true);
+
+    if is_active {
+        if let Some(conflict) = find_conflict(&ctx.db, &key, drone_models.as_deref(), None).await? {
+            return bad_request(format!("适用机型与模板 {} 重叠", conflict.uuid));
+        }
+    }
+
+    let now: DateTimeWithTimeZone = chrono::Utc::now().into();
+    let template_model = task_template::ActiveModel {
+        uuid: Set(Uuid::new_v4()),
+        key: Set(key),
+        name: Set(params.name.trim().to_string()),
+        description: Set(Some(
+            params.description.unwrap_or_else(|| parameters.describe()),
+        )),
+        drone_models: Set(drone_models.map(serde_json::to_value).transpose()?),
+        parameters: Set(params.parameters),
+        version: Set(1),
+        is_active: Set(is_active),
+        created_by: Set(Some(user.id)),
+        updated_by: Set(Some(user.id)),
+        created_at: Set(now),
+        updated_at: Set(now),
+        ..Default::default()
+    };
+
+    // 模板和首个版本快照一起写入
+    let txn = ctx.db.begin().await?;
+    let template = task_template::Entity::insert(template_model)
+        .exec_with_returning(&txn)
+        .await?;
+    task_template_version::ActiveModel::from(&template)
+        .insert(&txn)
+        .await?;
+    txn.commit().await?;
+
+    format::json(TemplateResponse::from(template))
+}
+
+/// 获取单个任务模板
+pub async fn get_one(
+    auth: JWT,
+    Path(template_uuid): Path<Uuid>,
+    State(ctx): State<AppContext>,
+) -> Result<Response> {
+    // 查找用户 (JWT中的pid实际上是email)
+    let user = user::Entity::find()
+        .filter(user::Column::Email.eq(&auth.claims.pid))
+        .one(&ctx.db)
+        .await?;
+
+    if user.is_none() {
+        return unauthorized("用户未找到");
+    }
+
+    let template = task_template::Entity::find()
+        .filter(task_template::Column::Uuid.eq(template_uuid))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(template) = template else {
+        return not_found();
+    };
+
+    format::json(TemplateResponse::from(template))
+}
+
+/// 更新任务模板，内容变化时版本号加一并保存快照
+pub async fn update(
+    auth: JWT,
+    Path(template_uuid): Path<Uuid>,
+    State(ctx): State<AppContext>,
+    Json(params): Json<UpdateTemplateParams>,
+) -> Result<Response> {
+    // 查找用户 (JWT中的pid实际上是email)
+    let user = user::Entity::find()
+        .filter(user::Column::Email.eq(&auth.claims.pid))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(user) = user else {
+        return unauthorized("用户未找到");
+    };
+
+    if !check_user_permission(&ctx.db, &auth.claims.pid, "tasks.write").await? {
+        return unauthorized("权限不足");
+    }
+
+    let template = task_template::Entity::find()
+        .filter(task_template::Column::Uuid.eq(template_uuid))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(template) = template else {
+        return not_found();
+    };
+
+    let key = match params.key {
+        Some(key) => key.trim().to_string(),
+        None => template.key.clone(),
+    };
+    if let Some(message) = validate_key(&key) {
+        return bad_request(message);
+    }
+    let name = match params.name {
+        Some(name) if name.trim().is_empty() => return bad_request("模板名称不能为空"),
+        Some(name) => name.trim().to_string(),
+        None => template.name.clone(),
+    };
+    let drone_models = match params.drone_models {
+        Some(models) => normalize_drone_models(models),
+        None => template.drone_models(),
+    };
+    let parsed = match params
+        .parameters
+        .as_ref()
+        .map(|p| parse_parameters(Some(p)))
+    {
+        Some(Ok((parsed, _))) => Some(parsed),
+        Some(Err(e)) => return bad_request(format!("任务参数无效: {}", e)),
+        None => None,
+    };
+    let description = match (params.description, parsed) {
+        (Some(description), _) => Some(description),
+        // 只改步骤时按新步骤重新生成描述，避免描述与步骤不一致
+        (None, Some(parsed)) => Some(parsed.describe()),
+        (None, None) => template.description.clone(),
+    };
+    let parameters = params
+        .parameters
+        .unwrap_or_else(|| template.parameters.clone());
+    let is_active = params.is_active.unwrap_or(template.is_active);
+
+    if is_active {
+        if let Some(conflict) =
+            find_conflict(&ctx.db, &key, drone_models.as_deref(), Some(template.id)).await?
+        {
+            return bad_request(format!("适用机型与模板 {} 重叠", conflict.uuid));
+        }
+    }
+
+    let drone_models = drone_models.map(serde_json::to_value).transpose()?;
+    let content_changed = name != template.name
+        || description != template.description
+        || drone_models != template.drone_models
+        || parameters != template.parameters;
+
+    let now: DateTimeWithTimeZone = chrono::Utc::now().into();
+    let version = if content_changed {
+        template.version + 1
+    } else {
+        template.version
+    };
+    let mut template_active: task_template::ActiveModel = template.into();
+    template_active.key = Set(key);
+    template_active.name = Set(name);
+    template_active.description = Set(description);
+    template_active.drone_models = Set(drone_models);
+    template_active.parameters = Set(parameters);
+    template_active.version = Set(version);
+    template_active.is_active = Set(is_active);
+    template_active.updated_by = Set(Some(user.id));
+    template_active.updated_at = Set(now);
+
+    // 内容变化时模板和新版本快照一起写入，避免出现没有快照的版本号
+    let txn = ctx.db.begin().await?;
+    let template = template_active.update(&txn).await?;
+    if content_changed {
+        task_template_version::ActiveModel::from(&template)
+            .insert(&txn)
+            .await?;
+    }
+    txn.commit().await?;
+
+    format::json(TemplateResponse::from(template))
+}
+
+/// 删除任务模板及其历史版本，已创建的任务不受影响
+pub async fn delete_template(
+    auth: JWT,
+    Path(template_uuid): Path<Uuid>,
+    State(ctx): State<AppContext>,
+) -> Result<Response> {
+    // 查找用户 (JWT中的pid实际上是email)
+    let user = user::Entity::find()
+        .filter(user::Column::Email.eq(&auth.claims.pid))
+        .one(&ctx.db)
+        .await?;
+
+    if user.is_none() {
+        return unauthorized("用户未找到");
+    }
+
+    if !check_user_permission(&ctx.db, &auth.claims.pid, "tasks.delete").await? {
+        return unauthorized("权限不足");
+    }
+
+    let result = task_template::Entity::delete_many()
+        .filter(task_template::Column::Uuid.eq(template_uuid))
+        .exec(&ctx.db)
+        .await?;
+
+    if result.rows_affected == 0 {
+        return not_found();
+    }
+
+    format::json(serde_json::json!({
+        "message": "模板删除成功"
+    }))
+}
+
+/// 获取任务模板的历史版本
+pub async fn versions(
+    auth: JWT,
+    Path(template_uuid): Path<Uuid>,
+    State(ctx): State<AppContext>,
+) -> Result<Response> {
+    // 查找用户 (JWT中的pid实际上是email)
+    let user = user::Entity::find()
+        .filter(user::Column::Email.eq(&auth.claims.pid))
+        .one(&ctx.db)
+        .await?;
+
+    if user.is_none() {
+        return unauthorized("用户未找到");
+    }
+
+    let template = task_template::Entity::find()
+        .filter(task_template::Column::Uuid.eq(template_uuid))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(template) = template else {
+        return not_found();
+    };
+
+    let versions = task_template_version::Entity::find()
+        .filter(task_template_version::Column::TemplateId.eq(template.id))
+        .order_by_desc(task_template_version::Column::Version)
+        .all(&ctx.db)
+        .await?;
+
+    let responses: Vec<TemplateVersionResponse> = versions
+        .into_iter()
+        .map(TemplateVersionResponse::from)
+        .collect();
+
+    format::json(responses)
+}
+
+/// 按模板为设备创建任务
+pub async fn create_task(
+    auth: JWT,
+    Path(template_uuid): Path<Uuid>,
+    State(ctx): State<AppContext>,
+    Json(params): Json<InstantiateParams>,
+) -> Result<Response> {
+    // 查找用户 (JWT中的pid实际上是email)
+    let user = user::Entity::find()
+        .filter(user::Column::Email.eq(&auth.claims.pid))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(user) = user else {
+        return unauthorized("用户未找到");
+    };
+
+    // 验证设备是否属于该用户
+    let device = device::Entity::find()
+        .filter(device::Column::Id.eq(params.device_id))
+        .filter(device::Column::UserId.eq(user.id))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(device) = device else {
+        return bad_request("设备未找到或无权限");
+    };
+
+    let template = task_template::Entity::find()
+        .filter(task_template::Column::Uuid.eq(template_uuid))
+        .one(&ctx.db)
+        .await?;
+
+    let Some(template) = template else {
+        return not_found();
+    };
+
+    instantiate(
+        &ctx.db,
+        &user,
+        device,
+        &template,
+        params.version,
+        params.name,
+        params.task_type,
+    )
+    .await
+}
+
+pub fn routes() -> Routes {
+    Routes::new()
+        .prefix("task-templates")
+        .add("/", get(list))
+        .add("/", post(create))
+        .add("/{template_uuid}", get(get_one))
+        .add("/{template_uuid}", put(update))
+        .add("/{template_uuid}", delete(delete_template))
+        .add("/{template_uuid}/versions", get(versions))
+        .add("/{template_uuid}/tasks", post(create_task))
+}
@@ -4,4 +4,5 @@ pub mod graphql;
 pub mod middleware;
 pub mod models;
 pub mod services;
+pub mod settings;
 pub mod tasks;
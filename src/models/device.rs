@@ -23,6 +23,10 @@ pub struct Model {
     pub mqtt_port: Option<i32>,
     pub mqtt_enabled: bool,
     pub is_connected: bool,
+    pub mavlink_system_id: Option<i32>,
+    pub nmea_port: Option<i32>,
+    pub collection_mode: bool,
+    pub device_group: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -61,6 +65,10 @@ pub struct CreateDeviceParams {
     pub drone_brand: Option<String>,
     pub mqtt_port: Option<i32>,
     pub mqtt_enabled: Option<bool>,
+    pub mavlink_system_id: Option<i32>,
+    pub nmea_port: Option<i32>,
+    pub collection_mode: Option<bool>,
+    pub device_group: Option<String>,
 }
 
 // 设备更新参数
@@ -78,6 +86,14 @@ pub struct UpdateDeviceParams {
     pub mqtt_port: Option<i32>,
     pub mqtt_enabled: Option<bool>,
     pub is_connected: Option<bool>,
+    /// 传 `null` 清除
+    #[serde(default, deserialize_with = "super::utils::nullable")]
+    pub mavlink_system_id: Option<Option<i32>>,
+    /// 传 `null` 清除
+    #[serde(default, deserialize_with = "super::utils::nullable")]
+    pub nmea_port: Option<Option<i32>>,
+    pub collection_mode: Option<bool>,
+    pub device_group: Option<String>,
 }
 
 // 设备响应
@@ -99,6 +115,10 @@ pub struct DeviceResponse {
     pub mqtt_port: Option<i32>,
     pub mqtt_enabled: bool,
     pub is_connected: bool,
+    pub mavlink_system_id: Option<i32>,
+    pub nmea_port: Option<i32>,
+    pub collection_mode: bool,
+    pub device_group: Option<String>,
 }
 
 impl From<Model> for DeviceResponse {
@@ -120,6 +140,10 @@ impl From<Model> for DeviceResponse {
             mqtt_port: device.mqtt_port,
             mqtt_enabled: device.mqtt_enabled,
             is_connected: device.is_connected,
+            mavlink_system_id: device.mavlink_system_id,
+            nmea_port: device.nmea_port,
+            collection_mode: device.collection_mode,
+            device_group: device.device_group,
         }
     }
 }
@@ -136,6 +160,22 @@ impl Model {
             return Err("WebSocket port is required".to_string());
         }
 
+        // 验证MAVLink系统ID
+        if let Some(system_id) = self.mavlink_system_id {
+            if !(1..=255).contains(&system_id) {
+                return Err(
+                    "Invalid MAVLink system ID: must be between 1 and 255".to_string(),
+                );
+            }
+        }
+
+        // 验证NMEA端口
+        if let Some(port) = self.nmea_port {
+            if !(1..=65535).contains(&port) {
+                return Err("Invalid NMEA port: must be between 1 and 65535".to_string());
+            }
+        }
+
         // 这里可以添加更复杂的连接验证逻辑
         // 目前只做基本的端口范围检查
         Ok(true)
@@ -117,20 +117,33 @@ impl Model {
         let now =
             chrono::Utc::now().with_timezone(&chrono::FixedOffset::east_opt(8 * 3600).unwrap());
 
+        Self::new_active_model(device_id, data_type, data_content, now)
+            .insert(db)
+            .await
+    }
+
+    /// 构建待插入的实时数据，`received_at` 使用数据实际接收时间
+    pub fn new_active_model(
+        device_id: i32,
+        data_type: &str,
+        data_content: JsonValue,
+        received_at: DateTimeWithTimeZone,
+    ) -> ActiveModel {
+        let now =
+            chrono::Utc::now().with_timezone(&chrono::FixedOffset::east_opt(8 * 3600).unwrap());
+
         // 清理JSON中的控制字符
         let cleaned_content = clean_json_value(data_content);
 
-        let new_data = ActiveModel {
+        ActiveModel {
             device_id: Set(device_id),
             data_type: Set(data_type.to_string()),
             data_content: Set(cleaned_content),
-            received_at: Set(now),
+            received_at: Set(received_at),
             created_at: Set(now),
             updated_at: Set(now),
             ..Default::default()
-        };
-
-        new_data.insert(db).await
+        }
     }
 
     /// 获取设备的最新实时数据
@@ -0,0 +1,38 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+
+/// 遥测时间桶聚合
+///
+/// `fields` 为 `{字段名: {min, max, avg, sum, count, last}}`，保留 sum/count 以便合并。
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "device_telemetry_rollup")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i64,
+    pub device_id: i32,
+    pub resolution: String, // "1s"、"1m" 或 "1h"
+    pub bucket_start: DateTimeWithTimeZone,
+    pub sample_count: i64,
+    pub fields: JsonValue,
+    pub created_at: DateTimeWithTimeZone,
+    pub updated_at: DateTimeWithTimeZone,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::device::Entity",
+        from = "Column::DeviceId",
+        to = "super::device::Column::Id"
+    )]
+    Device,
+}
+
+impl Related<super::device::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Device.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
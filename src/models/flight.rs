@@ -0,0 +1,59 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// 飞行架次，由遥测数据自动切分
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "flight")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    #[sea_orm(unique)]
+    pub uuid: Uuid,
+    pub device_id: i32,
+    pub task_id: Option<i32>,       // 起飞时正在运行的任务
+    pub status: String,             // in_progress, completed
+    pub start_reason: String,       // armed, airborne
+    pub end_reason: Option<String>, // disarmed, landed, data_gap, restart
+    pub start_time: DateTimeWithTimeZone,
+    pub end_time: Option<DateTimeWithTimeZone>,
+    pub duration_seconds: f64,
+    pub distance: f64, // 米
+    pub max_altitude: Option<f64>,
+    pub max_speed: Option<f64>, // 米/秒
+    pub battery_start: Option<f64>,
+    pub battery_end: Option<f64>,
+    pub battery_consumed: Option<f64>, // 百分比
+    pub created_at: DateTimeWithTimeZone,
+    pub updated_at: DateTimeWithTimeZone,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::device::Entity",
+        from = "Column::DeviceId",
+        to = "super::device::Column::Id"
+    )]
+    Device,
+    #[sea_orm(
+        belongs_to = "super::task::Entity",
+        from = "Column::TaskId",
+        to = "super::task::Column::Id"
+    )]
+    Task,
+}
+
+impl Related<super::device::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Device.def()
+    }
+}
+
+impl Related<super::task::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Task.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
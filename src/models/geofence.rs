@@ -0,0 +1,61 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// 电子围栏：指定设备或用户，都未指定时对所有设备生效
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "geofence")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    #[sea_orm(unique)]
+    pub uuid: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    pub kind: String, // inclusion / exclusion
+    pub shape: Json,  // 多边形或圆形
+    pub min_altitude: Option<f64>,
+    pub max_altitude: Option<f64>,
+    pub device_id: Option<i32>,
+    pub user_id: Option<i32>,
+    pub rtl_on_breach: bool, // 越界时自动返航
+    pub is_active: bool,
+    pub created_by: Option<i32>,
+    pub created_at: DateTimeWithTimeZone,
+    pub updated_at: DateTimeWithTimeZone,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::device::Entity",
+        from = "Column::DeviceId",
+        to = "super::device::Column::Id"
+    )]
+    Device,
+    #[sea_orm(
+        belongs_to = "super::user::Entity",
+        from = "Column::UserId",
+        to = "super::user::Column::Id"
+    )]
+    User,
+}
+
+impl Related<super::device::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Device.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+impl Model {
+    /// 围栏是否约束归属于 `owner_id` 的设备 `device_id`
+    pub fn applies_to(&self, device_id: i32, owner_id: i32) -> bool {
+        match (self.device_id, self.user_id) {
+            (Some(id), _) => id == device_id,
+            (None, Some(id)) => id == owner_id,
+            (None, None) => true,
+        }
+    }
+}
@@ -7,7 +7,11 @@ pub mod prelude;
 pub mod collection_data;
 pub mod device;
 pub mod device_realtime_data;
+pub mod device_telemetry_rollup;
 pub mod element_type;
+pub mod fleet_mission;
+pub mod flight;
+pub mod geofence;
 pub mod history;
 pub mod info;
 pub mod info_area;
@@ -17,6 +21,10 @@ pub mod region;
 pub mod role;
 pub mod role_permission;
 pub mod task;
+pub mod task_event;
+pub mod task_schedule;
+pub mod task_template;
+pub mod task_template_version;
 pub mod user;
 pub mod user_role;
 pub mod warn;
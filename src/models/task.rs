@@ -12,10 +12,16 @@ pub struct Model {
     pub name: String,
     pub description: Option<String>,
     pub task_type: String, // manual, auto, scheduled
-    pub status: String,    // pending, running, completed, failed, cancelled
+    pub status: String,    // pending, running, paused, completed, failed, cancelled, aborted
     pub device_id: i32,
     pub user_id: i32,
     pub parameters: Option<Json>, // 任务参数
+    pub progress: Option<Json>,   // 执行进度
+    pub template_id: Option<i32>, // 创建任务所用的模板
+    pub template_version: Option<i32>,
+    pub simulation: Option<Json>,      // 最近一次任务模拟结果
+    pub fleet_mission_id: Option<i32>, // 所属编队任务
+    pub reassigned_from: Option<i32>,  // 接管剩余航线的失败子任务
     pub start_time: Option<DateTimeWithTimeZone>,
     pub end_time: Option<DateTimeWithTimeZone>,
     pub created_at: DateTimeWithTimeZone,
@@ -36,6 +42,12 @@ pub enum Relation {
         to = "super::user::Column::Id"
     )]
     User,
+    #[sea_orm(
+        belongs_to = "super::fleet_mission::Entity",
+        from = "Column::FleetMissionId",
+        to = "super::fleet_mission::Column::Id"
+    )]
+    FleetMission,
 }
 
 impl Related<super::device::Entity> for Entity {
@@ -50,6 +62,12 @@ impl Related<super::user::Entity> for Entity {
     }
 }
 
+impl Related<super::fleet_mission::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::FleetMission.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelatedEntity)]
@@ -61,13 +79,15 @@ pub enum RelatedEntity {
 }
 
 // 任务状态枚举
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum TaskStatus {
     Pending,
     Running,
+    Paused,
     Completed,
     Failed,
     Cancelled,
+    Aborted,
 }
 
 impl ToString for TaskStatus {
@@ -75,26 +95,50 @@ impl ToString for TaskStatus {
         match self {
             TaskStatus::Pending => "pending".to_string(),
             TaskStatus::Running => "running".to_string(),
+            TaskStatus::Paused => "paused".to_string(),
             TaskStatus::Completed => "completed".to_string(),
             TaskStatus::Failed => "failed".to_string(),
             TaskStatus::Cancelled => "cancelled".to_string(),
+            TaskStatus::Aborted => "aborted".to_string(),
         }
     }
 }
 
-impl From<String> for TaskStatus {
-    fn from(s: String) -> Self {
-        match s.as_str() {
-            "pending" => TaskStatus::Pending,
-            "running" => TaskStatus::Running,
-            "completed" => TaskStatus::Completed,
-            "failed" => TaskStatus::Failed,
-            "cancelled" => TaskStatus::Cancelled,
-            _ => TaskStatus::Pending,
+impl std::str::FromStr for TaskStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pending" => Ok(TaskStatus::Pending),
+            "running" => Ok(TaskStatus::Running),
+            "paused" => Ok(TaskStatus::Paused),
+            "completed" => Ok(TaskStatus::Completed),
+            "failed" => Ok(TaskStatus::Failed),
+            "cancelled" => Ok(TaskStatus::Cancelled),
+            "aborted" => Ok(TaskStatus::Aborted),
+            _ => Err(format!("Unknown task status: {}", s)),
         }
     }
 }
 
+impl TaskStatus {
+    /// 任务状态机：
+    /// - pending → running、cancelled，或启动失败时 failed
+    /// - running → paused、completed、failed、cancelled、aborted
+    /// - paused → running、failed、cancelled、aborted
+    /// - failed、cancelled、aborted → pending（重新执行）
+    pub fn can_transition_to(&self, next: &TaskStatus) -> bool {
+        use TaskStatus::*;
+        matches!(
+            (self, next),
+            (Pending, Running | Cancelled | Failed)
+                | (Running, Paused | Completed | Failed | Cancelled | Aborted)
+                | (Paused, Running | Failed | Cancelled | Aborted)
+                | (Failed | Cancelled | Aborted, Pending)
+        )
+    }
+}
+
 // 任务类型枚举
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum TaskType {
@@ -134,70 +178,163 @@ pub struct TaskParameters {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TaskStep {
-    pub step_type: String, // takeoff, landing, move_to_height, move_to_heading, wait, photo
+    pub step_type: String, // takeoff, landing, move_to_height, move_to_heading, wait, photo, waypoint, survey, orbit
     pub parameters: serde_json::Value,
     pub timeout: Option<u32>,
 }
 
-// 预定义任务模板
-impl TaskParameters {
-    /// 创建一键任务参数
-    pub fn one_click_task() -> Self {
+// 步骤执行状态
+pub const STEP_PENDING: &str = "pending";
+pub const STEP_RUNNING: &str = "running";
+pub const STEP_COMPLETED: &str = "completed";
+pub const STEP_FAILED: &str = "failed";
+pub const STEP_CANCELLED: &str = "cancelled";
+
+// 任务执行进度
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TaskProgress {
+    pub current_step: Option<usize>, // 正在执行的步骤序号
+    pub steps: Vec<StepProgress>,
+    pub error: Option<String>, // 任务失败原因
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StepProgress {
+    #[serde(default)]
+    pub source_step: Option<usize>, // 所属任务参数步骤的序号，航线步骤展开为多个航点
+    pub step_type: String,
+    pub status: String, // pending, running, completed, failed, cancelled
+    pub attempts: u32,  // 已尝试次数
+    pub started_at: Option<DateTimeWithTimeZone>,
+    pub finished_at: Option<DateTimeWithTimeZone>,
+    pub error: Option<String>,
+}
+
+impl TaskProgress {
+    pub fn new(steps: Vec<StepProgress>) -> Self {
         Self {
-            steps: vec![
-                TaskStep {
-                    step_type: "takeoff".to_string(),
-                    parameters: serde_json::json!({}),
-                    timeout: Some(30),
-                },
-                TaskStep {
-                    step_type: "wait".to_string(),
-                    parameters: serde_json::json!({"duration": 10}),
-                    timeout: None,
-                },
-                TaskStep {
-                    step_type: "move_to_heading".to_string(),
-                    parameters: serde_json::json!({"heading": 0}),
-                    timeout: Some(30),
-                },
-                TaskStep {
-                    step_type: "wait".to_string(),
-                    parameters: serde_json::json!({"duration": 10}),
-                    timeout: None,
-                },
-                TaskStep {
-                    step_type: "move_to_height".to_string(),
-                    parameters: serde_json::json!({"height": 1.5}),
-                    timeout: Some(30),
-                },
-                TaskStep {
-                    step_type: "wait".to_string(),
-                    parameters: serde_json::json!({"duration": 10}),
-                    timeout: None,
-                },
-                TaskStep {
-                    step_type: "move_to_height".to_string(),
-                    parameters: serde_json::json!({"height": 2.0}),
-                    timeout: Some(30),
-                },
-                TaskStep {
-                    step_type: "move_to_heading".to_string(),
-                    parameters: serde_json::json!({"heading": 0}),
-                    timeout: Some(30),
-                },
-                TaskStep {
-                    step_type: "wait".to_string(),
-                    parameters: serde_json::json!({"duration": 10}),
-                    timeout: None,
-                },
-                TaskStep {
-                    step_type: "landing".to_string(),
-                    parameters: serde_json::json!({}),
-                    timeout: Some(60),
-                },
-            ],
-            timeout: Some(300), // 5分钟总超时
-            retry_count: Some(1),
+            current_step: None,
+            steps,
+            error: None,
+        }
+    }
+}
+
+impl StepProgress {
+    pub fn pending(step_type: &str, source_step: usize) -> Self {
+        Self {
+            source_step: Some(source_step),
+            step_type: step_type.to_string(),
+            status: STEP_PENDING.to_string(),
+            attempts: 0,
+            started_at: None,
+            finished_at: None,
+            error: None,
+        }
+    }
+}
+
+impl TaskParameters {
+    /// 按步骤生成任务描述，如 "起飞-悬停10秒-朝北-降落"
+    pub fn describe(&self) -> String {
+        self.steps
+            .iter()
+            .map(TaskStep::describe)
+            .collect::<Vec<_>>()
+            .join("-")
+    }
+}
+
+impl TaskStep {
+    fn describe(&self) -> String {
+        let number = |key: &str| self.parameters.get(key).and_then(serde_json::Value::as_f64);
+        match self.step_type.as_str() {
+            "takeoff" => "起飞".to_string(),
+            "landing" => "降落".to_string(),
+            "wait" | "hover" => match number("duration") {
+                Some(duration) => format!("悬停{}秒", duration),
+                None => "悬停".to_string(),
+            },
+            "move_to_height" => match number("height") {
+                Some(height) => format!("飞到{}米", height),
+                None => "调整高度".to_string(),
+            },
+            "move_to_heading" => match number("heading").map(|heading| heading.rem_euclid(360.0)) {
+                Some(0.0) => "朝北".to_string(),
+                Some(90.0) => "朝东".to_string(),
+                Some(180.0) => "朝南".to_string(),
+                Some(270.0) => "朝西".to_string(),
+                Some(heading) => format!("转向{}度", heading),
+                None => "调整航向".to_string(),
+            },
+            "photo" => "拍照".to_string(),
+            "waypoint" => "航点飞行".to_string(),
+            "survey" => "区域测绘".to_string(),
+            "orbit" => "环绕飞行".to_string(),
+            other => other.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TaskStatus::{self, *};
+
+    const ALL: [TaskStatus; 7] = [
+        Pending, Running, Paused, Completed, Failed, Cancelled, Aborted,
+    ];
+
+    #[test]
+    fn legal_transitions() {
+        let legal = [
+            (Pending, Running),
+            (Pending, Cancelled),
+            (Pending, Failed),
+            (Running, Paused),
+            (Running, Completed),
+            (Running, Failed),
+            (Running, Cancelled),
+            (Running, Aborted),
+            (Paused, Running),
+            (Paused, Failed),
+            (Paused, Cancelled),
+            (Paused, Aborted),
+            (Failed, Pending),
+            (Cancelled, Pending),
+            (Aborted, Pending),
+        ];
+        for from in ALL {
+            for to in ALL {
+                assert_eq!(
+                    from.can_transition_to(&to),
+                    legal.contains(&(from, to)),
+                    "{} -> {}",
+                    from.to_string(),
+                    to.to_string()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn illegal_transitions() {
+        // 未执行的任务不能直接完成或暂停
+        assert!(!Pending.can_transition_to(&Completed));
+        assert!(!Pending.can_transition_to(&Paused));
+        assert!(!Pending.can_transition_to(&Aborted));
+        // 暂停的任务需要先恢复执行
+        assert!(!Paused.can_transition_to(&Completed));
+        assert!(!Paused.can_transition_to(&Pending));
+        assert!(!Running.can_transition_to(&Pending));
+        // 已完成的任务不能重新排队，其它终止状态只能重新排队
+        assert!(!Completed.can_transition_to(&Pending));
+        for terminal in [Failed, Cancelled, Aborted] {
+            assert!(terminal.can_transition_to(&Pending));
+            assert!(!terminal.can_transition_to(&Running));
+            assert!(!terminal.can_transition_to(&Completed));
+        }
+        for status in ALL {
+            assert!(!status.can_transition_to(&status));
         }
     }
 }
@@ -0,0 +1,55 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// 任务调度：单次（`run_at`）或按 cron 表达式周期执行
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "task_schedule")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    #[sea_orm(unique)]
+    pub task_id: i32,
+    pub cron: Option<String>, // 为空时为单次调度
+    pub run_at: Option<DateTimeWithTimeZone>,
+    pub timezone: String,       // IANA 时区，如 Asia/Shanghai
+    pub misfire_policy: String, // skip, run_once
+    pub misfire_grace_secs: i32,
+    pub enabled: bool,
+    pub next_run_at: Option<DateTimeWithTimeZone>,
+    pub last_run_at: Option<DateTimeWithTimeZone>,
+    pub last_task_id: Option<i32>,   // 最近一次执行的任务
+    pub last_status: Option<String>, // launched, missed, failed
+    pub last_error: Option<String>,
+    pub created_at: DateTimeWithTimeZone,
+    pub updated_at: DateTimeWithTimeZone,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::task::Entity",
+        from = "Column::TaskId",
+        to = "super::task::Column::Id"
+    )]
+    Task,
+}
+
+impl Related<super::task::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Task.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+pub const DEFAULT_TIMEZONE: &str = "Asia/Shanghai";
+pub const DEFAULT_MISFIRE_GRACE_SECS: i32 = 60;
+
+// 错过执行时间（超过宽限期）时的处理方式
+pub const MISFIRE_SKIP: &str = "skip";
+pub const MISFIRE_RUN_ONCE: &str = "run_once";
+
+// 最近一次调度结果
+pub const RUN_LAUNCHED: &str = "launched";
+pub const RUN_MISSED: &str = "missed";
+pub const RUN_FAILED: &str = "failed";
@@ -0,0 +1,75 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// 任务模板：可复用的任务步骤，修改内容时版本号递增并保存快照
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "task_template")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    #[sea_orm(unique)]
+    pub uuid: Uuid,
+    pub key: String, // 模板标识，同一标识可按机型提供多个模板
+    pub name: String,
+    pub description: Option<String>,
+    pub drone_models: Option<Json>, // 适用机型列表，为空时适用于所有机型
+    pub parameters: Json,           // 任务参数
+    pub version: i32,
+    pub is_active: bool,
+    pub created_by: Option<i32>,
+    pub updated_by: Option<i32>,
+    pub created_at: DateTimeWithTimeZone,
+    pub updated_at: DateTimeWithTimeZone,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(has_many = "super::task_template_version::Entity")]
+    Versions,
+}
+
+impl Related<super::task_template_version::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Versions.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+/// 一键任务使用的模板标识
+pub const ONE_CLICK_KEY: &str = "one_click";
+
+/// 解析适用机型列表，`None` 表示适用于所有机型
+pub fn drone_models(value: Option<&Json>) -> Option<Vec<String>> {
+    let models: Vec<String> = value
+        .and_then(|value| serde_json::from_value(value.clone()).ok())
+        .unwrap_or_default();
+    if models.is_empty() {
+        None
+    } else {
+        Some(models)
+    }
+}
+
+/// 机型是否在适用列表中，不区分大小写
+pub fn applies_to(models: Option<&[String]>, drone_model: Option<&str>) -> bool {
+    match models {
+        None => true,
+        Some(models) => drone_model.is_some_and(|drone_model| {
+            models
+                .iter()
+                .any(|model| model.trim().eq_ignore_ascii_case(drone_model.trim()))
+        }),
+    }
+}
+
+impl Model {
+    pub fn drone_models(&self) -> Option<Vec<String>> {
+        drone_models(self.drone_models.as_ref())
+    }
+
+    pub fn applies_to(&self, drone_model: Option<&str>) -> bool {
+        applies_to(self.drone_models().as_deref(), drone_model)
+    }
+}
@@ -0,0 +1,54 @@
+use sea_orm::entity::prelude::*;
+use sea_orm::Set;
+use serde::{Deserialize, Serialize};
+
+/// 任务模板的历史版本快照
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "task_template_version")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub template_id: i32,
+    pub version: i32,
+    pub name: String,
+    pub description: Option<String>,
+    pub drone_models: Option<Json>,
+    pub parameters: Json,
+    pub created_by: Option<i32>,
+    pub created_at: DateTimeWithTimeZone,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::task_template::Entity",
+        from = "Column::TemplateId",
+        to = "super::task_template::Column::Id"
+    )]
+    TaskTemplate,
+}
+
+impl Related<super::task_template::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::TaskTemplate.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+impl From<&super::task_template::Model> for ActiveModel {
+    /// 模板当前内容的快照
+    fn from(template: &super::task_template::Model) -> Self {
+        Self {
+            template_id: Set(template.id),
+            version: Set(template.version),
+            name: Set(template.name.clone()),
+            description: Set(template.description.clone()),
+            drone_models: Set(template.drone_models.clone()),
+            parameters: Set(template.parameters.clone()),
+            created_by: Set(template.updated_by),
+            created_at: Set(template.updated_at),
+            ..Default::default()
+        }
+    }
+}
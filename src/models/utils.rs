@@ -78,3 +78,12 @@ where
         None => Ok(None),
     }
 }
+
+/// 区分字段缺失和显式 `null`：缺失为 `None`，`null` 为 `Some(None)`，需配合 `#[serde(default)]` 使用
+pub fn nullable<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
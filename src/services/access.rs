@@ -0,0 +1,230 @@
+//! 实时数据访问控制
+//!
+//! `/api/realtime/ws` 和设备代理端口在建立连接前校验凭证，支持登录 JWT 和用户 API Key，
+//! 可通过以下任一方式传入：
+//! - 查询参数 `token` 或 `api_key`；
+//! - `Authorization: Bearer <token>`；
+//! - `Sec-WebSocket-Protocol: bearer, <token>`，供浏览器等无法设置请求头的客户端使用，
+//!   服务端回应 `bearer` 子协议。
+//!
+//! 普通用户只能访问自己的设备，拥有 `system.admin`（或通配符 `*`）权限的用户可以访问全部设备。
+//! 设备相关的 HTTP 接口通过 [`Authenticated`] 提取器使用相同的权限规则，但凭证只能放在
+//! `Authorization` 请求头中。
+
+use axum::extract::FromRequestParts;
+use axum::http::{header, request::Parts, HeaderMap};
+use loco_rs::app::AppContext;
+use loco_rs::auth::jwt;
+use sea_orm::{ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, QuerySelect};
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use crate::middleware::rbac::get_user_permissions;
+use crate::models::{device, user};
+
+/// 通过 `Sec-WebSocket-Protocol` 传递凭证时使用的子协议
+pub const BEARER_PROTOCOL: &str = "bearer";
+
+/// 可访问全部设备的权限
+const ALL_DEVICES_PERMISSIONS: [&str; 2] = ["system.admin", "*"];
+
+/// 用户可访问的设备范围
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceScope {
+    All,
+    /// 指定用户拥有的设备
+    Owner(i32),
+}
+
+impl DeviceScope {
+    /// `owner` 为设备所属用户，未知设备只有 `All` 可以访问
+    pub fn allows(&self, owner: Option<i32>) -> bool {
+        match self {
+            Self::All => true,
+            Self::Owner(user_id) => owner == Some(*user_id),
+        }
+    }
+}
+
+/// 已认证的用户及其设备范围
+#[derive(Debug, Clone)]
+pub struct Principal {
+    pub user: user::Model,
+    pub scope: DeviceScope,
+}
+
+impl Principal {
+    /// 根据用户权限确定设备范围
+    pub async fn for_user(db: &DatabaseConnection, user: user::Model) -> loco_rs::Result<Self> {
+        let permissions = get_user_permissions(db, user.id).await?;
+        let scope = if ALL_DEVICES_PERMISSIONS
+            .iter()
+            .any(|permission| permissions.contains(*permission))
+        {
+            DeviceScope::All
+        } else {
+            DeviceScope::Owner(user.id)
+        };
+        Ok(Self { user, scope })
+    }
+
+    /// 通过 JWT 中的邮箱查找用户
+    pub async fn for_email(db: &DatabaseConnection, email: &str) -> loco_rs::Result<Option<Self>> {
+        let user = user::Entity::find()
+            .filter(user::Column::Email.eq(email))
+            .one(db)
+            .await?;
+        match user {
+            Some(user) => Ok(Some(Self::for_user(db, user).await?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn can_access(&self, device: &device::Model) -> bool {
+        self.scope.allows(Some(device.user_id))
+    }
+
+    /// 可访问的设备ID，`None` 表示全部设备
+    pub async fn device_ids(&self, db: &DatabaseConnection) -> Result<Option<HashSet<i32>>, DbErr> {
+        let DeviceScope::Owner(user_id) = self.scope else {
+            return Ok(None);
+        };
+        let ids: Vec<i32> = device::Entity::find()
+            .select_only()
+            .column(device::Column::Id)
+            .filter(device::Column::UserId.eq(user_id))
+            .into_tuple()
+            .all(db)
+            .await?;
+        Ok(Some(ids.into_iter().collect()))
+    }
+}
+
+/// 读取 `Sec-WebSocket-Protocol: bearer, <token>` 中的凭证
+fn protocol_token(protocols: &str) -> Option<&str> {
+    let mut items = protocols.split(',').map(str::trim);
+    while let Some(item) = items.next() {
+        if item.eq_ignore_ascii_case(BEARER_PROTOCOL) {
+            return items.next().filter(|token| !token.is_empty());
+        }
+    }
+    None
+}
+
+/// 从 WebSocket / SSE 连接请求中提取凭证，依次检查查询参数、`Authorization` 和 `Sec-WebSocket-Protocol`
+///
+/// 浏览器建立这两种连接时无法设置请求头，普通 HTTP 接口只接受 [`bearer_token`]
+pub fn request_token(query: Option<&str>, headers: &HeaderMap) -> Option<String> {
+    let from_query = query.and_then(|query| {
+        url::form_urlencoded::parse(query.as_bytes())
+            .find(|(key, value)| (key == "token" || key == "api_key") && !value.is_empty())
+            .map(|(_, value)| value.into_owned())
+    });
+
+    from_query.or_else(|| bearer_token(headers)).or_else(|| {
+        headers
+            .get(header::SEC_WEBSOCKET_PROTOCOL)
+            .and_then(|value| value.to_str().ok())
+            .and_then(protocol_token)
+            .map(str::to_string)
+    })
+}
+
+/// 从 `Authorization: Bearer` 请求头提取凭证
+pub fn bearer_token(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+        .map(str::to_string)
+}
+
+/// 客户端是否通过子协议传递了凭证，此时握手响应需要带上 `bearer` 子协议
+pub fn uses_bearer_protocol(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::SEC_WEBSOCKET_PROTOCOL)
+        .and_then(|value| value.to_str().ok())
+        .and_then(protocol_token)
+        .is_some()
+}
+
+/// 校验 JWT 或 API Key，JWT 无效时按 API Key 查找用户
+async fn authenticate(
+    db: &DatabaseConnection,
+    jwt_secret: Option<&str>,
+    token: &str,
+) -> Result<Principal, String> {
+    let claims = jwt_secret.and_then(|secret| jwt::JWT::new(secret).validate(token).ok());
+    let condition = match claims {
+        Some(data) => user::Column::Email.eq(data.claims.pid),
+        None => user::Column::ApiKey.eq(token),
+    };
+
+    let user = user::Entity::find()
+        .filter(condition)
+        .one(db)
+        .await
+        .map_err(|e| format!("Database error: {}", e))?
+        .ok_or_else(|| "Invalid or expired token".to_string())?;
+
+    Principal::for_user(db, user)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// 已通过 JWT 或 API Key 认证的请求，凭证只从 `Authorization` 请求头读取，
+/// 避免凭证出现在普通接口的 URL 和访问日志中
+pub struct Authenticated(pub Principal);
+
+impl FromRequestParts<AppContext> for Authenticated {
+    type Rejection = loco_rs::Error;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        ctx: &AppContext,
+    ) -> Result<Self, Self::Rejection> {
+        let token = bearer_token(&parts.headers)
+            .ok_or_else(|| loco_rs::Error::Unauthorized("缺少访问凭证".to_string()))?;
+        let jwt_secret = ctx
+            .config
+            .get_jwt_config()
+            .ok()
+            .map(|jwt| jwt.secret.as_str());
+        authenticate(&ctx.db, jwt_secret, &token)
+            .await
+            .map(Self)
+            .map_err(loco_rs::Error::Unauthorized)
+    }
+}
+
+/// 实时连接的凭证校验
+pub struct AccessControl {
+    db: Arc<DatabaseConnection>,
+    jwt_secret: Option<String>,
+}
+
+impl AccessControl {
+    pub fn new(db: Arc<DatabaseConnection>, jwt_secret: Option<String>) -> Self {
+        Self { db, jwt_secret }
+    }
+
+    /// 校验 JWT 或 API Key
+    pub async fn authenticate(&self, token: &str) -> Result<Principal, String> {
+        authenticate(&self.db, self.jwt_secret.as_deref(), token).await
+    }
+
+    /// 检查用户能否访问指定设备
+    pub async fn can_access_device(
+        &self,
+        principal: &Principal,
+        device_id: i32,
+    ) -> Result<bool, DbErr> {
+        if principal.scope == DeviceScope::All {
+            return Ok(true);
+        }
+        let device = device::Entity::find_by_id(device_id).one(&*self.db).await?;
+        Ok(device.is_some_and(|device| principal.can_access(&device)))
+    }
+}
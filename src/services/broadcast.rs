@@ -1,81 +1,380 @@
 use axum::extract::ws::{Message, WebSocket};
 use futures_util::{SinkExt, StreamExt};
+use sea_orm::{DatabaseConnection, EntityTrait, QuerySelect};
+use serde::Serialize;
 use serde_json::json;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use tokio::sync::{broadcast, RwLock};
+use tokio::time::{Instant, MissedTickBehavior};
 use tracing::{error, info, warn};
 
-use crate::services::realtime_data::UnifiedRealtimeMessage;
+use crate::models::device;
+use crate::services::access::DeviceScope;
+use crate::services::client_queue::{
+    ClientQueue, ClientQueueStats, CoalesceKey, OverflowPolicy, PushOutcome,
+};
+use crate::services::geofence::GEOFENCE_MESSAGE_TYPE;
+use crate::services::realtime_data::{RealtimeDataService, UnifiedRealtimeMessage};
+use crate::services::realtime_protocol::{
+    ClientMessage, ServerMessage, StreamOptions, Subscription, SubscriptionTargets,
+};
+use crate::services::sse::EventLog;
+use crate::services::telemetry::TelemetrySnapshot;
+use crate::settings::BroadcastSettings;
 
 pub type ClientId = String;
 pub type DeviceId = i32;
 
+/// 检查限频后待发送遥测的间隔
+const TELEMETRY_FLUSH_INTERVAL: std::time::Duration = std::time::Duration::from_millis(50);
+
+/// 遥测消息在发送队列中的合并标识
+const TELEMETRY_MESSAGE_TYPE: &str = "telemetry";
+
+/// 单台设备的遥测推送状态
+#[derive(Debug, Clone, Default)]
+struct TelemetryThrottle {
+    last_sent: Option<Instant>,
+    /// 上次推送的字段，未变化时不重复推送
+    last_value: Option<serde_json::Value>,
+    /// 限频窗口内有未推送的更新
+    pending: bool,
+}
+
 #[derive(Debug, Clone)]
 pub struct ClientConnection {
     pub client_id: ClientId,
-    pub device_id: Option<DeviceId>, // 客户端关注的设备ID，None表示关注所有设备
-    pub sender: tokio::sync::mpsc::UnboundedSender<Message>,
+    pub subscription: Subscription, // 客户端订阅的设备、分组和推送选项
+    pub scope: DeviceScope,         // 客户端用户可访问的设备
+    pub queue: ClientQueue,         // 有界发送队列
+    pub connected_at: chrono::DateTime<chrono::Utc>,
+    throttle: HashMap<DeviceId, TelemetryThrottle>,
+}
+
+impl ClientConnection {
+    /// 更新推送选项，重新开始限频计时
+    fn set_options(&mut self, options: &StreamOptions) {
+        self.subscription.set_options(options);
+        self.throttle.clear();
+    }
+
+    /// 设备遥测更新时调用，所选字段有变化且已过发送间隔时返回要推送的消息，否则记为待发送
+    fn offer_telemetry(
+        &mut self,
+        device_id: DeviceId,
+        telemetry: &TelemetrySnapshot,
+        now: Instant,
+    ) -> Option<ServerMessage> {
+        let selected = self.subscription.select_fields(telemetry);
+        let interval = self.subscription.min_interval();
+        let throttle = self.throttle.entry(device_id).or_default();
+        if throttle.last_value.as_ref() == Some(&selected) {
+            throttle.pending = false;
+            return None;
+        }
+        if throttle
+            .last_sent
+            .is_some_and(|last_sent| now.duration_since(last_sent) < interval)
+        {
+            throttle.pending = true;
+            return None;
+        }
+
+        throttle.last_sent = Some(now);
+        throttle.last_value = Some(selected.clone());
+        throttle.pending = false;
+        Some(ServerMessage::Telemetry {
+            device_id,
+            telemetry: selected,
+            timestamp: chrono::Utc::now().to_rfc3339(),
+        })
+    }
+
+    /// 限频窗口已结束、有待发送更新的设备
+    fn due_devices(&self, now: Instant) -> impl Iterator<Item = DeviceId> + '_ {
+        let interval = self.subscription.min_interval();
+        self.throttle
+            .iter()
+            .filter(move |(_, throttle)| {
+                throttle.pending
+                    && throttle
+                        .last_sent
+                        .is_none_or(|last_sent| now.duration_since(last_sent) >= interval)
+            })
+            .map(|(device_id, _)| *device_id)
+    }
+}
+
+/// 单个客户端的推送指标
+#[derive(Debug, Clone, Serialize)]
+pub struct ClientMetrics {
+    pub client_id: ClientId,
+    pub connected_at: String,
+    #[serde(flatten)]
+    pub queue: ClientQueueStats,
+}
+
+/// 广播服务指标
+#[derive(Debug, Clone, Serialize)]
+pub struct BroadcastMetrics {
+    pub overflow_policy: OverflowPolicy,
+    pub client_queue_capacity: usize,
+    /// 广播接收端落后而跳过的消息数
+    pub lagged: u64,
+    pub clients: Vec<ClientMetrics>,
+}
+
+/// 接收广播消息，接收端落后时记录并跳过丢失的消息，发送端全部关闭后返回 `None`
+pub async fn recv_lossy<T: Clone>(receiver: &mut broadcast::Receiver<T>, name: &str) -> Option<T> {
+    loop {
+        match receiver.recv().await {
+            Ok(message) => return Some(message),
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                warn!("{} lagged, skipped {} messages", name, skipped);
+            }
+            Err(broadcast::error::RecvError::Closed) => return None,
+        }
+    }
+}
+
+/// 推送给客户端的实时数据消息，回放使用相同格式
+pub fn realtime_message(message: &UnifiedRealtimeMessage) -> serde_json::Value {
+    json!({
+        "type": "realtime_data",
+        "device_id": message.device_id,
+        "message_type": message.message_type,
+        "data": message.data,
+        "timestamp": message.timestamp.to_rfc3339()
+    })
+}
+
+/// 推送过滤使用的设备信息
+#[derive(Debug, Clone)]
+struct DeviceEntry {
+    owner: i32,
+    group: Option<String>,
 }
 
 pub struct BroadcastService {
     clients: Arc<RwLock<HashMap<ClientId, ClientConnection>>>,
+    /// 设备ID到所属用户和分组的映射
+    devices: Arc<RwLock<HashMap<DeviceId, DeviceEntry>>>,
+    realtime_service: Arc<RealtimeDataService>,
     unified_receiver: broadcast::Receiver<UnifiedRealtimeMessage>,
+    settings: BroadcastSettings,
+    lagged: Arc<AtomicU64>,
+    /// SSE 推送使用的带序号事件缓冲
+    events: Arc<EventLog>,
 }
 
 impl BroadcastService {
-    pub fn new(unified_receiver: broadcast::Receiver<UnifiedRealtimeMessage>) -> Self {
+    pub fn new(
+        unified_receiver: broadcast::Receiver<UnifiedRealtimeMessage>,
+        realtime_service: Arc<RealtimeDataService>,
+        settings: BroadcastSettings,
+    ) -> Self {
         Self {
             clients: Arc::new(RwLock::new(HashMap::new())),
+            devices: Arc::new(RwLock::new(HashMap::new())),
+            realtime_service,
             unified_receiver,
+            events: Arc::new(EventLog::new(settings.event_buffer_size)),
+            settings,
+            lagged: Arc::new(AtomicU64::new(0)),
         }
     }
 
+    /// 从数据库加载设备所属用户和分组
+    pub async fn load_devices(
+        &self,
+        db: &DatabaseConnection,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let rows: Vec<(DeviceId, i32, Option<String>)> = device::Entity::find()
+            .select_only()
+            .column(device::Column::Id)
+            .column(device::Column::UserId)
+            .column(device::Column::DeviceGroup)
+            .into_tuple()
+            .all(db)
+            .await?;
+
+        let devices: HashMap<DeviceId, DeviceEntry> = rows
+            .into_iter()
+            .map(|(device_id, owner, group)| (device_id, DeviceEntry { owner, group }))
+            .collect();
+        info!("Loaded broadcast index for {} devices", devices.len());
+        *self.devices.write().await = devices;
+        Ok(())
+    }
+
     /// 启动广播服务
     pub async fn start(&mut self) {
-        let clients = Arc::clone(&self.clients);
-
-        while let Ok(unified_msg) = self.unified_receiver.recv().await {
-            info!("Broadcasting message for device {}", unified_msg.device_id);
-
-            // 创建要发送的消息
-            let broadcast_message = json!({
-                "type": "realtime_data",
-                "device_id": unified_msg.device_id,
-                "message_type": unified_msg.message_type,
-                "data": unified_msg.data,
-                "timestamp": unified_msg.timestamp.to_rfc3339()
-            });
-
-            let message_text = broadcast_message.to_string();
-            let ws_message = Message::Text(message_text.into());
-
-            // 获取需要发送消息的客户端
-            let clients_to_send = {
-                let clients_guard = clients.read().await;
-                clients_guard
-                    .values()
-                    .filter(|client| {
-                        // 发送给关注此设备的客户端，或关注所有设备的客户端
-                        client.device_id.is_none()
-                            || client.device_id == Some(unified_msg.device_id)
-                    })
-                    .cloned()
-                    .collect::<Vec<_>>()
-            };
-
-            // 发送消息给所有相关客户端
-            for client in clients_to_send {
-                if let Err(e) = client.sender.send(ws_message.clone()) {
+        let mut flush = tokio::time::interval(TELEMETRY_FLUSH_INTERVAL);
+        flush.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            tokio::select! {
+                result = self.unified_receiver.recv() => match result {
+                    Ok(message) => self.dispatch(message).await,
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!("Broadcast service lagged, skipped {} messages", skipped);
+                        self.lagged.fetch_add(skipped, Ordering::Relaxed);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+                _ = flush.tick() => self.flush_telemetry().await,
+            }
+        }
+    }
+
+    /// 将一条实时数据分发给有权访问且订阅了此设备或其分组的客户端
+    async fn dispatch(&self, unified_msg: UnifiedRealtimeMessage) {
+        let device_id = unified_msg.device_id;
+        info!("Broadcasting message for device {}", device_id);
+
+        // 创建要发送的消息
+        let message_text = realtime_message(&unified_msg).to_string();
+        self.events.record(device_id, message_text.clone());
+        let ws_message = Message::Text(message_text.into());
+
+        let entry = self.devices.read().await.get(&device_id).cloned();
+        let owner = entry.as_ref().map(|entry| entry.owner);
+        let group = entry.and_then(|entry| entry.group);
+
+        // 设置了推送选项的客户端从合并后的遥测快照中取值
+        let wants_telemetry = self
+            .clients
+            .read()
+            .await
+            .values()
+            .any(|client| client.subscription.wants_telemetry());
+        let telemetry = if wants_telemetry {
+            self.realtime_service.get_telemetry(device_id).await
+        } else {
+            None
+        };
+
+        // 电子围栏事件不参与合并，订阅遥测的客户端也直接收到
+        let is_event = unified_msg.message_type == GEOFENCE_MESSAGE_TYPE;
+        let now = Instant::now();
+        let raw_key = (device_id, unified_msg.message_type.clone());
+        let telemetry_key = (device_id, TELEMETRY_MESSAGE_TYPE.to_string());
+        let outgoing = {
+            let mut clients = self.clients.write().await;
+            clients
+                .values_mut()
+                .filter(|client| {
+                    client.scope.allows(owner)
+                        && client.subscription.matches(device_id, group.as_deref())
+                })
+                .filter_map(|client| {
+                    let (message, key) = if is_event {
+                        (ws_message.clone(), None)
+                    } else if client.subscription.wants_telemetry() {
+                        let message =
+                            client.offer_telemetry(device_id, telemetry.as_ref()?, now)?;
+                        (message.to_ws_message(), Some(telemetry_key.clone()))
+                    } else {
+                        (ws_message.clone(), Some(raw_key.clone()))
+                    };
+                    Some((client.client_id.clone(), client.queue.clone(), message, key))
+                })
+                .collect::<Vec<_>>()
+        };
+
+        for (client_id, queue, message, key) in outgoing {
+            self.deliver(&client_id, &queue, message, key).await;
+        }
+    }
+
+    /// 推送限频窗口内合并的最新遥测
+    async fn flush_telemetry(&self) {
+        let now = Instant::now();
+        let due: BTreeSet<DeviceId> = self
+            .clients
+            .read()
+            .await
+            .values()
+            .flat_map(|client| client.due_devices(now))
+            .collect();
+        if due.is_empty() {
+            return;
+        }
+
+        let devices = self.devices.read().await.clone();
+        let mut snapshots = HashMap::new();
+        for device_id in &due {
+            if let Some(snapshot) = self.realtime_service.get_telemetry(*device_id).await {
+                snapshots.insert(*device_id, snapshot);
+            }
+        }
+
+        let outgoing = {
+            let mut clients = self.clients.write().await;
+            let mut outgoing = Vec::new();
+            for client in clients.values_mut() {
+                let due: Vec<DeviceId> = client.due_devices(now).collect();
+                for device_id in due {
+                    let entry = devices.get(&device_id);
+                    let subscribed = client.scope.allows(entry.map(|entry| entry.owner))
+                        && client
+                            .subscription
+                            .matches(device_id, entry.and_then(|entry| entry.group.as_deref()));
+                    let message = match snapshots.get(&device_id) {
+                        Some(snapshot) if subscribed => {
+                            client.offer_telemetry(device_id, snapshot, now)
+                        }
+                        _ => None,
+                    };
+                    match message {
+                        Some(message) => outgoing.push((
+                            client.client_id.clone(),
+                            client.queue.clone(),
+                            message.to_ws_message(),
+                            Some((device_id, TELEMETRY_MESSAGE_TYPE.to_string())),
+                        )),
+                        None => {
+                            if let Some(throttle) = client.throttle.get_mut(&device_id) {
+                                throttle.pending = false;
+                            }
+                        }
+                    }
+                }
+            }
+            outgoing
+        };
+
+        for (client_id, queue, message, key) in outgoing {
+            self.deliver(&client_id, &queue, message, key).await;
+        }
+    }
+
+    /// 写入客户端发送队列，队列满时按策略丢弃
+    async fn deliver(
+        &self,
+        client_id: &ClientId,
+        queue: &ClientQueue,
+        message: Message,
+        key: Option<CoalesceKey>,
+    ) {
+        match queue.push(message, key) {
+            PushOutcome::Queued => {}
+            PushOutcome::Dropped | PushOutcome::Coalesced => {
+                let stats = queue.stats();
+                // 每个客户端首次溢出时告警，之后只计数
+                if stats.dropped + stats.coalesced == 1 {
                     warn!(
-                        "Failed to send message to client {}: {}",
-                        client.client_id, e
+                        "Client {} is consuming too slowly, applying {:?} policy",
+                        client_id, self.settings.overflow_policy
                     );
-                    // 移除失效的客户端
-                    let mut clients_guard = clients.write().await;
-                    clients_guard.remove(&client.client_id);
                 }
             }
+            PushOutcome::Closed => {
+                // 移除失效的客户端
+                self.clients.write().await.remove(client_id);
+            }
         }
     }
 
@@ -84,13 +383,20 @@ impl BroadcastService {
         &self,
         client_id: ClientId,
         device_id: Option<DeviceId>,
-    ) -> tokio::sync::mpsc::UnboundedReceiver<Message> {
-        let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+        scope: DeviceScope,
+    ) -> ClientQueue {
+        let queue = ClientQueue::new(
+            self.settings.client_queue_capacity,
+            self.settings.overflow_policy,
+        );
 
         let client = ClientConnection {
             client_id: client_id.clone(),
-            device_id,
-            sender,
+            subscription: Subscription::for_device(device_id),
+            scope,
+            queue: queue.clone(),
+            connected_at: chrono::Utc::now(),
+            throttle: HashMap::new(),
         };
 
         {
@@ -99,17 +405,48 @@ impl BroadcastService {
         }
 
         info!(
-            "Added WebSocket client: {} (device_id: {:?})",
-            client_id, device_id
+            "Added WebSocket client: {} (device_id: {:?}, scope: {:?})",
+            client_id, device_id, scope
         );
-        receiver
+        queue
     }
 
     /// 移除WebSocket客户端
     pub async fn remove_client(&self, client_id: &ClientId) {
         let mut clients = self.clients.write().await;
-        if clients.remove(client_id).is_some() {
-            info!("Removed WebSocket client: {}", client_id);
+        if let Some(client) = clients.remove(client_id) {
+            client.queue.close();
+            let stats = client.queue.stats();
+            info!(
+                "Removed WebSocket client: {} (sent: {}, dropped: {}, coalesced: {})",
+                client_id, stats.sent, stats.dropped, stats.coalesced
+            );
+        }
+    }
+
+    /// SSE 推送使用的事件缓冲
+    pub fn event_log(&self) -> Arc<EventLog> {
+        Arc::clone(&self.events)
+    }
+
+    /// 各客户端的发送队列指标
+    pub async fn metrics(&self) -> BroadcastMetrics {
+        let clients = self.clients.read().await;
+        let mut clients: Vec<ClientMetrics> = clients
+            .values()
+            .map(|client| ClientMetrics {
+                client_id: client.client_id.clone(),
+                connected_at: client.connected_at.to_rfc3339(),
+                queue: client.queue.stats(),
+            })
+            .collect();
+        clients.sort_by(|a, b| a.connected_at.cmp(&b.connected_at));
+
+        BroadcastMetrics {
+            overflow_policy: self.settings.overflow_policy,
+            client_queue_capacity: self.settings.client_queue_capacity,
+            lagged: self.lagged.load(Ordering::Relaxed),
+            clients,
         }
     }
 
@@ -121,35 +458,176 @@ impl BroadcastService {
 
     /// 获取关注特定设备的客户端数量
     pub async fn get_device_client_count(&self, device_id: DeviceId) -> usize {
+        let entry = self.devices.read().await.get(&device_id).cloned();
+        let owner = entry.as_ref().map(|entry| entry.owner);
+        let group = entry.and_then(|entry| entry.group);
         let clients = self.clients.read().await;
         clients
             .values()
-            .filter(|client| client.device_id == Some(device_id) || client.device_id.is_none())
+            .filter(|client| {
+                client.scope.allows(owner)
+                    && client.subscription.matches(device_id, group.as_deref())
+            })
             .count()
     }
 
+    /// 处理客户端发送的协议消息，回复写入该客户端的发送队列
+    pub async fn handle_client_message(&self, client_id: &ClientId, text: &str) {
+        let message = match ClientMessage::parse(text) {
+            Ok(message) => message,
+            Err(message) => {
+                self.reply(client_id, |_| vec![ServerMessage::Error { message }])
+                    .await;
+                return;
+            }
+        };
+
+        match message {
+            ClientMessage::Ping { id } => {
+                let pong = ServerMessage::Pong {
+                    id,
+                    timestamp: chrono::Utc::now().to_rfc3339(),
+                };
+                self.reply(client_id, |_| vec![pong]).await;
+            }
+            ClientMessage::Subscribe {
+                mut targets,
+                options,
+            } => {
+                info!(
+                    "Client {} subscribed to {:?} with {:?}",
+                    client_id, targets, options
+                );
+                let Some(scope) = self
+                    .clients
+                    .read()
+                    .await
+                    .get(client_id)
+                    .map(|client| client.scope)
+                else {
+                    return;
+                };
+
+                // 无权访问的设备不加入订阅
+                let devices = self.devices.read().await.clone();
+                let denied = targets.retain_devices(|device_id| {
+                    scope.allows(devices.get(&device_id).map(|entry| entry.owner))
+                });
+                let denied = (!denied.is_empty()).then(|| ServerMessage::Error {
+                    message: format!("No access to devices: {:?}", denied),
+                });
+                if targets.is_empty() && options.is_empty() {
+                    self.reply(client_id, |_| denied.into_iter().collect())
+                        .await;
+                    return;
+                }
+
+                // 先读取快照，再在同一把锁内更新订阅并入队，之后的增量一定排在快照之后
+                let snapshots = self.snapshots(&targets, scope).await;
+                self.reply(client_id, |client| {
+                    if !options.is_empty() {
+                        client.set_options(&options);
+                    }
+                    let subscription = &mut client.subscription;
+                    if !targets.is_empty() {
+                        subscription.subscribe(&targets);
+                    }
+                    let mut replies: Vec<ServerMessage> = denied.into_iter().collect();
+                    replies.push(subscription.to_message());
+                    replies.extend(snapshots);
+                    replies
+                })
+                .await;
+            }
+            ClientMessage::Unsubscribe(targets) => {
+                info!("Client {} unsubscribed from {:?}", client_id, targets);
+                self.reply(client_id, |client| {
+                    client.subscription.unsubscribe(&targets);
+                    vec![client.subscription.to_message()]
+                })
+                .await;
+            }
+        }
+    }
+
+    /// 在持有客户端表写锁时更新订阅并发送回复
+    async fn reply(
+        &self,
+        client_id: &ClientId,
+        update: impl FnOnce(&mut ClientConnection) -> Vec<ServerMessage>,
+    ) {
+        let mut clients = self.clients.write().await;
+        let Some(client) = clients.get_mut(client_id) else {
+            return;
+        };
+        for message in update(client) {
+            if client.queue.push(message.to_ws_message(), None) == PushOutcome::Closed {
+                break;
+            }
+        }
+    }
+
+    /// 新订阅设备的当前状态
+    ///
+    /// 显式指定的设备总是返回快照；按分组或全部订阅时只返回已有状态且有权访问的设备。
+    async fn snapshots(
+        &self,
+        targets: &SubscriptionTargets,
+        scope: DeviceScope,
+    ) -> Vec<ServerMessage> {
+        let devices = self.devices.read().await.clone();
+        let states = self.realtime_service.get_all_device_states().await;
+        let telemetry = self.realtime_service.get_all_telemetry().await;
+
+        let mut device_ids: BTreeSet<DeviceId> = targets.devices().collect();
+        let groups: Vec<&str> = targets.groups().collect();
+        for device_id in states.keys().chain(telemetry.keys()) {
+            let entry = devices.get(device_id);
+            if !scope.allows(entry.map(|entry| entry.owner)) {
+                continue;
+            }
+            let in_group = entry
+                .and_then(|entry| entry.group.as_deref())
+                .is_some_and(|group| groups.contains(&group));
+            if targets.all || in_group {
+                device_ids.insert(*device_id);
+            }
+        }
+
+        let timestamp = chrono::Utc::now().to_rfc3339();
+        device_ids
+            .into_iter()
+            .map(|device_id| ServerMessage::Snapshot {
+                device_id,
+                group: devices
+                    .get(&device_id)
+                    .and_then(|entry| entry.group.clone()),
+                state: states.get(&device_id).cloned(),
+                telemetry: telemetry.get(&device_id).cloned().map(Box::new),
+                timestamp: timestamp.clone(),
+            })
+            .collect()
+    }
+
     /// 处理WebSocket连接
     pub async fn handle_websocket(
         &self,
         websocket: WebSocket,
         client_id: ClientId,
         device_id: Option<DeviceId>,
+        scope: DeviceScope,
     ) {
-        let mut receiver = self.add_client(client_id.clone(), device_id).await;
+        let queue = self.add_client(client_id.clone(), device_id, scope).await;
         let (mut ws_sender, mut ws_receiver) = websocket.split();
 
         // 发送欢迎消息
-        let welcome_msg = json!({
-            "type": "welcome",
-            "client_id": client_id,
-            "device_id": device_id,
-            "message": "Connected to realtime data stream"
-        });
+        let welcome_msg = ServerMessage::Welcome {
+            client_id: client_id.clone(),
+            device_id,
+            message: "Connected to realtime data stream".to_string(),
+        };
 
-        if let Err(e) = ws_sender
-            .send(Message::Text(welcome_msg.to_string().into()))
-            .await
-        {
+        if let Err(e) = ws_sender.send(welcome_msg.to_ws_message()).await {
             error!("Failed to send welcome message: {}", e);
             self.remove_client(&client_id).await;
             return;
@@ -159,7 +637,7 @@ impl BroadcastService {
         let client_id_clone = client_id.clone();
         let broadcast_service = self.clone();
         let send_task = tokio::spawn(async move {
-            while let Some(message) = receiver.recv().await {
+            while let Some(message) = queue.pop().await {
                 if let Err(e) = ws_sender.send(message).await {
                     error!("Failed to send message to WebSocket: {}", e);
                     break;
@@ -175,43 +653,10 @@ impl BroadcastService {
             while let Some(msg) = ws_receiver.next().await {
                 match msg {
                     Ok(Message::Text(text)) => {
-                        // 处理客户端发送的文本消息
-                        info!("Received message from client {}: {}", client_id_clone, text);
-
-                        // 可以在这里处理客户端的控制消息，比如切换关注的设备
-                        if let Ok(parsed) = serde_json::from_str::<serde_json::Value>(&text) {
-                            if let Some(msg_type) = parsed.get("type").and_then(|v| v.as_str()) {
-                                match msg_type {
-                                    "subscribe_device" => {
-                                        if let Some(new_device_id) =
-                                            parsed.get("device_id").and_then(|v| v.as_i64())
-                                        {
-                                            // 更新客户端关注的设备
-                                            // 这里可以实现动态切换设备的逻辑
-                                            info!(
-                                                "Client {} wants to subscribe to device {}",
-                                                client_id_clone, new_device_id
-                                            );
-                                        }
-                                    }
-                                    "ping" => {
-                                        // 响应ping消息
-                                        let pong_msg = json!({
-                                            "type": "pong",
-                                            "timestamp": chrono::Utc::now().to_rfc3339()
-                                        });
-                                        // 这里需要发送响应，但由于架构限制，暂时记录日志
-                                        info!("Received ping from client {}", client_id_clone);
-                                    }
-                                    _ => {
-                                        warn!(
-                                            "Unknown message type from client {}: {}",
-                                            client_id_clone, msg_type
-                                        );
-                                    }
-                                }
-                            }
-                        }
+                        // 回复与实时数据经同一发送队列，由发送任务写出
+                        broadcast_service_clone
+                            .handle_client_message(&client_id_clone, &text)
+                            .await;
                     }
                     Ok(Message::Close(_)) => {
                         info!("WebSocket connection closed by client {}", client_id_clone);
@@ -250,7 +695,12 @@ impl Clone for BroadcastService {
     fn clone(&self) -> Self {
         Self {
             clients: Arc::clone(&self.clients),
+            devices: Arc::clone(&self.devices),
+            realtime_service: Arc::clone(&self.realtime_service),
             unified_receiver: self.unified_receiver.resubscribe(),
+            settings: self.settings.clone(),
+            lagged: Arc::clone(&self.lagged),
+            events: Arc::clone(&self.events),
         }
     }
 }
@@ -0,0 +1,168 @@
+//! WebSocket 客户端发送队列
+//!
+//! 每个客户端一个有界队列，由连接的发送任务逐条写出。客户端消费过慢导致队列写满时按配置的策略丢弃：
+//! - `drop_oldest`：丢弃最早的一条消息；
+//! - `coalesce_latest`：同一设备同一来源的实时数据只保留最新一条，没有可合并的消息时丢弃最早的一条。
+
+use axum::extract::ws::Message;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+
+/// 队列写满时的处理策略
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverflowPolicy {
+    #[default]
+    DropOldest,
+    CoalesceLatest,
+}
+
+/// 可合并消息的标识：设备ID和数据来源
+pub type CoalesceKey = (i32, String);
+
+/// 入队结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PushOutcome {
+    Queued,
+    /// 队列已满，丢弃了最早的消息
+    Dropped,
+    /// 队列已满，替换了同一设备的旧消息
+    Coalesced,
+    /// 连接已关闭
+    Closed,
+}
+
+/// 队列统计
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ClientQueueStats {
+    pub queued: usize,
+    pub capacity: usize,
+    pub sent: u64,
+    pub dropped: u64,
+    pub coalesced: u64,
+}
+
+#[derive(Debug)]
+struct Queued {
+    key: Option<CoalesceKey>,
+    message: Message,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    items: VecDeque<Queued>,
+    closed: bool,
+    sent: u64,
+    dropped: u64,
+    coalesced: u64,
+}
+
+#[derive(Debug)]
+struct Shared {
+    state: Mutex<State>,
+    notify: Notify,
+    capacity: usize,
+    policy: OverflowPolicy,
+}
+
+/// 单个客户端的有界发送队列，克隆后共享同一队列
+#[derive(Debug, Clone)]
+pub struct ClientQueue {
+    shared: Arc<Shared>,
+}
+
+impl ClientQueue {
+    pub fn new(capacity: usize, policy: OverflowPolicy) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                state: Mutex::new(State::default()),
+                notify: Notify::new(),
+                capacity: capacity.max(1),
+                policy,
+            }),
+        }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.shared
+            .state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// 入队，`key` 为空的消息（协议回复等）不参与合并
+    pub fn push(&self, message: Message, key: Option<CoalesceKey>) -> PushOutcome {
+        let mut state = self.state();
+        if state.closed {
+            return PushOutcome::Closed;
+        }
+
+        let mut outcome = PushOutcome::Queued;
+        if state.items.len() >= self.shared.capacity {
+            let same_key = match (&key, self.shared.policy) {
+                (Some(key), OverflowPolicy::CoalesceLatest) => state
+                    .items
+                    .iter()
+                    .position(|item| item.key.as_ref() == Some(key)),
+                _ => None,
+            };
+            match same_key {
+                Some(index) => {
+                    state.items.remove(index);
+                    state.coalesced += 1;
+                    outcome = PushOutcome::Coalesced;
+                }
+                None => {
+                    state.items.pop_front();
+                    state.dropped += 1;
+                    outcome = PushOutcome::Dropped;
+                }
+            }
+        }
+        state.items.push_back(Queued { key, message });
+        drop(state);
+
+        self.shared.notify.notify_one();
+        outcome
+    }
+
+    /// 取出下一条消息，队列关闭后返回 `None`
+    pub async fn pop(&self) -> Option<Message> {
+        loop {
+            let notified = self.shared.notify.notified();
+            {
+                let mut state = self.state();
+                if state.closed {
+                    return None;
+                }
+                if let Some(item) = state.items.pop_front() {
+                    state.sent += 1;
+                    return Some(item.message);
+                }
+            }
+            notified.await;
+        }
+    }
+
+    /// 关闭队列，丢弃未发送的消息
+    pub fn close(&self) {
+        let mut state = self.state();
+        state.closed = true;
+        state.items.clear();
+        drop(state);
+        self.shared.notify.notify_one();
+    }
+
+    pub fn stats(&self) -> ClientQueueStats {
+        let state = self.state();
+        ClientQueueStats {
+            queued: state.items.len(),
+            capacity: self.shared.capacity,
+            sent: state.sent,
+            dropped: state.dropped,
+            coalesced: state.coalesced,
+        }
+    }
+}
@@ -4,12 +4,18 @@ use std::sync::Arc;
 use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::{broadcast, oneshot, RwLock, mpsc};
 use tokio_tungstenite::{
-    accept_async, connect_async, tungstenite::Message, MaybeTlsStream, WebSocketStream,
+    accept_async, accept_hdr_async, connect_async,
+    tungstenite::handshake::server::{Callback, ErrorResponse, Request, Response},
+    tungstenite::protocol::{frame::coding::CloseCode, CloseFrame},
+    tungstenite::Message,
+    MaybeTlsStream, WebSocketStream,
 };
 use tracing::{error, info, warn};
 
 use uuid::Uuid;
 
+use crate::services::access::{self, AccessControl};
+use crate::services::broadcast::recv_lossy;
 use crate::services::mqtt_service::MqttService;
 use crate::services::realtime_data::{RealtimeDataService, UnifiedRealtimeMessage};
 
@@ -48,6 +54,8 @@ pub struct DeviceWebSocketProxyService {
     mqtt_service: Arc<MqttService>,
     /// 统一消息广播接收器
     unified_receiver: broadcast::Receiver<UnifiedRealtimeMessage>,
+    /// 前端连接的凭证校验
+    access: Arc<AccessControl>,
 }
 
 impl DeviceWebSocketProxyService {
@@ -55,6 +63,7 @@ impl DeviceWebSocketProxyService {
         realtime_service: Arc<RealtimeDataService>,
         mqtt_service: Arc<MqttService>,
         unified_receiver: broadcast::Receiver<UnifiedRealtimeMessage>,
+        access: Arc<AccessControl>,
     ) -> Self {
         Self {
             device_proxies: Arc::new(RwLock::new(HashMap::new())),
@@ -66,6 +75,7 @@ impl DeviceWebSocketProxyService {
             realtime_service,
             mqtt_service,
             unified_receiver,
+            access,
         }
     }
 
@@ -140,6 +150,7 @@ impl DeviceWebSocketProxyService {
         let device_id = proxy_info.device_id;
         let unified_receiver = self.unified_receiver.resubscribe();
         let device_command_senders = self.device_command_senders.clone();
+        let access = self.access.clone();
         // 消息现在通过统一广播系统发送，无需单独的发送器
 
         // 创建关闭信号
@@ -154,27 +165,22 @@ impl DeviceWebSocketProxyService {
                             Ok((stream, addr)) => {
                                 info!("New client connected to device {} proxy from {}", device_id, addr);
 
-                                match accept_async(stream).await {
-                                    Ok(ws_stream) => {
-                                        // 处理客户端连接
-                                        let device_command_senders_clone = device_command_senders.clone();
-                                        let unified_receiver_clone = unified_receiver.resubscribe();
-
-                                        tokio::spawn(async move {
-                                            if let Err(e) = handle_proxy_client_connection(
-                                                ws_stream,
-                                                device_id,
-                                                device_command_senders_clone,
-                                                unified_receiver_clone,
-                                            ).await {
-                                                error!("Error handling proxy client connection: {}", e);
-                                            }
-                                        });
+                                // 处理客户端连接（握手和认证在独立任务中完成，不阻塞监听）
+                                let device_command_senders_clone = device_command_senders.clone();
+                                let unified_receiver_clone = unified_receiver.resubscribe();
+                                let access_clone = access.clone();
+
+                                tokio::spawn(async move {
+                                    if let Err(e) = handle_proxy_client_connection(
+                                        stream,
+                                        device_id,
+                                        access_clone,
+                                        device_command_senders_clone,
+                                        unified_receiver_clone,
+                                    ).await {
+                                        error!("Error handling proxy client connection: {}", e);
                                     }
-                                    Err(e) => {
-                                        error!("Failed to accept WebSocket connection: {}", e);
-                                    }
-                                }
+                                });
                             }
                             Err(e) => {
                                 error!("Failed to accept connection: {}", e);
@@ -378,7 +384,11 @@ impl DeviceWebSocketProxyService {
                         info!("Received from device {}: {}", device_id, text);
 
                         // 解析无人机数据并存储到数据库
-                        if let Some(ws_msg) = crate::services::realtime_data::RealtimeDataService::parse_websocket_message(device_id, &text) {
+                        if crate::services::nmea::is_nmea(&text) {
+                            if let Err(e) = realtime_service.process_nmea_text(device_id, &text).await {
+                                error!("Failed to process NMEA data: {}", e);
+                            }
+                        } else if let Some(ws_msg) = crate::services::realtime_data::RealtimeDataService::parse_websocket_message(device_id, &text) {
                             if let Err(e) = realtime_service.process_websocket_message(ws_msg).await {
                                 error!("Failed to process WebSocket message: {}", e);
                             }
@@ -643,7 +653,15 @@ async fn handle_device_server_connection(
                 info!("Received from device {}: {}", device_id, text);
 
                 // 解析无人机数据并存储到数据库
-                if let Some(ws_msg) = RealtimeDataService::parse_websocket_message(device_id, &text)
+                if crate::services::nmea::is_nmea(&text) {
+                    if let Err(e) = realtime_service.process_nmea_text(device_id, &text).await {
+                        error!(
+                            "Failed to process NMEA data from device {}: {}",
+                            device_id, e
+                        );
+                    }
+                } else if let Some(ws_msg) =
+                    RealtimeDataService::parse_websocket_message(device_id, &text)
                 {
                     if let Err(e) = realtime_service.process_websocket_message(ws_msg).await {
                         error!(
@@ -704,20 +722,75 @@ async fn handle_device_server_connection(
     Ok(())
 }
 
+/// 握手时读取凭证，缺少凭证的连接直接以 401 拒绝
+struct HandshakeToken<'a>(&'a mut Option<String>);
+
+impl Callback for HandshakeToken<'_> {
+    fn on_request(
+        self,
+        request: &Request,
+        mut response: Response,
+    ) -> Result<Response, ErrorResponse> {
+        *self.0 = access::request_token(request.uri().query(), request.headers());
+        if self.0.is_none() {
+            let mut rejection = ErrorResponse::new(Some("Missing access token".to_string()));
+            *rejection.status_mut() = axum::http::StatusCode::UNAUTHORIZED;
+            return Err(rejection);
+        }
+        if access::uses_bearer_protocol(request.headers()) {
+            response.headers_mut().insert(
+                axum::http::header::SEC_WEBSOCKET_PROTOCOL,
+                axum::http::HeaderValue::from_static(access::BEARER_PROTOCOL),
+            );
+        }
+        Ok(response)
+    }
+}
+
 /// 处理前端客户端连接
 async fn handle_proxy_client_connection(
-    ws_stream: WebSocketStream<TcpStream>,
+    stream: TcpStream,
     device_id: i32,
+    access: Arc<AccessControl>,
     device_command_senders: Arc<RwLock<HashMap<i32, mpsc::UnboundedSender<String>>>>,
     mut unified_receiver: broadcast::Receiver<UnifiedRealtimeMessage>,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut token = None;
+    let mut ws_stream = accept_hdr_async(stream, HandshakeToken(&mut token)).await?;
+
+    // 校验凭证和设备权限，失败时以 1008 关闭连接
+    let denied = match access
+        .authenticate(token.as_deref().unwrap_or_default())
+        .await
+    {
+        Ok(principal) => match access.can_access_device(&principal, device_id).await {
+            Ok(true) => None,
+            Ok(false) => Some(format!("No access to device {}", device_id)),
+            Err(e) => Some(format!("Database error: {}", e)),
+        },
+        Err(e) => Some(e),
+    };
+    if let Some(reason) = denied {
+        warn!(
+            "Rejected frontend client for device {} proxy: {}",
+            device_id, reason
+        );
+        let frame = CloseFrame {
+            code: CloseCode::Policy,
+            reason: reason.into(),
+        };
+        ws_stream.close(Some(frame)).await?;
+        return Ok(());
+    }
+
     let (mut ws_sender, mut ws_receiver) = ws_stream.split();
 
     info!("Frontend client connected to device {} proxy", device_id);
 
     // 启动消息转发任务（设备 -> 前端）
     let forward_task = tokio::spawn(async move {
-        while let Ok(unified_msg) = unified_receiver.recv().await {
+        while let Some(unified_msg) = recv_lossy(&mut unified_receiver, "Device proxy client").await
+        {
             if unified_msg.device_id == device_id {
                 // 格式化消息
                 let message = match unified_msg.message_type.as_str() {
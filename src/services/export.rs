@@ -0,0 +1,447 @@
+//! 遥测数据导出
+//!
+//! 按 `(received_at, id)` 正序分批读取 `device_realtime_data`，边读边写入响应流，
+//! 不在内存中缓存整个时间范围。支持 CSV（可指定 GBK 等编码）、NDJSON、GPX 航迹和
+//! 带高度拉伸的 KML 路径。
+
+use axum::body::Bytes;
+use encoding_rs::Encoding;
+use sea_orm::{
+    ColumnTrait, Condition, ConnectionTrait, DatabaseConnection, QueryFilter, QueryOrder,
+    QuerySelect,
+};
+use serde_json::{json, Map, Value as JsonValue};
+use tokio::sync::mpsc;
+use tracing::error;
+
+use crate::models::device_realtime_data;
+use crate::services::history::{self, HistoryFilter};
+use crate::services::telemetry::{self, websocket_fields};
+
+/// 每批读取的行数
+const EXPORT_BATCH_SIZE: u64 = 1000;
+
+/// 未指定字段时 CSV 导出的列
+const DEFAULT_CSV_FIELDS: [&str; 15] = [
+    "latitude",
+    "longitude",
+    "altitude",
+    "relative_altitude",
+    "roll",
+    "pitch",
+    "yaw",
+    "heading",
+    "ground_speed",
+    "climb_rate",
+    "battery_remaining",
+    "battery_voltage",
+    "satellites",
+    "hdop",
+    "flight_mode",
+];
+
+/// 导出格式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Csv,
+    Ndjson,
+    Gpx,
+    Kml,
+}
+
+impl ExportFormat {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "csv" => Some(Self::Csv),
+            "ndjson" | "jsonl" => Some(Self::Ndjson),
+            "gpx" => Some(Self::Gpx),
+            "kml" => Some(Self::Kml),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Ndjson => "ndjson",
+            Self::Gpx => "gpx",
+            Self::Kml => "kml",
+        }
+    }
+
+    pub fn content_type(self, encoding: &'static Encoding) -> String {
+        match self {
+            Self::Csv => format!("text/csv; charset={}", encoding.name()),
+            Self::Ndjson => "application/x-ndjson".to_string(),
+            Self::Gpx => "application/gpx+xml".to_string(),
+            Self::Kml => "application/vnd.google-earth.kml+xml".to_string(),
+        }
+    }
+}
+
+/// 导出参数
+#[derive(Debug, Clone)]
+pub struct ExportOptions {
+    pub format: ExportFormat,
+    /// CSV 输出编码，其它格式固定为 UTF-8
+    pub encoding: &'static Encoding,
+    /// CSV 列，为空时使用默认列并附带原始数据
+    pub fields: Vec<String>,
+    /// GPX/KML 中的航迹名称
+    pub name: String,
+}
+
+/// 一行数据转换为统一字段，WebSocket 的 field:value 会额外展开为统一字段名，原始字段名仍可使用
+fn normalized_fields(row: &device_realtime_data::Model) -> Map<String, JsonValue> {
+    let mut fields = Map::new();
+    if let JsonValue::Object(content) = &row.data_content {
+        for (key, value) in content {
+            fields.insert(key.clone(), value.clone());
+            if let ("websocket", JsonValue::String(text)) = (row.data_type.as_str(), value) {
+                fields.extend(websocket_fields(key, text));
+            }
+        }
+    }
+    fields
+}
+
+/// 按 `a.b` 路径读取字段
+fn lookup<'a>(fields: &'a Map<String, JsonValue>, path: &str) -> Option<&'a JsonValue> {
+    let mut segments = path.split('.');
+    let mut value = fields.get(segments.next()?)?;
+    for segment in segments {
+        value = value.get(segment)?;
+    }
+    Some(value)
+}
+
+fn csv_cell(value: Option<&JsonValue>) -> String {
+    match value {
+        None | Some(JsonValue::Null) => String::new(),
+        Some(JsonValue::String(text)) => text.clone(),
+        Some(other) => other.to_string(),
+    }
+}
+
+pub fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// 航迹点
+struct TrackPoint {
+    latitude: f64,
+    longitude: f64,
+    altitude: Option<f64>,
+    relative_altitude: Option<f64>,
+}
+
+impl TrackPoint {
+    fn from_fields(fields: &Map<String, JsonValue>) -> Option<Self> {
+        let latitude = fields.get("latitude").and_then(telemetry::as_f64)?;
+        let longitude = fields.get("longitude").and_then(telemetry::as_f64)?;
+        // 未定位时部分设备上报 0,0
+        if !(-90.0..=90.0).contains(&latitude)
+            || !(-180.0..=180.0).contains(&longitude)
+            || (latitude == 0.0 && longitude == 0.0)
+        {
+            return None;
+        }
+        Some(Self {
+            latitude,
+            longitude,
+            altitude: fields.get("altitude").and_then(telemetry::as_f64),
+            relative_altitude: fields.get("relative_altitude").and_then(telemetry::as_f64),
+        })
+    }
+}
+
+/// KML 高度基准，由第一个航迹点决定
+#[derive(Clone, Copy)]
+enum KmlAltitude {
+    Absolute,
+    RelativeToGround,
+}
+
+/// 按格式逐批生成输出
+struct Formatter {
+    options: ExportOptions,
+    columns: Vec<String>,
+    started: bool,
+    kml_altitude: Option<KmlAltitude>,
+}
+
+impl Formatter {
+    fn new(options: ExportOptions) -> Self {
+        let columns = if options.fields.is_empty() {
+            DEFAULT_CSV_FIELDS.iter().map(|f| f.to_string()).collect()
+        } else {
+            options.fields.clone()
+        };
+        Self {
+            options,
+            columns,
+            started: false,
+            kml_altitude: None,
+        }
+    }
+
+    fn header(&mut self) -> Result<Vec<u8>, csv::Error> {
+        Ok(match self.options.format {
+            ExportFormat::Csv => {
+                let mut writer = csv::Writer::from_writer(Vec::new());
+                let mut header = vec!["received_at".to_string(), "data_type".to_string()];
+                header.extend(self.columns.iter().cloned());
+                if self.options.fields.is_empty() {
+                    header.push("data_content".to_string());
+                }
+                writer.write_record(&header)?;
+                let text = String::from_utf8(writer.into_inner().unwrap_or_default())
+                    .unwrap_or_default();
+                // Excel 需要 BOM 才能识别 UTF-8
+                let bom = if self.options.encoding == encoding_rs::UTF_8 {
+                    "\u{feff}"
+                } else {
+                    ""
+                };
+                self.encode(&format!("{}{}", bom, text))
+            }
+            ExportFormat::Ndjson => Vec::new(),
+            ExportFormat::Gpx => format!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+                 <gpx version=\"1.1\" creator=\"tiantong-uav-vcsc\" xmlns=\"http://www.topografix.com/GPX/1/1\">\n\
+                 <trk><name>{}</name><trkseg>\n",
+                xml_escape(&self.options.name)
+            )
+            .into_bytes(),
+            // 坐标列表在第一个点确定高度基准后开始
+            ExportFormat::Kml => format!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+                 <kml xmlns=\"http://www.opengis.net/kml/2.2\">\n<Document>\n<name>{}</name>\n\
+                 <Style id=\"track\"><LineStyle><color>ff0000ff</color><width>3</width></LineStyle>\
+                 <PolyStyle><color>7f00ff00</color></PolyStyle></Style>\n\
+                 <Placemark><name>{}</name><styleUrl>#track</styleUrl>\n\
+                 <LineString><extrude>1</extrude><tessellate>1</tessellate>",
+                xml_escape(&self.options.name),
+                xml_escape(&self.options.name)
+            )
+            .into_bytes(),
+        })
+    }
+
+    fn encode(&self, text: &str) -> Vec<u8> {
+        let (bytes, _, _) = self.options.encoding.encode(text);
+        bytes.into_owned()
+    }
+
+    fn batch(&mut self, rows: &[device_realtime_data::Model]) -> Result<Vec<u8>, csv::Error> {
+        let mut out = if self.started {
+            Vec::new()
+        } else {
+            self.started = true;
+            self.header()?
+        };
+
+        match self.options.format {
+            ExportFormat::Csv => {
+                let mut writer = csv::Writer::from_writer(Vec::new());
+                for row in rows {
+                    let fields = normalized_fields(row);
+                    let mut record = vec![row.received_at.to_rfc3339(), row.data_type.clone()];
+                    record.extend(
+                        self.columns
+                            .iter()
+                            .map(|column| csv_cell(lookup(&fields, column))),
+                    );
+                    if self.options.fields.is_empty() {
+                        record.push(row.data_content.to_string());
+                    }
+                    writer.write_record(&record)?;
+                }
+                let text =
+                    String::from_utf8(writer.into_inner().unwrap_or_default()).unwrap_or_default();
+                out.extend(self.encode(&text));
+            }
+            ExportFormat::Ndjson => {
+                for row in rows {
+                    let line = json!({
+                        "id": row.id,
+                        "device_id": row.device_id,
+                        "data_type": row.data_type,
+                        "data_content": row.data_content,
+                        "received_at": row.received_at.to_rfc3339(),
+                    });
+                    out.extend(line.to_string().into_bytes());
+                    out.push(b'\n');
+                }
+            }
+            ExportFormat::Gpx => {
+                for row in rows {
+                    let Some(point) = TrackPoint::from_fields(&normalized_fields(row)) else {
+                        continue;
+                    };
+                    out.extend(
+                        format!(
+                            "<trkpt lat=\"{}\" lon=\"{}\">",
+                            point.latitude, point.longitude
+                        )
+                        .into_bytes(),
+                    );
+                    if let Some(altitude) = point.altitude.or(point.relative_altitude) {
+                        out.extend(format!("<ele>{}</ele>", altitude).into_bytes());
+                    }
+                    out.extend(
+                        format!(
+                            "<time>{}</time></trkpt>\n",
+                            row.received_at
+                                .with_timezone(&chrono::Utc)
+                                .to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
+                        )
+                        .into_bytes(),
+                    );
+                }
+            }
+            ExportFormat::Kml => {
+                for row in rows {
+                    let Some(point) = TrackPoint::from_fields(&normalized_fields(row)) else {
+                        continue;
+                    };
+                    let mode = match self.kml_altitude {
+                        Some(mode) => mode,
+                        None => {
+                            let mode = if point.relative_altitude.is_some() {
+                                KmlAltitude::RelativeToGround
+                            } else {
+                                KmlAltitude::Absolute
+                            };
+                            self.kml_altitude = Some(mode);
+                            let name = match mode {
+                                KmlAltitude::Absolute => "absolute",
+                                KmlAltitude::RelativeToGround => "relativeToGround",
+                            };
+                            out.extend(
+                                format!("<altitudeMode>{}</altitudeMode>\n<coordinates>\n", name)
+                                    .into_bytes(),
+                            );
+                            mode
+                        }
+                    };
+                    let altitude = match mode {
+                        KmlAltitude::Absolute => point.altitude.or(point.relative_altitude),
+                        KmlAltitude::RelativeToGround => point.relative_altitude.or(point.altitude),
+                    };
+                    out.extend(
+                        format!(
+                            "{},{},{}\n",
+                            point.longitude,
+                            point.latitude,
+                            altitude.unwrap_or(0.0)
+                        )
+                        .into_bytes(),
+                    );
+                }
+            }
+        }
+
+        Ok(out)
+    }
+
+    fn footer(&mut self) -> Result<Vec<u8>, csv::Error> {
+        let mut out = if self.started {
+            Vec::new()
+        } else {
+            self.started = true;
+            self.header()?
+        };
+        match self.options.format {
+            ExportFormat::Csv | ExportFormat::Ndjson => {}
+            ExportFormat::Gpx => out.extend(b"</trkseg></trk>\n</gpx>\n"),
+            ExportFormat::Kml => {
+                if self.kml_altitude.is_none() {
+                    out.extend(b"\n<coordinates>\n");
+                }
+                out.extend(b"</coordinates></LineString></Placemark>\n</Document>\n</kml>\n");
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// 启动导出任务，返回输出数据块的接收端
+///
+/// 通道容量很小，客户端读取变慢时数据库读取也随之暂停。
+pub fn spawn_export(
+    db: DatabaseConnection,
+    device_id: i32,
+    filter: HistoryFilter,
+    options: ExportOptions,
+) -> mpsc::Receiver<Result<Bytes, std::io::Error>> {
+    let (sender, receiver) = mpsc::channel(4);
+
+    tokio::spawn(async move {
+        let mut formatter = Formatter::new(options);
+        let base = history::filtered_select(db.get_database_backend(), device_id, &filter)
+            .order_by_asc(device_realtime_data::Column::ReceivedAt)
+            .order_by_asc(device_realtime_data::Column::Id);
+        let mut last: Option<(chrono::DateTime<chrono::FixedOffset>, i64)> = None;
+
+        loop {
+            let mut select = base.clone();
+            if let Some((received_at, id)) = last {
+                select = select.filter(
+                    Condition::any()
+                        .add(device_realtime_data::Column::ReceivedAt.gt(received_at))
+                        .add(
+                            Condition::all()
+                                .add(device_realtime_data::Column::ReceivedAt.eq(received_at))
+                                .add(device_realtime_data::Column::Id.gt(id)),
+                        ),
+                );
+            }
+
+            let rows = match select.limit(EXPORT_BATCH_SIZE).all(&db).await {
+                Ok(rows) => rows,
+                Err(e) => {
+                    error!("Telemetry export for device {} failed: {}", device_id, e);
+                    let _ = sender.send(Err(std::io::Error::other(e))).await;
+                    return;
+                }
+            };
+
+            let done = (rows.len() as u64) < EXPORT_BATCH_SIZE;
+            if let Some(row) = rows.last() {
+                last = Some((row.received_at, row.id));
+            }
+
+            let chunk = if done {
+                formatter.batch(&rows).and_then(|mut chunk| {
+                    chunk.extend(formatter.footer()?);
+                    Ok(chunk)
+                })
+            } else {
+                formatter.batch(&rows)
+            };
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    error!("Telemetry export for device {} failed: {}", device_id, e);
+                    let _ = sender.send(Err(std::io::Error::other(e))).await;
+                    return;
+                }
+            };
+
+            // 客户端断开后停止读取
+            if !chunk.is_empty() && sender.send(Ok(Bytes::from(chunk))).await.is_err() {
+                return;
+            }
+            if done {
+                return;
+            }
+        }
+    });
+
+    receiver
+}
@@ -0,0 +1,881 @@
+//! 编队任务
+//!
+//! 编队任务（[`fleet_mission::Model`]）拆分为多台设备各自执行的子任务（`task.fleet_mission_id`）：
+//! - 创建时可以为每台设备分别指定任务参数，也可以给出一组设备和一份参数，由 [`split_parameters`]
+//!   将其中连续的航线步骤（`waypoint` / `survey` / `orbit`）展开为一条航线后按顺序连续切分给各设备，
+//!   其它步骤（起飞、降落等）每台设备都执行；
+//! - 开始时先校验所有子任务（航线、续航、电子围栏、设备空闲），全部通过后同时开始，任一子任务未能
+//!   开始时取消已开始的子任务，编队任务失败；
+//! - 编队任务执行中子任务未完成就结束（失败、被中止或因电子围栏返航）时按 `failure_policy` 处理：
+//!   `continue` 其它设备继续执行；`abort_all` 中止其它设备的子任务；`reassign` 将该设备未完成的航点
+//!   生成新的子任务（`reassigned_from`）交给其它设备，空闲设备立即执行，否则在剩余步骤最少的设备
+//!   完成当前子任务后执行；
+//! - 所有子任务结束后，每个子任务都已完成或其未完成的航点已由接管的子任务完成时编队任务完成，否则失败。
+
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, QueryOrder,
+    Set,
+};
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::models::fleet_mission::{self, FailurePolicy};
+use crate::models::task::{
+    self, TaskParameters, TaskProgress, TaskStatus, TaskStep, STEP_COMPLETED,
+};
+use crate::services::broadcast::recv_lossy;
+use crate::services::mission::{self, CameraAction, Waypoint};
+use crate::services::task_executor::{parse_parameters, StepAction, TaskExecutor, TaskFinished};
+use crate::services::task_state::{self, Actor};
+
+const INTERRUPTED_BY_RESTART: &str = "Interrupted by server restart";
+
+/// 编队任务协调服务
+pub struct FleetCoordinator {
+    db: Arc<DatabaseConnection>,
+    executor: Arc<TaskExecutor>,
+    /// 串行处理编队任务的开始、停止和子任务结束事件
+    lock: Mutex<()>,
+}
+
+impl FleetCoordinator {
+    pub fn new(db: Arc<DatabaseConnection>, executor: Arc<TaskExecutor>) -> Self {
+        Self {
+            db,
+            executor,
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// 启动子任务结束事件的处理循环
+    pub fn start(self: &Arc<Self>) {
+        let coordinator = Arc::clone(self);
+        let mut finished = self.executor.subscribe_finished();
+        tokio::spawn(async move {
+            while let Some(finished) = recv_lossy(&mut finished, "Fleet coordinator").await {
+                if let Err(e) = coordinator.handle_finished(&finished).await {
+                    error!(
+                        "Failed to handle finished task {} for fleet mission: {}",
+                        finished.task_id, e
+                    );
+                }
+            }
+        });
+    }
+
+    /// 服务重启前执行中的编队任务已中断，标记为失败并取消排队的子任务
+    pub async fn fail_interrupted(&self) -> Result<u64, DbErr> {
+        let interrupted = fleet_mission::Entity::find()
+            .filter(fleet_mission::Column::Status.eq(TaskStatus::Running.to_string()))
+            .all(&*self.db)
+            .await?;
+
+        let count = interrupted.len() as u64;
+        for mission in interrupted {
+            for task in self.sub_tasks(mission.id).await? {
+                if task.status == TaskStatus::Pending.to_string() {
+                    self.cancel_pending(&task, INTERRUPTED_BY_RESTART).await;
+                }
+            }
+            self.finish(
+                mission,
+                TaskStatus::Failed,
+                Some(INTERRUPTED_BY_RESTART.to_string()),
+            )
+            .await?;
+        }
+        Ok(count)
+    }
+
+    /// 同时开始编队任务的所有子任务，任一子任务无法开始时不起飞任何设备
+    pub async fn start_mission(&self, mission_id: i32, actor: Actor) -> Result<(), String> {
+        let _guard = self.lock.lock().await;
+        let mission = self.find(mission_id).await?;
+        if mission.status != TaskStatus::Pending.to_string() {
+            return Err(format!("Fleet mission is {}", mission.status));
+        }
+        let tasks: Vec<task::Model> = self
+            .sub_tasks(mission.id)
+            .await
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .filter(|task| task.status == TaskStatus::Pending.to_string())
+            .collect();
+        if tasks.is_empty() {
+            return Err("Fleet mission has no pending sub-tasks".to_string());
+        }
+
+        // 先校验所有子任务，避免部分设备起飞后才发现其它设备无法执行
+        for task in &tasks {
+            let error = |e: String| format!("Sub-task {}: {}", task.name, e);
+            if self.executor.is_device_busy(task.device_id).await {
+                return Err(error(format!(
+                    "Device {} is already running another task",
+                    task.device_id
+                )));
+            }
+            let plan = self
+                .executor
+                .plan(task.device_id, task.parameters.as_ref())
+                .await
+                .map_err(error)?;
+            plan.estimate.check().map_err(error)?;
+            self.executor
+                .check_geofences(task.device_id, &plan)
+                .await
+                .map_err(error)?;
+        }
+
+        let mut started = Vec::new();
+        for task in &tasks {
+            if let Err(e) = self.executor.start(task, actor).await {
+                let message = format!("Sub-task {} failed to start: {}", task.name, e);
+                for &task_id in &started {
+                    self.executor
+                        .stop(
+                            task_id,
+                            TaskStatus::Aborted,
+                            Actor::System,
+                            Some(message.clone()),
+                        )
+                        .await;
+                }
+                for task in tasks.iter().filter(|task| !started.contains(&task.id)) {
+                    self.cancel_pending(task, &message).await;
+                }
+                self.finish(mission, TaskStatus::Failed, Some(message.clone()))
+                    .await
+                    .map_err(|e| e.to_string())?;
+                return Err(message);
+            }
+            started.push(task.id);
+        }
+
+        let mut active: fleet_mission::ActiveModel = mission.into();
+        active.status = Set(TaskStatus::Running.to_string());
+        active.error = Set(None);
+        active.start_time = Set(Some(now()));
+        active.updated_at = Set(now());
+        active.update(&*self.db).await.map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    /// 停止编队任务，执行中的子任务按 `status`（cancelled 或 aborted）停止，排队的子任务取消
+    pub async fn stop_mission(
+        &self,
+        mission_id: i32,
+        status: TaskStatus,
+        actor: Actor,
+        reason: Option<String>,
+    ) -> Result<(), String> {
+        let _guard = self.lock.lock().await;
+        let mission = self.find(mission_id).await?;
+        if mission.status != TaskStatus::Pending.to_string()
+            && mission.status != TaskStatus::Running.to_string()
+        {
+            return Err(format!("Fleet mission is {}", mission.status));
+        }
+
+        for task in self
+            .sub_tasks(mission.id)
+            .await
+            .map_err(|e| e.to_string())?
+        {
+            match task.status.parse::<TaskStatus>() {
+                Ok(TaskStatus::Running | TaskStatus::Paused) => {
+                    self.executor
+                        .stop(task.id, status, actor, reason.clone())
+                        .await;
+                }
+                Ok(TaskStatus::Pending) => {
+                    if let Err(e) = task_state::transition(
+                        &self.db,
+                        task.id,
+                        TaskStatus::Cancelled,
+                        actor,
+                        reason.clone(),
+                        task::ActiveModel {
+                            ..Default::default()
+                        },
+                    )
+                    .await
+                    {
+                        warn!("Failed to cancel sub-task {}: {}", task.id, e);
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.finish(mission, status, reason)
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    async fn handle_finished(&self, finished: &TaskFinished) -> Result<(), DbErr> {
+        let _guard = self.lock.lock().await;
+        let Some(task) = task::Entity::find_by_id(finished.task_id)
+            .one(&*self.db)
+            .await?
+        else {
+            return Ok(());
+        };
+        let Some(mission_id) = task.fleet_mission_id else {
+            return Ok(());
+        };
+        let Some(mission) = fleet_mission::Entity::find_by_id(mission_id)
+            .one(&*self.db)
+            .await?
+        else {
+            return Ok(());
+        };
+        // 编队任务已停止时子任务的结束由停止操作引起，无需处理
+        if mission.status != TaskStatus::Running.to_string() {
+            return Ok(());
+        }
+
+        if finished.status == TaskStatus::Completed {
+            self.start_queued(&mission, finished.device_id).await?;
+        } else {
+            let policy = mission
+                .failure_policy
+                .parse()
+                .unwrap_or(FailurePolicy::Continue);
+            info!(
+                "Sub-task {} of fleet mission {} ended as {}, applying policy {}",
+                task.id,
+                mission.id,
+                finished.status.to_string(),
+                policy.as_str()
+            );
+            match policy {
+                FailurePolicy::Continue => {}
+                FailurePolicy::AbortAll => {
+                    let reason = format!("Sub-task {} {}", task.name, finished.status.to_string());
+                    self.abort_all(&mission, &reason).await?;
+                    return self.finish(mission, TaskStatus::Failed, Some(reason)).await;
+                }
+                FailurePolicy::Reassign => {
+                    // 失败设备排队的子任务也一并交给其它设备
+                    let mut unfinished = vec![task];
+                    for queued in self.sub_tasks(mission.id).await? {
+                        if queued.device_id == finished.device_id
+                            && queued.status == TaskStatus::Pending.to_string()
+                        {
+                            let reason = format!("Device {} failed", finished.device_id);
+                            if let Some(queued) = self.cancel_pending(&queued, &reason).await {
+                                unfinished.push(queued);
+                            }
+                        }
+                    }
+                    for task in unfinished {
+                        if let Err(e) = self.reassign(&mission, &task).await {
+                            warn!(
+                                "Failed to reassign sub-task {} of fleet mission {}: {}",
+                                task.id, mission.id, e
+                            );
+                        }
+                    }
+                }
+            }
+        }
+
+        self.check_finished(mission).await
+    }
+
+    /// 中止其它设备执行中的子任务并取消排队的子任务
+    async fn abort_all(&self, mission: &fleet_mission::Model, reason: &str) -> Result<(), DbErr> {
+        for task in self.sub_tasks(mission.id).await? {
+            match task.status.parse::<TaskStatus>() {
+                Ok(TaskStatus::Running | TaskStatus::Paused) => {
+                    self.executor
+                        .stop(
+                            task.id,
+                            TaskStatus::Aborted,
+                            Actor::System,
+                            Some(reason.to_string()),
+                        )
+                        .await;
+                }
+                Ok(TaskStatus::Pending) => {
+                    self.cancel_pending(&task, reason).await;
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// 设备完成子任务后开始其排队的下一个子任务
+    async fn start_queued(
+        &self,
+        mission: &fleet_mission::Model,
+        device_id: i32,
+    ) -> Result<(), DbErr> {
+        let next = task::Entity::find()
+            .filter(task::Column::FleetMissionId.eq(mission.id))
+            .filter(task::Column::DeviceId.eq(device_id))
+            .filter(task::Column::Status.eq(TaskStatus::Pending.to_string()))
+            .order_by_asc(task::Column::Id)
+            .one(&*self.db)
+            .await?;
+        if let Some(next) = next {
+            self.start_sub_task(&next).await;
+        }
+        Ok(())
+    }
+
+    /// 将子任务未完成的航点生成新的子任务，交给编队中其它设备执行
+    async fn reassign(
+        &self,
+        mission: &fleet_mission::Model,
+        failed: &task::Model,
+    ) -> Result<(), String> {
+        let (original, _) = parse_parameters(failed.parameters.as_ref())?;
+        let remaining = self.remaining_waypoints(failed).await?;
+        if remaining.is_empty() {
+            return Err("No unfinished waypoints to reassign".to_string());
+        }
+
+        let sub_tasks = self
+            .sub_tasks(mission.id)
+            .await
+            .map_err(|e| e.to_string())?;
+        let device_id = pick_device(&sub_tasks).ok_or("No other device available to take over")?;
+
+        let mut steps = vec![TaskStep {
+            step_type: "takeoff".to_string(),
+            parameters: json!({ "altitude": remaining[0].0.altitude }),
+            timeout: None,
+        }];
+        steps.extend(
+            remaining
+                .iter()
+                .map(|(waypoint, timeout)| waypoint_step(waypoint, Some(timeout.as_secs() as u32))),
+        );
+        steps.push(TaskStep {
+            step_type: "landing".to_string(),
+            parameters: json!({}),
+            timeout: None,
+        });
+        let parameters = TaskParameters {
+            steps,
+            timeout: None,
+            retry_count: original.retry_count,
+        };
+
+        let now = now();
+        let task = task::ActiveModel {
+            uuid: Set(Uuid::new_v4()),
+            name: Set(failed.name.clone()),
+            description: Set(Some(format!(
+                "接管子任务 {} 未完成的 {} 个航点",
+                failed.name,
+                remaining.len()
+            ))),
+            task_type: Set(failed.task_type.clone()),
+            status: Set(TaskStatus::Pending.to_string()),
+            device_id: Set(device_id),
+            user_id: Set(failed.user_id),
+            parameters: Set(serde_json::to_value(&parameters).ok()),
+            fleet_mission_id: Set(Some(mission.id)),
+            reassigned_from: Set(Some(failed.id)),
+            created_at: Set(now),
+            updated_at: Set(now),
+            ..Default::default()
+        }
+        .insert(&*self.db)
+        .await
+        .map_err(|e| e.to_string())?;
+        task_state::record_created(
+            &*self.db,
+            &task,
+            Actor::System,
+            Some(format!(
+                "Reassigned {} waypoints from task {}",
+                remaining.len(),
+                failed.uuid
+            )),
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+        info!(
+            "Reassigned {} waypoints of task {} to device {} as task {}",
+            remaining.len(),
+            failed.id,
+            device_id,
+            task.id
+        );
+
+        // 设备正在执行其它子任务时排队，完成后再开始
+        if !self.executor.is_device_busy(device_id).await {
+            self.start_sub_task(&task).await;
+        }
+        Ok(())
+    }
+
+    /// 子任务中尚未完成的航点及各自的超时
+    async fn remaining_waypoints(
+        &self,
+        task: &task::Model,
+    ) -> Result<Vec<(Waypoint, Duration)>, String> {
+        let plan = self
+            .executor
+            .plan(task.device_id, task.parameters.as_ref())
+            .await?;
+        let progress: Option<TaskProgress> = task
+            .progress
+            .clone()
+            .and_then(|value| serde_json::from_value(value).ok());
+        Ok(plan
+            .steps
+            .into_iter()
+            .enumerate()
+            .filter_map(|(index, step)| {
+                let StepAction::Waypoint(waypoint) = step.action else {
+                    return None;
+                };
+                let completed = progress
+                    .as_ref()
+                    .and_then(|progress| progress.steps.get(index))
+                    .is_some_and(|step| step.status == STEP_COMPLETED);
+                (!completed).then_some((waypoint, step.timeout))
+            })
+            .collect())
+    }
+
+    /// 开始排队的子任务，无法开始时标记为失败
+    async fn start_sub_task(&self, task: &task::Model) {
+        if let Err(e) = self.executor.start(task, Actor::System).await {
+            warn!("Failed to start sub-task {}: {}", task.id, e);
+            if let Err(e) = task_state::transition(
+                &self.db,
+                task.id,
+                TaskStatus::Failed,
+                Actor::System,
+                Some(e),
+                task::ActiveModel {
+                    end_time: Set(Some(now())),
+                    ..Default::default()
+                },
+            )
+            .await
+            {
+                warn!("Failed to mark sub-task {} as failed: {}", task.id, e);
+            }
+        }
+    }
+
+    /// 所有子任务结束后汇总编队任务的结果
+    async fn check_finished(&self, mission: fleet_mission::Model) -> Result<(), DbErr> {
+        let sub_tasks = self.sub_tasks(mission.id).await?;
+        if sub_tasks.iter().any(|task| !is_terminal(&task.status)) {
+            return Ok(());
+        }
+
+        let uncovered: Vec<&task::Model> = sub_tasks
+            .iter()
+            .filter(|task| !is_covered(task, &sub_tasks))
+            .collect();
+        if uncovered.is_empty() {
+            return self.finish(mission, TaskStatus::Completed, None).await;
+        }
+        let names: Vec<String> = uncovered
+            .iter()
+            .map(|task| format!("{} ({})", task.name, task.status))
+            .collect();
+        let error = format!(
+            "{} of {} sub-tasks did not complete: {}",
+            uncovered.len(),
+            sub_tasks.len(),
+            names.join(", ")
+        );
+        self.finish(mission, TaskStatus::Failed, Some(error)).await
+    }
+
+    async fn finish(
+        &self,
+        mission: fleet_mission::Model,
+        status: TaskStatus,
+        error: Option<String>,
+    ) -> Result<(), DbErr> {
+        info!("Fleet mission {} {}", mission.id, status.to_string());
+        let mut active: fleet_mission::ActiveModel = mission.into();
+        active.status = Set(status.to_string());
+        active.error = Set(error);
+        active.end_time = Set(Some(now()));
+        active.updated_at = Set(now());
+        active.update(&*self.db).await?;
+        Ok(())
+    }
+
+    /// 取消排队的子任务，返回取消后的子任务
+    async fn cancel_pending(&self, task: &task::Model, reason: &str) -> Option<task::Model> {
+        match task_state::transition(
+            &self.db,
+            task.id,
+            TaskStatus::Cancelled,
+            Actor::System,
+            Some(reason.to_string()),
+            task::ActiveModel {
+                ..Default::default()
+            },
+        )
+        .await
+        {
+            Ok(task) => Some(task),
+            Err(e) => {
+                warn!("Failed to cancel sub-task {}: {}", task.id, e);
+                None
+            }
+        }
+    }
+
+    async fn find(&self, mission_id: i32) -> Result<fleet_mission::Model, String> {
+        fleet_mission::Entity::find_by_id(mission_id)
+            .one(&*self.db)
+            .await
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| "Fleet mission not found".to_string())
+    }
+
+    async fn sub_tasks(&self, mission_id: i32) -> Result<Vec<task::Model>, DbErr> {
+        task::Entity::find()
+            .filter(task::Column::FleetMissionId.eq(mission_id))
+            .order_by_asc(task::Column::Id)
+            .all(&*self.db)
+            .await
+    }
+}
+
+/// 将任务参数拆分给 `count` 台设备：连续的航线步骤展开为一条航线后按顺序连续切分，其它步骤每台设备都执行
+pub fn split_parameters(
+    parameters: &TaskParameters,
+    count: usize,
+) -> Result<Vec<TaskParameters>, String> {
+    if count == 0 {
+        return Err("No devices to split the mission across".to_string());
+    }
+
+    let mut parts: Vec<Vec<TaskStep>> = vec![Vec::new(); count];
+    // 当前连续航线步骤展开的航点及所在步骤的超时，起始步骤序号
+    let mut route: Vec<(Waypoint, Option<u32>)> = Vec::new();
+    let mut route_start = 0;
+    let mut geographic = false;
+    for (index, step) in parameters.steps.iter().enumerate() {
+        if mission::is_geographic(&step.step_type) {
+            geographic = true;
+            if route.is_empty() {
+                route_start = index;
+            }
+            let waypoints =
+                mission::waypoints(step).map_err(|e| format!("Step {}: {}", index + 1, e))?;
+            route.extend(
+                waypoints
+                    .into_iter()
+                    .map(|waypoint| (waypoint, step.timeout)),
+            );
+            continue;
+        }
+        split_route(&mut parts, std::mem::take(&mut route), route_start)?;
+        for part in &mut parts {
+            part.push(step.clone());
+        }
+    }
+    split_route(&mut parts, route, route_start)?;
+    if !geographic {
+        return Err("Task has no waypoint, survey or orbit steps to split".to_string());
+    }
+
+    Ok(parts
+        .into_iter()
+        .map(|steps| TaskParameters {
+            steps,
+            timeout: parameters.timeout,
+            retry_count: parameters.retry_count,
+        })
+        .collect())
+}
+
+/// 将一条航线按顺序连续切分，航点数不能整除时前面的设备多分一个
+fn split_route(
+    parts: &mut [Vec<TaskStep>],
+    route: Vec<(Waypoint, Option<u32>)>,
+    route_start: usize,
+) -> Result<(), String> {
+    if route.is_empty() {
+        return Ok(());
+    }
+    let count = parts.len();
+    if route.len() < count {
+        return Err(format!(
+            "Route starting at step {} has {} waypoints, cannot be split across {} devices",
+            route_start + 1,
+            route.len(),
+            count
+        ));
+    }
+    let (size, extra) = (route.len() / count, route.len() % count);
+    let mut route = route.into_iter();
+    for (i, part) in parts.iter_mut().enumerate() {
+        part.extend(
+            route
+                .by_ref()
+                .take(size + usize::from(i < extra))
+                .map(|(waypoint, timeout)| waypoint_step(&waypoint, timeout)),
+        );
+    }
+    Ok(())
+}
+
+fn waypoint_step(waypoint: &Waypoint, timeout: Option<u32>) -> TaskStep {
+    let camera_action = match waypoint.camera_action {
+        CameraAction::None => "none",
+        CameraAction::Photo => "photo",
+    };
+    TaskStep {
+        step_type: "waypoint".to_string(),
+        parameters: json!({
+            "latitude": waypoint.latitude,
+            "longitude": waypoint.longitude,
+            "altitude": waypoint.altitude,
+            "speed": waypoint.speed,
+            "loiter": waypoint.loiter,
+            "camera_action": camera_action,
+        }),
+        timeout,
+    }
+}
+
+/// 选择接管航点的设备：排除有失败子任务的设备，没有未结束子任务的设备优先，其次是剩余步骤最少的设备
+fn pick_device(sub_tasks: &[task::Model]) -> Option<i32> {
+    let failed: HashSet<i32> = sub_tasks
+        .iter()
+        .filter(|task| {
+            is_terminal(&task.status) && task.status != TaskStatus::Completed.to_string()
+        })
+        .map(|task| task.device_id)
+        .collect();
+
+    let mut load: HashMap<i32, usize> = HashMap::new();
+    for task in sub_tasks
+        .iter()
+        .filter(|task| !failed.contains(&task.device_id))
+    {
+        let remaining = load.entry(task.device_id).or_default();
+        if !is_terminal(&task.status) {
+            *remaining += remaining_steps(task);
+        }
+    }
+    load.into_iter()
+        .min_by_key(|(device_id, remaining)| (*remaining, *device_id))
+        .map(|(device_id, _)| device_id)
+}
+
+/// 子任务尚未完成的步骤数，未开始时按参数中的步骤数估计
+fn remaining_steps(task: &task::Model) -> usize {
+    let progress: Option<TaskProgress> = task
+        .progress
+        .clone()
+        .and_then(|value| serde_json::from_value(value).ok());
+    match progress {
+        Some(progress) if task.status != TaskStatus::Pending.to_string() => progress
+            .steps
+            .iter()
+            .filter(|step| step.status != STEP_COMPLETED)
+            .count(),
+        _ => parse_parameters(task.parameters.as_ref())
+            .map(|(_, steps)| steps.len())
+            .unwrap_or(0),
+    }
+}
+
+/// 任务或编队任务是否已结束（完成、失败、取消或中止）
+pub fn is_terminal(status: &str) -> bool {
+    !matches!(
+        status.parse::<TaskStatus>(),
+        Ok(TaskStatus::Pending | TaskStatus::Running | TaskStatus::Paused)
+    )
+}
+
+/// 子任务已完成，或其未完成的航点已由接管的子任务完成
+fn is_covered(task: &task::Model, sub_tasks: &[task::Model]) -> bool {
+    task.status == TaskStatus::Completed.to_string()
+        || sub_tasks
+            .iter()
+            .filter(|other| other.reassigned_from == Some(task.id))
+            .any(|other| is_covered(other, sub_tasks))
+}
+
+fn now() -> chrono::DateTime<chrono::FixedOffset> {
+    chrono::Utc::now().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(step_type: &str, parameters: serde_json::Value) -> TaskStep {
+        TaskStep {
+            step_type: step_type.to_string(),
+            parameters,
+            timeout: None,
+        }
+    }
+
+    fn waypoint(latitude: f64) -> TaskStep {
+        step(
+            "waypoint",
+            json!({ "latitude": latitude, "longitude": 116.3, "altitude": 50.0 }),
+        )
+    }
+
+    fn plan(steps: Vec<TaskStep>) -> TaskParameters {
+        TaskParameters {
+            steps,
+            timeout: Some(600),
+            retry_count: Some(1),
+        }
+    }
+
+    fn latitudes(steps: &[TaskStep]) -> Vec<f64> {
+        steps
+            .iter()
+            .filter(|step| step.step_type == "waypoint")
+            .map(|step| step.parameters["latitude"].as_f64().unwrap())
+            .collect()
+    }
+
+    fn sub_task(id: i32, device_id: i32, status: TaskStatus, steps: usize) -> task::Model {
+        let parameters = plan(
+            (0..steps)
+                .map(|i| waypoint(39.9 + i as f64 * 0.001))
+                .collect(),
+        );
+        task::Model {
+            id,
+            uuid: Uuid::new_v4(),
+            name: format!("sub task {}", id),
+            description: None,
+            task_type: "manual".to_string(),
+            status: status.to_string(),
+            device_id,
+            user_id: 1,
+            parameters: Some(serde_json::to_value(parameters).unwrap()),
+            progress: None,
+            template_id: None,
+            template_version: None,
+            simulation: None,
+            fleet_mission_id: Some(1),
+            reassigned_from: None,
+            start_time: None,
+            end_time: None,
+            created_at: now(),
+            updated_at: now(),
+        }
+    }
+
+    #[test]
+    fn uneven_split_gives_extra_waypoints_to_first_devices() {
+        let route = (0..8).map(|i| waypoint(39.0 + f64::from(i))).collect();
+        let parts = split_parameters(&plan(route), 3).unwrap();
+
+        assert_eq!(
+            parts
+                .iter()
+                .map(|part| latitudes(&part.steps))
+                .collect::<Vec<_>>(),
+            vec![
+                vec![39.0, 40.0, 41.0],
+                vec![42.0, 43.0, 44.0],
+                vec![45.0, 46.0],
+            ]
+        );
+        assert!(parts
+            .iter()
+            .all(|part| part.timeout == Some(600) && part.retry_count == Some(1)));
+    }
+
+    #[test]
+    fn route_shorter_than_device_count_is_rejected() {
+        let route = vec![waypoint(39.0), waypoint(39.1)];
+        let error = split_parameters(&plan(route), 3).unwrap_err();
+        assert!(error.contains("2 waypoints"), "{}", error);
+        assert!(split_parameters(&plan(vec![waypoint(39.0)]), 0).is_err());
+    }
+
+    #[test]
+    fn non_geographic_steps_are_copied_to_every_device() {
+        let steps = vec![
+            step("takeoff", json!({ "altitude": 30.0 })),
+            waypoint(39.0),
+            waypoint(39.1),
+            step("photo", json!({})),
+            waypoint(39.2),
+            waypoint(39.3),
+            step("landing", json!({})),
+        ];
+        let parts = split_parameters(&plan(steps), 2).unwrap();
+
+        for (part, expected) in parts.iter().zip([[39.0, 39.2], [39.1, 39.3]]) {
+            let types: Vec<&str> = part
+                .steps
+                .iter()
+                .map(|step| step.step_type.as_str())
+                .collect();
+            assert_eq!(
+                types,
+                ["takeoff", "waypoint", "photo", "waypoint", "landing"]
+            );
+            assert_eq!(latitudes(&part.steps), expected);
+        }
+
+        let error = split_parameters(&plan(vec![step("takeoff", json!({}))]), 2).unwrap_err();
+        assert!(error.contains("no waypoint"), "{}", error);
+    }
+
+    #[test]
+    fn pick_device_skips_failed_devices_and_prefers_least_loaded() {
+        let sub_tasks = vec![
+            sub_task(1, 10, TaskStatus::Failed, 4),
+            sub_task(2, 20, TaskStatus::Running, 5),
+            sub_task(3, 30, TaskStatus::Pending, 2),
+            // 设备 10 的其它子任务仍在执行，但该设备已有失败的子任务
+            sub_task(4, 10, TaskStatus::Pending, 1),
+        ];
+        assert_eq!(pick_device(&sub_tasks), Some(30));
+
+        // 子任务都已结束的设备最空闲
+        let mut sub_tasks = sub_tasks;
+        sub_tasks.push(sub_task(5, 40, TaskStatus::Completed, 9));
+        assert_eq!(pick_device(&sub_tasks), Some(40));
+
+        assert_eq!(
+            pick_device(&[sub_task(1, 10, TaskStatus::Aborted, 3)]),
+            None
+        );
+    }
+
+    #[test]
+    fn is_covered_follows_reassignment_chain() {
+        let failed = sub_task(1, 10, TaskStatus::Failed, 4);
+        let mut takeover = sub_task(2, 20, TaskStatus::Aborted, 2);
+        takeover.reassigned_from = Some(1);
+        let mut second_takeover = sub_task(3, 30, TaskStatus::Running, 1);
+        second_takeover.reassigned_from = Some(2);
+
+        let mut sub_tasks = vec![failed.clone(), takeover, second_takeover];
+        assert!(!is_covered(&failed, &sub_tasks));
+
+        sub_tasks[2].status = TaskStatus::Completed.to_string();
+        assert!(is_covered(&failed, &sub_tasks));
+        assert!(!is_covered(
+            &sub_task(4, 40, TaskStatus::Failed, 1),
+            &sub_tasks
+        ));
+    }
+}
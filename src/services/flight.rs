@@ -0,0 +1,474 @@
+//! 飞行架次识别
+//!
+//! 根据遥测快照把设备数据切分为飞行架次：解锁或离地时开始，上锁、落地超时或数据中断时结束。
+//! 只解锁未离地的记录不会写入数据库。架次开始时若设备有运行中的任务，则关联该任务。
+
+use chrono::FixedOffset;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::models::{flight, task};
+use crate::services::telemetry::{as_bool, TelemetrySnapshot, Timestamp};
+use crate::settings::FlightSettings;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, Set,
+};
+
+/// 样本队列容量
+const QUEUE_CAPACITY: usize = 10_000;
+/// 相邻两点推算速度超过该值（米/秒）时视为定位跳变，不计入航程
+const MAX_PLAUSIBLE_SPEED: f64 = 150.0;
+/// 地球平均半径（米）
+pub const EARTH_RADIUS_M: f64 = 6_371_008.8;
+
+pub const STATUS_IN_PROGRESS: &str = "in_progress";
+pub const STATUS_COMPLETED: &str = "completed";
+
+/// 两点间大圆距离（米）
+pub fn haversine_distance(from: (f64, f64), to: (f64, f64)) -> f64 {
+    let (lat1, lon1) = (from.0.to_radians(), from.1.to_radians());
+    let (lat2, lon2) = (to.0.to_radians(), to.1.to_radians());
+    let a = ((lat2 - lat1) / 2.0).sin().powi(2)
+        + lat1.cos() * lat2.cos() * ((lon2 - lon1) / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_M * a.sqrt().asin()
+}
+
+/// 从快照中提取的架次判断所需字段
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    device_id: i32,
+    timestamp: Timestamp,
+    armed: Option<bool>,
+    /// 设备自报的飞行状态（大疆 `isfly`）
+    flying: Option<bool>,
+    /// 本次更新的位置 (纬度, 经度)
+    position: Option<(f64, f64)>,
+    relative_altitude: Option<f64>,
+    amsl_altitude: Option<f64>,
+    ground_speed: Option<f64>,
+    battery: Option<f64>,
+}
+
+impl Sample {
+    fn from_snapshot(device_id: i32, snapshot: &TelemetrySnapshot, timestamp: Timestamp) -> Self {
+        let altitude = snapshot.altitude.as_ref().map(|a| &a.value);
+        Self {
+            device_id,
+            timestamp,
+            armed: snapshot.armed.as_ref().map(|a| a.value),
+            flying: snapshot
+                .extensions
+                .get("flying")
+                .and_then(|v| as_bool(&v.value)),
+            // 只取本次更新的位置，避免重复计算航程
+            position: snapshot
+                .position
+                .as_ref()
+                .filter(|p| p.updated_at == timestamp)
+                .map(|p| (p.value.latitude, p.value.longitude)),
+            relative_altitude: altitude.and_then(|a| a.relative),
+            amsl_altitude: altitude.and_then(|a| a.amsl),
+            ground_speed: snapshot
+                .velocity
+                .as_ref()
+                .and_then(|v| v.value.ground_speed),
+            battery: snapshot.battery.as_ref().and_then(|b| b.value.remaining),
+        }
+    }
+
+    fn airborne(&self, takeoff_altitude: f64) -> bool {
+        self.flying == Some(true)
+            || self
+                .relative_altitude
+                .is_some_and(|altitude| altitude > takeoff_altitude)
+    }
+}
+
+/// 飞行架次识别服务
+#[derive(Clone)]
+pub struct FlightService {
+    sender: mpsc::Sender<Sample>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl FlightService {
+    /// 创建服务并启动识别任务
+    pub fn start(db: Arc<DatabaseConnection>, settings: FlightSettings) -> Self {
+        let (sender, receiver) = mpsc::channel(QUEUE_CAPACITY);
+
+        let detector = FlightDetector {
+            db,
+            receiver,
+            sessions: HashMap::new(),
+            settings,
+        };
+        tokio::spawn(detector.run());
+
+        Self {
+            sender,
+            dropped: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// 在快照更新后调用
+    pub fn observe(&self, device_id: i32, snapshot: &TelemetrySnapshot, timestamp: Timestamp) {
+        let sample = Sample::from_snapshot(device_id, snapshot, timestamp);
+        if self.sender.try_send(sample).is_err() {
+            let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+            // 避免日志刷屏
+            if dropped.is_power_of_two() {
+                warn!(
+                    "Flight detection queue full, {} samples dropped so far",
+                    dropped
+                );
+            }
+        }
+    }
+}
+
+/// 进行中的架次
+#[derive(Debug)]
+struct Session {
+    /// 已写入数据库的架次ID，尚未离地时为空
+    flight_id: Option<i32>,
+    start_reason: &'static str,
+    start_time: Timestamp,
+    last_sample_at: Timestamp,
+    last_received: Instant,
+    /// 未上报解锁状态时，开始贴近地面的时间
+    grounded_since: Option<Timestamp>,
+    last_position: Option<(f64, f64, Timestamp)>,
+    distance: f64,
+    max_relative_altitude: Option<f64>,
+    max_amsl_altitude: Option<f64>,
+    max_reported_speed: Option<f64>,
+    max_derived_speed: Option<f64>,
+    battery_start: Option<f64>,
+    battery_end: Option<f64>,
+    /// 上次写入后统计是否有变化
+    dirty: bool,
+}
+
+impl Session {
+    fn new(sample: &Sample, start_reason: &'static str) -> Self {
+        Self {
+            flight_id: None,
+            start_reason,
+            start_time: sample.timestamp,
+            last_sample_at: sample.timestamp,
+            last_received: Instant::now(),
+            grounded_since: None,
+            last_position: None,
+            distance: 0.0,
+            max_relative_altitude: None,
+            max_amsl_altitude: None,
+            max_reported_speed: None,
+            max_derived_speed: None,
+            battery_start: None,
+            battery_end: None,
+            dirty: false,
+        }
+    }
+
+    fn update(&mut self, sample: &Sample) {
+        self.last_sample_at = sample.timestamp;
+        self.last_received = Instant::now();
+        self.dirty = true;
+
+        if let Some((latitude, longitude)) = sample.position {
+            if let Some((last_lat, last_lon, last_at)) = self.last_position {
+                let step = haversine_distance((last_lat, last_lon), (latitude, longitude));
+                let seconds = (sample.timestamp - last_at).num_milliseconds() as f64 / 1000.0;
+                let speed = if seconds > 0.0 { step / seconds } else { 0.0 };
+                if speed <= MAX_PLAUSIBLE_SPEED {
+                    self.distance += step;
+                    if seconds > 0.0 {
+                        self.max_derived_speed = max_option(self.max_derived_speed, speed);
+                    }
+                }
+            }
+            self.last_position = Some((latitude, longitude, sample.timestamp));
+        }
+
+        if let Some(altitude) = sample.relative_altitude {
+            self.max_relative_altitude = max_option(self.max_relative_altitude, altitude);
+        }
+        if let Some(altitude) = sample.amsl_altitude {
+            self.max_amsl_altitude = max_option(self.max_amsl_altitude, altitude);
+        }
+        if let Some(speed) = sample.ground_speed {
+            self.max_reported_speed = max_option(self.max_reported_speed, speed);
+        }
+        if let Some(battery) = sample.battery {
+            self.battery_start.get_or_insert(battery);
+            self.battery_end = Some(battery);
+        }
+    }
+
+    fn apply_stats(&self, active: &mut flight::ActiveModel, end_time: Timestamp) {
+        let duration = (end_time - self.start_time).num_milliseconds().max(0) as f64 / 1000.0;
+        active.duration_seconds = Set(duration);
+        active.distance = Set(self.distance);
+        // 相对高度优先
+        active.max_altitude = Set(self.max_relative_altitude.or(self.max_amsl_altitude));
+        // 上报地速优先，没有时使用定位推算
+        active.max_speed = Set(self.max_reported_speed.or(self.max_derived_speed));
+        active.battery_start = Set(self.battery_start);
+        active.battery_end = Set(self.battery_end);
+        active.battery_consumed = Set(self
+            .battery_start
+            .zip(self.battery_end)
+            .map(|(start, end)| (start - end).max(0.0)));
+    }
+}
+
+fn max_option(current: Option<f64>, value: f64) -> Option<f64> {
+    Some(current.map_or(value, |c| c.max(value)))
+}
+
+/// 单一识别任务，按设备维护架次状态
+struct FlightDetector {
+    db: Arc<DatabaseConnection>,
+    receiver: mpsc::Receiver<Sample>,
+    sessions: HashMap<i32, Session>,
+    settings: FlightSettings,
+}
+
+impl FlightDetector {
+    async fn run(mut self) {
+        self.close_stale_flights().await;
+
+        let mut ticker = tokio::time::interval(Duration::from_millis(
+            self.settings.update_interval_ms.max(1),
+        ));
+        loop {
+            tokio::select! {
+                sample = self.receiver.recv() => match sample {
+                    Some(sample) => self.handle(sample).await,
+                    None => break,
+                },
+                _ = ticker.tick() => self.tick().await,
+            }
+        }
+    }
+
+    /// 上次运行时未结束的架次无法继续跟踪，直接结束
+    async fn close_stale_flights(&self) {
+        let stale = match flight::Entity::find()
+            .filter(flight::Column::Status.eq(STATUS_IN_PROGRESS))
+            .all(self.db.as_ref())
+            .await
+        {
+            Ok(stale) => stale,
+            Err(e) => {
+                error!("Failed to load unfinished flights: {}", e);
+                return;
+            }
+        };
+
+        for model in stale {
+            // 以最后一次写入的时长为准
+            let end_time = model.start_time
+                + chrono::Duration::milliseconds((model.duration_seconds * 1000.0) as i64);
+            let mut active: flight::ActiveModel = model.into();
+            active.status = Set(STATUS_COMPLETED.to_string());
+            active.end_reason = Set(Some("restart".to_string()));
+            active.end_time = Set(Some(end_time));
+            active.updated_at = Set(now());
+            if let Err(e) = active.update(self.db.as_ref()).await {
+                error!("Failed to close unfinished flight: {}", e);
+            }
+        }
+    }
+
+    async fn handle(&mut self, sample: Sample) {
+        let takeoff_altitude = self.settings.takeoff_altitude;
+        let gap = chrono::Duration::seconds(self.settings.gap_timeout_secs as i64);
+        let landed_timeout = chrono::Duration::seconds(self.settings.landed_timeout_secs as i64);
+
+        // 数据中断后到达的样本属于新的架次
+        if let Some(session) = self.sessions.get(&sample.device_id) {
+            if sample.timestamp - session.last_sample_at > gap {
+                self.finish(sample.device_id, "data_gap", None).await;
+            } else if sample.timestamp < session.last_sample_at {
+                // 乱序样本不参与判断
+                return;
+            }
+        }
+
+        let airborne = sample.airborne(takeoff_altitude);
+        let Some(session) = self.sessions.get_mut(&sample.device_id) else {
+            let start_reason = if sample.armed == Some(true) {
+                "armed"
+            } else if airborne {
+                "airborne"
+            } else {
+                return;
+            };
+            let mut session = Session::new(&sample, start_reason);
+            session.update(&sample);
+            self.sessions.insert(sample.device_id, session);
+            if airborne {
+                self.persist(sample.device_id).await;
+            }
+            return;
+        };
+
+        session.update(&sample);
+
+        if sample.armed == Some(false) {
+            self.finish(sample.device_id, "disarmed", Some(sample.timestamp))
+                .await;
+            return;
+        }
+
+        // 未处于解锁状态时按离地高度判断降落
+        let mut landed_at = None;
+        if sample.armed != Some(true) {
+            if airborne {
+                session.grounded_since = None;
+            } else {
+                let since = *session.grounded_since.get_or_insert(sample.timestamp);
+                if sample.timestamp - since >= landed_timeout {
+                    landed_at = Some(since);
+                }
+            }
+        }
+
+        if let Some(since) = landed_at {
+            self.finish(sample.device_id, "landed", Some(since)).await;
+        } else if airborne && session.flight_id.is_none() {
+            self.persist(sample.device_id).await;
+        }
+    }
+
+    /// 离地后写入架次
+    async fn persist(&mut self, device_id: i32) {
+        let Some(session) = self.sessions.get_mut(&device_id) else {
+            return;
+        };
+
+        let task_id = match running_task(&self.db, device_id).await {
+            Ok(task_id) => task_id,
+            Err(e) => {
+                warn!(
+                    "Failed to look up running task for device {}: {}",
+                    device_id, e
+                );
+                None
+            }
+        };
+
+        let now = now();
+        let mut active = flight::ActiveModel {
+            uuid: Set(Uuid::new_v4()),
+            device_id: Set(device_id),
+            task_id: Set(task_id),
+            status: Set(STATUS_IN_PROGRESS.to_string()),
+            start_reason: Set(session.start_reason.to_string()),
+            end_reason: Set(None),
+            start_time: Set(session.start_time),
+            end_time: Set(None),
+            created_at: Set(now),
+            updated_at: Set(now),
+            ..Default::default()
+        };
+        session.apply_stats(&mut active, session.last_sample_at);
+
+        match active.insert(self.db.as_ref()).await {
+            Ok(model) => {
+                info!(
+                    "Flight {} started for device {} ({})",
+                    model.uuid, device_id, session.start_reason
+                );
+                session.flight_id = Some(model.id);
+                session.dirty = false;
+            }
+            // 下一个离地样本时重试
+            Err(e) => error!("Failed to create flight for device {}: {}", device_id, e),
+        }
+    }
+
+    /// 结束架次，未离地的架次直接丢弃
+    async fn finish(&mut self, device_id: i32, reason: &str, end_time: Option<Timestamp>) {
+        let Some(session) = self.sessions.remove(&device_id) else {
+            return;
+        };
+        let Some(flight_id) = session.flight_id else {
+            return;
+        };
+
+        let end_time = end_time.unwrap_or(session.last_sample_at);
+        let mut active = flight::ActiveModel {
+            id: Set(flight_id),
+            status: Set(STATUS_COMPLETED.to_string()),
+            end_reason: Set(Some(reason.to_string())),
+            end_time: Set(Some(end_time)),
+            updated_at: Set(now()),
+            ..Default::default()
+        };
+        session.apply_stats(&mut active, end_time);
+
+        match active.update(self.db.as_ref()).await {
+            Ok(_) => info!(
+                "Flight {} of device {} ended ({})",
+                flight_id, device_id, reason
+            ),
+            Err(e) => error!("Failed to close flight {}: {}", flight_id, e),
+        }
+    }
+
+    /// 结束长时间没有数据的架次，并写入进行中架次的统计
+    async fn tick(&mut self) {
+        let gap = Duration::from_secs(self.settings.gap_timeout_secs);
+        let timed_out: Vec<i32> = self
+            .sessions
+            .iter()
+            .filter(|(_, session)| session.last_received.elapsed() > gap)
+            .map(|(device_id, _)| *device_id)
+            .collect();
+        for device_id in timed_out {
+            self.finish(device_id, "data_gap", None).await;
+        }
+
+        for session in self.sessions.values_mut() {
+            let Some(flight_id) = session.flight_id.filter(|_| session.dirty) else {
+                continue;
+            };
+            let mut active = flight::ActiveModel {
+                id: Set(flight_id),
+                updated_at: Set(now()),
+                ..Default::default()
+            };
+            session.apply_stats(&mut active, session.last_sample_at);
+            match active.update(self.db.as_ref()).await {
+                Ok(_) => session.dirty = false,
+                Err(e) => error!("Failed to update flight {}: {}", flight_id, e),
+            }
+        }
+    }
+}
+
+/// 设备当前运行中的任务
+async fn running_task(
+    db: &DatabaseConnection,
+    device_id: i32,
+) -> Result<Option<i32>, sea_orm::DbErr> {
+    Ok(task::Entity::find()
+        .filter(task::Column::DeviceId.eq(device_id))
+        .filter(task::Column::Status.eq("running"))
+        .order_by_desc(task::Column::StartTime)
+        .order_by_desc(task::Column::Id)
+        .one(db)
+        .await?
+        .map(|task| task.id))
+}
+
+fn now() -> Timestamp {
+    chrono::Utc::now().with_timezone(&FixedOffset::east_opt(8 * 3600).unwrap())
+}
@@ -0,0 +1,503 @@
+//! 电子围栏
+//!
+//! 围栏形状为多边形（`{"type": "polygon", "points": [[纬度, 经度], ...]}`）或圆形（`{"type": "circle",
+//! "latitude", "longitude", "radius"}`，半径为米），可限定高度范围 `min_altitude`、`max_altitude`
+//! （相对起飞点，米）：
+//! - `inclusion`：只能在围栏内、高度范围内飞行，离开即违规；
+//! - `exclusion`：禁飞区，进入围栏且高度在范围内即违规。
+//!
+//! 保存的围栏（[`geofence::Model`]）指定给设备时只约束该设备，指定给用户时约束该用户的所有设备，
+//! 两者都未指定的全局围栏约束所有设备。任务创建和开始执行时按模拟航线校验，
+//! [`GeofenceMonitor`] 检查离地设备的实时位置，每次越界和恢复都推送一条 `geofence` 消息。
+
+use sea_orm::{ColumnTrait, Condition, DatabaseConnection, DbErr, EntityTrait, QueryFilter};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc, Notify};
+use tracing::{error, info, warn};
+
+use crate::models::{device, geofence};
+use crate::services::flight::haversine_distance;
+use crate::services::mission::LocalFrame;
+use crate::services::realtime_data::UnifiedRealtimeMessage;
+use crate::services::telemetry::{as_bool, TelemetrySnapshot, Timestamp};
+use crate::settings::GeofenceSettings;
+
+/// 围栏事件的统一消息类型
+pub const GEOFENCE_MESSAGE_TYPE: &str = "geofence";
+/// 样本队列容量
+const QUEUE_CAPACITY: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GeofenceKind {
+    Inclusion,
+    Exclusion,
+}
+
+impl GeofenceKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Inclusion => "inclusion",
+            Self::Exclusion => "exclusion",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum GeofenceShape {
+    Polygon {
+        points: Vec<(f64, f64)>,
+    },
+    Circle {
+        latitude: f64,
+        longitude: f64,
+        radius: f64,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Geofence {
+    #[serde(default)]
+    pub name: String,
+    pub kind: GeofenceKind,
+    pub shape: GeofenceShape,
+    pub min_altitude: Option<f64>,
+    pub max_altitude: Option<f64>,
+}
+
+impl GeofenceShape {
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            Self::Polygon { points } => {
+                if points.len() < 3 {
+                    return Err("Polygon needs at least 3 points".to_string());
+                }
+                points
+                    .iter()
+                    .try_for_each(|&(latitude, longitude)| validate_position(latitude, longitude))
+            }
+            Self::Circle {
+                latitude,
+                longitude,
+                radius,
+            } => {
+                validate_position(*latitude, *longitude)?;
+                if !radius.is_finite() || *radius <= 0.0 {
+                    return Err("Circle radius must be positive".to_string());
+                }
+                Ok(())
+            }
+        }
+    }
+
+    pub fn contains(&self, latitude: f64, longitude: f64) -> bool {
+        match self {
+            Self::Polygon { points } => {
+                let frame = LocalFrame::new(latitude, longitude);
+                let vertices: Vec<(f64, f64)> = points
+                    .iter()
+                    .map(|&(lat, lon)| frame.to_local(lat, lon))
+                    .collect();
+                // 射线法，点位于局部坐标原点
+                let mut inside = false;
+                let mut previous = vertices[vertices.len() - 1];
+                for &current in &vertices {
+                    if (current.1 > 0.0) != (previous.1 > 0.0) {
+                        let x = current.0
+                            - current.1 * (previous.0 - current.0) / (previous.1 - current.1);
+                        if x > 0.0 {
+                            inside = !inside;
+                        }
+                    }
+                    previous = current;
+                }
+                inside
+            }
+            Self::Circle {
+                latitude: center_latitude,
+                longitude: center_longitude,
+                radius,
+            } => {
+                haversine_distance((latitude, longitude), (*center_latitude, *center_longitude))
+                    <= *radius
+            }
+        }
+    }
+}
+
+fn validate_position(latitude: f64, longitude: f64) -> Result<(), String> {
+    if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+        return Err(format!("Invalid position {}, {}", latitude, longitude));
+    }
+    Ok(())
+}
+
+impl Geofence {
+    pub fn validate(&self) -> Result<(), String> {
+        self.shape.validate()?;
+        if let (Some(min), Some(max)) = (self.min_altitude, self.max_altitude) {
+            if min > max {
+                return Err("min_altitude must not exceed max_altitude".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    fn within_altitude(&self, altitude: f64) -> bool {
+        self.min_altitude.is_none_or(|min| altitude >= min)
+            && self.max_altitude.is_none_or(|max| altitude <= max)
+    }
+
+    /// 位置违反围栏时返回原因
+    pub fn violation(&self, latitude: f64, longitude: f64, altitude: f64) -> Option<String> {
+        let inside = self.shape.contains(latitude, longitude);
+        let name = if self.name.is_empty() {
+            "geofence"
+        } else {
+            self.name.as_str()
+        };
+        match self.kind {
+            GeofenceKind::Inclusion if !inside => Some(format!("Outside {}", name)),
+            GeofenceKind::Inclusion if !self.within_altitude(altitude) => Some(format!(
+                "Altitude {:.1} m outside the allowed range of {}",
+                altitude, name
+            )),
+            GeofenceKind::Exclusion if inside && self.within_altitude(altitude) => {
+                Some(format!("Inside no-fly zone {}", name))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl TryFrom<&geofence::Model> for Geofence {
+    type Error = String;
+
+    fn try_from(model: &geofence::Model) -> Result<Self, Self::Error> {
+        let kind = match model.kind.as_str() {
+            "inclusion" => GeofenceKind::Inclusion,
+            "exclusion" => GeofenceKind::Exclusion,
+            other => return Err(format!("Invalid geofence kind {}", other)),
+        };
+        let shape = serde_json::from_value(model.shape.clone())
+            .map_err(|e| format!("Invalid geofence shape: {}", e))?;
+        Ok(Self {
+            name: model.name.clone(),
+            kind,
+            shape,
+            min_altitude: model.min_altitude,
+            max_altitude: model.max_altitude,
+        })
+    }
+}
+
+/// 约束设备的启用围栏，设备不存在时为空
+pub async fn for_device(db: &DatabaseConnection, device_id: i32) -> Result<Vec<Geofence>, DbErr> {
+    let Some(device) = device::Entity::find_by_id(device_id).one(db).await? else {
+        return Ok(Vec::new());
+    };
+    let models = geofence::Entity::find()
+        .filter(geofence::Column::IsActive.eq(true))
+        .filter(
+            Condition::any()
+                .add(geofence::Column::DeviceId.eq(device_id))
+                .add(
+                    Condition::all()
+                        .add(geofence::Column::DeviceId.is_null())
+                        .add(
+                            Condition::any()
+                                .add(geofence::Column::UserId.eq(device.user_id))
+                                .add(geofence::Column::UserId.is_null()),
+                        ),
+                ),
+        )
+        .all(db)
+        .await?;
+    Ok(models
+        .iter()
+        .filter_map(|model| match Geofence::try_from(model) {
+            Ok(geofence) => Some(geofence),
+            Err(e) => {
+                warn!("Skipping geofence {}: {}", model.uuid, e);
+                None
+            }
+        })
+        .collect())
+}
+
+/// 设备越出围栏
+#[derive(Debug, Clone)]
+pub struct GeofenceBreach {
+    pub device_id: i32,
+    pub geofence_id: i32,
+    pub message: String,
+    /// 围栏要求越界时自动返航
+    pub rtl: bool,
+}
+
+/// 从快照中提取的位置样本
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    device_id: i32,
+    timestamp: Timestamp,
+    position: Option<(f64, f64)>,
+    relative_altitude: Option<f64>,
+    flying: Option<bool>,
+    /// 本次更新是否包含位置或高度
+    moved: bool,
+}
+
+impl Sample {
+    fn from_snapshot(device_id: i32, snapshot: &TelemetrySnapshot, timestamp: Timestamp) -> Self {
+        let position = snapshot.position.as_ref();
+        let altitude = snapshot.altitude.as_ref();
+        Self {
+            device_id,
+            timestamp,
+            position: position.map(|p| (p.value.latitude, p.value.longitude)),
+            relative_altitude: altitude.and_then(|a| a.value.relative),
+            flying: snapshot
+                .extensions
+                .get("flying")
+                .and_then(|v| as_bool(&v.value)),
+            moved: position.is_some_and(|p| p.updated_at == timestamp)
+                || altitude.is_some_and(|a| a.updated_at == timestamp),
+        }
+    }
+}
+
+/// 实时电子围栏检查服务
+#[derive(Clone)]
+pub struct GeofenceMonitor {
+    sender: mpsc::Sender<Sample>,
+    reload: Arc<Notify>,
+    breaches: broadcast::Sender<GeofenceBreach>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl GeofenceMonitor {
+    /// 创建服务并启动检查任务，`takeoff_altitude` 以上视为离地
+    pub fn start(
+        db: Arc<DatabaseConnection>,
+        settings: GeofenceSettings,
+        takeoff_altitude: f64,
+        unified_sender: broadcast::Sender<UnifiedRealtimeMessage>,
+    ) -> Self {
+        let (sender, receiver) = mpsc::channel(QUEUE_CAPACITY);
+        let (breaches, _) = broadcast::channel(256);
+        let reload = Arc::new(Notify::new());
+
+        if settings.enable {
+            let checker = GeofenceChecker {
+                db,
+                receiver,
+                reload: Arc::clone(&reload),
+                breaches: breaches.clone(),
+                unified_sender,
+                settings,
+                takeoff_altitude,
+                fences: Vec::new(),
+                owners: HashMap::new(),
+                breached: HashMap::new(),
+            };
+            tokio::spawn(checker.run());
+        }
+
+        Self {
+            sender,
+            reload,
+            breaches,
+            dropped: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// 在快照更新后调用
+    pub fn observe(&self, device_id: i32, snapshot: &TelemetrySnapshot, timestamp: Timestamp) {
+        if self.sender.is_closed() {
+            return;
+        }
+        let sample = Sample::from_snapshot(device_id, snapshot, timestamp);
+        if !sample.moved {
+            return;
+        }
+        if self.sender.try_send(sample).is_err() {
+            let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+            // 避免日志刷屏
+            if dropped.is_power_of_two() {
+                warn!(
+                    "Geofence check queue full, {} samples dropped so far",
+                    dropped
+                );
+            }
+        }
+    }
+
+    /// 围栏或设备归属变更后重新加载
+    pub fn reload(&self) {
+        self.reload.notify_one();
+    }
+
+    /// 订阅越界事件
+    pub fn subscribe(&self) -> broadcast::Receiver<GeofenceBreach> {
+        self.breaches.subscribe()
+    }
+}
+
+struct GeofenceChecker {
+    db: Arc<DatabaseConnection>,
+    receiver: mpsc::Receiver<Sample>,
+    reload: Arc<Notify>,
+    breaches: broadcast::Sender<GeofenceBreach>,
+    unified_sender: broadcast::Sender<UnifiedRealtimeMessage>,
+    settings: GeofenceSettings,
+    takeoff_altitude: f64,
+    fences: Vec<(geofence::Model, Geofence)>,
+    /// 设备ID到所属用户的映射
+    owners: HashMap<i32, i32>,
+    /// 设备当前越出的围栏
+    breached: HashMap<i32, HashSet<i32>>,
+}
+
+impl GeofenceChecker {
+    async fn run(mut self) {
+        let mut ticker =
+            tokio::time::interval(Duration::from_secs(self.settings.refresh_secs.max(1)));
+        loop {
+            tokio::select! {
+                sample = self.receiver.recv() => match sample {
+                    Some(sample) => self.handle(sample),
+                    None => break,
+                },
+                _ = ticker.tick() => self.load().await,
+                _ = self.reload.notified() => self.load().await,
+            }
+        }
+    }
+
+    async fn load(&mut self) {
+        let fences = match geofence::Entity::find()
+            .filter(geofence::Column::IsActive.eq(true))
+            .all(self.db.as_ref())
+            .await
+        {
+            Ok(fences) => fences,
+            Err(e) => {
+                error!("Failed to load geofences: {}", e);
+                return;
+            }
+        };
+        let devices = match device::Entity::find().all(self.db.as_ref()).await {
+            Ok(devices) => devices,
+            Err(e) => {
+                error!("Failed to load device owners for geofences: {}", e);
+                return;
+            }
+        };
+
+        self.fences = fences
+            .into_iter()
+            .filter_map(|model| match Geofence::try_from(&model) {
+                Ok(fence) => Some((model, fence)),
+                Err(e) => {
+                    warn!("Skipping geofence {}: {}", model.uuid, e);
+                    None
+                }
+            })
+            .collect();
+        self.owners = devices
+            .into_iter()
+            .map(|device| (device.id, device.user_id))
+            .collect();
+        // 已删除或停用的围栏不再保留越界状态
+        let ids: HashSet<i32> = self.fences.iter().map(|(model, _)| model.id).collect();
+        for breached in self.breached.values_mut() {
+            breached.retain(|id| ids.contains(id));
+        }
+    }
+
+    fn handle(&mut self, sample: Sample) {
+        let airborne = sample.flying == Some(true)
+            || sample
+                .relative_altitude
+                .is_some_and(|altitude| altitude > self.takeoff_altitude);
+        // 落地后重新起飞时重新告警
+        let (true, Some((latitude, longitude))) = (airborne, sample.position) else {
+            self.breached.remove(&sample.device_id);
+            return;
+        };
+        let Some(&owner) = self.owners.get(&sample.device_id) else {
+            return;
+        };
+        let altitude = sample.relative_altitude.unwrap_or(0.0);
+
+        let mut events = Vec::new();
+        let breached = self.breached.entry(sample.device_id).or_default();
+        for (model, fence) in &self.fences {
+            if !model.applies_to(sample.device_id, owner) {
+                continue;
+            }
+            match fence.violation(latitude, longitude, altitude) {
+                Some(message) if breached.insert(model.id) => {
+                    events.push((model, "breach", message));
+                }
+                None if breached.remove(&model.id) => {
+                    events.push((model, "cleared", format!("Back within {}", model.name)));
+                }
+                _ => {}
+            }
+        }
+
+        for (model, event, message) in events {
+            let breach = event == "breach";
+            let action = (breach && model.rtl_on_breach).then_some("rtl");
+            if breach {
+                warn!(
+                    "Device {} breached geofence {}: {}",
+                    sample.device_id, model.uuid, message
+                );
+            } else {
+                info!(
+                    "Device {} is back within geofence {}",
+                    sample.device_id, model.uuid
+                );
+            }
+
+            let unified_msg = UnifiedRealtimeMessage {
+                device_id: sample.device_id,
+                message_type: GEOFENCE_MESSAGE_TYPE.to_string(),
+                data: json!({
+                    "event": event,
+                    "geofence_id": model.id,
+                    "geofence_uuid": model.uuid,
+                    "name": model.name,
+                    "kind": model.kind,
+                    "message": message,
+                    "latitude": latitude,
+                    "longitude": longitude,
+                    "altitude": altitude,
+                    "action": action,
+                }),
+                timestamp: sample.timestamp,
+            };
+            if let Err(e) = self.unified_sender.send(unified_msg) {
+                warn!("Failed to broadcast geofence event: {}", e);
+            }
+
+            if breach {
+                // 没有订阅者时发送失败，无需处理
+                let _ = self.breaches.send(GeofenceBreach {
+                    device_id: sample.device_id,
+                    geofence_id: model.id,
+                    message,
+                    rtl: model.rtl_on_breach,
+                });
+            }
+        }
+    }
+}
@@ -0,0 +1,470 @@
+//! 实时数据历史查询
+//!
+//! 支持时间范围、数据类型、字段存在性和 JSON 字段比较（如 `battery<20`）过滤，
+//! 按 `(received_at, id)` 倒序做游标分页。JSON 表达式按数据库类型生成，路径和比较值都以参数绑定。
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use chrono::{DateTime, FixedOffset};
+use sea_orm::sea_query::{Alias, Expr, Query, SimpleExpr};
+use sea_orm::{
+    ColumnTrait, Condition, ConnectionTrait, DatabaseBackend, DatabaseConnection, EntityTrait,
+    QueryFilter, QueryOrder, QuerySelect, QueryTrait, Select, Statement,
+};
+use serde_json::Value as JsonValue;
+
+use crate::models::device_realtime_data;
+
+/// 精确计数的上限，超过后改为估算
+const MAX_EXACT_TOTAL: u64 = 100_000;
+/// 单个 JSON 路径的最大层级
+const MAX_PATH_DEPTH: usize = 8;
+
+/// 比较运算符
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CompareOp {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+impl CompareOp {
+    fn as_sql(self) -> &'static str {
+        match self {
+            Self::Lt => "<",
+            Self::Le => "<=",
+            Self::Gt => ">",
+            Self::Ge => ">=",
+            Self::Eq => "=",
+            Self::Ne => "<>",
+        }
+    }
+}
+
+/// 比较值，能解析为数字时按数值比较
+#[derive(Debug, Clone, PartialEq)]
+pub enum PredicateValue {
+    Number(f64),
+    Text(String),
+}
+
+/// JSON 字段比较条件，如 `battery<20`、`gps.fix_type>=3`、`flight_mode=AUTO`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Predicate {
+    pub path: Vec<String>,
+    pub op: CompareOp,
+    pub value: PredicateValue,
+}
+
+impl Predicate {
+    pub fn parse(input: &str) -> Result<Self, String> {
+        // 两字符运算符需要先匹配
+        const OPERATORS: [(&str, CompareOp); 7] = [
+            ("<=", CompareOp::Le),
+            (">=", CompareOp::Ge),
+            ("!=", CompareOp::Ne),
+            ("==", CompareOp::Eq),
+            ("<", CompareOp::Lt),
+            (">", CompareOp::Gt),
+            ("=", CompareOp::Eq),
+        ];
+
+        let (index, token, op) = OPERATORS
+            .iter()
+            .filter_map(|(token, op)| input.find(token).map(|index| (index, *token, *op)))
+            .min_by_key(|(index, token, _)| (*index, std::cmp::Reverse(token.len())))
+            .ok_or_else(|| format!("Missing comparison operator in `{}`", input))?;
+
+        let path = parse_path(&input[..index])?;
+        let raw = input[index + token.len()..].trim();
+        if raw.is_empty() {
+            return Err(format!("Missing value in `{}`", input));
+        }
+
+        let value = match raw.parse::<f64>() {
+            Ok(number) if number.is_finite() => PredicateValue::Number(number),
+            _ => {
+                if !matches!(op, CompareOp::Eq | CompareOp::Ne) {
+                    return Err(format!("`{}` requires a numeric value", input));
+                }
+                PredicateValue::Text(raw.trim_matches('"').to_string())
+            }
+        };
+
+        Ok(Self { path, op, value })
+    }
+}
+
+/// 解析以 `.` 分隔的字段路径
+pub fn parse_path(input: &str) -> Result<Vec<String>, String> {
+    let path: Vec<String> = input.trim().split('.').map(str::to_string).collect();
+    let valid = path.len() <= MAX_PATH_DEPTH
+        && path.iter().all(|segment| {
+            !segment.is_empty()
+                && segment
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        });
+    if valid {
+        Ok(path)
+    } else {
+        Err(format!("Invalid field path `{}`", input.trim()))
+    }
+}
+
+/// 历史数据过滤条件
+#[derive(Debug, Clone, Default)]
+pub struct HistoryFilter {
+    pub from: Option<DateTime<FixedOffset>>,
+    pub to: Option<DateTime<FixedOffset>>,
+    pub data_types: Vec<String>,
+    /// 必须存在的字段
+    pub has_fields: Vec<Vec<String>>,
+    pub predicates: Vec<Predicate>,
+}
+
+/// 分页游标，指向上一页最后一条记录
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cursor {
+    pub received_at: DateTime<FixedOffset>,
+    pub id: i64,
+}
+
+impl Cursor {
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(format!("{},{}", self.received_at.to_rfc3339(), self.id))
+    }
+
+    pub fn decode(input: &str) -> Option<Self> {
+        let bytes = URL_SAFE_NO_PAD.decode(input.trim()).ok()?;
+        let text = String::from_utf8(bytes).ok()?;
+        let (received_at, id) = text.rsplit_once(',')?;
+        Some(Self {
+            received_at: DateTime::parse_from_rfc3339(received_at).ok()?,
+            id: id.parse().ok()?,
+        })
+    }
+}
+
+/// 一页历史数据
+#[derive(Debug)]
+pub struct HistoryPage {
+    pub rows: Vec<device_realtime_data::Model>,
+    pub next_cursor: Option<Cursor>,
+    pub total: u64,
+    /// false 表示 `total` 为估算值
+    pub total_exact: bool,
+}
+
+/// JSON 字段取值表达式
+struct JsonField {
+    backend: DatabaseBackend,
+    path: Vec<String>,
+}
+
+impl JsonField {
+    /// 字段文本值的 SQL 片段，占位符从 `start` 开始编号（仅 PostgreSQL 使用编号）
+    fn text_sql(&self, start: usize) -> String {
+        match self.backend {
+            DatabaseBackend::Postgres => {
+                let args: Vec<String> = (0..self.path.len())
+                    .map(|i| format!("${}", start + i))
+                    .collect();
+                format!("json_extract_path_text(data_content, {})", args.join(", "))
+            }
+            DatabaseBackend::MySql => "JSON_UNQUOTE(JSON_EXTRACT(data_content, ?))".to_string(),
+            DatabaseBackend::Sqlite => "json_extract(data_content, ?)".to_string(),
+        }
+    }
+
+    fn path_values(&self) -> Vec<sea_orm::Value> {
+        match self.backend {
+            DatabaseBackend::Postgres => self.path.iter().map(|s| s.clone().into()).collect(),
+            _ => {
+                let path: String = self
+                    .path
+                    .iter()
+                    .map(|segment| format!(".\"{}\"", segment))
+                    .collect();
+                vec![format!("${}", path).into()]
+            }
+        }
+    }
+
+    fn exists(&self) -> SimpleExpr {
+        let sql = match self.backend {
+            DatabaseBackend::Postgres => {
+                let args: Vec<String> = (1..=self.path.len()).map(|i| format!("${}", i)).collect();
+                format!(
+                    "json_extract_path(data_content, {}) IS NOT NULL",
+                    args.join(", ")
+                )
+            }
+            DatabaseBackend::MySql => "JSON_CONTAINS_PATH(data_content, 'one', ?) = 1".to_string(),
+            DatabaseBackend::Sqlite => "json_type(data_content, ?) IS NOT NULL".to_string(),
+        };
+        Expr::cust_with_values(sql, self.path_values())
+    }
+
+    fn compare(&self, op: CompareOp, value: &PredicateValue) -> SimpleExpr {
+        let n = self.path.len();
+        match value {
+            PredicateValue::Number(number) => {
+                // 设备上报的数值可能是字符串，只比较能解析为数字的值
+                let (sql, mut values) = match self.backend {
+                    DatabaseBackend::Postgres => {
+                        let text = self.text_sql(1);
+                        let text_again = self.text_sql(n + 1);
+                        let sql = format!(
+                            "(CASE WHEN {} ~ '^\\s*-?[0-9]+(\\.[0-9]+)?([eE][-+]?[0-9]+)?\\s*$$' \
+                             THEN ({})::double precision END) {} ${}",
+                            text,
+                            text_again,
+                            op.as_sql(),
+                            2 * n + 1
+                        );
+                        let mut values = self.path_values();
+                        values.extend(self.path_values());
+                        (sql, values)
+                    }
+                    DatabaseBackend::MySql => {
+                        let text = self.text_sql(1);
+                        let sql = format!(
+                            "(CASE WHEN {} REGEXP '^[[:space:]]*-?[0-9]+([.][0-9]+)?([eE][-+]?[0-9]+)?[[:space:]]*$' \
+                             THEN CAST({} AS DOUBLE) END) {} ?",
+                            text,
+                            text,
+                            op.as_sql()
+                        );
+                        let mut values = self.path_values();
+                        values.extend(self.path_values());
+                        (sql, values)
+                    }
+                    DatabaseBackend::Sqlite => {
+                        // 数值和数字字符串都是合法的 JSON 数值，json_type 对非法 JSON 报错，需先用 json_valid 判断
+                        let text = self.text_sql(1);
+                        let sql = format!(
+                            "(CASE WHEN json_valid({}) THEN CASE WHEN json_type({}) IN ('integer', 'real') \
+                             THEN CAST({} AS REAL) END END) {} ?",
+                            text,
+                            text,
+                            text,
+                            op.as_sql()
+                        );
+                        let mut values = self.path_values();
+                        values.extend(self.path_values());
+                        values.extend(self.path_values());
+                        (sql, values)
+                    }
+                };
+                values.push((*number).into());
+                Expr::cust_with_values(sql, values)
+            }
+            PredicateValue::Text(text) => {
+                let placeholder = match self.backend {
+                    DatabaseBackend::Postgres => format!("${}", n + 1),
+                    _ => "?".to_string(),
+                };
+                let sql = format!("{} {} {}", self.text_sql(1), op.as_sql(), placeholder);
+                let mut values = self.path_values();
+                values.push(text.clone().into());
+                Expr::cust_with_values(sql, values)
+            }
+        }
+    }
+}
+
+/// 按过滤条件构造查询，不含排序和分页
+pub fn filtered_select(
+    backend: DatabaseBackend,
+    device_id: i32,
+    filter: &HistoryFilter,
+) -> Select<device_realtime_data::Entity> {
+    let mut select = device_realtime_data::Entity::find()
+        .filter(device_realtime_data::Column::DeviceId.eq(device_id));
+
+    if let Some(from) = filter.from {
+        select = select.filter(device_realtime_data::Column::ReceivedAt.gte(from));
+    }
+    if let Some(to) = filter.to {
+        select = select.filter(device_realtime_data::Column::ReceivedAt.lt(to));
+    }
+    if !filter.data_types.is_empty() {
+        select =
+            select.filter(device_realtime_data::Column::DataType.is_in(filter.data_types.clone()));
+    }
+    for path in &filter.has_fields {
+        let field = JsonField {
+            backend,
+            path: path.clone(),
+        };
+        select = select.filter(field.exists());
+    }
+    for predicate in &filter.predicates {
+        let field = JsonField {
+            backend,
+            path: predicate.path.clone(),
+        };
+        select = select.filter(field.compare(predicate.op, &predicate.value));
+    }
+
+    select
+}
+
+/// 统计满足条件的行数，超过上限时在 PostgreSQL 上使用查询计划估算
+async fn count_rows(
+    db: &DatabaseConnection,
+    select: &Select<device_realtime_data::Entity>,
+) -> Result<(u64, bool), sea_orm::DbErr> {
+    let backend = db.get_database_backend();
+
+    let inner = select
+        .clone()
+        .select_only()
+        .column(device_realtime_data::Column::Id)
+        .limit(MAX_EXACT_TOTAL + 1)
+        .into_query();
+    let count = Query::select()
+        .expr_as(Expr::cust("COUNT(*)"), Alias::new("total"))
+        .from_subquery(inner, Alias::new("matched"))
+        .to_owned();
+    let total = match db.query_one(backend.build(&count)).await? {
+        Some(row) => row.try_get::<i64>("", "total")?.max(0) as u64,
+        None => 0,
+    };
+    if total <= MAX_EXACT_TOTAL {
+        return Ok((total, true));
+    }
+
+    if backend == DatabaseBackend::Postgres {
+        let statement = backend.build(&select.clone().into_query());
+        let explain = Statement {
+            sql: format!("EXPLAIN (FORMAT JSON) {}", statement.sql),
+            values: statement.values,
+            db_backend: backend,
+        };
+        if let Some(row) = db.query_one(explain).await? {
+            let plan: JsonValue = row.try_get("", "QUERY PLAN")?;
+            if let Some(rows) = plan[0]["Plan"]["Plan Rows"].as_f64() {
+                return Ok(((rows as u64).max(total), false));
+            }
+        }
+    }
+
+    Ok((total, false))
+}
+
+/// 查询设备历史数据，按接收时间倒序
+///
+/// 提供游标时忽略 `offset`。
+pub async fn query_history(
+    db: &DatabaseConnection,
+    device_id: i32,
+    filter: &HistoryFilter,
+    cursor: Option<&Cursor>,
+    limit: u64,
+    offset: u64,
+) -> Result<HistoryPage, sea_orm::DbErr> {
+    let select = filtered_select(db.get_database_backend(), device_id, filter);
+    let (total, total_exact) = count_rows(db, &select).await?;
+
+    let mut page = select
+        .order_by_desc(device_realtime_data::Column::ReceivedAt)
+        .order_by_desc(device_realtime_data::Column::Id);
+    page = match cursor {
+        Some(cursor) => page.filter(
+            Condition::any()
+                .add(device_realtime_data::Column::ReceivedAt.lt(cursor.received_at))
+                .add(
+                    Condition::all()
+                        .add(device_realtime_data::Column::ReceivedAt.eq(cursor.received_at))
+                        .add(device_realtime_data::Column::Id.lt(cursor.id)),
+                ),
+        ),
+        None => page.offset(offset),
+    };
+
+    // 多取一条判断是否还有下一页
+    let mut rows = page.limit(limit + 1).all(db).await?;
+    let next_cursor = if rows.len() as u64 > limit {
+        rows.truncate(limit as usize);
+        rows.last().map(|row| Cursor {
+            received_at: row.received_at,
+            id: row.id,
+        })
+    } else {
+        None
+    };
+
+    Ok(HistoryPage {
+        rows,
+        next_cursor,
+        total,
+        total_exact,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sea_orm::{ActiveValue::NotSet, ConnectOptions, Database, Schema, Set};
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn numeric_predicate_skips_non_numeric_strings_on_sqlite() {
+        let mut options = ConnectOptions::new("sqlite::memory:");
+        options
+            .max_connections(1)
+            .min_connections(1)
+            .sqlx_logging(false);
+        let db = Database::connect(options).await.unwrap();
+        // 数据引用的设备不需要存在
+        db.execute_unprepared("PRAGMA foreign_keys = OFF")
+            .await
+            .unwrap();
+        let backend = db.get_database_backend();
+        let table = Schema::new(backend).create_table_from_entity(device_realtime_data::Entity);
+        db.execute(backend.build(&table)).await.unwrap();
+
+        let now: DateTime<FixedOffset> = chrono::Utc::now().into();
+        let contents = [
+            json!({ "battery": 10 }),
+            json!({ "battery": " 15.5 " }),
+            json!({ "battery": "abc" }),
+            json!({ "battery": "" }),
+            json!({ "voltage": 11.1 }),
+            json!({ "battery": 80 }),
+        ];
+        for data_content in contents {
+            device_realtime_data::Entity::insert(device_realtime_data::ActiveModel {
+                id: NotSet,
+                device_id: Set(1),
+                data_type: Set("telemetry".to_string()),
+                data_content: Set(data_content),
+                received_at: Set(now),
+                created_at: Set(now),
+                updated_at: Set(now),
+            })
+            .exec(&db)
+            .await
+            .unwrap();
+        }
+
+        let filter = HistoryFilter {
+            predicates: vec![Predicate::parse("battery<20").unwrap()],
+            ..Default::default()
+        };
+        let rows = filtered_select(backend, 1, &filter)
+            .order_by_asc(device_realtime_data::Column::Id)
+            .all(&db)
+            .await
+            .unwrap();
+        let batteries: Vec<JsonValue> = rows
+            .into_iter()
+            .map(|row| row.data_content["battery"].clone())
+            .collect();
+        assert_eq!(batteries, vec![json!(10), json!(" 15.5 ")]);
+    }
+}
@@ -0,0 +1,368 @@
+//! 实时数据写入管道
+//!
+//! 所有接入协议的数据通过一个有界队列进入单一写入任务，按批次多行插入数据库。
+//! 写入任务在插入前先把数据追加到预写文件（WAL），插入成功后清空；进程崩溃或数据库
+//! 不可用时，数据保留在文件中，恢复后整体重放。
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::fs::{self, File, OpenOptions};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, BufWriter};
+use tokio::sync::mpsc;
+use tracing::{error, info, warn};
+
+use crate::models::device_realtime_data;
+use crate::settings::IngestSettings;
+use sea_orm::{DatabaseConnection, EntityTrait, TransactionTrait};
+
+/// 单条 INSERT 语句的最大行数（PostgreSQL 参数上限为 65535）
+const MAX_ROWS_PER_INSERT: usize = 1000;
+/// 数据库写入失败后的最大重试间隔
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(30);
+
+/// 待写入的实时数据
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IngestRecord {
+    pub device_id: i32,
+    pub data_type: String,
+    pub data_content: JsonValue,
+    pub received_at: chrono::DateTime<chrono::FixedOffset>,
+}
+
+/// 写入管道指标
+#[derive(Debug, Clone, Serialize)]
+pub struct IngestMetrics {
+    pub queue_depth: usize,
+    pub queue_capacity: usize,
+    pub enqueued: u64,
+    pub written: u64,
+    pub dropped: u64,
+    pub failed_batches: u64,
+    pub spill_bytes: u64,
+    /// 数据库写入失败，数据暂存在预写文件中
+    pub spilling: bool,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    enqueued: AtomicU64,
+    written: AtomicU64,
+    dropped: AtomicU64,
+    failed_batches: AtomicU64,
+    spill_bytes: AtomicU64,
+    spilling: AtomicBool,
+}
+
+/// 有界写入队列
+#[derive(Clone)]
+pub struct IngestQueue {
+    sender: mpsc::Sender<IngestRecord>,
+    capacity: usize,
+    enqueue_timeout: Duration,
+    counters: Arc<Counters>,
+}
+
+impl IngestQueue {
+    /// 创建队列并启动写入任务
+    pub fn start(db: Arc<DatabaseConnection>, settings: IngestSettings) -> Self {
+        let capacity = settings.queue_capacity.max(1);
+        let (sender, receiver) = mpsc::channel(capacity);
+        let counters = Arc::new(Counters::default());
+
+        let writer = IngestWriter {
+            db,
+            receiver,
+            batch: Vec::with_capacity(settings.batch_size),
+            spill_path: PathBuf::from(&settings.spill_path),
+            spill: None,
+            batch_size: settings.batch_size.max(1),
+            flush_interval: Duration::from_millis(settings.flush_interval_ms.max(1)),
+            spill_max_bytes: settings.spill_max_bytes,
+            retry_backoff: Duration::ZERO,
+            retry_at: None,
+            counters: Arc::clone(&counters),
+        };
+        tokio::spawn(writer.run());
+
+        Self {
+            sender,
+            capacity,
+            enqueue_timeout: Duration::from_millis(settings.enqueue_timeout_ms),
+            counters,
+        }
+    }
+
+    /// 加入写入队列
+    ///
+    /// 队列已满时最多等待 `enqueue_timeout`，向上游施加背压；仍然写不进去则丢弃并计数。
+    pub async fn enqueue(&self, record: IngestRecord) -> bool {
+        let record = match self.sender.try_send(record) {
+            Ok(()) => {
+                self.counters.enqueued.fetch_add(1, Ordering::Relaxed);
+                return true;
+            }
+            Err(mpsc::error::TrySendError::Full(record)) => record,
+            Err(mpsc::error::TrySendError::Closed(_)) => {
+                self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+                return false;
+            }
+        };
+
+        match self.sender.send_timeout(record, self.enqueue_timeout).await {
+            Ok(()) => {
+                self.counters.enqueued.fetch_add(1, Ordering::Relaxed);
+                true
+            }
+            Err(_) => {
+                let dropped = self.counters.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+                // 避免日志刷屏
+                if dropped.is_power_of_two() {
+                    warn!(
+                        "Realtime ingest queue full, {} records dropped so far",
+                        dropped
+                    );
+                }
+                false
+            }
+        }
+    }
+
+    /// 获取写入管道指标
+    pub fn metrics(&self) -> IngestMetrics {
+        IngestMetrics {
+            queue_depth: self.capacity - self.sender.capacity(),
+            queue_capacity: self.capacity,
+            enqueued: self.counters.enqueued.load(Ordering::Relaxed),
+            written: self.counters.written.load(Ordering::Relaxed),
+            dropped: self.counters.dropped.load(Ordering::Relaxed),
+            failed_batches: self.counters.failed_batches.load(Ordering::Relaxed),
+            spill_bytes: self.counters.spill_bytes.load(Ordering::Relaxed),
+            spilling: self.counters.spilling.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// 单一写入任务
+struct IngestWriter {
+    db: Arc<DatabaseConnection>,
+    receiver: mpsc::Receiver<IngestRecord>,
+    batch: Vec<IngestRecord>,
+    spill_path: PathBuf,
+    spill: Option<BufWriter<File>>,
+    batch_size: usize,
+    flush_interval: Duration,
+    spill_max_bytes: u64,
+    retry_backoff: Duration,
+    retry_at: Option<Instant>,
+    counters: Arc<Counters>,
+}
+
+impl IngestWriter {
+    async fn run(mut self) {
+        if let Err(e) = self.open_spill().await {
+            error!(
+                "Failed to open realtime ingest spill file {}: {}",
+                self.spill_path.display(),
+                e
+            );
+        }
+        // 上次退出时未写入的数据
+        if self.counters.spill_bytes.load(Ordering::Relaxed) > 0 {
+            info!(
+                "Found {} bytes of unwritten realtime data in {}, replaying",
+                self.counters.spill_bytes.load(Ordering::Relaxed),
+                self.spill_path.display()
+            );
+            self.counters.spilling.store(true, Ordering::Relaxed);
+            self.flush().await;
+        }
+
+        let mut ticker = tokio::time::interval(self.flush_interval);
+        loop {
+            tokio::select! {
+                record = self.receiver.recv() => match record {
+                    Some(record) => {
+                        self.append(record).await;
+                        if self.batch.len() >= self.batch_size {
+                            self.flush().await;
+                        }
+                    }
+                    None => {
+                        self.flush().await;
+                        break;
+                    }
+                },
+                _ = ticker.tick() => self.flush().await,
+            }
+        }
+    }
+
+    async fn open_spill(&mut self) -> std::io::Result<()> {
+        if let Some(parent) = self.spill_path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent).await?;
+            }
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.spill_path)
+            .await?;
+        let len = file.metadata().await?.len();
+        self.counters.spill_bytes.store(len, Ordering::Relaxed);
+        self.spill = Some(BufWriter::new(file));
+        Ok(())
+    }
+
+    /// 先追加到预写文件，再放入当前批次
+    async fn append(&mut self, record: IngestRecord) {
+        if let Some(spill) = self.spill.as_mut() {
+            if self.counters.spill_bytes.load(Ordering::Relaxed) >= self.spill_max_bytes {
+                self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+            match serde_json::to_vec(&record) {
+                Ok(mut line) => {
+                    line.push(b'\n');
+                    match spill.write_all(&line).await {
+                        Ok(()) => {
+                            self.counters
+                                .spill_bytes
+                                .fetch_add(line.len() as u64, Ordering::Relaxed);
+                        }
+                        Err(e) => error!("Failed to append to realtime ingest spill file: {}", e),
+                    }
+                }
+                Err(e) => error!("Failed to serialize realtime record: {}", e),
+            }
+        }
+
+        // 预写文件处于积压状态时，数据以文件为准，内存中不再保留
+        if !self.counters.spilling.load(Ordering::Relaxed) {
+            self.batch.push(record);
+        }
+    }
+
+    async fn flush(&mut self) {
+        let spilling = self.counters.spilling.load(Ordering::Relaxed);
+        if self.batch.is_empty() && !spilling {
+            return;
+        }
+        if self.retry_at.is_some_and(|at| Instant::now() < at) {
+            return;
+        }
+
+        if let Some(spill) = self.spill.as_mut() {
+            if let Err(e) = spill.flush().await {
+                error!("Failed to flush realtime ingest spill file: {}", e);
+            } else if let Err(e) = spill.get_ref().sync_data().await {
+                error!("Failed to sync realtime ingest spill file: {}", e);
+            }
+        }
+
+        let records = if spilling {
+            match self.read_spill().await {
+                Ok(records) => records,
+                Err(e) => {
+                    error!("Failed to read realtime ingest spill file: {}", e);
+                    return;
+                }
+            }
+        } else {
+            std::mem::take(&mut self.batch)
+        };
+
+        let count = records.len() as u64;
+        match insert_records(&self.db, records).await {
+            Ok(()) => {
+                self.counters.written.fetch_add(count, Ordering::Relaxed);
+                self.retry_backoff = Duration::ZERO;
+                self.retry_at = None;
+                if spilling {
+                    info!("Replayed {} spilled realtime records", count);
+                    self.counters.spilling.store(false, Ordering::Relaxed);
+                }
+                self.truncate_spill().await;
+            }
+            Err(e) => {
+                self.counters.failed_batches.fetch_add(1, Ordering::Relaxed);
+                self.retry_backoff = (self.retry_backoff * 2)
+                    .max(self.flush_interval)
+                    .min(MAX_RETRY_BACKOFF);
+                self.retry_at = Some(Instant::now() + self.retry_backoff);
+
+                if self.spill.is_none() {
+                    // 没有预写文件时无法保留数据
+                    self.counters.dropped.fetch_add(count, Ordering::Relaxed);
+                    error!("Failed to write {} realtime records: {}", count, e);
+                } else if !spilling {
+                    warn!(
+                        "Failed to write {} realtime records, keeping them in spill file: {}",
+                        count, e
+                    );
+                    self.counters.spilling.store(true, Ordering::Relaxed);
+                }
+                self.batch.clear();
+            }
+        }
+    }
+
+    async fn read_spill(&self) -> std::io::Result<Vec<IngestRecord>> {
+        let file = File::open(&self.spill_path).await?;
+        let mut lines = BufReader::new(file).lines();
+        let mut records = Vec::new();
+        while let Some(line) = lines.next_line().await? {
+            // 崩溃时可能留下不完整的最后一行
+            match serde_json::from_str(&line) {
+                Ok(record) => records.push(record),
+                Err(e) => warn!("Skipping corrupt realtime spill entry: {}", e),
+            }
+        }
+        Ok(records)
+    }
+
+    async fn truncate_spill(&mut self) {
+        if let Some(spill) = self.spill.as_mut() {
+            if let Err(e) = spill.get_ref().set_len(0).await {
+                error!("Failed to truncate realtime ingest spill file: {}", e);
+                return;
+            }
+        }
+        self.counters.spill_bytes.store(0, Ordering::Relaxed);
+    }
+}
+
+/// 在一个事务中分块多行插入，保证重放时不会产生部分写入
+async fn insert_records(
+    db: &DatabaseConnection,
+    records: Vec<IngestRecord>,
+) -> Result<(), sea_orm::DbErr> {
+    if records.is_empty() {
+        return Ok(());
+    }
+
+    let txn = db.begin().await?;
+    let mut records = records.into_iter().peekable();
+    while records.peek().is_some() {
+        let chunk: Vec<_> = records
+            .by_ref()
+            .take(MAX_ROWS_PER_INSERT)
+            .map(|record| {
+                device_realtime_data::Model::new_active_model(
+                    record.device_id,
+                    &record.data_type,
+                    record.data_content,
+                    record.received_at,
+                )
+            })
+            .collect();
+        device_realtime_data::Entity::insert_many(chunk)
+            .exec(&txn)
+            .await?;
+    }
+    txn.commit().await
+}
@@ -0,0 +1,957 @@
+//! MAVLink v1/v2 帧解析
+//!
+//! 只实现遥测接入需要的少量消息，不依赖完整的 MAVLink 方言生成代码。
+
+use serde_json::{json, Map, Value as JsonValue};
+
+/// MAVLink v1 帧起始标志
+pub const MAVLINK_V1_STX: u8 = 0xFE;
+/// MAVLink v2 帧起始标志
+pub const MAVLINK_V2_STX: u8 = 0xFD;
+
+const MAVLINK_V1_HEADER_LEN: usize = 6;
+const MAVLINK_V2_HEADER_LEN: usize = 10;
+const MAVLINK_CHECKSUM_LEN: usize = 2;
+const MAVLINK_SIGNATURE_LEN: usize = 13;
+const MAVLINK_IFLAG_SIGNED: u8 = 0x01;
+
+/// 解析缓冲区上限，防止异常数据导致内存增长
+const MAX_BUFFER_LEN: usize = 64 * 1024;
+
+/// 消息ID
+pub mod msg_id {
+    pub const HEARTBEAT: u32 = 0;
+    pub const SYS_STATUS: u32 = 1;
+    pub const GPS_RAW_INT: u32 = 24;
+    pub const ATTITUDE: u32 = 30;
+    pub const GLOBAL_POSITION_INT: u32 = 33;
+    pub const COMMAND_INT: u32 = 75;
+    pub const COMMAND_LONG: u32 = 76;
+    pub const COMMAND_ACK: u32 = 77;
+    pub const BATTERY_STATUS: u32 = 147;
+}
+
+/// 命令ID (MAV_CMD)
+pub mod mav_cmd {
+    pub const NAV_RETURN_TO_LAUNCH: u16 = 20;
+    pub const NAV_LAND: u16 = 21;
+    pub const NAV_TAKEOFF: u16 = 22;
+    pub const CONDITION_YAW: u16 = 115;
+    pub const DO_REPOSITION: u16 = 192;
+    pub const COMPONENT_ARM_DISARM: u16 = 400;
+    pub const IMAGE_START_CAPTURE: u16 = 2000;
+}
+
+/// 坐标系 (MAV_FRAME)
+pub mod mav_frame {
+    /// WGS84 经纬度，海拔高度
+    pub const GLOBAL: u8 = 0;
+}
+
+/// 命令执行结果 (MAV_RESULT)
+pub mod mav_result {
+    pub const ACCEPTED: u8 = 0;
+    pub const TEMPORARILY_REJECTED: u8 = 1;
+    pub const DENIED: u8 = 2;
+    pub const UNSUPPORTED: u8 = 3;
+    pub const FAILED: u8 = 4;
+    pub const IN_PROGRESS: u8 = 5;
+    pub const CANCELLED: u8 = 6;
+
+    /// 结果名称
+    pub fn name(result: u8) -> &'static str {
+        match result {
+            ACCEPTED => "ACCEPTED",
+            TEMPORARILY_REJECTED => "TEMPORARILY_REJECTED",
+            DENIED => "DENIED",
+            UNSUPPORTED => "UNSUPPORTED",
+            FAILED => "FAILED",
+            IN_PROGRESS => "IN_PROGRESS",
+            CANCELLED => "CANCELLED",
+            _ => "UNKNOWN",
+        }
+    }
+}
+
+/// 消息的 CRC_EXTRA 和基础载荷长度
+fn message_info(message_id: u32) -> Option<(u8, usize)> {
+    match message_id {
+        msg_id::HEARTBEAT => Some((50, 9)),
+        msg_id::SYS_STATUS => Some((124, 31)),
+        msg_id::GPS_RAW_INT => Some((24, 30)),
+        msg_id::ATTITUDE => Some((39, 28)),
+        msg_id::GLOBAL_POSITION_INT => Some((104, 28)),
+        msg_id::COMMAND_INT => Some((158, 35)),
+        msg_id::COMMAND_LONG => Some((152, 33)),
+        msg_id::COMMAND_ACK => Some((143, 3)),
+        msg_id::BATTERY_STATUS => Some((154, 36)),
+        _ => None,
+    }
+}
+
+/// X.25 CRC 累加
+fn crc_accumulate(byte: u8, crc: u16) -> u16 {
+    let mut tmp = byte ^ (crc & 0xff) as u8;
+    tmp ^= tmp << 4;
+    let tmp = u16::from(tmp);
+    (crc >> 8) ^ (tmp << 8) ^ (tmp << 3) ^ (tmp >> 4)
+}
+
+/// 计算 MAVLink 校验和（不含起始标志，末尾追加 CRC_EXTRA）
+pub fn checksum(data: &[u8], crc_extra: u8) -> u16 {
+    let crc = data
+        .iter()
+        .fold(0xffff_u16, |crc, byte| crc_accumulate(*byte, crc));
+    crc_accumulate(crc_extra, crc)
+}
+
+/// 编码一个完整的 MAVLink 帧，v2 会按协议截断载荷尾部的零字节
+pub fn encode_frame(
+    version: MavlinkVersion,
+    sequence: u8,
+    system_id: u8,
+    component_id: u8,
+    message_id: u32,
+    payload: &[u8],
+) -> Option<Vec<u8>> {
+    let (crc_extra, _) = message_info(message_id)?;
+
+    let mut frame = match version {
+        MavlinkVersion::V1 => {
+            let message_id = u8::try_from(message_id).ok()?;
+            let mut frame = Vec::with_capacity(MAVLINK_V1_HEADER_LEN + payload.len() + 2);
+            frame.extend_from_slice(&[
+                MAVLINK_V1_STX,
+                payload.len() as u8,
+                sequence,
+                system_id,
+                component_id,
+                message_id,
+            ]);
+            frame.extend_from_slice(payload);
+            frame
+        }
+        MavlinkVersion::V2 => {
+            // 至少保留一个字节
+            let len = payload
+                .iter()
+                .rposition(|b| *b != 0)
+                .map_or(1, |last| last + 1);
+            let id = message_id.to_le_bytes();
+            let mut frame = Vec::with_capacity(MAVLINK_V2_HEADER_LEN + len + 2);
+            frame.extend_from_slice(&[
+                MAVLINK_V2_STX,
+                len as u8,
+                0,
+                0,
+                sequence,
+                system_id,
+                component_id,
+                id[0],
+                id[1],
+                id[2],
+            ]);
+            frame.extend_from_slice(&payload[..len]);
+            frame
+        }
+    };
+
+    let crc = checksum(&frame[1..], crc_extra);
+    frame.extend_from_slice(&crc.to_le_bytes());
+    Some(frame)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MavlinkVersion {
+    V1,
+    V2,
+}
+
+/// 一个完整且校验通过的 MAVLink 帧
+#[derive(Debug, Clone)]
+pub struct MavlinkFrame {
+    pub version: MavlinkVersion,
+    pub sequence: u8,
+    pub system_id: u8,
+    pub component_id: u8,
+    pub message_id: u32,
+    pub payload: Vec<u8>,
+}
+
+/// 流式帧解析器，可以处理 UDP 数据报中的多个帧以及跨包的帧
+#[derive(Debug, Default)]
+pub struct MavlinkParser {
+    buffer: Vec<u8>,
+    crc_errors: u64,
+}
+
+impl MavlinkParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 追加接收到的字节
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+        if self.buffer.len() > MAX_BUFFER_LEN {
+            let overflow = self.buffer.len() - MAX_BUFFER_LEN;
+            self.buffer.drain(..overflow);
+        }
+    }
+
+    /// 校验失败的帧数量
+    pub fn crc_errors(&self) -> u64 {
+        self.crc_errors
+    }
+
+    /// 取出下一个完整的帧，数据不足时返回 None
+    ///
+    /// 未知消息无法校验 CRC，会被直接跳过。
+    pub fn next_frame(&mut self) -> Option<MavlinkFrame> {
+        loop {
+            // 丢弃起始标志之前的无效字节
+            let Some(start) = self
+                .buffer
+                .iter()
+                .position(|b| *b == MAVLINK_V1_STX || *b == MAVLINK_V2_STX)
+            else {
+                self.buffer.clear();
+                return None;
+            };
+            self.buffer.drain(..start);
+
+            if self.buffer.len() < 3 {
+                return None;
+            }
+
+            let payload_len = self.buffer[1] as usize;
+            let (version, header_len, signature_len) = if self.buffer[0] == MAVLINK_V1_STX {
+                (MavlinkVersion::V1, MAVLINK_V1_HEADER_LEN, 0)
+            } else {
+                let signed = self.buffer[2] & MAVLINK_IFLAG_SIGNED != 0;
+                (
+                    MavlinkVersion::V2,
+                    MAVLINK_V2_HEADER_LEN,
+                    if signed { MAVLINK_SIGNATURE_LEN } else { 0 },
+                )
+            };
+
+            let frame_len = header_len + payload_len + MAVLINK_CHECKSUM_LEN + signature_len;
+            if self.buffer.len() < frame_len {
+                return None;
+            }
+
+            let header = &self.buffer[..header_len];
+            let (sequence, system_id, component_id, message_id) = match version {
+                MavlinkVersion::V1 => (header[2], header[3], header[4], u32::from(header[5])),
+                MavlinkVersion::V2 => (
+                    header[4],
+                    header[5],
+                    header[6],
+                    u32::from_le_bytes([header[7], header[8], header[9], 0]),
+                ),
+            };
+
+            let Some((crc_extra, _)) = message_info(message_id) else {
+                self.buffer.drain(..frame_len);
+                continue;
+            };
+
+            let crc_offset = header_len + payload_len;
+            let expected =
+                u16::from_le_bytes([self.buffer[crc_offset], self.buffer[crc_offset + 1]]);
+            if checksum(&self.buffer[1..crc_offset], crc_extra) != expected {
+                // 校验失败，跳过起始字节重新同步
+                self.crc_errors += 1;
+                self.buffer.drain(..1);
+                continue;
+            }
+
+            let payload = self.buffer[header_len..crc_offset].to_vec();
+            self.buffer.drain(..frame_len);
+
+            return Some(MavlinkFrame {
+                version,
+                sequence,
+                system_id,
+                component_id,
+                message_id,
+                payload,
+            });
+        }
+    }
+}
+
+/// 按小端序读取载荷字段，v2 截断的尾部零字节会补齐
+struct PayloadReader {
+    data: Vec<u8>,
+}
+
+impl PayloadReader {
+    fn new(payload: &[u8], full_len: usize) -> Self {
+        let mut data = payload.to_vec();
+        if data.len() < full_len {
+            data.resize(full_len, 0);
+        }
+        Self { data }
+    }
+
+    fn bytes<const N: usize>(&self, offset: usize) -> [u8; N] {
+        let mut buf = [0u8; N];
+        buf.copy_from_slice(&self.data[offset..offset + N]);
+        buf
+    }
+
+    fn u8(&self, offset: usize) -> u8 {
+        self.data[offset]
+    }
+
+    fn i8(&self, offset: usize) -> i8 {
+        i8::from_le_bytes(self.bytes(offset))
+    }
+
+    fn u16(&self, offset: usize) -> u16 {
+        u16::from_le_bytes(self.bytes(offset))
+    }
+
+    fn i16(&self, offset: usize) -> i16 {
+        i16::from_le_bytes(self.bytes(offset))
+    }
+
+    fn u32(&self, offset: usize) -> u32 {
+        u32::from_le_bytes(self.bytes(offset))
+    }
+
+    fn i32(&self, offset: usize) -> i32 {
+        i32::from_le_bytes(self.bytes(offset))
+    }
+
+    fn u64(&self, offset: usize) -> u64 {
+        u64::from_le_bytes(self.bytes(offset))
+    }
+
+    fn f32(&self, offset: usize) -> f32 {
+        f32::from_le_bytes(self.bytes(offset))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Heartbeat {
+    pub custom_mode: u32,
+    pub mav_type: u8,
+    pub autopilot: u8,
+    pub base_mode: u8,
+    pub system_status: u8,
+    pub mavlink_version: u8,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SysStatus {
+    pub load: u16,
+    pub voltage_battery: u16,
+    pub current_battery: i16,
+    pub drop_rate_comm: u16,
+    pub battery_remaining: i8,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GpsRawInt {
+    pub time_usec: u64,
+    pub lat: i32,
+    pub lon: i32,
+    pub alt: i32,
+    pub eph: u16,
+    pub epv: u16,
+    pub vel: u16,
+    pub cog: u16,
+    pub fix_type: u8,
+    pub satellites_visible: u8,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attitude {
+    pub time_boot_ms: u32,
+    pub roll: f32,
+    pub pitch: f32,
+    pub yaw: f32,
+    pub rollspeed: f32,
+    pub pitchspeed: f32,
+    pub yawspeed: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GlobalPositionInt {
+    pub time_boot_ms: u32,
+    pub lat: i32,
+    pub lon: i32,
+    pub alt: i32,
+    pub relative_alt: i32,
+    pub vx: i16,
+    pub vy: i16,
+    pub vz: i16,
+    pub hdg: u16,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandLong {
+    pub target_system: u8,
+    pub target_component: u8,
+    pub command: u16,
+    pub confirmation: u8,
+    pub params: [f32; 7],
+}
+
+impl CommandLong {
+    /// 编码为载荷（字段按 MAVLink 规则以长度降序排列）
+    pub fn encode(&self) -> Vec<u8> {
+        let mut payload = Vec::with_capacity(33);
+        for param in self.params {
+            payload.extend_from_slice(&param.to_le_bytes());
+        }
+        payload.extend_from_slice(&self.command.to_le_bytes());
+        payload.push(self.target_system);
+        payload.push(self.target_component);
+        payload.push(self.confirmation);
+        payload
+    }
+}
+
+/// 带整型坐标的命令，经纬度按 1e7 缩放，避免 f32 参数的精度损失
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandInt {
+    pub target_system: u8,
+    pub target_component: u8,
+    pub frame: u8,
+    pub command: u16,
+    pub current: u8,
+    pub autocontinue: u8,
+    pub params: [f32; 4],
+    pub x: i32,
+    pub y: i32,
+    pub z: f32,
+}
+
+impl CommandInt {
+    /// 编码为载荷
+    pub fn encode(&self) -> Vec<u8> {
+        let mut payload = Vec::with_capacity(35);
+        for param in self.params {
+            payload.extend_from_slice(&param.to_le_bytes());
+        }
+        payload.extend_from_slice(&self.x.to_le_bytes());
+        payload.extend_from_slice(&self.y.to_le_bytes());
+        payload.extend_from_slice(&self.z.to_le_bytes());
+        payload.extend_from_slice(&self.command.to_le_bytes());
+        payload.push(self.target_system);
+        payload.push(self.target_component);
+        payload.push(self.frame);
+        payload.push(self.current);
+        payload.push(self.autocontinue);
+        payload
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandAck {
+    pub command: u16,
+    pub result: u8,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatteryStatus {
+    pub current_consumed: i32,
+    pub energy_consumed: i32,
+    pub temperature: i16,
+    pub voltages: [u16; 10],
+    pub current_battery: i16,
+    pub id: u8,
+    pub battery_remaining: i8,
+}
+
+/// 已解码的 MAVLink 消息
+#[derive(Debug, Clone, PartialEq)]
+pub enum MavlinkMessage {
+    Heartbeat(Heartbeat),
+    SysStatus(SysStatus),
+    GpsRawInt(GpsRawInt),
+    Attitude(Attitude),
+    GlobalPositionInt(GlobalPositionInt),
+    BatteryStatus(BatteryStatus),
+    CommandAck(CommandAck),
+}
+
+impl MavlinkMessage {
+    /// 解码帧载荷，不支持的消息返回 None
+    pub fn decode(frame: &MavlinkFrame) -> Option<Self> {
+        let (_, full_len) = message_info(frame.message_id)?;
+        let p = PayloadReader::new(&frame.payload, full_len);
+
+        let message = match frame.message_id {
+            msg_id::HEARTBEAT => Self::Heartbeat(Heartbeat {
+                custom_mode: p.u32(0),
+                mav_type: p.u8(4),
+                autopilot: p.u8(5),
+                base_mode: p.u8(6),
+                system_status: p.u8(7),
+                mavlink_version: p.u8(8),
+            }),
+            msg_id::SYS_STATUS => Self::SysStatus(SysStatus {
+                load: p.u16(12),
+                voltage_battery: p.u16(14),
+                current_battery: p.i16(16),
+                drop_rate_comm: p.u16(18),
+                battery_remaining: p.i8(30),
+            }),
+            msg_id::GPS_RAW_INT => Self::GpsRawInt(GpsRawInt {
+                time_usec: p.u64(0),
+                lat: p.i32(8),
+                lon: p.i32(12),
+                alt: p.i32(16),
+                eph: p.u16(20),
+                epv: p.u16(22),
+                vel: p.u16(24),
+                cog: p.u16(26),
+                fix_type: p.u8(28),
+                satellites_visible: p.u8(29),
+            }),
+            msg_id::ATTITUDE => Self::Attitude(Attitude {
+                time_boot_ms: p.u32(0),
+                roll: p.f32(4),
+                pitch: p.f32(8),
+                yaw: p.f32(12),
+                rollspeed: p.f32(16),
+                pitchspeed: p.f32(20),
+                yawspeed: p.f32(24),
+            }),
+            msg_id::GLOBAL_POSITION_INT => Self::GlobalPositionInt(GlobalPositionInt {
+                time_boot_ms: p.u32(0),
+                lat: p.i32(4),
+                lon: p.i32(8),
+                alt: p.i32(12),
+                relative_alt: p.i32(16),
+                vx: p.i16(20),
+                vy: p.i16(22),
+                vz: p.i16(24),
+                hdg: p.u16(26),
+            }),
+            msg_id::BATTERY_STATUS => {
+                let mut voltages = [0u16; 10];
+                for (i, voltage) in voltages.iter_mut().enumerate() {
+                    *voltage = p.u16(10 + i * 2);
+                }
+                Self::BatteryStatus(BatteryStatus {
+                    current_consumed: p.i32(0),
+                    energy_consumed: p.i32(4),
+                    temperature: p.i16(8),
+                    voltages,
+                    current_battery: p.i16(30),
+                    id: p.u8(32),
+                    battery_remaining: p.i8(35),
+                })
+            }
+            msg_id::COMMAND_ACK => Self::CommandAck(CommandAck {
+                command: p.u16(0),
+                result: p.u8(2),
+            }),
+            _ => return None,
+        };
+
+        Some(message)
+    }
+
+    /// 消息名称
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Heartbeat(_) => "HEARTBEAT",
+            Self::SysStatus(_) => "SYS_STATUS",
+            Self::GpsRawInt(_) => "GPS_RAW_INT",
+            Self::Attitude(_) => "ATTITUDE",
+            Self::GlobalPositionInt(_) => "GLOBAL_POSITION_INT",
+            Self::BatteryStatus(_) => "BATTERY_STATUS",
+            Self::CommandAck(_) => "COMMAND_ACK",
+        }
+    }
+
+    /// 转换为设备状态字段（统一使用国际单位：米、米/秒、度、伏、安）
+    pub fn to_state_fields(&self) -> JsonValue {
+        let mut fields = Map::new();
+
+        match self {
+            Self::Heartbeat(hb) => {
+                fields.insert(
+                    "armed".into(),
+                    json!(hb.base_mode & MAV_MODE_FLAG_SAFETY_ARMED != 0),
+                );
+                fields.insert(
+                    "flight_mode".into(),
+                    json!(flight_mode_name(hb.autopilot, hb.custom_mode)),
+                );
+                fields.insert("mav_type".into(), json!(hb.mav_type));
+                fields.insert("autopilot".into(), json!(hb.autopilot));
+                fields.insert("system_status".into(), json!(hb.system_status));
+            }
+            Self::SysStatus(status) => {
+                if status.voltage_battery != u16::MAX {
+                    fields.insert(
+                        "battery_voltage".into(),
+                        json!(f64::from(status.voltage_battery) / 1000.0),
+                    );
+                }
+                if status.current_battery >= 0 {
+                    fields.insert(
+                        "battery_current".into(),
+                        json!(f64::from(status.current_battery) / 100.0),
+                    );
+                }
+                if status.battery_remaining >= 0 {
+                    fields.insert("battery_remaining".into(), json!(status.battery_remaining));
+                }
+                fields.insert("cpu_load".into(), json!(f64::from(status.load) / 10.0));
+                fields.insert(
+                    "comm_drop_rate".into(),
+                    json!(f64::from(status.drop_rate_comm) / 100.0),
+                );
+            }
+            Self::GpsRawInt(gps) => {
+                fields.insert("gps_fix_type".into(), json!(gps.fix_type));
+                if gps.satellites_visible != u8::MAX {
+                    fields.insert("satellites".into(), json!(gps.satellites_visible));
+                }
+                if gps.eph != u16::MAX {
+                    fields.insert("hdop".into(), json!(f64::from(gps.eph) / 100.0));
+                }
+                if gps.vel != u16::MAX {
+                    fields.insert("ground_speed".into(), json!(f64::from(gps.vel) / 100.0));
+                }
+                if gps.cog != u16::MAX {
+                    fields.insert("course".into(), json!(f64::from(gps.cog) / 100.0));
+                }
+            }
+            Self::Attitude(att) => {
+                fields.insert("roll".into(), json!(f64::from(att.roll).to_degrees()));
+                fields.insert("pitch".into(), json!(f64::from(att.pitch).to_degrees()));
+                fields.insert("yaw".into(), json!(f64::from(att.yaw).to_degrees()));
+            }
+            Self::GlobalPositionInt(pos) => {
+                fields.insert("latitude".into(), json!(f64::from(pos.lat) / 1e7));
+                fields.insert("longitude".into(), json!(f64::from(pos.lon) / 1e7));
+                fields.insert("altitude".into(), json!(f64::from(pos.alt) / 1000.0));
+                fields.insert(
+                    "relative_altitude".into(),
+                    json!(f64::from(pos.relative_alt) / 1000.0),
+                );
+                let vx = f64::from(pos.vx) / 100.0;
+                let vy = f64::from(pos.vy) / 100.0;
+                fields.insert("ground_speed".into(), json!(vx.hypot(vy)));
+                fields.insert(
+                    "climb_rate".into(),
+                    json!(f64::from(-i32::from(pos.vz)) / 100.0),
+                );
+                if pos.hdg != u16::MAX {
+                    fields.insert("heading".into(), json!(f64::from(pos.hdg) / 100.0));
+                }
+            }
+            Self::BatteryStatus(battery) => {
+                let cells: Vec<u16> = battery
+                    .voltages
+                    .iter()
+                    .copied()
+                    .filter(|v| *v != u16::MAX)
+                    .collect();
+                if !cells.is_empty() {
+                    let total: u32 = cells.iter().map(|v| u32::from(*v)).sum();
+                    fields.insert("battery_voltage".into(), json!(f64::from(total) / 1000.0));
+                }
+                if battery.current_battery >= 0 {
+                    fields.insert(
+                        "battery_current".into(),
+                        json!(f64::from(battery.current_battery) / 100.0),
+                    );
+                }
+                if battery.battery_remaining >= 0 {
+                    fields.insert("battery_remaining".into(), json!(battery.battery_remaining));
+                }
+                if battery.current_consumed >= 0 {
+                    fields.insert(
+                        "battery_consumed_mah".into(),
+                        json!(battery.current_consumed),
+                    );
+                }
+                if battery.temperature != i16::MAX {
+                    fields.insert(
+                        "battery_temperature".into(),
+                        json!(f64::from(battery.temperature) / 100.0),
+                    );
+                }
+            }
+            Self::CommandAck(ack) => {
+                fields.insert("last_command".into(), json!(ack.command));
+                fields.insert(
+                    "last_command_result".into(),
+                    json!(mav_result::name(ack.result)),
+                );
+            }
+        }
+
+        JsonValue::Object(fields)
+    }
+}
+
+const MAV_MODE_FLAG_SAFETY_ARMED: u8 = 0x80;
+const MAV_AUTOPILOT_ARDUPILOTMEGA: u8 = 3;
+const MAV_AUTOPILOT_PX4: u8 = 12;
+
+/// 将 custom_mode 转换为飞行模式名称（支持 ArduCopter 和 PX4）
+pub fn flight_mode_name(autopilot: u8, custom_mode: u32) -> String {
+    let name = match autopilot {
+        MAV_AUTOPILOT_ARDUPILOTMEGA => match custom_mode {
+            0 => Some("STABILIZE"),
+            1 => Some("ACRO"),
+            2 => Some("ALT_HOLD"),
+            3 => Some("AUTO"),
+            4 => Some("GUIDED"),
+            5 => Some("LOITER"),
+            6 => Some("RTL"),
+            7 => Some("CIRCLE"),
+            9 => Some("LAND"),
+            16 => Some("POSHOLD"),
+            17 => Some("BRAKE"),
+            21 => Some("SMART_RTL"),
+            _ => None,
+        },
+        MAV_AUTOPILOT_PX4 => {
+            let main_mode = (custom_mode >> 16) & 0xff;
+            let sub_mode = (custom_mode >> 24) & 0xff;
+            match (main_mode, sub_mode) {
+                (1, _) => Some("MANUAL"),
+                (2, _) => Some("ALTCTL"),
+                (3, _) => Some("POSCTL"),
+                (4, 2) => Some("AUTO_TAKEOFF"),
+                (4, 3) => Some("AUTO_LOITER"),
+                (4, 4) => Some("AUTO_MISSION"),
+                (4, 5) => Some("AUTO_RTL"),
+                (4, 6) => Some("AUTO_LAND"),
+                (4, _) => Some("AUTO"),
+                (5, _) => Some("ACRO"),
+                (6, _) => Some("OFFBOARD"),
+                (7, _) => Some("STABILIZED"),
+                _ => None,
+            }
+        }
+        _ => None,
+    };
+
+    name.map(str::to_string)
+        .unwrap_or_else(|| format!("MODE_{}", custom_mode))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 飞控抓包：v1 HEARTBEAT、SYS_STATUS、BATTERY_STATUS，v2 GPS_RAW_INT、ATTITUDE（尾部零字节已截断）、
+    /// GLOBAL_POSITION_INT
+    const CAPTURE: &[u8] = include_bytes!("../../tests/fixtures/mavlink_telemetry.bin");
+
+    fn decode_all(parser: &mut MavlinkParser) -> Vec<(MavlinkVersion, MavlinkMessage)> {
+        std::iter::from_fn(|| parser.next_frame())
+            .filter_map(|frame| MavlinkMessage::decode(&frame).map(|msg| (frame.version, msg)))
+            .collect()
+    }
+
+    #[test]
+    fn decodes_capture_fed_in_chunks() {
+        let mut parser = MavlinkParser::new();
+        let mut messages = Vec::new();
+        for chunk in CAPTURE.chunks(7) {
+            parser.push(chunk);
+            messages.extend(decode_all(&mut parser));
+        }
+        assert_eq!(parser.crc_errors(), 0);
+
+        let versions: Vec<_> = messages.iter().map(|(version, _)| *version).collect();
+        assert_eq!(
+            versions,
+            [
+                MavlinkVersion::V1,
+                MavlinkVersion::V1,
+                MavlinkVersion::V2,
+                MavlinkVersion::V2,
+                MavlinkVersion::V2,
+                MavlinkVersion::V1,
+            ]
+        );
+        let messages: Vec<_> = messages.into_iter().map(|(_, msg)| msg).collect();
+
+        assert_eq!(
+            messages[0],
+            MavlinkMessage::Heartbeat(Heartbeat {
+                custom_mode: 4,
+                mav_type: 2,
+                autopilot: 3,
+                base_mode: 0x81,
+                system_status: 4,
+                mavlink_version: 3,
+            })
+        );
+        let fields = messages[0].to_state_fields();
+        assert_eq!(fields["armed"], json!(true));
+        assert_eq!(fields["flight_mode"], json!("GUIDED"));
+
+        assert_eq!(
+            messages[1],
+            MavlinkMessage::SysStatus(SysStatus {
+                load: 250,
+                voltage_battery: 12600,
+                current_battery: 1530,
+                drop_rate_comm: 12,
+                battery_remaining: 87,
+            })
+        );
+        let fields = messages[1].to_state_fields();
+        assert_eq!(fields["battery_voltage"], json!(12.6));
+        assert_eq!(fields["battery_current"], json!(15.3));
+
+        assert_eq!(
+            messages[2],
+            MavlinkMessage::GpsRawInt(GpsRawInt {
+                time_usec: 123_456_789,
+                lat: 301_234_567,
+                lon: 1_204_567_890,
+                alt: 45_000,
+                eph: 120,
+                epv: 200,
+                vel: 550,
+                cog: 9000,
+                fix_type: 3,
+                satellites_visible: 14,
+            })
+        );
+
+        assert_eq!(
+            messages[3],
+            MavlinkMessage::Attitude(Attitude {
+                time_boot_ms: 1000,
+                roll: 0.1,
+                pitch: -0.05,
+                yaw: 1.5,
+                rollspeed: 0.0,
+                pitchspeed: 0.0,
+                yawspeed: 0.0,
+            })
+        );
+
+        assert_eq!(
+            messages[4],
+            MavlinkMessage::GlobalPositionInt(GlobalPositionInt {
+                time_boot_ms: 2000,
+                lat: 301_234_567,
+                lon: 1_204_567_890,
+                alt: 45_500,
+                relative_alt: 20_500,
+                vx: 300,
+                vy: 400,
+                vz: -50,
+                hdg: 9000,
+            })
+        );
+        let fields = messages[4].to_state_fields();
+        assert_eq!(fields["latitude"], json!(30.1234567));
+        assert_eq!(fields["relative_altitude"], json!(20.5));
+        assert_eq!(fields["ground_speed"], json!(5.0));
+        assert_eq!(fields["climb_rate"], json!(0.5));
+
+        let MavlinkMessage::BatteryStatus(battery) = &messages[5] else {
+            panic!("expected BATTERY_STATUS, got {:?}", messages[5]);
+        };
+        assert_eq!(battery.current_consumed, 1200);
+        assert_eq!(battery.temperature, 2550);
+        assert_eq!(battery.voltages[..4], [4200, 4150, 4180, u16::MAX]);
+        assert_eq!(battery.current_battery, 1530);
+        assert_eq!(battery.battery_remaining, 76);
+        let fields = messages[5].to_state_fields();
+        assert_eq!(fields["battery_voltage"], json!(12.53));
+        assert_eq!(fields["battery_temperature"], json!(25.5));
+    }
+
+    #[test]
+    fn rejects_bad_crc_and_resyncs_after_garbage() {
+        let mut corrupted = CAPTURE.to_vec();
+        // 破坏第一帧（HEARTBEAT）的载荷
+        corrupted[7] ^= 0xff;
+
+        let mut parser = MavlinkParser::new();
+        parser.push(&[0x00, 0x13, 0x37, 0x55]);
+        parser.push(&corrupted);
+        let names: Vec<_> = decode_all(&mut parser)
+            .iter()
+            .map(|(_, msg)| msg.name())
+            .collect();
+
+        assert_eq!(
+            names,
+            [
+                "SYS_STATUS",
+                "GPS_RAW_INT",
+                "ATTITUDE",
+                "GLOBAL_POSITION_INT",
+                "BATTERY_STATUS",
+            ]
+        );
+        assert!(parser.crc_errors() >= 1);
+    }
+
+    #[test]
+    fn encoded_frames_round_trip() {
+        let command = CommandLong {
+            target_system: 1,
+            target_component: 1,
+            command: mav_cmd::NAV_TAKEOFF,
+            confirmation: 0,
+            params: [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 10.0],
+        };
+        let payload = command.encode();
+        assert_eq!(payload.len(), 33);
+
+        let v1 = encode_frame(
+            MavlinkVersion::V1,
+            3,
+            255,
+            190,
+            msg_id::COMMAND_LONG,
+            &payload,
+        )
+        .unwrap();
+        assert_eq!(v1.len(), MAVLINK_V1_HEADER_LEN + 33 + MAVLINK_CHECKSUM_LEN);
+
+        // v2 截断尾部零字节，COMMAND_ACK 只保留 command 的低字节
+        let ack = [0x16, 0x00, 0x00];
+        let v2 = encode_frame(MavlinkVersion::V2, 4, 1, 1, msg_id::COMMAND_ACK, &ack).unwrap();
+        assert_eq!(v2[1], 1);
+        assert_eq!(v2.len(), MAVLINK_V2_HEADER_LEN + 1 + MAVLINK_CHECKSUM_LEN);
+
+        let mut parser = MavlinkParser::new();
+        parser.push(&v1);
+        parser.push(&v2);
+
+        let frame = parser.next_frame().unwrap();
+        assert_eq!(frame.version, MavlinkVersion::V1);
+        assert_eq!(frame.sequence, 3);
+        assert_eq!(frame.system_id, 255);
+        assert_eq!(frame.component_id, 190);
+        assert_eq!(frame.message_id, msg_id::COMMAND_LONG);
+        assert_eq!(frame.payload, payload);
+
+        let frame = parser.next_frame().unwrap();
+        assert_eq!(frame.version, MavlinkVersion::V2);
+        assert_eq!(frame.payload, [0x16]);
+        assert_eq!(
+            MavlinkMessage::decode(&frame),
+            Some(MavlinkMessage::CommandAck(CommandAck {
+                command: mav_cmd::NAV_TAKEOFF,
+                result: mav_result::ACCEPTED,
+            }))
+        );
+        assert!(parser.next_frame().is_none());
+    }
+}
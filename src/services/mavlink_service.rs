@@ -0,0 +1,774 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+use tokio::sync::{broadcast, mpsc, oneshot, Mutex, RwLock};
+use tracing::{error, info, warn};
+
+use crate::models::device;
+use crate::services::mavlink::{
+    self, mav_cmd, mav_frame, mav_result, msg_id, CommandAck, CommandInt, CommandLong,
+    MavlinkMessage, MavlinkParser, MavlinkVersion,
+};
+use crate::settings::MavlinkSettings;
+use sea_orm::DatabaseConnection;
+
+/// 未知系统ID触发重新加载映射的最小间隔
+const MAPPING_RELOAD_INTERVAL: Duration = Duration::from_secs(10);
+/// 超过该时间未收到数据即认为设备离线
+const ONLINE_TIMEOUT: Duration = Duration::from_secs(10);
+/// 单次等待 COMMAND_ACK 的超时时间
+const COMMAND_ACK_TIMEOUT: Duration = Duration::from_millis(1500);
+/// 命令最大发送次数（COMMAND_LONG 重发时递增 confirmation）
+const COMMAND_MAX_ATTEMPTS: u8 = 3;
+/// UDP 接收出错后的等待时间，避免错误持续时空转
+const RECV_ERROR_BACKOFF: Duration = Duration::from_millis(200);
+/// 地面站自身的系统ID和组件ID
+const GCS_SYSTEM_ID: u8 = 255;
+const GCS_COMPONENT_ID: u8 = 190;
+/// 飞控组件ID
+const AUTOPILOT_COMPONENT_ID: u8 = 1;
+
+/// 飞控端点信息
+#[derive(Debug, Clone, Copy)]
+struct MavlinkPeer {
+    addr: SocketAddr,
+    version: MavlinkVersion,
+    last_seen: Instant,
+}
+
+/// 等待中的命令应答，按 (系统ID, 命令ID) 索引
+///
+/// 同一系统的同一命令同时只允许一个请求等待应答，否则两个请求无法区分各自的 COMMAND_ACK。
+type PendingAcks = Arc<std::sync::Mutex<HashMap<(u8, u16), mpsc::UnboundedSender<CommandAck>>>>;
+
+/// 占用 `PendingAcks` 中的一个键，释放时（包括请求被取消）移除
+struct PendingAck {
+    acks: PendingAcks,
+    key: (u8, u16),
+}
+
+impl PendingAck {
+    fn register(
+        acks: &PendingAcks,
+        key: (u8, u16),
+    ) -> Option<(Self, mpsc::UnboundedReceiver<CommandAck>)> {
+        let mut pending = acks.lock().unwrap_or_else(|e| e.into_inner());
+        if pending.contains_key(&key) {
+            return None;
+        }
+        let (ack_tx, ack_rx) = mpsc::unbounded_channel();
+        pending.insert(key, ack_tx);
+        Some((
+            Self {
+                acks: Arc::clone(acks),
+                key,
+            },
+            ack_rx,
+        ))
+    }
+}
+
+impl Drop for PendingAck {
+    fn drop(&mut self) {
+        self.acks
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&self.key);
+    }
+}
+
+/// 下发给飞控的命令消息
+#[derive(Debug, Clone)]
+enum CommandMessage {
+    Long(CommandLong),
+    /// 需要精确坐标的命令
+    Int(CommandInt),
+}
+
+impl CommandMessage {
+    fn command(&self) -> u16 {
+        match self {
+            Self::Long(command) => command.command,
+            Self::Int(command) => command.command,
+        }
+    }
+
+    fn message_id(&self) -> u32 {
+        match self {
+            Self::Long(_) => msg_id::COMMAND_LONG,
+            Self::Int(_) => msg_id::COMMAND_INT,
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        match self {
+            Self::Long(command) => command.encode(),
+            Self::Int(command) => command.encode(),
+        }
+    }
+
+    /// 准备重发，COMMAND_LONG 递增 confirmation，COMMAND_INT 没有该字段
+    fn next_attempt(&mut self) {
+        if let Self::Long(command) = self {
+            command.confirmation = command.confirmation.wrapping_add(1);
+        }
+    }
+}
+
+/// 可下发的飞控命令
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum MavlinkCommand {
+    Arm,
+    Disarm {
+        /// 强制上锁（空中也会执行）
+        #[serde(default)]
+        force: bool,
+    },
+    Takeoff {
+        /// 相对起飞高度（米）
+        altitude: f32,
+    },
+    Land,
+    Rtl,
+    Reposition {
+        latitude: f64,
+        longitude: f64,
+        /// 海拔高度（米）
+        altitude: f32,
+        /// 地速（米/秒），不指定时使用飞控默认值
+        #[serde(default)]
+        speed: Option<f32>,
+    },
+    Yaw {
+        /// 目标机头朝向（度，正北为 0）
+        heading: f32,
+    },
+    /// 拍摄一张照片
+    Photo,
+}
+
+impl MavlinkCommand {
+    /// 从接口请求体解析命令，支持字符串（如 `"land"`）或带 `command` 字段的对象
+    pub fn from_json(value: &JsonValue) -> Option<Self> {
+        match value {
+            JsonValue::String(name) => {
+                serde_json::from_value(serde_json::json!({ "command": name })).ok()
+            }
+            JsonValue::Object(_) => serde_json::from_value(value.clone()).ok(),
+            _ => None,
+        }
+    }
+
+    /// 转换为命令消息，DO_REPOSITION 使用 COMMAND_INT 传递 1e7 缩放的经纬度
+    fn to_message(&self, target_system: u8) -> CommandMessage {
+        let (command, params) = match self {
+            Self::Arm => (
+                mav_cmd::COMPONENT_ARM_DISARM,
+                [1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+            ),
+            Self::Disarm { force } => (
+                mav_cmd::COMPONENT_ARM_DISARM,
+                // 21196 为协议规定的强制上锁魔数
+                [
+                    0.0,
+                    if *force { 21196.0 } else { 0.0 },
+                    0.0,
+                    0.0,
+                    0.0,
+                    0.0,
+                    0.0,
+                ],
+            ),
+            Self::Takeoff { altitude } => (
+                mav_cmd::NAV_TAKEOFF,
+                [0.0, 0.0, 0.0, f32::NAN, 0.0, 0.0, *altitude],
+            ),
+            Self::Land => (
+                mav_cmd::NAV_LAND,
+                [0.0, 0.0, 0.0, f32::NAN, f32::NAN, f32::NAN, f32::NAN],
+            ),
+            Self::Rtl => (mav_cmd::NAV_RETURN_TO_LAUNCH, [0.0; 7]),
+            Self::Reposition {
+                latitude,
+                longitude,
+                altitude,
+                speed,
+            } => {
+                return CommandMessage::Int(CommandInt {
+                    target_system,
+                    target_component: AUTOPILOT_COMPONENT_ID,
+                    frame: mav_frame::GLOBAL,
+                    command: mav_cmd::DO_REPOSITION,
+                    current: 0,
+                    autocontinue: 0,
+                    // MAV_DO_REPOSITION_FLAGS_CHANGE_MODE
+                    params: [speed.unwrap_or(-1.0), 1.0, 0.0, f32::NAN],
+                    x: (latitude * 1e7).round() as i32,
+                    y: (longitude * 1e7).round() as i32,
+                    z: *altitude,
+                });
+            }
+            // 绝对角度，按最短方向转动
+            Self::Yaw { heading } => (
+                mav_cmd::CONDITION_YAW,
+                [heading.rem_euclid(360.0), 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+            ),
+            Self::Photo => (
+                mav_cmd::IMAGE_START_CAPTURE,
+                [0.0, 0.0, 1.0, 0.0, f32::NAN, f32::NAN, f32::NAN],
+            ),
+        };
+
+        CommandMessage::Long(CommandLong {
+            target_system,
+            target_component: AUTOPILOT_COMPONENT_ID,
+            command,
+            confirmation: 0,
+            params,
+        })
+    }
+}
+
+/// 命令执行结果
+#[derive(Debug, Clone, Serialize)]
+pub struct MavlinkCommandResult {
+    pub command: u16,
+    pub result: u8,
+    pub result_name: &'static str,
+    pub accepted: bool,
+    pub attempts: u8,
+}
+
+#[derive(Debug, Clone)]
+pub struct MavlinkTelemetry {
+    pub device_id: i32,
+    pub system_id: u8,
+    pub message_name: String,
+    pub payload: JsonValue,
+    pub timestamp: chrono::DateTime<chrono::FixedOffset>,
+}
+
+/// MAVLink UDP 遥测接入服务
+pub struct MavlinkService {
+    db: Arc<DatabaseConnection>,
+    settings: MavlinkSettings,
+    message_sender: broadcast::Sender<MavlinkTelemetry>,
+    /// 系统ID -> 设备ID
+    system_devices: Arc<RwLock<HashMap<u8, i32>>>,
+    /// 系统ID -> 最近一次收到数据的端点
+    system_peers: Arc<RwLock<HashMap<u8, MavlinkPeer>>>,
+    last_mapping_reload: Arc<Mutex<Option<Instant>>>,
+    pending_acks: PendingAcks,
+    socket: RwLock<Option<Arc<UdpSocket>>>,
+    sequence: AtomicU8,
+    shutdown_tx: Mutex<Option<oneshot::Sender<()>>>,
+}
+
+impl MavlinkService {
+    pub fn new(
+        db: Arc<DatabaseConnection>,
+        settings: MavlinkSettings,
+    ) -> (Self, broadcast::Receiver<MavlinkTelemetry>) {
+        let (message_sender, message_receiver) = broadcast::channel(1000);
+
+        let service = Self {
+            db,
+            settings,
+            message_sender,
+            system_devices: Arc::new(RwLock::new(HashMap::new())),
+            system_peers: Arc::new(RwLock::new(HashMap::new())),
+            last_mapping_reload: Arc::new(Mutex::new(None)),
+            pending_acks: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            socket: RwLock::new(None),
+            sequence: AtomicU8::new(0),
+            shutdown_tx: Mutex::new(None),
+        };
+
+        (service, message_receiver)
+    }
+
+    /// 启动UDP监听
+    pub async fn start(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if !self.settings.enable {
+            info!("MAVLink listener is disabled");
+            return Ok(());
+        }
+
+        self.load_system_mappings().await?;
+
+        let addr = format!("{}:{}", self.settings.binding, self.settings.port);
+        let socket = UdpSocket::bind(&addr).await?;
+        info!("MAVLink UDP listener bound on {}", addr);
+        self.listen(socket).await;
+
+        Ok(())
+    }
+
+    /// 在已绑定的套接字上接收数据
+    async fn listen(&self, socket: UdpSocket) {
+        let socket = Arc::new(socket);
+        *self.socket.write().await = Some(Arc::clone(&socket));
+
+        let (shutdown_tx, mut shutdown_rx) = oneshot::channel();
+        *self.shutdown_tx.lock().await = Some(shutdown_tx);
+
+        let db = Arc::clone(&self.db);
+        let message_sender = self.message_sender.clone();
+        let system_devices = Arc::clone(&self.system_devices);
+        let system_peers = Arc::clone(&self.system_peers);
+        let last_mapping_reload = Arc::clone(&self.last_mapping_reload);
+        let pending_acks = Arc::clone(&self.pending_acks);
+
+        tokio::spawn(async move {
+            let mut buffer = [0u8; 2048];
+            let mut parsers: HashMap<SocketAddr, MavlinkParser> = HashMap::new();
+            let mut unknown_systems: HashSet<u8> = HashSet::new();
+
+            loop {
+                let (len, peer) = tokio::select! {
+                    result = socket.recv_from(&mut buffer) => match result {
+                        Ok(received) => received,
+                        Err(e) => {
+                            error!("MAVLink UDP receive error: {}", e);
+                            tokio::time::sleep(RECV_ERROR_BACKOFF).await;
+                            continue;
+                        }
+                    },
+                    _ = &mut shutdown_rx => {
+                        info!("MAVLink listener shutdown signal received");
+                        break;
+                    }
+                };
+
+                let parser = parsers.entry(peer).or_default();
+                parser.push(&buffer[..len]);
+
+                while let Some(frame) = parser.next_frame() {
+                    system_peers.write().await.insert(
+                        frame.system_id,
+                        MavlinkPeer {
+                            addr: peer,
+                            version: frame.version,
+                            last_seen: Instant::now(),
+                        },
+                    );
+
+                    let mut device_id = system_devices.read().await.get(&frame.system_id).copied();
+                    if device_id.is_none() {
+                        // 未知系统ID，按间隔尝试重新加载映射
+                        let should_reload = {
+                            let mut last_reload = last_mapping_reload.lock().await;
+                            let due =
+                                last_reload.is_none_or(|t| t.elapsed() >= MAPPING_RELOAD_INTERVAL);
+                            if due {
+                                *last_reload = Some(Instant::now());
+                            }
+                            due
+                        };
+                        if should_reload {
+                            match query_system_mappings(&db).await {
+                                Ok(mappings) => *system_devices.write().await = mappings,
+                                Err(e) => error!("Failed to reload MAVLink mappings: {}", e),
+                            }
+                            device_id = system_devices.read().await.get(&frame.system_id).copied();
+                        }
+                    }
+
+                    let Some(device_id) = device_id else {
+                        if unknown_systems.insert(frame.system_id) {
+                            warn!(
+                                "Received MAVLink data from unmapped system {} ({})",
+                                frame.system_id, peer
+                            );
+                        }
+                        continue;
+                    };
+                    unknown_systems.remove(&frame.system_id);
+
+                    let Some(message) = MavlinkMessage::decode(&frame) else {
+                        continue;
+                    };
+
+                    // 命令应答交给等待中的请求
+                    if let MavlinkMessage::CommandAck(ack) = &message {
+                        let pending = pending_acks.lock().unwrap_or_else(|e| e.into_inner());
+                        if let Some(waiter) = pending.get(&(frame.system_id, ack.command)) {
+                            let _ = waiter.send(ack.clone());
+                        }
+                    }
+
+                    let telemetry = MavlinkTelemetry {
+                        device_id,
+                        system_id: frame.system_id,
+                        message_name: message.name().to_string(),
+                        payload: message.to_state_fields(),
+                        timestamp: chrono::Utc::now()
+                            .with_timezone(&chrono::FixedOffset::east_opt(8 * 3600).unwrap()),
+                    };
+
+                    if let Err(e) = message_sender.send(telemetry) {
+                        warn!("Failed to broadcast MAVLink message: {}", e);
+                    }
+                }
+            }
+        });
+    }
+
+    /// 停止UDP监听
+    pub async fn stop(&self) {
+        if let Some(shutdown_tx) = self.shutdown_tx.lock().await.take() {
+            let _ = shutdown_tx.send(());
+        }
+        *self.socket.write().await = None;
+    }
+
+    /// 向设备下发命令并等待 COMMAND_ACK
+    ///
+    /// 超时未应答时重发，全部超时返回错误。同一命令已在等待应答时直接拒绝。
+    pub async fn send_command(
+        &self,
+        device_id: i32,
+        command: &MavlinkCommand,
+    ) -> Result<MavlinkCommandResult, Box<dyn std::error::Error + Send + Sync>> {
+        let socket = self
+            .socket
+            .read()
+            .await
+            .clone()
+            .ok_or("MAVLink listener is not running")?;
+        let system_id = self
+            .get_system_id(device_id)
+            .await
+            .ok_or_else(|| format!("Device {} has no MAVLink system ID", device_id))?;
+        let peer = self
+            .system_peers
+            .read()
+            .await
+            .get(&system_id)
+            .copied()
+            .ok_or_else(|| format!("MAVLink system {} has not been seen yet", system_id))?;
+
+        let mut message = command.to_message(system_id);
+        let Some((_pending, mut ack_rx)) =
+            PendingAck::register(&self.pending_acks, (system_id, message.command()))
+        else {
+            return Err(format!(
+                "MAVLink command {} to system {} is already waiting for an acknowledgement",
+                message.command(),
+                system_id
+            )
+            .into());
+        };
+
+        for attempt in 1..=COMMAND_MAX_ATTEMPTS {
+            let frame = mavlink::encode_frame(
+                peer.version,
+                self.sequence.fetch_add(1, Ordering::Relaxed),
+                GCS_SYSTEM_ID,
+                GCS_COMPONENT_ID,
+                message.message_id(),
+                &message.encode(),
+            )
+            .ok_or("Failed to encode MAVLink command")?;
+
+            socket.send_to(&frame, peer.addr).await?;
+
+            match tokio::time::timeout(COMMAND_ACK_TIMEOUT, ack_rx.recv()).await {
+                Ok(Some(ack)) => {
+                    info!(
+                        "MAVLink command {} to system {} acknowledged: {}",
+                        ack.command,
+                        system_id,
+                        mav_result::name(ack.result)
+                    );
+                    return Ok(MavlinkCommandResult {
+                        command: ack.command,
+                        result: ack.result,
+                        result_name: mav_result::name(ack.result),
+                        accepted: ack.result == mav_result::ACCEPTED
+                            || ack.result == mav_result::IN_PROGRESS,
+                        attempts: attempt,
+                    });
+                }
+                _ => {
+                    warn!(
+                        "MAVLink command {} to system {} not acknowledged (attempt {})",
+                        message.command(),
+                        system_id,
+                        attempt
+                    );
+                    message.next_attempt();
+                }
+            }
+        }
+
+        Err(format!(
+            "MAVLink command {} was not acknowledged by system {}",
+            message.command(),
+            system_id
+        )
+        .into())
+    }
+
+    /// 从数据库加载系统ID映射
+    pub async fn load_system_mappings(
+        &self,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mappings = query_system_mappings(&self.db).await?;
+        info!("Loaded {} MAVLink system mappings", mappings.len());
+        *self.system_devices.write().await = mappings;
+        Ok(())
+    }
+
+    /// 获取系统ID对应的设备ID
+    pub async fn get_device_id(&self, system_id: u8) -> Option<i32> {
+        self.system_devices.read().await.get(&system_id).copied()
+    }
+
+    /// 获取设备对应的系统ID
+    pub async fn get_system_id(&self, device_id: i32) -> Option<u8> {
+        self.system_devices
+            .read()
+            .await
+            .iter()
+            .find(|(_, id)| **id == device_id)
+            .map(|(system_id, _)| *system_id)
+    }
+
+    /// 获取设备最近一次发送数据的地址
+    pub async fn get_device_addr(&self, device_id: i32) -> Option<SocketAddr> {
+        let system_id = self.get_system_id(device_id).await?;
+        self.system_peers
+            .read()
+            .await
+            .get(&system_id)
+            .map(|peer| peer.addr)
+    }
+
+    /// 检查设备最近是否通过MAVLink发送过数据
+    pub async fn is_device_online(&self, device_id: i32) -> bool {
+        match self.get_system_id(device_id).await {
+            Some(system_id) => self
+                .system_peers
+                .read()
+                .await
+                .get(&system_id)
+                .is_some_and(|peer| peer.last_seen.elapsed() < ONLINE_TIMEOUT),
+            None => false,
+        }
+    }
+
+    /// 获取消息发送器的克隆
+    pub fn get_message_sender(&self) -> broadcast::Sender<MavlinkTelemetry> {
+        self.message_sender.clone()
+    }
+}
+
+/// 查询所有配置了MAVLink系统ID的设备
+async fn query_system_mappings(
+    db: &DatabaseConnection,
+) -> Result<HashMap<u8, i32>, sea_orm::DbErr> {
+    use sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
+
+    let devices = device::Entity::find()
+        .filter(device::Column::MavlinkSystemId.is_not_null())
+        .filter(device::Column::IsActive.eq(true))
+        .all(db)
+        .await?;
+
+    Ok(devices
+        .into_iter()
+        .filter_map(|d| {
+            d.mavlink_system_id
+                .and_then(|system_id| u8::try_from(system_id).ok())
+                .map(|system_id| (system_id, d.id))
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SYSTEM_ID: u8 = 42;
+    const DEVICE_ID: i32 = 7;
+
+    /// 模拟飞控对 COMMAND_LONG 的应答方式
+    #[derive(Clone, Copy)]
+    enum Reply {
+        Ack(u8),
+        Silent,
+    }
+
+    /// 模拟飞控收到的命令 (命令ID, confirmation)
+    type Received = Arc<std::sync::Mutex<Vec<(u16, u8)>>>;
+
+    async fn start_service() -> (
+        MavlinkService,
+        broadcast::Receiver<MavlinkTelemetry>,
+        SocketAddr,
+    ) {
+        let (service, telemetry) = MavlinkService::new(
+            Arc::new(DatabaseConnection::Disconnected),
+            MavlinkSettings::default(),
+        );
+        service
+            .system_devices
+            .write()
+            .await
+            .insert(SYSTEM_ID, DEVICE_ID);
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        service.listen(socket).await;
+        (service, telemetry, addr)
+    }
+
+    /// 启动模拟飞控：先发送 HEARTBEAT 让服务记录端点，之后按 `reply` 应答收到的命令
+    async fn start_autopilot(
+        service: &MavlinkService,
+        service_addr: SocketAddr,
+        reply: Reply,
+    ) -> Received {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let heartbeat = mavlink::encode_frame(
+            MavlinkVersion::V2,
+            0,
+            SYSTEM_ID,
+            AUTOPILOT_COMPONENT_ID,
+            msg_id::HEARTBEAT,
+            &[0, 0, 0, 0, 2, 3, 0x81, 4, 3],
+        )
+        .unwrap();
+        socket.send_to(&heartbeat, service_addr).await.unwrap();
+        while !service.is_device_online(DEVICE_ID).await {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+
+        let received = Received::default();
+        let commands = Arc::clone(&received);
+        tokio::spawn(async move {
+            let mut buffer = [0u8; 512];
+            let mut parser = MavlinkParser::new();
+            let mut sequence: u8 = 1;
+            loop {
+                let (len, gcs) = socket.recv_from(&mut buffer).await.unwrap();
+                parser.push(&buffer[..len]);
+                while let Some(frame) = parser.next_frame() {
+                    assert_eq!(frame.message_id, msg_id::COMMAND_LONG);
+                    let mut payload = frame.payload.clone();
+                    payload.resize(33, 0);
+                    let command = u16::from_le_bytes([payload[28], payload[29]]);
+                    commands.lock().unwrap().push((command, payload[32]));
+
+                    let Reply::Ack(result) = reply else {
+                        continue;
+                    };
+                    let [low, high] = command.to_le_bytes();
+                    let ack = mavlink::encode_frame(
+                        frame.version,
+                        sequence,
+                        SYSTEM_ID,
+                        AUTOPILOT_COMPONENT_ID,
+                        msg_id::COMMAND_ACK,
+                        &[low, high, result],
+                    )
+                    .unwrap();
+                    socket.send_to(&ack, gcs).await.unwrap();
+                    sequence = sequence.wrapping_add(1);
+                }
+            }
+        });
+        received
+    }
+
+    #[tokio::test]
+    async fn command_is_acknowledged() {
+        let (service, _telemetry, addr) = start_service().await;
+        let received = start_autopilot(&service, addr, Reply::Ack(mav_result::ACCEPTED)).await;
+
+        let result = service
+            .send_command(DEVICE_ID, &MavlinkCommand::Arm)
+            .await
+            .unwrap();
+
+        assert!(result.accepted);
+        assert_eq!(result.command, mav_cmd::COMPONENT_ARM_DISARM);
+        assert_eq!(result.result_name, "ACCEPTED");
+        assert_eq!(result.attempts, 1);
+        assert_eq!(
+            *received.lock().unwrap(),
+            [(mav_cmd::COMPONENT_ARM_DISARM, 0)]
+        );
+    }
+
+    #[tokio::test]
+    async fn command_is_rejected() {
+        let (service, _telemetry, addr) = start_service().await;
+        start_autopilot(&service, addr, Reply::Ack(mav_result::DENIED)).await;
+
+        let result = service
+            .send_command(DEVICE_ID, &MavlinkCommand::Takeoff { altitude: 10.0 })
+            .await
+            .unwrap();
+
+        assert!(!result.accepted);
+        assert_eq!(result.command, mav_cmd::NAV_TAKEOFF);
+        assert_eq!(result.result_name, "DENIED");
+        assert_eq!(result.attempts, 1);
+    }
+
+    #[tokio::test]
+    async fn command_is_retried_until_timeout() {
+        let (service, _telemetry, addr) = start_service().await;
+        let received = start_autopilot(&service, addr, Reply::Silent).await;
+        let service = Arc::new(service);
+
+        let started = Instant::now();
+        let first = tokio::spawn({
+            let service = Arc::clone(&service);
+            async move { service.send_command(DEVICE_ID, &MavlinkCommand::Land).await }
+        });
+        while received.lock().unwrap().is_empty() {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+
+        // 同一命令在等待应答时不能再次下发
+        let second = service.send_command(DEVICE_ID, &MavlinkCommand::Land).await;
+        assert!(second.unwrap_err().to_string().contains("already waiting"));
+
+        assert!(first.await.unwrap().is_err());
+        assert!(started.elapsed() >= COMMAND_ACK_TIMEOUT * u32::from(COMMAND_MAX_ATTEMPTS));
+        assert_eq!(
+            *received.lock().unwrap(),
+            [
+                (mav_cmd::NAV_LAND, 0),
+                (mav_cmd::NAV_LAND, 1),
+                (mav_cmd::NAV_LAND, 2),
+            ]
+        );
+        assert!(service.pending_acks.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn reposition_uses_command_int() {
+        let command = MavlinkCommand::Reposition {
+            latitude: 30.1234567,
+            longitude: 120.4567891,
+            altitude: 50.0,
+            speed: None,
+        };
+        let CommandMessage::Int(message) = command.to_message(SYSTEM_ID) else {
+            panic!("DO_REPOSITION should be sent as COMMAND_INT");
+        };
+        assert_eq!(message.command, mav_cmd::DO_REPOSITION);
+        assert_eq!(message.x, 301_234_567);
+        assert_eq!(message.y, 1_204_567_891);
+        assert_eq!(message.encode().len(), 35);
+    }
+}
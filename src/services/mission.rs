@@ -0,0 +1,520 @@
+//! 航线规划
+//!
+//! 地理步骤在执行前展开为航点，高度均为相对起飞点高度（米）：
+//! - `waypoint`：单个航点，`latitude`、`longitude`、`altitude`，可选 `speed`（米/秒）、`loiter`
+//!   （到达后悬停秒数）和 `camera_action`（`photo` 到达后拍照，默认 `none`）；
+//! - `survey`：在 `polygon`（`[[纬度, 经度], ...]`）内按 `altitude` 生成往返扫描航线。航线方向为
+//!   `angle`（正北为 0 度，默认 0），间距由 `spacing`（米）指定，或按相机水平视场角 `camera_fov`（默认
+//!   84 度）和旁向重叠率 `overlap`（默认 70%）计算。`camera_action` 为 `photo`（默认）时沿航线按航向
+//!   重叠率 `front_overlap`（默认同 `overlap`）逐点拍照，为 `none` 时只生成航线端点；
+//! - `orbit`：以 `latitude`、`longitude` 为中心、`radius`（米）为半径按 `altitude` 环绕 `turns` 圈
+//!   （默认 1），每圈 `points` 个航点（默认 12），从方位角 `start_bearing`（默认 0）开始，`clockwise`
+//!   默认顺时针，`camera_action` 默认 `none`。
+//!
+//! 扫描和环绕步骤可以指定 `speed`，作用于生成的每个航点。[`MissionPlanner`] 估算航线的水平距离和飞行
+//! 时间，并按设备机型的电池续航校验。
+
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+
+use crate::models::task::TaskStep;
+use crate::services::flight::{haversine_distance, EARTH_RADIUS_M};
+use crate::services::task_executor::{DroneCommand, PlannedStep, StepAction};
+use crate::services::telemetry::{as_bool, as_f64};
+use crate::settings::MissionSettings;
+
+const DEFAULT_CAMERA_FOV: f64 = 84.0;
+const DEFAULT_OVERLAP: f64 = 70.0;
+const MAX_OVERLAP: f64 = 95.0;
+const DEFAULT_ORBIT_POINTS: u32 = 12;
+/// 单个步骤最多生成的航点数
+const MAX_WAYPOINTS: usize = 2000;
+/// 航线端点之间短于该长度（米）时只生成一个航点
+const MIN_SEGMENT_LENGTH: f64 = 0.5;
+
+/// 到达航点后的相机动作
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CameraAction {
+    None,
+    Photo,
+}
+
+/// 航点
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Waypoint {
+    pub latitude: f64,
+    pub longitude: f64,
+    /// 相对起飞点高度（米）
+    pub altitude: f64,
+    /// 水平速度（米/秒），不指定时使用飞控默认值
+    pub speed: Option<f64>,
+    /// 到达后悬停时间（秒）
+    pub loiter: f64,
+    pub camera_action: CameraAction,
+}
+
+/// 是否为需要展开为航点的地理步骤
+pub fn is_geographic(step_type: &str) -> bool {
+    matches!(step_type, "waypoint" | "survey" | "orbit")
+}
+
+/// 将地理步骤展开为航点
+pub fn waypoints(step: &TaskStep) -> Result<Vec<Waypoint>, String> {
+    let params = StepParams(step);
+    let waypoints = match step.step_type.as_str() {
+        "waypoint" => vec![Waypoint {
+            latitude: params.latitude("latitude")?,
+            longitude: params.longitude("longitude")?,
+            altitude: params.positive("altitude")?,
+            speed: params.speed()?,
+            loiter: params.non_negative("loiter")?.unwrap_or(0.0),
+            camera_action: params.camera_action(CameraAction::None)?,
+        }],
+        "survey" => survey(&params)?,
+        "orbit" => orbit(&params)?,
+        other => return Err(format!("Unsupported step type: {}", other)),
+    };
+    if waypoints.is_empty() {
+        return Err(format!("{} produced no waypoints", step.step_type));
+    }
+    Ok(waypoints)
+}
+
+/// 步骤参数读取
+struct StepParams<'a>(&'a TaskStep);
+
+impl StepParams<'_> {
+    fn get(&self, key: &str) -> Option<&JsonValue> {
+        self.0.parameters.get(key).filter(|value| !value.is_null())
+    }
+
+    fn number(&self, key: &str) -> Result<Option<f64>, String> {
+        match self.get(key) {
+            None => Ok(None),
+            Some(value) => as_f64(value)
+                .filter(|value| value.is_finite())
+                .map(Some)
+                .ok_or_else(|| format!("{} {} must be a number", self.0.step_type, key)),
+        }
+    }
+
+    fn required(&self, key: &str) -> Result<f64, String> {
+        self.number(key)?
+            .ok_or_else(|| format!("{} requires numeric {}", self.0.step_type, key))
+    }
+
+    fn positive(&self, key: &str) -> Result<f64, String> {
+        let value = self.required(key)?;
+        if value <= 0.0 {
+            return Err(format!("{} {} must be positive", self.0.step_type, key));
+        }
+        Ok(value)
+    }
+
+    fn non_negative(&self, key: &str) -> Result<Option<f64>, String> {
+        let value = self.number(key)?;
+        if value.is_some_and(|value| value < 0.0) {
+            return Err(format!("{} {} must not be negative", self.0.step_type, key));
+        }
+        Ok(value)
+    }
+
+    fn latitude(&self, key: &str) -> Result<f64, String> {
+        let value = self.required(key)?;
+        if !(-90.0..=90.0).contains(&value) {
+            return Err(format!("{} {} is out of range", self.0.step_type, key));
+        }
+        Ok(value)
+    }
+
+    fn longitude(&self, key: &str) -> Result<f64, String> {
+        let value = self.required(key)?;
+        if !(-180.0..=180.0).contains(&value) {
+            return Err(format!("{} {} is out of range", self.0.step_type, key));
+        }
+        Ok(value)
+    }
+
+    fn speed(&self) -> Result<Option<f64>, String> {
+        match self.number("speed")? {
+            Some(speed) if speed <= 0.0 => {
+                Err(format!("{} speed must be positive", self.0.step_type))
+            }
+            speed => Ok(speed),
+        }
+    }
+
+    /// 重叠率（百分比）
+    fn overlap(&self, key: &str, default: f64) -> Result<f64, String> {
+        let value = self.number(key)?.unwrap_or(default);
+        if !(0.0..=MAX_OVERLAP).contains(&value) {
+            return Err(format!(
+                "{} {} must be between 0 and {}",
+                self.0.step_type, key, MAX_OVERLAP
+            ));
+        }
+        Ok(value)
+    }
+
+    fn camera_action(&self, default: CameraAction) -> Result<CameraAction, String> {
+        match self.get("camera_action") {
+            None => Ok(default),
+            Some(value) => match value.as_str() {
+                Some("none") => Ok(CameraAction::None),
+                Some("photo") => Ok(CameraAction::Photo),
+                _ => Err(format!(
+                    "{} camera_action must be none or photo",
+                    self.0.step_type
+                )),
+            },
+        }
+    }
+}
+
+/// 以某点为原点的局部平面坐标（东向、北向，米），适用于数公里范围内的航线
+pub(crate) struct LocalFrame {
+    latitude: f64,
+    longitude: f64,
+    cos_latitude: f64,
+}
+
+impl LocalFrame {
+    /// 每度纬度对应的米数
+    const METERS_PER_DEGREE: f64 = EARTH_RADIUS_M * std::f64::consts::PI / 180.0;
+
+    pub(crate) fn new(latitude: f64, longitude: f64) -> Self {
+        Self {
+            latitude,
+            longitude,
+            cos_latitude: latitude.to_radians().cos().max(1e-6),
+        }
+    }
+
+    pub(crate) fn to_local(&self, latitude: f64, longitude: f64) -> (f64, f64) {
+        (
+            (longitude - self.longitude) * Self::METERS_PER_DEGREE * self.cos_latitude,
+            (latitude - self.latitude) * Self::METERS_PER_DEGREE,
+        )
+    }
+
+    pub(crate) fn to_geo(&self, east: f64, north: f64) -> (f64, f64) {
+        (
+            self.latitude + north / Self::METERS_PER_DEGREE,
+            self.longitude + east / (Self::METERS_PER_DEGREE * self.cos_latitude),
+        )
+    }
+}
+
+fn parse_polygon(value: Option<&JsonValue>) -> Result<Vec<(f64, f64)>, String> {
+    let invalid = || "survey polygon must be an array of [latitude, longitude] points".to_string();
+    let points = value.and_then(JsonValue::as_array).ok_or_else(invalid)?;
+    let polygon = points
+        .iter()
+        .map(|point| match point.as_array().map(Vec::as_slice) {
+            Some([latitude, longitude]) => {
+                let latitude = as_f64(latitude).filter(|v| (-90.0..=90.0).contains(v));
+                let longitude = as_f64(longitude).filter(|v| (-180.0..=180.0).contains(v));
+                latitude.zip(longitude).ok_or_else(invalid)
+            }
+            _ => Err(invalid()),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if polygon.len() < 3 {
+        return Err("survey polygon requires at least 3 points".to_string());
+    }
+    Ok(polygon)
+}
+
+/// 扫描航线：在多边形内沿 `angle` 方向生成平行航线，相邻航线方向相反
+fn survey(params: &StepParams) -> Result<Vec<Waypoint>, String> {
+    let polygon = parse_polygon(params.get("polygon"))?;
+    let altitude = params.positive("altitude")?;
+    let speed = params.speed()?;
+    let camera_action = params.camera_action(CameraAction::Photo)?;
+    let fov = params.number("camera_fov")?.unwrap_or(DEFAULT_CAMERA_FOV);
+    if !(1.0..180.0).contains(&fov) {
+        return Err("survey camera_fov must be between 1 and 180 degrees".to_string());
+    }
+    // 相机垂直向下时单张照片覆盖的地面宽度
+    let footprint = 2.0 * altitude * (fov / 2.0).to_radians().tan();
+    let overlap = params.overlap("overlap", DEFAULT_OVERLAP)?;
+    let front_overlap = params.overlap("front_overlap", overlap)?;
+    let spacing = match params.number("spacing")? {
+        Some(spacing) if spacing <= 0.0 => {
+            return Err("survey spacing must be positive".to_string());
+        }
+        Some(spacing) => spacing,
+        None => footprint * (1.0 - overlap / 100.0),
+    };
+    let photo_interval = footprint * (1.0 - front_overlap / 100.0);
+    let angle = params.number("angle")?.unwrap_or(0.0);
+
+    let count = polygon.len() as f64;
+    let frame = LocalFrame::new(
+        polygon.iter().map(|point| point.0).sum::<f64>() / count,
+        polygon.iter().map(|point| point.1).sum::<f64>() / count,
+    );
+    // 旋转到航线坐标系：u 垂直于航线，v 沿航线方向
+    let (sin, cos) = angle.to_radians().sin_cos();
+    let to_line = |(east, north): (f64, f64)| (east * cos - north * sin, east * sin + north * cos);
+    let from_line = |(u, v): (f64, f64)| frame.to_geo(u * cos + v * sin, -u * sin + v * cos);
+    let vertices: Vec<(f64, f64)> = polygon
+        .iter()
+        .map(|&(latitude, longitude)| to_line(frame.to_local(latitude, longitude)))
+        .collect();
+
+    let u_min = vertices.iter().map(|v| v.0).fold(f64::INFINITY, f64::min);
+    let u_max = vertices
+        .iter()
+        .map(|v| v.0)
+        .fold(f64::NEG_INFINITY, f64::max);
+    let width = u_max - u_min;
+    let lines = ((width / spacing).ceil() as usize).max(1);
+    if lines > MAX_WAYPOINTS {
+        return Err(format!(
+            "survey would generate more than {} waypoints",
+            MAX_WAYPOINTS
+        ));
+    }
+    // 航线在多边形宽度内居中
+    let first = u_min + (width - (lines - 1) as f64 * spacing) / 2.0;
+
+    let mut points = Vec::new();
+    for line in 0..lines {
+        let u = first + line as f64 * spacing;
+        let mut crossings: Vec<f64> = vertices
+            .iter()
+            .zip(vertices.iter().cycle().skip(1))
+            .filter(|(a, b)| (a.0 <= u && u < b.0) || (b.0 <= u && u < a.0))
+            .map(|(a, b)| a.1 + (u - a.0) * (b.1 - a.1) / (b.0 - a.0))
+            .collect();
+        crossings.sort_by(f64::total_cmp);
+        let mut segments: Vec<(f64, f64)> = crossings
+            .chunks_exact(2)
+            .map(|pair| (pair[0], pair[1]))
+            .collect();
+        if line % 2 == 1 {
+            segments = segments.into_iter().rev().map(|(a, b)| (b, a)).collect();
+        }
+
+        for (from, to) in segments {
+            let length = (to - from).abs();
+            let steps = if length < MIN_SEGMENT_LENGTH {
+                0
+            } else if camera_action == CameraAction::Photo {
+                ((length / photo_interval).ceil() as usize).max(1)
+            } else {
+                1
+            };
+            for step in 0..=steps {
+                let v = if steps == 0 {
+                    (from + to) / 2.0
+                } else {
+                    from + (to - from) * step as f64 / steps as f64
+                };
+                points.push(from_line((u, v)));
+                if points.len() > MAX_WAYPOINTS {
+                    return Err(format!(
+                        "survey would generate more than {} waypoints",
+                        MAX_WAYPOINTS
+                    ));
+                }
+            }
+        }
+    }
+
+    Ok(points
+        .into_iter()
+        .map(|(latitude, longitude)| Waypoint {
+            latitude,
+            longitude,
+            altitude,
+            speed,
+            loiter: 0.0,
+            camera_action,
+        })
+        .collect())
+}
+
+/// 环绕航线：从起始方位角开始沿圆周生成航点，最后回到起点
+fn orbit(params: &StepParams) -> Result<Vec<Waypoint>, String> {
+    let latitude = params.latitude("latitude")?;
+    let longitude = params.longitude("longitude")?;
+    let radius = params.positive("radius")?;
+    let altitude = params.positive("altitude")?;
+    let speed = params.speed()?;
+    let camera_action = params.camera_action(CameraAction::None)?;
+    let turns = params.number("turns")?.unwrap_or(1.0);
+    if turns <= 0.0 {
+        return Err("orbit turns must be positive".to_string());
+    }
+    let points_per_turn = params
+        .number("points")?
+        .unwrap_or(DEFAULT_ORBIT_POINTS.into());
+    if points_per_turn < 3.0 || points_per_turn.fract() != 0.0 {
+        return Err("orbit points must be an integer of at least 3".to_string());
+    }
+    let start_bearing = params.number("start_bearing")?.unwrap_or(0.0);
+    let clockwise = params.get("clockwise").and_then(as_bool).unwrap_or(true);
+
+    let total = (turns * points_per_turn).ceil() as usize;
+    if total >= MAX_WAYPOINTS {
+        return Err(format!(
+            "orbit would generate more than {} waypoints",
+            MAX_WAYPOINTS
+        ));
+    }
+    let step_angle = 360.0 / points_per_turn * if clockwise { 1.0 } else { -1.0 };
+    let frame = LocalFrame::new(latitude, longitude);
+
+    Ok((0..=total)
+        .map(|index| {
+            let (sin, cos) = (start_bearing + step_angle * index as f64)
+                .to_radians()
+                .sin_cos();
+            let (latitude, longitude) = frame.to_geo(radius * sin, radius * cos);
+            Waypoint {
+                latitude,
+                longitude,
+                altitude,
+                speed,
+                loiter: 0.0,
+                camera_action,
+            }
+        })
+        .collect())
+}
+
+/// 机型的运动参数
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DroneProfile {
+    /// 水平飞行速度（米/秒）
+    pub cruise_speed: f64,
+    /// 爬升速度（米/秒）
+    pub climb_rate: f64,
+    /// 下降速度（米/秒）
+    pub descent_rate: f64,
+    /// 转向速度（度/秒）
+    pub yaw_rate: f64,
+    /// 电池续航（秒）
+    pub endurance_secs: u32,
+}
+
+impl DroneProfile {
+    /// 从 `from` 高度到 `to` 高度所需时间（秒）
+    pub fn climb_time(&self, from: f64, to: f64) -> f64 {
+        if to >= from {
+            (to - from) / self.climb_rate
+        } else {
+            (from - to) / self.descent_rate
+        }
+    }
+}
+
+/// 航线估算结果
+#[derive(Debug, Clone, Serialize)]
+pub struct MissionEstimate {
+    pub waypoint_count: usize,
+    /// 水平飞行距离（米），不含从当前位置到第一个航点的距离时 `from_current_position` 为 false
+    pub distance_m: f64,
+    /// 预计飞行时间（秒）
+    pub flight_time_secs: f64,
+    pub from_current_position: bool,
+    pub drone_model: Option<String>,
+    /// 机型电池续航（秒）
+    pub endurance_secs: u32,
+    /// 扣除预留电量后可用的飞行时间（秒）
+    pub usable_endurance_secs: f64,
+    pub within_endurance: bool,
+}
+
+impl MissionEstimate {
+    /// 预计飞行时间超出可用续航时返回错误
+    pub fn check(&self) -> Result<(), String> {
+        if self.within_endurance {
+            return Ok(());
+        }
+        Err(format!(
+            "Estimated flight time {:.0} s ({:.0} m) exceeds usable endurance {:.0} s of drone model {}",
+            self.flight_time_secs,
+            self.distance_m,
+            self.usable_endurance_secs,
+            self.drone_model.as_deref().unwrap_or("(default)")
+        ))
+    }
+}
+
+/// 航线估算
+pub struct MissionPlanner {
+    settings: MissionSettings,
+}
+
+impl MissionPlanner {
+    pub fn new(settings: MissionSettings) -> Self {
+        Self { settings }
+    }
+
+    pub fn settings(&self) -> &MissionSettings {
+        &self.settings
+    }
+
+    /// 估算航线距离和飞行时间。水平飞行按航点速度或机型巡航速度计算，起降和高度变化按机型爬升、
+    /// 下降速度计算，转向和拍照不计时间；`origin` 为设备当前位置，未知时从第一个航点开始计算水平距离。
+    pub fn estimate(
+        &self,
+        steps: &[PlannedStep],
+        origin: Option<(f64, f64)>,
+        drone_model: Option<&str>,
+    ) -> MissionEstimate {
+        let profile = self.settings.profile_for(drone_model);
+        let climb = |from: f64, to: f64| profile.climb_time(from, to);
+
+        let mut position = origin;
+        let mut altitude = 0.0;
+        let mut distance = 0.0;
+        let mut time = 0.0;
+        let mut waypoint_count = 0;
+        for step in steps {
+            match &step.action {
+                StepAction::Command(DroneCommand::Takeoff { altitude: target })
+                | StepAction::Command(DroneCommand::MoveToHeight { height: target }) => {
+                    time += climb(altitude, *target);
+                    altitude = *target;
+                }
+                StepAction::Command(DroneCommand::Land) => {
+                    time += climb(altitude, 0.0);
+                    altitude = 0.0;
+                }
+                StepAction::Command(_) => {}
+                StepAction::Wait(duration) => time += duration.as_secs_f64(),
+                StepAction::Waypoint(waypoint) => {
+                    let target = (waypoint.latitude, waypoint.longitude);
+                    if let Some(from) = position {
+                        let leg = haversine_distance(from, target);
+                        distance += leg;
+                        time += leg / waypoint.speed.unwrap_or(profile.cruise_speed);
+                    }
+                    time += climb(altitude, waypoint.altitude) + waypoint.loiter;
+                    altitude = waypoint.altitude;
+                    position = Some(target);
+                    waypoint_count += 1;
+                }
+            }
+        }
+
+        let endurance = profile.endurance_secs;
+        let reserve = (self.settings.reserve_percent / 100.0).clamp(0.0, 1.0);
+        let usable = f64::from(endurance) * (1.0 - reserve);
+        MissionEstimate {
+            waypoint_count,
+            distance_m: distance,
+            flight_time_secs: time,
+            from_current_position: origin.is_some(),
+            drone_model: drone_model.map(str::to_string),
+            endurance_secs: endurance,
+            usable_endurance_secs: usable,
+            within_endurance: time <= usable,
+        }
+    }
+}
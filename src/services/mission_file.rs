@@ -0,0 +1,907 @@
+//! 航线文件导入导出
+//!
+//! 支持 QGroundControl `.plan`（JSON）以及 KML/KMZ 路径：
+//! - 导入 `.plan` 时按任务项逐条转换为任务步骤：起飞（22）、航点（16，悬停时间为 `loiter`）、定点悬停
+//!   （19）、降落（21）、返航（20，飞回 `plannedHomePosition` 后降落）、延时（93）、改变速度（178，作用于
+//!   之后的航点）、改变高度（186）、转向（115）、拍照（2000、203，紧跟航点时作为航点的相机动作），
+//!   QGC 扫描（survey）复合项转换为 `survey` 步骤。航点高度只支持相对起飞点高度（frame 3），其它命令和
+//!   高度基准返回错误，不静默丢弃；
+//! - 导入 KML/KMZ 时依次连接文档中的 LineString，没有 LineString 时按顺序使用 Point，并在航点前后添加
+//!   起飞和降落。坐标高度仅在 `altitudeMode` 为 `relativeToGround` 时使用，其它情况需要指定统一高度；
+//! - 导出时先按 [`parse_parameters`](crate::services::task_executor::parse_parameters) 展开步骤，扫描和
+//!   环绕导出为逐个航点。KML 只包含航线路径和航点，不含起降、拍照等动作。
+
+use chrono::{Datelike, Timelike};
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use serde_json::{json, Value as JsonValue};
+use std::io::{Cursor, Read, Write};
+use zip::result::ZipError;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, DateTime, ZipArchive, ZipWriter};
+
+use crate::models::task::{TaskParameters, TaskStep};
+use crate::services::export::xml_escape;
+use crate::services::mission::CameraAction;
+use crate::services::task_executor::{DroneCommand, PlannedStep, StepAction};
+use crate::services::telemetry::as_f64;
+
+/// KMZ 中 KML 文件解压后的最大字节数
+const MAX_KML_SIZE: u64 = 16 * 1024 * 1024;
+
+// MAVLink 任务命令
+const NAV_WAYPOINT: u64 = 16;
+const NAV_LOITER_TIME: u64 = 19;
+const NAV_RETURN_TO_LAUNCH: u64 = 20;
+const NAV_LAND: u64 = 21;
+const NAV_TAKEOFF: u64 = 22;
+const NAV_DELAY: u64 = 93;
+const CONDITION_YAW: u64 = 115;
+const DO_CHANGE_SPEED: u64 = 178;
+const DO_CHANGE_ALTITUDE: u64 = 186;
+const DO_DIGICAM_CONTROL: u64 = 203;
+const IMAGE_START_CAPTURE: u64 = 2000;
+/// MAV_FRAME_GLOBAL_RELATIVE_ALT
+const FRAME_RELATIVE_ALT: u64 = 3;
+/// MAV_FRAME_MISSION，非导航命令使用
+const FRAME_MISSION: u64 = 2;
+
+/// 航线文件格式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MissionFormat {
+    Plan,
+    Kml,
+    Kmz,
+}
+
+impl MissionFormat {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "plan" | "qgc" => Some(Self::Plan),
+            "kml" => Some(Self::Kml),
+            "kmz" => Some(Self::Kmz),
+            _ => None,
+        }
+    }
+
+    /// 按文件内容识别格式
+    pub fn detect(content: &[u8]) -> Option<Self> {
+        if content.starts_with(b"PK\x03\x04") {
+            return Some(Self::Kmz);
+        }
+        let content = content.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(content);
+        match content.iter().find(|byte| !byte.is_ascii_whitespace()) {
+            Some(b'{') => Some(Self::Plan),
+            Some(b'<') => Some(Self::Kml),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Plan => "plan",
+            Self::Kml => "kml",
+            Self::Kmz => "kmz",
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Plan => "application/json",
+            Self::Kml => "application/vnd.google-earth.kml+xml",
+            Self::Kmz => "application/vnd.google-earth.kmz",
+        }
+    }
+}
+
+/// KML 路径导入选项
+#[derive(Debug, Clone, Default)]
+pub struct KmlImportOptions {
+    /// 统一的相对起飞点高度（米），指定时忽略坐标中的高度
+    pub altitude: Option<f64>,
+    /// 航点水平速度（米/秒）
+    pub speed: Option<f64>,
+}
+
+/// 导入结果
+#[derive(Debug, Clone)]
+pub struct ImportedMission {
+    /// 文件中的航线名称
+    pub name: Option<String>,
+    pub parameters: TaskParameters,
+}
+
+/// 导入航线文件
+pub fn import(
+    format: MissionFormat,
+    content: &[u8],
+    options: &KmlImportOptions,
+) -> Result<ImportedMission, String> {
+    match format {
+        MissionFormat::Plan => import_plan(content),
+        MissionFormat::Kml => import_kml(content, options),
+        MissionFormat::Kmz => import_kml(&read_kmz(content)?, options),
+    }
+}
+
+/// 导出航线文件，`home` 为计划起飞点，未知时使用第一个航点
+pub fn export(
+    format: MissionFormat,
+    name: &str,
+    steps: &[PlannedStep],
+    home: Option<(f64, f64)>,
+) -> Result<Vec<u8>, String> {
+    match format {
+        MissionFormat::Plan => export_plan(steps, home),
+        MissionFormat::Kml => export_kml(name, steps),
+        MissionFormat::Kmz => write_kmz(&export_kml(name, steps)?),
+    }
+}
+
+fn step(step_type: &str, mut parameters: JsonValue) -> TaskStep {
+    // 省略未指定的可选参数
+    if let Some(object) = parameters.as_object_mut() {
+        object.retain(|_, value| !value.is_null());
+    }
+    TaskStep {
+        step_type: step_type.to_string(),
+        parameters,
+        timeout: None,
+    }
+}
+
+fn mission_parameters(steps: Vec<TaskStep>) -> Result<TaskParameters, String> {
+    if steps.is_empty() {
+        return Err("Mission file contains no steps".to_string());
+    }
+    Ok(TaskParameters {
+        steps,
+        timeout: None,
+        retry_count: None,
+    })
+}
+
+// ---- QGroundControl .plan ----
+
+fn import_plan(content: &[u8]) -> Result<ImportedMission, String> {
+    let plan: JsonValue =
+        serde_json::from_slice(content).map_err(|e| format!("Invalid plan file: {}", e))?;
+    if plan.get("fileType").and_then(JsonValue::as_str) != Some("Plan") {
+        return Err("Not a QGroundControl plan file".to_string());
+    }
+    let mission = plan
+        .get("mission")
+        .ok_or("Plan file has no mission".to_string())?;
+    let home = mission
+        .get("plannedHomePosition")
+        .and_then(JsonValue::as_array)
+        .and_then(|position| Some((as_f64(position.first()?)?, as_f64(position.get(1)?)?)));
+    let items = mission
+        .get("items")
+        .and_then(JsonValue::as_array)
+        .ok_or("Plan mission has no items".to_string())?;
+
+    let mut steps: Vec<TaskStep> = Vec::new();
+    let mut speed: Option<f64> = None;
+    let mut altitude = 0.0;
+    for (index, item) in items.iter().enumerate() {
+        let error = |e: String| format!("Mission item {}: {}", index + 1, e);
+        match item.get("type").and_then(JsonValue::as_str) {
+            Some("SimpleItem") => {
+                import_simple_item(item, home, &mut speed, &mut altitude, &mut steps)
+                    .map_err(error)?
+            }
+            Some("ComplexItem") => {
+                let survey = import_survey(item, speed).map_err(error)?;
+                if let Some(survey_altitude) = survey.parameters.get("altitude").and_then(as_f64) {
+                    altitude = survey_altitude;
+                }
+                steps.push(survey);
+            }
+            other => {
+                return Err(error(format!(
+                    "Unsupported item type {}",
+                    other.unwrap_or("(none)")
+                )))
+            }
+        }
+    }
+
+    Ok(ImportedMission {
+        name: None,
+        parameters: mission_parameters(steps)?,
+    })
+}
+
+fn import_simple_item(
+    item: &JsonValue,
+    home: Option<(f64, f64)>,
+    speed: &mut Option<f64>,
+    altitude: &mut f64,
+    steps: &mut Vec<TaskStep>,
+) -> Result<(), String> {
+    let command = item
+        .get("command")
+        .and_then(JsonValue::as_u64)
+        .ok_or("Missing command")?;
+    let frame = item.get("frame").and_then(JsonValue::as_u64);
+    let params: Vec<Option<f64>> = item
+        .get("params")
+        .and_then(JsonValue::as_array)
+        .map(|params| params.iter().map(as_f64).collect())
+        .unwrap_or_default();
+    let param = |index: usize| {
+        params
+            .get(index)
+            .copied()
+            .flatten()
+            .filter(|v| v.is_finite())
+    };
+    let relative_frame = || {
+        if frame == Some(FRAME_RELATIVE_ALT) {
+            Ok(())
+        } else {
+            Err(format!(
+                "Command {} uses frame {}, only relative altitude (frame 3) is supported",
+                command,
+                frame.map_or("(none)".to_string(), |frame| frame.to_string())
+            ))
+        }
+    };
+    let position = || {
+        param(4)
+            .zip(param(5))
+            .filter(|&(latitude, longitude)| latitude != 0.0 || longitude != 0.0)
+    };
+
+    match command {
+        NAV_WAYPOINT | NAV_LOITER_TIME => {
+            relative_frame()?;
+            let (latitude, longitude) =
+                position().ok_or(format!("Command {} has no coordinate", command))?;
+            *altitude = param(6).ok_or(format!("Command {} has no altitude", command))?;
+            steps.push(step(
+                "waypoint",
+                json!({
+                    "latitude": latitude,
+                    "longitude": longitude,
+                    "altitude": *altitude,
+                    "speed": *speed,
+                    "loiter": param(0).unwrap_or(0.0).max(0.0),
+                }),
+            ));
+        }
+        NAV_TAKEOFF => {
+            relative_frame()?;
+            *altitude = param(6).ok_or("Takeoff has no altitude")?;
+            steps.push(step("takeoff", json!({ "altitude": *altitude })));
+        }
+        NAV_LAND => steps.push(step("landing", json!({}))),
+        NAV_RETURN_TO_LAUNCH => {
+            let (latitude, longitude) =
+                home.ok_or("Return to launch requires plannedHomePosition")?;
+            steps.push(step(
+                "waypoint",
+                json!({
+                    "latitude": latitude,
+                    "longitude": longitude,
+                    "altitude": *altitude,
+                    "speed": *speed,
+                }),
+            ));
+            steps.push(step("landing", json!({})));
+        }
+        NAV_DELAY => {
+            let seconds = param(0).filter(|seconds| *seconds >= 0.0);
+            let seconds = seconds.ok_or("Delay requires seconds in param 1")?;
+            steps.push(step("wait", json!({ "duration": seconds })));
+        }
+        DO_CHANGE_SPEED => *speed = param(1).filter(|speed| *speed > 0.0),
+        DO_CHANGE_ALTITUDE => {
+            *altitude = param(0).ok_or("Change altitude has no altitude")?;
+            steps.push(step("move_to_height", json!({ "height": *altitude })));
+        }
+        CONDITION_YAW => {
+            if param(3).unwrap_or(0.0) != 0.0 {
+                return Err("Relative yaw is not supported".to_string());
+            }
+            let heading = param(0).ok_or("Yaw has no heading")?;
+            steps.push(step("move_to_heading", json!({ "heading": heading })));
+        }
+        IMAGE_START_CAPTURE | DO_DIGICAM_CONTROL => {
+            // 紧跟航点的拍照作为该航点的相机动作
+            match steps.last_mut() {
+                Some(last)
+                    if last.step_type == "waypoint"
+                        && last.parameters.get("camera_action").is_none() =>
+                {
+                    last.parameters["camera_action"] = json!("photo");
+                }
+                _ => steps.push(step("photo", json!({}))),
+            }
+        }
+        other => return Err(format!("Unsupported mission command {}", other)),
+    }
+    Ok(())
+}
+
+fn import_survey(item: &JsonValue, speed: Option<f64>) -> Result<TaskStep, String> {
+    let kind = item.get("complexItemType").and_then(JsonValue::as_str);
+    if kind != Some("survey") {
+        return Err(format!(
+            "Unsupported complex item {}",
+            kind.unwrap_or("(none)")
+        ));
+    }
+    let polygon = item
+        .get("polygon")
+        .filter(|polygon| polygon.is_array())
+        .ok_or("Survey has no polygon")?;
+    let camera = item
+        .get("TransectStyleComplexItem")
+        .and_then(|transect| transect.get("CameraCalc"))
+        .ok_or("Survey has no camera settings")?;
+    if camera
+        .get("DistanceToSurfaceRelative")
+        .and_then(JsonValue::as_bool)
+        == Some(false)
+    {
+        return Err("Survey altitude must be relative to launch".to_string());
+    }
+    let number = |value: &JsonValue, key: &str| value.get(key).and_then(as_f64);
+    let altitude = number(camera, "DistanceToSurface").ok_or("Survey has no altitude")?;
+
+    let mut parameters = json!({
+        "polygon": polygon,
+        "altitude": altitude,
+        "angle": number(item, "angle").unwrap_or(0.0),
+        "speed": speed,
+        "camera_action": "photo",
+    });
+    if let Some(spacing) = number(camera, "AdjustedFootprintSide").filter(|v| *v > 0.0) {
+        parameters["spacing"] = json!(spacing);
+    }
+    if let Some(overlap) = number(camera, "SideOverlap") {
+        parameters["overlap"] = json!(overlap);
+    }
+    if let Some(overlap) = number(camera, "FrontalOverlap") {
+        parameters["front_overlap"] = json!(overlap);
+    }
+    Ok(step("survey", parameters))
+}
+
+/// QGC 任务项，参数中的 NaN 导出为 null
+fn simple_item(command: u64, frame: u64, params: [f64; 7]) -> JsonValue {
+    let params: Vec<JsonValue> = params
+        .iter()
+        .map(|value| {
+            if value.is_finite() {
+                json!(value)
+            } else {
+                JsonValue::Null
+            }
+        })
+        .collect();
+    json!({
+        "type": "SimpleItem",
+        "command": command,
+        "frame": frame,
+        "params": params,
+        "autoContinue": true,
+    })
+}
+
+fn export_plan(steps: &[PlannedStep], home: Option<(f64, f64)>) -> Result<Vec<u8>, String> {
+    let first_waypoint = steps.iter().find_map(|step| match &step.action {
+        StepAction::Waypoint(waypoint) => Some((waypoint.latitude, waypoint.longitude)),
+        _ => None,
+    });
+    let home = home.or(first_waypoint);
+    let (home_latitude, home_longitude) = home.unwrap_or((f64::NAN, f64::NAN));
+
+    let mut items = Vec::new();
+    let mut speed = None;
+    for step in steps {
+        match &step.action {
+            StepAction::Command(DroneCommand::Takeoff { altitude }) => items.push(simple_item(
+                NAV_TAKEOFF,
+                FRAME_RELATIVE_ALT,
+                [
+                    0.0,
+                    0.0,
+                    0.0,
+                    f64::NAN,
+                    home_latitude,
+                    home_longitude,
+                    *altitude,
+                ],
+            )),
+            StepAction::Command(DroneCommand::Land) => items.push(simple_item(
+                NAV_LAND,
+                FRAME_RELATIVE_ALT,
+                [0.0, 0.0, 0.0, f64::NAN, f64::NAN, f64::NAN, 0.0],
+            )),
+            StepAction::Command(DroneCommand::MoveToHeight { height }) => items.push(simple_item(
+                DO_CHANGE_ALTITUDE,
+                FRAME_MISSION,
+                [*height, FRAME_RELATIVE_ALT as f64, 0.0, 0.0, 0.0, 0.0, 0.0],
+            )),
+            StepAction::Command(DroneCommand::MoveToHeading { heading }) => {
+                items.push(simple_item(
+                    CONDITION_YAW,
+                    FRAME_MISSION,
+                    [*heading, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+                ))
+            }
+            StepAction::Command(DroneCommand::Photo) => items.push(simple_item(
+                IMAGE_START_CAPTURE,
+                FRAME_MISSION,
+                [0.0, 0.0, 1.0, 0.0, f64::NAN, f64::NAN, f64::NAN],
+            )),
+            StepAction::Command(DroneCommand::ReturnToLaunch) => {
+                items.push(simple_item(NAV_RETURN_TO_LAUNCH, FRAME_MISSION, [0.0; 7]))
+            }
+            StepAction::Command(DroneCommand::GoTo { .. }) => {
+                return Err("Direct GoTo commands cannot be exported".to_string());
+            }
+            StepAction::Wait(duration) => items.push(simple_item(
+                NAV_DELAY,
+                FRAME_MISSION,
+                [duration.as_secs_f64(), -1.0, -1.0, -1.0, 0.0, 0.0, 0.0],
+            )),
+            StepAction::Waypoint(waypoint) => {
+                if waypoint.speed.is_some() && waypoint.speed != speed {
+                    speed = waypoint.speed;
+                    items.push(simple_item(
+                        DO_CHANGE_SPEED,
+                        FRAME_MISSION,
+                        [
+                            1.0,
+                            waypoint.speed.unwrap_or(-1.0),
+                            -1.0,
+                            0.0,
+                            0.0,
+                            0.0,
+                            0.0,
+                        ],
+                    ));
+                }
+                items.push(simple_item(
+                    NAV_WAYPOINT,
+                    FRAME_RELATIVE_ALT,
+                    [
+                        waypoint.loiter,
+                        0.0,
+                        0.0,
+                        f64::NAN,
+                        waypoint.latitude,
+                        waypoint.longitude,
+                        waypoint.altitude,
+                    ],
+                ));
+                if waypoint.camera_action == CameraAction::Photo {
+                    items.push(simple_item(
+                        IMAGE_START_CAPTURE,
+                        FRAME_MISSION,
+                        [0.0, 0.0, 1.0, 0.0, f64::NAN, f64::NAN, f64::NAN],
+                    ));
+                }
+            }
+        }
+    }
+    for (index, item) in items.iter_mut().enumerate() {
+        item["doJumpId"] = json!(index + 1);
+    }
+
+    let plan = json!({
+        "fileType": "Plan",
+        "version": 1,
+        "groundStation": "QGroundControl",
+        "mission": {
+            "version": 2,
+            // MAV_AUTOPILOT_GENERIC、MAV_TYPE_QUADROTOR
+            "firmwareType": 0,
+            "vehicleType": 2,
+            "cruiseSpeed": 15,
+            "hoverSpeed": 5,
+            "plannedHomePosition": home.map(|(latitude, longitude)| json!([latitude, longitude, 0])),
+            "items": items,
+        },
+        "geoFence": { "version": 2, "circles": [], "polygons": [] },
+        "rallyPoints": { "version": 2, "points": [] },
+    });
+    serde_json::to_vec_pretty(&plan).map_err(|e| e.to_string())
+}
+
+// ---- KML / KMZ ----
+
+/// KML 中的一条路径或一个点
+struct KmlGeometry {
+    line: bool,
+    coordinates: Vec<(f64, f64, Option<f64>)>,
+    altitude_mode: Option<String>,
+}
+
+fn parse_coordinates(text: &str) -> Result<Vec<(f64, f64, Option<f64>)>, String> {
+    text.split_whitespace()
+        .map(|tuple| {
+            let mut values = tuple.split(',').map(|value| value.trim().parse::<f64>());
+            match (values.next(), values.next(), values.next()) {
+                (Some(Ok(longitude)), Some(Ok(latitude)), altitude) => {
+                    Ok((latitude, longitude, altitude.and_then(Result::ok)))
+                }
+                _ => Err(format!("Invalid KML coordinate: {}", tuple)),
+            }
+        })
+        .collect()
+}
+
+fn import_kml(content: &[u8], options: &KmlImportOptions) -> Result<ImportedMission, String> {
+    let mut reader = Reader::from_reader(content);
+    reader.config_mut().trim_text(true);
+
+    let mut buf = Vec::new();
+    let mut path: Vec<String> = Vec::new();
+    let mut geometries: Vec<KmlGeometry> = Vec::new();
+    let mut current: Option<KmlGeometry> = None;
+    let mut document_name: Option<String> = None;
+    loop {
+        let event = reader
+            .read_event_into(&mut buf)
+            .map_err(|e| format!("Invalid KML: {}", e))?;
+        match event {
+            Event::Start(element) => {
+                let name = String::from_utf8_lossy(element.local_name().as_ref()).to_string();
+                if name == "LineString" || name == "Point" {
+                    current = Some(KmlGeometry {
+                        line: name == "LineString",
+                        coordinates: Vec::new(),
+                        altitude_mode: None,
+                    });
+                }
+                path.push(name);
+            }
+            Event::End(_) => {
+                if let Some(name) = path.pop() {
+                    if name == "LineString" || name == "Point" {
+                        geometries.extend(current.take());
+                    }
+                }
+            }
+            Event::Text(text) => {
+                let text = text.unescape().map_err(|e| format!("Invalid KML: {}", e))?;
+                let parent = path.iter().rev().nth(1).map(String::as_str);
+                match (path.last().map(String::as_str), current.as_mut()) {
+                    (Some("coordinates"), Some(geometry)) => {
+                        geometry.coordinates.extend(parse_coordinates(&text)?);
+                    }
+                    (Some("altitudeMode"), Some(geometry)) => {
+                        geometry.altitude_mode = Some(text.trim().to_string());
+                    }
+                    (Some("name"), None) if parent == Some("Document") => {
+                        document_name.get_or_insert_with(|| text.trim().to_string());
+                    }
+                    _ => {}
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    // 有路径时只使用路径，没有路径时按顺序使用点
+    let has_line = geometries.iter().any(|geometry| geometry.line);
+    let mut waypoints = Vec::new();
+    for geometry in geometries
+        .iter()
+        .filter(|geometry| geometry.line == has_line)
+    {
+        let relative = geometry.altitude_mode.as_deref() == Some("relativeToGround");
+        for &(latitude, longitude, altitude) in &geometry.coordinates {
+            let altitude = options
+                .altitude
+                .or(altitude.filter(|altitude| relative && *altitude > 0.0))
+                .ok_or(
+                    "KML coordinates have no altitude relative to ground, specify altitude"
+                        .to_string(),
+                )?;
+            waypoints.push(step(
+                "waypoint",
+                json!({
+                    "latitude": latitude,
+                    "longitude": longitude,
+                    "altitude": altitude,
+                    "speed": options.speed,
+                }),
+            ));
+        }
+    }
+    if waypoints.is_empty() {
+        return Err("KML contains no LineString or Point coordinates".to_string());
+    }
+
+    let takeoff_altitude = waypoints[0].parameters["altitude"].clone();
+    let mut steps = vec![step("takeoff", json!({ "altitude": takeoff_altitude }))];
+    steps.extend(waypoints);
+    steps.push(step("landing", json!({})));
+    Ok(ImportedMission {
+        name: document_name.filter(|name| !name.is_empty()),
+        parameters: mission_parameters(steps)?,
+    })
+}
+
+fn export_kml(name: &str, steps: &[PlannedStep]) -> Result<Vec<u8>, String> {
+    let waypoints: Vec<_> = steps
+        .iter()
+        .filter_map(|step| match &step.action {
+            StepAction::Waypoint(waypoint) => Some(waypoint),
+            _ => None,
+        })
+        .collect();
+    if waypoints.is_empty() {
+        return Err("Task has no waypoints to export as KML".to_string());
+    }
+
+    let name = xml_escape(name);
+    let coordinates: Vec<String> = waypoints
+        .iter()
+        .map(|waypoint| {
+            format!(
+                "{},{},{}",
+                waypoint.longitude, waypoint.latitude, waypoint.altitude
+            )
+        })
+        .collect();
+    let mut kml = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <kml xmlns=\"http://www.opengis.net/kml/2.2\">\n<Document>\n<name>{}</name>\n\
+         <Placemark><name>{}</name><LineString><altitudeMode>relativeToGround</altitudeMode>\
+         <coordinates>{}</coordinates></LineString></Placemark>\n",
+        name,
+        name,
+        coordinates.join(" ")
+    );
+    for (index, coordinate) in coordinates.iter().enumerate() {
+        kml.push_str(&format!(
+            "<Placemark><name>WP{}</name><Point><altitudeMode>relativeToGround</altitudeMode>\
+             <coordinates>{}</coordinates></Point></Placemark>\n",
+            index + 1,
+            coordinate
+        ));
+    }
+    kml.push_str("</Document>\n</kml>\n");
+    Ok(kml.into_bytes())
+}
+
+/// 从 KMZ（ZIP）中读取 KML，优先使用 doc.kml，否则使用第一个 .kml 文件
+fn read_kmz(content: &[u8]) -> Result<Vec<u8>, String> {
+    let mut archive =
+        ZipArchive::new(Cursor::new(content)).map_err(|_| "Invalid KMZ archive".to_string())?;
+
+    let mut selected = None;
+    for index in 0..archive.len() {
+        let entry = archive
+            .by_index_raw(index)
+            .map_err(|_| "Invalid KMZ archive".to_string())?;
+        let name = entry.name().to_ascii_lowercase();
+        if name.ends_with(".kml") && (selected.is_none() || name == "doc.kml") {
+            selected = Some(index);
+        }
+    }
+
+    let index = selected.ok_or("KMZ contains no KML file")?;
+    let file = archive.by_index(index).map_err(|e| match e {
+        ZipError::UnsupportedArchive(reason) => format!("Unsupported KMZ archive: {}", reason),
+        _ => "Invalid KMZ archive".to_string(),
+    })?;
+    if file.size() > MAX_KML_SIZE {
+        return Err("KML in KMZ is too large".to_string());
+    }
+    let mut kml = Vec::new();
+    file.take(MAX_KML_SIZE + 1)
+        .read_to_end(&mut kml)
+        .map_err(|_| "Invalid KMZ archive".to_string())?;
+    if kml.len() as u64 > MAX_KML_SIZE {
+        return Err("KML in KMZ is too large".to_string());
+    }
+    Ok(kml)
+}
+
+/// 将 KML 打包为只含 doc.kml 的 KMZ
+fn write_kmz(kml: &[u8]) -> Result<Vec<u8>, String> {
+    let now = chrono::Local::now().naive_local();
+    let mut options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    // ZIP 时间只能表示 1980 至 2107 年
+    if let Ok(time) = DateTime::from_date_and_time(
+        now.year().clamp(1980, 2107) as u16,
+        now.month() as u8,
+        now.day() as u8,
+        now.hour() as u8,
+        now.minute() as u8,
+        now.second() as u8,
+    ) {
+        options = options.last_modified_time(time);
+    }
+
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    writer
+        .start_file("doc.kml", options)
+        .map_err(|e| e.to_string())?;
+    writer.write_all(kml).map_err(|e| e.to_string())?;
+    let kmz = writer.finish().map_err(|e| e.to_string())?;
+    Ok(kmz.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::mission::Waypoint;
+    use crate::services::task_executor::parse_parameters;
+
+    fn planned(parameters: JsonValue) -> Vec<PlannedStep> {
+        parse_parameters(Some(&parameters)).unwrap().1
+    }
+
+    fn actions(parameters: &TaskParameters) -> Vec<StepAction> {
+        let parameters = serde_json::to_value(parameters).unwrap();
+        planned(parameters)
+            .into_iter()
+            .map(|step| step.action)
+            .collect()
+    }
+
+    fn mission() -> Vec<PlannedStep> {
+        planned(json!({
+            "steps": [
+                { "step_type": "takeoff", "parameters": { "altitude": 20 } },
+                {
+                    "step_type": "waypoint",
+                    "parameters": {
+                        "latitude": 30.1234567, "longitude": 120.4567891, "altitude": 25.5,
+                        "speed": 5, "loiter": 2, "camera_action": "photo"
+                    }
+                },
+                {
+                    "step_type": "orbit",
+                    "parameters": {
+                        "latitude": 30.124, "longitude": 120.457, "radius": 30, "altitude": 40,
+                        "speed": 5, "points": 4
+                    }
+                },
+                { "step_type": "move_to_height", "parameters": { "height": 35 } },
+                { "step_type": "move_to_heading", "parameters": { "heading": 90 } },
+                { "step_type": "wait", "parameters": { "duration": 3 } },
+                { "step_type": "photo", "parameters": {} },
+                { "step_type": "landing", "parameters": {} }
+            ]
+        }))
+    }
+
+    fn waypoints(steps: &[PlannedStep]) -> Vec<(f64, f64, f64)> {
+        steps
+            .iter()
+            .filter_map(|step| match &step.action {
+                StepAction::Waypoint(waypoint) => {
+                    Some((waypoint.latitude, waypoint.longitude, waypoint.altitude))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// KML 只保留航点坐标，导入时前后加上起飞和降落
+    fn assert_path_imported(steps: &[PlannedStep], imported: &ImportedMission) {
+        assert_eq!(imported.name.as_deref(), Some("巡检 & 测试"));
+        let imported = actions(&imported.parameters);
+        let expected = waypoints(steps);
+        assert_eq!(imported.len(), expected.len() + 2);
+        assert_eq!(
+            imported.first(),
+            Some(&StepAction::Command(DroneCommand::Takeoff {
+                altitude: expected[0].2
+            }))
+        );
+        assert_eq!(
+            imported.last(),
+            Some(&StepAction::Command(DroneCommand::Land))
+        );
+        let imported: Vec<_> = imported[1..imported.len() - 1]
+            .iter()
+            .map(|action| match action {
+                StepAction::Waypoint(waypoint) => {
+                    (waypoint.latitude, waypoint.longitude, waypoint.altitude)
+                }
+                other => panic!("unexpected action {:?}", other),
+            })
+            .collect();
+        assert_eq!(imported, expected);
+    }
+
+    #[test]
+    fn plan_round_trip() {
+        let steps = mission();
+        let content = export(MissionFormat::Plan, "巡检", &steps, Some((30.12, 120.45))).unwrap();
+        assert_eq!(MissionFormat::detect(&content), Some(MissionFormat::Plan));
+
+        let imported = import(MissionFormat::Plan, &content, &KmlImportOptions::default()).unwrap();
+        let imported = actions(&imported.parameters);
+        assert_eq!(imported.len(), steps.len());
+        for (imported, step) in imported.iter().zip(&steps) {
+            match (imported, &step.action) {
+                // serde_json 解析浮点数可能相差末位
+                (StepAction::Waypoint(imported), StepAction::Waypoint(expected)) => {
+                    assert!((imported.latitude - expected.latitude).abs() < 1e-9);
+                    assert!((imported.longitude - expected.longitude).abs() < 1e-9);
+                    assert_eq!(
+                        Waypoint {
+                            latitude: expected.latitude,
+                            longitude: expected.longitude,
+                            ..imported.clone()
+                        },
+                        *expected
+                    );
+                }
+                (imported, expected) => assert_eq!(imported, expected),
+            }
+        }
+    }
+
+    #[test]
+    fn kml_round_trip() {
+        let steps = mission();
+        let content = export(MissionFormat::Kml, "巡检 & 测试", &steps, None).unwrap();
+        assert_eq!(MissionFormat::detect(&content), Some(MissionFormat::Kml));
+
+        let imported = import(MissionFormat::Kml, &content, &KmlImportOptions::default()).unwrap();
+        assert_path_imported(&steps, &imported);
+    }
+
+    #[test]
+    fn kmz_round_trip() {
+        let steps = mission();
+        let content = export(MissionFormat::Kmz, "巡检 & 测试", &steps, None).unwrap();
+        assert_eq!(MissionFormat::detect(&content), Some(MissionFormat::Kmz));
+
+        let imported = import(MissionFormat::Kmz, &content, &KmlImportOptions::default()).unwrap();
+        assert_path_imported(&steps, &imported);
+    }
+
+    #[test]
+    fn kmz_prefers_doc_kml() {
+        let steps = mission();
+        let kml = export(MissionFormat::Kml, "巡检 & 测试", &steps, None).unwrap();
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+        writer.start_file("images/readme.txt", stored).unwrap();
+        writer.write_all(b"not a path").unwrap();
+        writer.start_file("other.kml", stored).unwrap();
+        writer.write_all(b"<kml></kml>").unwrap();
+        writer.start_file("doc.kml", stored).unwrap();
+        writer.write_all(&kml).unwrap();
+        let content = writer.finish().unwrap().into_inner();
+
+        let imported = import(MissionFormat::Kmz, &content, &KmlImportOptions::default()).unwrap();
+        assert_path_imported(&steps, &imported);
+    }
+
+    #[test]
+    fn rejects_invalid_kmz() {
+        let options = KmlImportOptions::default();
+        assert_eq!(
+            import(MissionFormat::Kmz, b"PK\x03\x04garbage", &options).unwrap_err(),
+            "Invalid KMZ archive"
+        );
+
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        writer
+            .start_file("readme.txt", FileOptions::default())
+            .unwrap();
+        writer.write_all(b"no path here").unwrap();
+        let content = writer.finish().unwrap().into_inner();
+        assert_eq!(
+            import(MissionFormat::Kmz, &content, &options).unwrap_err(),
+            "KMZ contains no KML file"
+        );
+    }
+}
@@ -1,8 +1,32 @@
+pub mod access;
 pub mod app_state;
 pub mod broadcast;
+pub mod client_queue;
 pub mod device_websocket_proxy;
+pub mod export;
+pub mod fleet_mission;
+pub mod flight;
+pub mod geofence;
+pub mod history;
+pub mod ingest;
+pub mod mavlink;
+pub mod mavlink_service;
+pub mod mission;
+pub mod mission_file;
 pub mod mqtt_broker;
 pub mod mqtt_service;
+pub mod nmea;
+pub mod nmea_service;
 pub mod realtime_data;
+pub mod realtime_protocol;
+pub mod replay;
+pub mod retention;
+pub mod rollup;
 pub mod service_manager;
+pub mod simulation;
+pub mod sse;
+pub mod task_executor;
+pub mod task_scheduler;
+pub mod task_state;
+pub mod telemetry;
 pub mod websocket_proxy;
@@ -1,11 +1,12 @@
-use serde_json::{json, Value as JsonValue};
+use serde_json::Value as JsonValue;
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::{broadcast, RwLock};
 use tracing::{error, info, warn};
 
-use crate::models::{device, device_realtime_data};
-use crate::services::mqtt_broker::{MqttBrokerMessage, MqttBrokerService};
+use crate::models::device;
+use crate::services::broadcast::recv_lossy;
+use crate::services::mqtt_broker::MqttBrokerService;
 use sea_orm::DatabaseConnection;
 
 #[derive(Debug, Clone)]
@@ -124,30 +125,19 @@ impl MqttService {
             brokers.insert(config.device_id, broker_service);
         }
 
-        // 启动消息监听
-        let db = Arc::clone(&self.db);
+        // 启动消息监听（入库由实时数据服务统一处理）
         let message_sender = self.message_sender.clone();
         let device_id = config.device_id;
 
         tokio::spawn(async move {
-            while let Ok(broker_msg) = broker_receiver.recv().await {
+            while let Some(broker_msg) =
+                recv_lossy(&mut broker_receiver, "MQTT broker listener").await
+            {
                 info!(
                     "Received MQTT broker message for device {}: {:?}",
                     device_id, broker_msg.payload
                 );
 
-                // 存储到数据库
-                if let Err(e) = device_realtime_data::Model::create_realtime_data(
-                    &db,
-                    device_id,
-                    "mqtt",
-                    broker_msg.payload.clone(),
-                )
-                .await
-                {
-                    error!("Failed to save MQTT data to database: {}", e);
-                }
-
                 // 转换为统一的MQTT消息格式
                 let mqtt_message = MqttMessage {
                     device_id,
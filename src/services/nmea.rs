@@ -0,0 +1,423 @@
+//! NMEA 0183 语句解析
+//!
+//! 支持 GGA、RMC、VTG 三种定位语句，接受任意 talker 前缀（GP、GN、GL、BD 等）。
+
+use serde_json::{json, Map, Value as JsonValue};
+use std::fmt;
+
+/// 节转米/秒
+const KNOTS_TO_MPS: f64 = 0.514_444;
+/// 千米/小时转米/秒
+const KMH_TO_MPS: f64 = 1.0 / 3.6;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NmeaError {
+    /// 不是以 `$` 开头的语句
+    NotNmea,
+    /// 缺少校验和
+    MissingChecksum,
+    /// 校验和不匹配
+    ChecksumMismatch { expected: u8, actual: u8 },
+    /// 不支持的语句类型
+    Unsupported(String),
+    /// 字段格式错误
+    InvalidField(&'static str),
+}
+
+impl fmt::Display for NmeaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotNmea => write!(f, "not an NMEA sentence"),
+            Self::MissingChecksum => write!(f, "missing checksum"),
+            Self::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "checksum mismatch: expected {:02X}, got {:02X}",
+                expected, actual
+            ),
+            Self::Unsupported(kind) => write!(f, "unsupported sentence type: {}", kind),
+            Self::InvalidField(field) => write!(f, "invalid field: {}", field),
+        }
+    }
+}
+
+impl std::error::Error for NmeaError {}
+
+/// GGA - 定位信息
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gga {
+    pub time: Option<chrono::NaiveTime>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    /// 定位质量（0 无效，1 GPS，2 差分，4 RTK 固定，5 RTK 浮点...）
+    pub fix_quality: u8,
+    pub satellites: Option<u8>,
+    pub hdop: Option<f64>,
+    /// 海拔高度（米）
+    pub altitude: Option<f64>,
+}
+
+/// RMC - 推荐最小定位信息
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rmc {
+    pub time: Option<chrono::NaiveTime>,
+    pub date: Option<chrono::NaiveDate>,
+    /// 状态位为 `A` 时有效
+    pub valid: bool,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    /// 地速（米/秒）
+    pub speed: Option<f64>,
+    /// 真北航向（度）
+    pub course: Option<f64>,
+}
+
+/// VTG - 地面速度和航向
+#[derive(Debug, Clone, PartialEq)]
+pub struct Vtg {
+    /// 真北航向（度）
+    pub course: Option<f64>,
+    /// 地速（米/秒）
+    pub speed: Option<f64>,
+}
+
+/// 已解析的 NMEA 语句
+#[derive(Debug, Clone, PartialEq)]
+pub enum NmeaSentence {
+    Gga(Gga),
+    Rmc(Rmc),
+    Vtg(Vtg),
+}
+
+impl NmeaSentence {
+    /// 语句类型
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Gga(_) => "GGA",
+            Self::Rmc(_) => "RMC",
+            Self::Vtg(_) => "VTG",
+        }
+    }
+
+    /// 是否为有效的位置定位
+    pub fn is_valid_fix(&self) -> bool {
+        match self {
+            Self::Gga(gga) => {
+                gga.fix_quality > 0 && gga.latitude.is_some() && gga.longitude.is_some()
+            }
+            Self::Rmc(rmc) => rmc.valid && rmc.latitude.is_some() && rmc.longitude.is_some(),
+            Self::Vtg(_) => false,
+        }
+    }
+
+    /// 转换为设备状态字段（单位与 MAVLink 接入保持一致：米、米/秒、度）
+    pub fn to_state_fields(&self) -> JsonValue {
+        let mut fields = Map::new();
+
+        match self {
+            Self::Gga(gga) => {
+                fields.insert("fix_quality".into(), json!(gga.fix_quality));
+                fields.insert("gps_valid".into(), json!(gga.fix_quality > 0));
+                if gga.fix_quality > 0 {
+                    if let (Some(lat), Some(lon)) = (gga.latitude, gga.longitude) {
+                        fields.insert("latitude".into(), json!(lat));
+                        fields.insert("longitude".into(), json!(lon));
+                    }
+                    if let Some(altitude) = gga.altitude {
+                        fields.insert("altitude".into(), json!(altitude));
+                    }
+                }
+                if let Some(satellites) = gga.satellites {
+                    fields.insert("satellites".into(), json!(satellites));
+                }
+                if let Some(hdop) = gga.hdop {
+                    fields.insert("hdop".into(), json!(hdop));
+                }
+            }
+            Self::Rmc(rmc) => {
+                fields.insert("gps_valid".into(), json!(rmc.valid));
+                if rmc.valid {
+                    if let (Some(lat), Some(lon)) = (rmc.latitude, rmc.longitude) {
+                        fields.insert("latitude".into(), json!(lat));
+                        fields.insert("longitude".into(), json!(lon));
+                    }
+                    if let Some(speed) = rmc.speed {
+                        fields.insert("ground_speed".into(), json!(speed));
+                    }
+                    if let Some(course) = rmc.course {
+                        fields.insert("course".into(), json!(course));
+                    }
+                }
+                if let (Some(date), Some(time)) = (rmc.date, rmc.time) {
+                    fields.insert(
+                        "gps_time".into(),
+                        json!(date.and_time(time).and_utc().to_rfc3339()),
+                    );
+                }
+            }
+            Self::Vtg(vtg) => {
+                if let Some(speed) = vtg.speed {
+                    fields.insert("ground_speed".into(), json!(speed));
+                }
+                if let Some(course) = vtg.course {
+                    fields.insert("course".into(), json!(course));
+                }
+            }
+        }
+
+        JsonValue::Object(fields)
+    }
+}
+
+/// 判断文本是否为 NMEA 数据（以 `$` 开头）
+pub fn is_nmea(text: &str) -> bool {
+    text.trim_start().starts_with('$')
+}
+
+/// 解析一条 NMEA 语句，校验和必须存在且正确
+pub fn parse_sentence(line: &str) -> Result<NmeaSentence, NmeaError> {
+    let line = line.trim();
+    let body = line.strip_prefix('$').ok_or(NmeaError::NotNmea)?;
+    let (data, checksum) = body.split_once('*').ok_or(NmeaError::MissingChecksum)?;
+
+    let expected =
+        u8::from_str_radix(checksum.trim(), 16).map_err(|_| NmeaError::InvalidField("checksum"))?;
+    let actual = data.bytes().fold(0u8, |acc, b| acc ^ b);
+    if expected != actual {
+        return Err(NmeaError::ChecksumMismatch { expected, actual });
+    }
+
+    let fields: Vec<&str> = data.split(',').collect();
+    let address = fields[0];
+    // 地址为 talker(2) + 类型(3)，专有语句（P 开头）不处理
+    if address.len() != 5 || !address.is_ascii() || address.starts_with('P') {
+        return Err(NmeaError::Unsupported(address.to_string()));
+    }
+    let field = |index: usize| fields.get(index).copied().unwrap_or("");
+
+    match &address[2..] {
+        "GGA" => Ok(NmeaSentence::Gga(Gga {
+            time: parse_time(field(1))?,
+            latitude: parse_coordinate(field(2), field(3), 2)?,
+            longitude: parse_coordinate(field(4), field(5), 3)?,
+            fix_quality: parse_number(field(6), "fix_quality")?.unwrap_or(0),
+            satellites: parse_number(field(7), "satellites")?,
+            hdop: parse_number(field(8), "hdop")?,
+            altitude: parse_number(field(9), "altitude")?,
+        })),
+        "RMC" => Ok(NmeaSentence::Rmc(Rmc {
+            time: parse_time(field(1))?,
+            valid: field(2) == "A",
+            latitude: parse_coordinate(field(3), field(4), 2)?,
+            longitude: parse_coordinate(field(5), field(6), 3)?,
+            speed: parse_number::<f64>(field(7), "speed")?.map(|knots| knots * KNOTS_TO_MPS),
+            course: parse_number(field(8), "course")?,
+            date: parse_date(field(9))?,
+        })),
+        "VTG" => {
+            // 优先使用 km/h 字段，缺失时使用节
+            let speed = match parse_number::<f64>(field(7), "speed")? {
+                Some(kmh) => Some(kmh * KMH_TO_MPS),
+                None => parse_number::<f64>(field(5), "speed")?.map(|knots| knots * KNOTS_TO_MPS),
+            };
+            Ok(NmeaSentence::Vtg(Vtg {
+                course: parse_number(field(1), "course")?,
+                speed,
+            }))
+        }
+        kind => Err(NmeaError::Unsupported(kind.to_string())),
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(
+    value: &str,
+    name: &'static str,
+) -> Result<Option<T>, NmeaError> {
+    if value.is_empty() {
+        return Ok(None);
+    }
+    value
+        .parse()
+        .map(Some)
+        .map_err(|_| NmeaError::InvalidField(name))
+}
+
+/// 解析 ddmm.mmmm / dddmm.mmmm 格式坐标为十进制度
+fn parse_coordinate(
+    value: &str,
+    hemisphere: &str,
+    degree_digits: usize,
+) -> Result<Option<f64>, NmeaError> {
+    if value.is_empty() {
+        return Ok(None);
+    }
+    if value.len() < degree_digits + 2 || !value.is_char_boundary(degree_digits) {
+        return Err(NmeaError::InvalidField("coordinate"));
+    }
+
+    let degrees: f64 = value[..degree_digits]
+        .parse()
+        .map_err(|_| NmeaError::InvalidField("coordinate"))?;
+    let minutes: f64 = value[degree_digits..]
+        .parse()
+        .map_err(|_| NmeaError::InvalidField("coordinate"))?;
+    if minutes >= 60.0 {
+        return Err(NmeaError::InvalidField("coordinate"));
+    }
+
+    let decimal = degrees + minutes / 60.0;
+    match hemisphere {
+        "N" | "E" => Ok(Some(decimal)),
+        "S" | "W" => Ok(Some(-decimal)),
+        _ => Err(NmeaError::InvalidField("hemisphere")),
+    }
+}
+
+/// 解析 hhmmss(.ss) 格式时间
+fn parse_time(value: &str) -> Result<Option<chrono::NaiveTime>, NmeaError> {
+    if value.is_empty() {
+        return Ok(None);
+    }
+    chrono::NaiveTime::parse_from_str(value, "%H%M%S%.f")
+        .map(Some)
+        .map_err(|_| NmeaError::InvalidField("time"))
+}
+
+/// 解析 ddmmyy 格式日期
+fn parse_date(value: &str) -> Result<Option<chrono::NaiveDate>, NmeaError> {
+    if value.is_empty() {
+        return Ok(None);
+    }
+    chrono::NaiveDate::parse_from_str(value, "%d%m%y")
+        .map(Some)
+        .map_err(|_| NmeaError::InvalidField("date"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: Option<f64>, expected: f64) {
+        let actual = actual.expect("missing value");
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn parses_gga_and_converts_coordinates_to_degrees() {
+        let sentence =
+            parse_sentence("$GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*47\r\n")
+                .unwrap();
+        assert_eq!(sentence.kind(), "GGA");
+        assert!(sentence.is_valid_fix());
+
+        let NmeaSentence::Gga(gga) = sentence else {
+            panic!("expected GGA");
+        };
+        assert_eq!(gga.time, chrono::NaiveTime::from_hms_opt(12, 35, 19));
+        assert_close(gga.latitude, 48.0 + 7.038 / 60.0);
+        assert_close(gga.longitude, 11.0 + 31.0 / 60.0);
+        assert_eq!(gga.fix_quality, 1);
+        assert_eq!(gga.satellites, Some(8));
+        assert_eq!(gga.hdop, Some(0.9));
+        assert_eq!(gga.altitude, Some(545.4));
+    }
+
+    #[test]
+    fn parses_rmc_speed_and_date() {
+        let sentence =
+            parse_sentence("$GPRMC,123519,A,4807.038,N,01131.000,E,022.4,084.4,230394,003.1,W*6A")
+                .unwrap();
+        let NmeaSentence::Rmc(rmc) = &sentence else {
+            panic!("expected RMC");
+        };
+        assert!(rmc.valid);
+        assert_close(rmc.speed, 22.4 * KNOTS_TO_MPS);
+        assert_eq!(rmc.course, Some(84.4));
+        assert_eq!(rmc.date, chrono::NaiveDate::from_ymd_opt(1994, 3, 23));
+        assert_eq!(
+            sentence.to_state_fields()["gps_time"],
+            "1994-03-23T12:35:19+00:00"
+        );
+    }
+
+    #[test]
+    fn southern_and_western_hemispheres_are_negative() {
+        let NmeaSentence::Gga(gga) = parse_sentence(
+            "$GPGGA,092750.000,5321.6802,N,00630.3372,W,1,8,1.03,61.7,M,55.2,M,,*76",
+        )
+        .unwrap() else {
+            panic!("expected GGA");
+        };
+        assert_close(gga.latitude, 53.0 + 21.6802 / 60.0);
+        assert_close(gga.longitude, -(6.0 + 30.3372 / 60.0));
+
+        // GN 多系统联合定位
+        let NmeaSentence::Rmc(rmc) =
+            parse_sentence("$GNRMC,033007.00,A,3351.30200,S,15112.65400,E,0.012,,181026,,,A*7F")
+                .unwrap()
+        else {
+            panic!("expected RMC");
+        };
+        assert_close(rmc.latitude, -(33.0 + 51.302 / 60.0));
+        assert_close(rmc.longitude, 151.0 + 12.654 / 60.0);
+        assert_eq!(rmc.course, None);
+    }
+
+    #[test]
+    fn empty_fields_parse_as_missing() {
+        let sentence = parse_sentence("$GPGGA,,,,,,0,00,99.99,,,,,,*48").unwrap();
+        assert!(!sentence.is_valid_fix());
+        assert_eq!(
+            sentence,
+            NmeaSentence::Gga(Gga {
+                time: None,
+                latitude: None,
+                longitude: None,
+                fix_quality: 0,
+                satellites: Some(0),
+                hdop: Some(99.99),
+                altitude: None,
+            })
+        );
+        assert_eq!(
+            sentence.to_state_fields(),
+            json!({ "fix_quality": 0, "gps_valid": false, "satellites": 0, "hdop": 99.99 })
+        );
+
+        let sentence = parse_sentence("$GPRMC,,V,,,,,,,,,,N*53").unwrap();
+        assert!(!sentence.is_valid_fix());
+        assert_eq!(sentence.to_state_fields(), json!({ "gps_valid": false }));
+    }
+
+    #[test]
+    fn rejects_bad_checksums_and_non_nmea_input() {
+        assert_eq!(
+            parse_sentence("$GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*48"),
+            Err(NmeaError::ChecksumMismatch {
+                expected: 0x48,
+                actual: 0x47
+            })
+        );
+        // 数据被篡改后原校验和不再匹配
+        assert!(matches!(
+            parse_sentence("$GPGGA,123519,4807.038,S,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*47"),
+            Err(NmeaError::ChecksumMismatch { .. })
+        ));
+        assert_eq!(
+            parse_sentence("$GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,"),
+            Err(NmeaError::MissingChecksum)
+        );
+        assert_eq!(
+            parse_sentence("$GPGGA,,,,,,0,00,99.99,,,,,,*ZZ"),
+            Err(NmeaError::InvalidField("checksum"))
+        );
+        assert_eq!(
+            parse_sentence("GPGGA,,,,,,0,00,99.99,,,,,,*48"),
+            Err(NmeaError::NotNmea)
+        );
+    }
+}
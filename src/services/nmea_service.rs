@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::net::TcpListener;
+use tokio::sync::{broadcast, oneshot, RwLock};
+use tracing::{error, info, warn};
+
+use crate::models::device;
+use crate::services::nmea::{self, NmeaSentence};
+use crate::settings::NmeaSettings;
+use sea_orm::DatabaseConnection;
+
+/// 单行最大长度，NMEA 规定一条语句不超过 82 字节，这里留出余量
+const MAX_LINE_LEN: usize = 1024;
+
+#[derive(Debug, Clone)]
+pub struct NmeaMessage {
+    pub device_id: i32,
+    pub sentence: NmeaSentence,
+    pub timestamp: chrono::DateTime<chrono::FixedOffset>,
+}
+
+/// 设备ID -> (端口, 停止信号)
+type DeviceListeners = Arc<RwLock<HashMap<i32, (u16, oneshot::Sender<()>)>>>;
+
+/// 每个设备一个 TCP 监听端口，挂载的定位吊舱直接推送 NMEA 文本流
+pub struct NmeaService {
+    db: Arc<DatabaseConnection>,
+    settings: NmeaSettings,
+    message_sender: broadcast::Sender<NmeaMessage>,
+    listeners: DeviceListeners,
+}
+
+impl NmeaService {
+    pub fn new(
+        db: Arc<DatabaseConnection>,
+        settings: NmeaSettings,
+    ) -> (Self, broadcast::Receiver<NmeaMessage>) {
+        let (message_sender, message_receiver) = broadcast::channel(1000);
+
+        let service = Self {
+            db,
+            settings,
+            message_sender,
+            listeners: Arc::new(RwLock::new(HashMap::new())),
+        };
+
+        (service, message_receiver)
+    }
+
+    /// 为设备启动NMEA TCP监听，端口变化时会重启监听
+    pub async fn add_device_listener(
+        &self,
+        device_id: i32,
+        port: u16,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if self
+            .listeners
+            .read()
+            .await
+            .get(&device_id)
+            .is_some_and(|(current, _)| *current == port)
+        {
+            return Ok(());
+        }
+        self.remove_device_listener(device_id).await;
+
+        let binding = self.settings.binding.as_str();
+        let listener = TcpListener::bind((binding, port)).await.map_err(|e| {
+            format!(
+                "Failed to bind NMEA listener for device {} on {}:{}: {}",
+                device_id, binding, port, e
+            )
+        })?;
+        info!(
+            "Started NMEA listener for device {} on {}:{}",
+            device_id, binding, port
+        );
+
+        let (shutdown_tx, mut shutdown_rx) = oneshot::channel();
+        self.listeners
+            .write()
+            .await
+            .insert(device_id, (port, shutdown_tx));
+
+        let message_sender = self.message_sender.clone();
+        tokio::spawn(async move {
+            loop {
+                let (stream, peer) = tokio::select! {
+                    result = listener.accept() => match result {
+                        Ok(accepted) => accepted,
+                        Err(e) => {
+                            error!("NMEA listener accept error for device {}: {}", device_id, e);
+                            continue;
+                        }
+                    },
+                    _ = &mut shutdown_rx => {
+                        info!("NMEA listener for device {} stopped", device_id);
+                        break;
+                    }
+                };
+
+                info!("NMEA source connected for device {}: {}", device_id, peer);
+                let message_sender = message_sender.clone();
+                tokio::spawn(async move {
+                    let mut lines = BufReader::new(stream).lines();
+                    loop {
+                        match lines.next_line().await {
+                            Ok(Some(line)) => {
+                                if line.len() > MAX_LINE_LEN || !nmea::is_nmea(&line) {
+                                    continue;
+                                }
+                                match nmea::parse_sentence(&line) {
+                                    Ok(sentence) => {
+                                        let message = NmeaMessage {
+                                            device_id,
+                                            sentence,
+                                            timestamp: chrono::Utc::now().with_timezone(
+                                                &chrono::FixedOffset::east_opt(8 * 3600).unwrap(),
+                                            ),
+                                        };
+                                        if let Err(e) = message_sender.send(message) {
+                                            warn!("Failed to broadcast NMEA message: {}", e);
+                                        }
+                                    }
+                                    Err(nmea::NmeaError::Unsupported(_)) => {}
+                                    Err(e) => {
+                                        warn!(
+                                            "Invalid NMEA sentence from device {}: {} ({})",
+                                            device_id, line, e
+                                        );
+                                    }
+                                }
+                            }
+                            Ok(None) => break,
+                            Err(e) => {
+                                warn!("NMEA stream error for device {}: {}", device_id, e);
+                                break;
+                            }
+                        }
+                    }
+                    info!(
+                        "NMEA source disconnected for device {}: {}",
+                        device_id, peer
+                    );
+                });
+            }
+        });
+
+        Ok(())
+    }
+
+    /// 停止设备的NMEA TCP监听
+    pub async fn remove_device_listener(&self, device_id: i32) {
+        if let Some((port, shutdown_tx)) = self.listeners.write().await.remove(&device_id) {
+            let _ = shutdown_tx.send(());
+            info!(
+                "Stopping NMEA listener for device {} on port {}",
+                device_id, port
+            );
+        }
+    }
+
+    /// 从数据库加载所有配置了NMEA端口的设备
+    pub async fn load_device_configs(
+        &self,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        use sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
+
+        let devices = device::Entity::find()
+            .filter(device::Column::NmeaPort.is_not_null())
+            .filter(device::Column::IsActive.eq(true))
+            .all(&*self.db)
+            .await?;
+
+        for device_model in devices {
+            if let Some(port) = device_model.nmea_port {
+                if let Err(e) = self.add_device_listener(device_model.id, port as u16).await {
+                    error!("{}", e);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// 检查设备NMEA监听是否正在运行
+    pub async fn is_device_listening(&self, device_id: i32) -> bool {
+        self.listeners.read().await.contains_key(&device_id)
+    }
+}
@@ -1,13 +1,22 @@
-use serde_json::{json, Value as JsonValue};
-use std::collections::HashMap;
+use serde_json::{json, Map, Value as JsonValue};
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::sync::{broadcast, RwLock};
-use tokio::time::interval;
 use tracing::{error, info, warn};
 
-use crate::models::device_realtime_data;
+use crate::models::{collection_data, device, device_realtime_data};
+use crate::services::broadcast::recv_lossy;
+use crate::services::flight::FlightService;
+use crate::services::geofence::{GeofenceBreach, GeofenceMonitor};
+use crate::services::ingest::{IngestMetrics, IngestQueue, IngestRecord};
+use crate::services::mavlink_service::MavlinkTelemetry;
 use crate::services::mqtt_service::MqttMessage;
+use crate::services::nmea::{self, NmeaSentence};
+use crate::services::nmea_service::NmeaMessage;
+use crate::services::rollup::RollupService;
+use crate::services::telemetry::{websocket_fields, TelemetrySnapshot, Timestamp};
+use crate::settings::RealtimeSettings;
 use sea_orm::DatabaseConnection;
 
 #[derive(Debug, Clone)]
@@ -26,41 +35,52 @@ pub struct UnifiedRealtimeMessage {
     pub timestamp: chrono::DateTime<chrono::FixedOffset>,
 }
 
-#[derive(Debug, Clone)]
-struct PendingDataEntry {
-    device_id: i32,
-    data_type: String,
-    data_content: JsonValue,
-    timestamp: chrono::DateTime<chrono::FixedOffset>,
-}
-
 #[derive(Clone)]
 pub struct RealtimeDataService {
     db: Arc<DatabaseConnection>,
     unified_sender: broadcast::Sender<UnifiedRealtimeMessage>,
     device_states: Arc<RwLock<HashMap<i32, JsonValue>>>,
-    pending_data: Arc<RwLock<Vec<PendingDataEntry>>>,    
-    last_save_time: Arc<RwLock<HashMap<i32, Instant>>>,
+    /// 设备类型化遥测快照
+    telemetry: Arc<RwLock<HashMap<i32, TelemetrySnapshot>>>,
+    ingest: IngestQueue,
+    /// 数值字段降采样聚合
+    rollup: RollupService,
+    /// 飞行架次识别
+    flight: FlightService,
+    /// 电子围栏实时检查
+    geofence: GeofenceMonitor,
+    /// 处于采集模式的设备
+    collection_devices: Arc<RwLock<HashSet<i32>>>,
+    last_collection_time: Arc<RwLock<HashMap<i32, Instant>>>,
+    collection_interval: Duration,
 }
 
 impl RealtimeDataService {
-    pub fn new(db: Arc<DatabaseConnection>) -> (Self, broadcast::Receiver<UnifiedRealtimeMessage>) {
+    pub fn new(
+        db: Arc<DatabaseConnection>,
+        settings: RealtimeSettings,
+    ) -> (Self, broadcast::Receiver<UnifiedRealtimeMessage>) {
         let (unified_sender, unified_receiver) = broadcast::channel(1000);
 
         let service = Self {
             db: Arc::clone(&db),
-            unified_sender,
+            unified_sender: unified_sender.clone(),
             device_states: Arc::new(RwLock::new(HashMap::new())),
-            pending_data: Arc::new(RwLock::new(Vec::new())),
-            last_save_time: Arc::new(RwLock::new(HashMap::new())),
+            telemetry: Arc::new(RwLock::new(HashMap::new())),
+            ingest: IngestQueue::start(Arc::clone(&db), settings.ingest.clone()),
+            rollup: RollupService::start(Arc::clone(&db), settings.rollup.clone()),
+            flight: FlightService::start(Arc::clone(&db), settings.flight.clone()),
+            geofence: GeofenceMonitor::start(
+                Arc::clone(&db),
+                settings.geofence.clone(),
+                settings.flight.takeoff_altitude,
+                unified_sender.clone(),
+            ),
+            collection_devices: Arc::new(RwLock::new(HashSet::new())),
+            last_collection_time: Arc::new(RwLock::new(HashMap::new())),
+            collection_interval: Duration::from_secs(settings.nmea.collection_interval),
         };
 
-        // 启动批量存储任务
-        let service_clone = service.clone();
-        tokio::spawn(async move {
-            service_clone.start_batch_save_task().await;
-        });
-
         (service, unified_receiver)
     }
 
@@ -69,166 +89,107 @@ impl RealtimeDataService {
         self.unified_sender.subscribe()
     }
 
-    /// 启动批量存储任务
-    async fn start_batch_save_task(&self) {
-        let mut interval = interval(Duration::from_secs(5));
+    /// 围栏或设备归属变更后重新加载电子围栏
+    pub fn reload_geofences(&self) {
+        self.geofence.reload();
+    }
 
-        loop {
-            interval.tick().await;
-            if let Err(e) = self.flush_pending_data().await {
-                error!("Failed to flush pending data: {}", e);
-            }
-        }
+    /// 订阅电子围栏越界事件
+    pub fn subscribe_geofence_breaches(&self) -> broadcast::Receiver<GeofenceBreach> {
+        self.geofence.subscribe()
     }
 
-    /// 刷新待存储的数据到数据库
-    async fn flush_pending_data(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let pending_data = {
-            let mut pending = self.pending_data.write().await;
-            if pending.is_empty() {
-                return Ok(());
-            }
-            let data = pending.clone();
-            pending.clear();
-            data
-        };
+    /// 获取写入管道指标
+    pub fn ingest_metrics(&self) -> IngestMetrics {
+        self.ingest.metrics()
+    }
 
-        if !pending_data.is_empty() {
-            info!(
-                "Flushing {} pending data entries to database",
-                pending_data.len()
-            );
-
-            // 批量插入数据
-            for entry in pending_data {
-                if let Err(e) = device_realtime_data::Model::create_realtime_data(
-                    &self.db,
-                    entry.device_id,
-                    &entry.data_type,
-                    entry.data_content,
-                )
-                .await
-                {
-                    error!(
-                        "Failed to save realtime data for device {}: {}",
-                        entry.device_id, e
-                    );
+    /// 接入数据的公共处理
+    ///
+    /// 依次写入队列、合并设备状态、记录聚合、更新遥测快照，释放快照锁后交给架次识别和电子围栏，
+    /// 最后广播统一消息。`content` 为存储内容，其字段合并到设备状态；`fields` 为统一字段，
+    /// 用于聚合和遥测快照；`data` 为广播内容。返回更新后的遥测快照。
+    async fn ingest_fields(
+        &self,
+        device_id: i32,
+        data_type: &str,
+        content: JsonValue,
+        fields: &Map<String, JsonValue>,
+        data: JsonValue,
+        timestamp: Timestamp,
+    ) -> TelemetrySnapshot {
+        // 更新设备状态
+        if let JsonValue::Object(content_obj) = &content {
+            let mut states = self.device_states.write().await;
+            let current_state = states.entry(device_id).or_insert_with(|| json!({}));
+            if let JsonValue::Object(current_obj) = current_state {
+                for (key, value) in content_obj {
+                    current_obj.insert(key.clone(), value.clone());
                 }
             }
         }
 
-        Ok(())
-    }
-
-    /// 检查是否应该立即保存数据（基于时间间隔）
-    async fn should_save_immediately(&self, device_id: i32) -> bool {
-        const IMMEDIATE_SAVE_INTERVAL: Duration = Duration::from_secs(30); // 30秒强制保存一次
-
-        let mut last_save_times = self.last_save_time.write().await;
-        let now = Instant::now();
-
-        if let Some(last_save) = last_save_times.get(&device_id) {
-            if now.duration_since(*last_save) >= IMMEDIATE_SAVE_INTERVAL {
-                last_save_times.insert(device_id, now);
-                return true;
+        // 加入写入队列
+        self.ingest
+            .enqueue(IngestRecord {
+                device_id,
+                data_type: data_type.to_string(),
+                data_content: content,
+                received_at: timestamp,
+            })
+            .await;
+
+        // 更新遥测快照
+        let snapshot = {
+            let mut telemetry = self.telemetry.write().await;
+            let snapshot = telemetry.entry(device_id).or_default();
+            if !fields.is_empty() {
+                snapshot.apply_fields(fields, timestamp);
             }
-        } else {
-            last_save_times.insert(device_id, now);
-            return true;
+            snapshot.clone()
+        };
+        if !fields.is_empty() {
+            self.rollup.record(device_id, fields, timestamp);
+            self.flight.observe(device_id, &snapshot, timestamp);
+            self.geofence.observe(device_id, &snapshot, timestamp);
         }
 
-        false
+        // 广播统一消息
+        let unified_msg = UnifiedRealtimeMessage {
+            device_id,
+            message_type: data_type.to_string(),
+            data,
+            timestamp,
+        };
+        if let Err(e) = self.unified_sender.send(unified_msg) {
+            warn!("Failed to broadcast unified {} message: {}", data_type, e);
+        }
+
+        snapshot
     }
 
     /// 启动服务，监听MQTT消息
     pub async fn start_mqtt_listener(&self, mut mqtt_receiver: broadcast::Receiver<MqttMessage>) {
-        let unified_sender = self.unified_sender.clone();
-        let device_states = Arc::clone(&self.device_states);
-        let pending_data = Arc::clone(&self.pending_data);
-        let last_save_time = Arc::clone(&self.last_save_time);
-        let db = Arc::clone(&self.db);
+        let service = self.clone();
 
         tokio::spawn(async move {
-            while let Ok(mqtt_msg) = mqtt_receiver.recv().await {
+            while let Some(mqtt_msg) = recv_lossy(&mut mqtt_receiver, "MQTT listener").await {
                 info!("Processing MQTT message for device {}", mqtt_msg.device_id);
 
-                // 检查是否需要立即保存
-                let should_save_now = {
-                    const IMMEDIATE_SAVE_INTERVAL: std::time::Duration =
-                        std::time::Duration::from_secs(30);
-                    let mut last_save_times = last_save_time.write().await;
-                    let now = std::time::Instant::now();
-
-                    if let Some(last_save) = last_save_times.get(&mqtt_msg.device_id) {
-                        if now.duration_since(*last_save) >= IMMEDIATE_SAVE_INTERVAL {
-                            last_save_times.insert(mqtt_msg.device_id, now);
-                            true
-                        } else {
-                            false
-                        }
-                    } else {
-                        last_save_times.insert(mqtt_msg.device_id, now);
-                        true
-                    }
+                let fields = match &mqtt_msg.payload {
+                    JsonValue::Object(fields) => fields.clone(),
+                    _ => Map::new(),
                 };
-
-                if should_save_now {
-                    // 立即保存到数据库
-                    if let Err(e) = device_realtime_data::Model::create_realtime_data(
-                        &db,
+                service
+                    .ingest_fields(
                         mqtt_msg.device_id,
                         "mqtt",
                         mqtt_msg.payload.clone(),
+                        &fields,
+                        mqtt_msg.payload,
+                        mqtt_msg.timestamp,
                     )
-                    .await
-                    {
-                        error!(
-                            "Failed to save MQTT data for device {}: {}",
-                            mqtt_msg.device_id, e
-                        );
-                    }
-                } else {
-                    // 添加到待存储队列
-                    let pending_entry = PendingDataEntry {
-                        device_id: mqtt_msg.device_id,
-                        data_type: "mqtt".to_string(),
-                        data_content: mqtt_msg.payload.clone(),
-                        timestamp: mqtt_msg.timestamp,
-                    };
-
-                    let mut pending = pending_data.write().await;
-                    pending.push(pending_entry);
-                }
-
-                // 更新设备状态
-                {
-                    let mut states = device_states.write().await;
-                    let current_state = states
-                        .entry(mqtt_msg.device_id)
-                        .or_insert_with(|| json!({}));
-
-                    // 合并MQTT数据到当前状态
-                    if let JsonValue::Object(ref mut current_obj) = current_state {
-                        if let JsonValue::Object(mqtt_obj) = &mqtt_msg.payload {
-                            for (key, value) in mqtt_obj {
-                                current_obj.insert(key.clone(), value.clone());
-                            }
-                        }
-                    }
-                }
-
-                // 创建统一消息
-                let unified_msg = UnifiedRealtimeMessage {
-                    device_id: mqtt_msg.device_id,
-                    message_type: "mqtt".to_string(),
-                    data: mqtt_msg.payload,
-                    timestamp: mqtt_msg.timestamp,
-                };
-
-                // 广播统一消息
-                if let Err(e) = unified_sender.send(unified_msg) {
-                    warn!("Failed to broadcast unified MQTT message: {}", e);
-                }
+                    .await;
             }
         });
     }
@@ -244,64 +205,246 @@ impl RealtimeDataService {
         );
 
         // 解析WebSocket消息格式 (field:value)
-        let field_name = ws_msg.field.clone();
-        let field_value = ws_msg.value.clone();
-        let json_data = json!({
-            field_name: field_value
+        let content = json!({ ws_msg.field.clone(): ws_msg.value.clone() });
+        let fields = websocket_fields(&ws_msg.field, &ws_msg.value);
+        // 广播时直接传递原始的field:value格式
+        let raw_message = format!("{}:{}", ws_msg.field, ws_msg.value);
+
+        self.ingest_fields(
+            ws_msg.device_id,
+            "websocket",
+            content,
+            &fields,
+            json!(raw_message),
+            ws_msg.timestamp,
+        )
+        .await;
+
+        Ok(())
+    }
+
+    /// 启动MAVLink遥测监听
+    pub async fn start_mavlink_listener(
+        &self,
+        mut mavlink_receiver: broadcast::Receiver<MavlinkTelemetry>,
+    ) {
+        let service = self.clone();
+
+        tokio::spawn(async move {
+            while let Some(telemetry) = recv_lossy(&mut mavlink_receiver, "MAVLink listener").await
+            {
+                if let Err(e) = service.process_mavlink_message(telemetry).await {
+                    error!("Failed to process MAVLink message: {}", e);
+                }
+            }
+        });
+    }
+
+    /// 处理MAVLink遥测消息
+    pub async fn process_mavlink_message(
+        &self,
+        telemetry: MavlinkTelemetry,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let JsonValue::Object(fields) = telemetry.payload else {
+            return Ok(());
+        };
+
+        // 存储内容附带消息名称，便于按消息类型查询历史
+        let mut content = fields.clone();
+        content.insert("message".to_string(), json!(telemetry.message_name));
+        let content = JsonValue::Object(content);
+
+        self.ingest_fields(
+            telemetry.device_id,
+            "mavlink",
+            content.clone(),
+            &fields,
+            content,
+            telemetry.timestamp,
+        )
+        .await;
+
+        Ok(())
+    }
+
+    /// 启动NMEA消息监听
+    pub async fn start_nmea_listener(&self, mut nmea_receiver: broadcast::Receiver<NmeaMessage>) {
+        let service = self.clone();
+
+        tokio::spawn(async move {
+            while let Some(message) = recv_lossy(&mut nmea_receiver, "NMEA listener").await {
+                if let Err(e) = service
+                    .process_nmea_sentence(message.device_id, message.sentence, message.timestamp)
+                    .await
+                {
+                    error!("Failed to process NMEA sentence: {}", e);
+                }
+            }
         });
+    }
+
+    /// 处理WebSocket文本帧中的NMEA数据，一帧可以包含多条语句
+    pub async fn process_nmea_text(
+        &self,
+        device_id: i32,
+        text: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let timestamp =
+            chrono::Utc::now().with_timezone(&chrono::FixedOffset::east_opt(8 * 3600).unwrap());
+
+        for line in text.lines().filter(|line| nmea::is_nmea(line)) {
+            match nmea::parse_sentence(line) {
+                Ok(sentence) => {
+                    self.process_nmea_sentence(device_id, sentence, timestamp)
+                        .await?;
+                }
+                Err(nmea::NmeaError::Unsupported(_)) => {}
+                Err(e) => {
+                    warn!(
+                        "Invalid NMEA sentence from device {}: {} ({})",
+                        device_id, line, e
+                    );
+                }
+            }
+        }
 
-        // 检查是否需要立即保存
-        let should_save_now = self.should_save_immediately(ws_msg.device_id).await;
+        Ok(())
+    }
 
-        if should_save_now {
-            // 立即保存到数据库
-            device_realtime_data::Model::create_realtime_data(
-                &self.db,
-                ws_msg.device_id,
-                "websocket",
-                json_data.clone(),
+    /// 处理一条已解析的NMEA语句
+    pub async fn process_nmea_sentence(
+        &self,
+        device_id: i32,
+        sentence: NmeaSentence,
+        timestamp: Timestamp,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let JsonValue::Object(fields) = sentence.to_state_fields() else {
+            return Ok(());
+        };
+        let mut content = fields.clone();
+        content.insert("sentence".to_string(), json!(sentence.kind()));
+        let content = JsonValue::Object(content);
+
+        let snapshot = self
+            .ingest_fields(
+                device_id,
+                "nmea",
+                content.clone(),
+                &fields,
+                content,
+                timestamp,
             )
-            .await?;
-        } else {
-            // 添加到待存储队列
-            let pending_entry = PendingDataEntry {
-                device_id: ws_msg.device_id,
-                data_type: "websocket".to_string(),
-                data_content: json_data.clone(),
-                timestamp: ws_msg.timestamp,
-            };
-
-            let mut pending_data = self.pending_data.write().await;
-            pending_data.push(pending_entry);
+            .await;
+
+        // 采集模式下有效定位自动生成采集数据
+        if sentence.is_valid_fix() {
+            self.record_collection_data(device_id, &snapshot, timestamp)
+                .await?;
         }
 
-        // 更新设备状态
-        {
-            let mut states = self.device_states.write().await;
-            let current_state = states.entry(ws_msg.device_id).or_insert_with(|| json!({}));
+        Ok(())
+    }
+
+    /// 按设备状态生成采集数据，受采集间隔限制
+    async fn record_collection_data(
+        &self,
+        device_id: i32,
+        snapshot: &TelemetrySnapshot,
+        timestamp: chrono::DateTime<chrono::FixedOffset>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        use sea_orm::prelude::Decimal;
+        use sea_orm::{ActiveModelBehavior, ActiveModelTrait, Set};
+
+        if !self.collection_devices.read().await.contains(&device_id) {
+            return Ok(());
+        }
+
+        let Some(position) = &snapshot.position else {
+            return Ok(());
+        };
 
-            if let JsonValue::Object(ref mut current_obj) = current_state {
-                current_obj.insert(ws_msg.field.clone(), json!(ws_msg.value));
+        {
+            let mut last_collection_time = self.last_collection_time.write().await;
+            if last_collection_time
+                .get(&device_id)
+                .is_some_and(|last| last.elapsed() < self.collection_interval)
+            {
+                return Ok(());
             }
+            last_collection_time.insert(device_id, Instant::now());
         }
 
-        // 创建统一消息 - 直接传递原始的field:value格式
-        let raw_message = format!("{}:{}", ws_msg.field, ws_msg.value);
-        let unified_msg = UnifiedRealtimeMessage {
-            device_id: ws_msg.device_id,
-            message_type: "websocket".to_string(),
-            data: json!(raw_message),
-            timestamp: ws_msg.timestamp,
+        let to_decimal = |value: f64| Decimal::from_f64_retain(value);
+        let (Some(latitude), Some(longitude)) = (
+            to_decimal(position.value.latitude),
+            to_decimal(position.value.longitude),
+        ) else {
+            return Ok(());
         };
+        let altitude = snapshot
+            .altitude
+            .as_ref()
+            .and_then(|altitude| altitude.value.amsl.or(altitude.value.relative));
+        // 环境数据来自传感器的 MQTT 上报
+        let temperature = snapshot
+            .extension_f64("temperature")
+            .or_else(|| snapshot.extension_f64("temperature_c"));
+        let humidity = snapshot.extension_f64("humidity");
+
+        collection_data::ActiveModel {
+            longitude: Set(longitude),
+            latitude: Set(latitude),
+            altitude: Set(altitude.and_then(to_decimal)),
+            temperature: Set(temperature.and_then(to_decimal)),
+            humidity: Set(humidity.and_then(to_decimal)),
+            device_id: Set(Some(device_id)),
+            collected_at: Set(timestamp),
+            ..collection_data::ActiveModel::new()
+        }
+        .insert(&*self.db)
+        .await?;
 
-        // 广播统一消息
-        if let Err(e) = self.unified_sender.send(unified_msg) {
-            warn!("Failed to broadcast unified WebSocket message: {}", e);
+        info!("Recorded collection data for device {}", device_id);
+        Ok(())
+    }
+
+    /// 设置设备采集模式
+    pub async fn set_collection_mode(&self, device_id: i32, enabled: bool) {
+        let mut collection_devices = self.collection_devices.write().await;
+        if enabled {
+            collection_devices.insert(device_id);
+        } else {
+            collection_devices.remove(&device_id);
+            self.last_collection_time.write().await.remove(&device_id);
         }
+    }
 
+    /// 从数据库加载处于采集模式的设备
+    pub async fn load_collection_modes(
+        &self,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        use sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
+
+        let devices = device::Entity::find()
+            .filter(device::Column::CollectionMode.eq(true))
+            .filter(device::Column::IsActive.eq(true))
+            .all(&*self.db)
+            .await?;
+
+        *self.collection_devices.write().await = devices.into_iter().map(|d| d.id).collect();
         Ok(())
     }
 
+    /// 获取设备遥测快照
+    pub async fn get_telemetry(&self, device_id: i32) -> Option<TelemetrySnapshot> {
+        self.telemetry.read().await.get(&device_id).cloned()
+    }
+
+    /// 获取所有设备遥测快照
+    pub async fn get_all_telemetry(&self) -> HashMap<i32, TelemetrySnapshot> {
+        self.telemetry.read().await.clone()
+    }
+
     /// 获取设备当前状态
     pub async fn get_device_state(&self, device_id: i32) -> Option<JsonValue> {
         let states = self.device_states.read().await;
@@ -356,8 +499,10 @@ impl RealtimeDataService {
             .await?;
 
         let mut device_data: HashMap<i32, JsonValue> = HashMap::new();
+        let mut device_telemetry: HashMap<i32, TelemetrySnapshot> = HashMap::new();
 
-        for data in latest_data {
+        // 按时间正序合并，保证新数据覆盖旧数据
+        for data in latest_data.into_iter().rev() {
             let device_state = device_data
                 .entry(data.device_id)
                 .or_insert_with(|| json!({}));
@@ -369,6 +514,20 @@ impl RealtimeDataService {
                     }
                 }
             }
+
+            if let JsonValue::Object(data_obj) = &data.data_content {
+                let snapshot = device_telemetry.entry(data.device_id).or_default();
+                if data.data_type == "websocket" {
+                    for (field, value) in data_obj {
+                        let value = value
+                            .as_str()
+                            .map_or_else(|| value.to_string(), str::to_string);
+                        snapshot.apply_websocket_field(field, &value, data.received_at);
+                    }
+                } else {
+                    snapshot.apply_fields(data_obj, data.received_at);
+                }
+            }
         }
 
         // 更新内存中的状态
@@ -376,6 +535,10 @@ impl RealtimeDataService {
             let mut states = self.device_states.write().await;
             *states = device_data;
         }
+        {
+            let mut telemetry = self.telemetry.write().await;
+            *telemetry = device_telemetry;
+        }
 
         info!(
             "Loaded device states for {} devices",
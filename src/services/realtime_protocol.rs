@@ -0,0 +1,586 @@
+//! `/api/realtime/ws` 客户端协议
+//!
+//! 所有消息均为 JSON 文本帧，以 `type` 字段区分。
+//!
+//! 客户端 → 服务端（[`ClientMessage`]）：
+//! - `subscribe`：订阅设备或设备分组，如
+//!   `{"type":"subscribe","device_ids":[1,2],"groups":["巡检一队"]}`，`{"type":"subscribe","all":true}`
+//!   订阅全部设备。兼容旧格式 `{"type":"subscribe_device","device_id":1}`。
+//!   可同时携带推送选项 `fields`（遥测字段白名单，见 [`TELEMETRY_FIELDS`]）和 `max_rate`
+//!   （每台设备每秒最多推送次数），如 `{"type":"subscribe","all":true,"fields":["position"],"max_rate":2}`；
+//!   只修改选项时可省略订阅目标。选项对整个连接生效，`fields: []` 且 `max_rate: 0` 恢复原始数据推送。
+//! - `unsubscribe`：参数同 `subscribe`，`all: true` 取消全部订阅。
+//! - `ping`：服务端回复 `pong`，并原样带回可选的 `id`。
+//!
+//! 服务端 → 客户端（[`ServerMessage`]）：
+//! - `welcome`：连接建立后发送一次。
+//! - `subscriptions`：每次订阅变更后的完整订阅。
+//! - `snapshot`：新订阅设备的当前状态，随后才是该设备的增量数据。
+//! - `realtime_data`：实时数据，格式见 [`super::broadcast::realtime_message`]。
+//! - `telemetry`：设置了推送选项的客户端不再接收 `realtime_data`，改为接收服务端合并后的遥测快照，
+//!   只包含所选字段；所选字段没有变化时不推送，超过 `max_rate` 的更新合并为窗口结束时的最新值。
+//! - `pong`、`error`。
+//!
+//! 连接时未指定 `device_id` 的客户端默认接收全部设备；首次按设备或分组订阅后改为只接收所订阅的内容。
+//! 无论如何订阅，客户端都只会收到其用户有权访问的设备（见 [`super::access`]），
+//! 显式订阅无权访问的设备会收到 `error`。
+
+use axum::extract::ws::Message;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::collections::HashSet;
+
+use crate::services::telemetry::TelemetrySnapshot;
+
+/// 可订阅的遥测字段，对应 [`TelemetrySnapshot`] 的顶层字段
+pub const TELEMETRY_FIELDS: [&str; 10] = [
+    "position",
+    "altitude",
+    "attitude",
+    "velocity",
+    "battery",
+    "gps",
+    "flight_mode",
+    "armed",
+    "signal_strength",
+    "extensions",
+];
+
+/// 每台设备推送频率上限（Hz）
+pub const MAX_RATE_LIMIT: f64 = 50.0;
+
+/// 订阅或取消订阅的目标
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SubscriptionTargets {
+    #[serde(default)]
+    pub device_id: Option<i32>,
+    #[serde(default)]
+    pub device_ids: Vec<i32>,
+    #[serde(default)]
+    pub group: Option<String>,
+    #[serde(default)]
+    pub groups: Vec<String>,
+    #[serde(default)]
+    pub all: bool,
+}
+
+impl SubscriptionTargets {
+    pub fn devices(&self) -> impl Iterator<Item = i32> + '_ {
+        self.device_id.iter().chain(&self.device_ids).copied()
+    }
+
+    pub fn groups(&self) -> impl Iterator<Item = &str> {
+        self.group
+            .iter()
+            .chain(&self.groups)
+            .map(|group| group.trim())
+            .filter(|group| !group.is_empty())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.all && self.devices().next().is_none() && self.groups().next().is_none()
+    }
+
+    /// 只保留满足条件的设备，返回被移除的设备
+    pub fn retain_devices(&mut self, mut allowed: impl FnMut(i32) -> bool) -> Vec<i32> {
+        let mut removed = Vec::new();
+        if let Some(device_id) = self.device_id.filter(|device_id| !allowed(*device_id)) {
+            self.device_id = None;
+            removed.push(device_id);
+        }
+        self.device_ids.retain(|device_id| {
+            let keep = allowed(*device_id);
+            if !keep {
+                removed.push(*device_id);
+            }
+            keep
+        });
+        removed
+    }
+}
+
+/// 推送选项，未携带的选项保持不变
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StreamOptions {
+    #[serde(default)]
+    pub fields: Option<Vec<String>>,
+    #[serde(default)]
+    pub max_rate: Option<f64>,
+}
+
+impl StreamOptions {
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_none() && self.max_rate.is_none()
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if let Some(field) = self
+            .fields
+            .iter()
+            .flatten()
+            .find(|field| !TELEMETRY_FIELDS.contains(&field.as_str()))
+        {
+            return Err(format!(
+                "Unknown field: {}, expected one of {:?}",
+                field, TELEMETRY_FIELDS
+            ));
+        }
+        match self.max_rate {
+            Some(rate) if !rate.is_finite() || !(0.0..=MAX_RATE_LIMIT).contains(&rate) => {
+                Err(format!("max_rate must be between 0 and {}", MAX_RATE_LIMIT))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// 客户端消息
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    #[serde(alias = "subscribe_device")]
+    Subscribe {
+        #[serde(flatten)]
+        targets: SubscriptionTargets,
+        #[serde(flatten)]
+        options: StreamOptions,
+    },
+    #[serde(alias = "unsubscribe_device")]
+    Unsubscribe(SubscriptionTargets),
+    Ping {
+        #[serde(default)]
+        id: Option<JsonValue>,
+    },
+}
+
+impl ClientMessage {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let message: Self =
+            serde_json::from_str(text).map_err(|e| format!("Invalid message: {}", e))?;
+        match &message {
+            Self::Subscribe { options, .. } if !options.is_empty() => {
+                options.validate().map(|_| message)
+            }
+            Self::Subscribe { targets, .. } | Self::Unsubscribe(targets) if targets.is_empty() => {
+                Err("Expected device_id, device_ids, group, groups or all".to_string())
+            }
+            _ => Ok(message),
+        }
+    }
+}
+
+/// 服务端消息
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    Welcome {
+        client_id: String,
+        device_id: Option<i32>,
+        message: String,
+    },
+    Subscriptions {
+        all: bool,
+        device_ids: Vec<i32>,
+        groups: Vec<String>,
+        fields: Vec<String>,
+        max_rate: Option<f64>,
+    },
+    Snapshot {
+        device_id: i32,
+        group: Option<String>,
+        state: Option<JsonValue>,
+        telemetry: Option<Box<TelemetrySnapshot>>,
+        timestamp: String,
+    },
+    Telemetry {
+        device_id: i32,
+        /// 所选字段，格式同 [`TelemetrySnapshot`]
+        telemetry: JsonValue,
+        timestamp: String,
+    },
+    Pong {
+        id: Option<JsonValue>,
+        timestamp: String,
+    },
+    Error {
+        message: String,
+    },
+}
+
+impl ServerMessage {
+    pub fn to_ws_message(&self) -> Message {
+        let text = serde_json::to_string(self).unwrap_or_default();
+        Message::Text(text.into())
+    }
+}
+
+/// 单个客户端的订阅
+#[derive(Debug, Clone, Default)]
+pub struct Subscription {
+    pub all: bool,
+    pub devices: HashSet<i32>,
+    pub groups: HashSet<String>,
+    /// 遥测字段白名单，为空表示全部字段
+    pub fields: Vec<String>,
+    /// 每台设备每秒最多推送次数，0 表示不限
+    pub max_rate: f64,
+    /// 连接时未指定设备而默认接收全部，显式订阅后取消
+    implicit_all: bool,
+}
+
+impl Subscription {
+    /// 连接参数对应的初始订阅
+    pub fn for_device(device_id: Option<i32>) -> Self {
+        match device_id {
+            Some(device_id) => Self {
+                devices: HashSet::from([device_id]),
+                ..Default::default()
+            },
+            None => Self {
+                all: true,
+                implicit_all: true,
+                ..Default::default()
+            },
+        }
+    }
+
+    pub fn matches(&self, device_id: i32, group: Option<&str>) -> bool {
+        self.all
+            || self.devices.contains(&device_id)
+            || group.is_some_and(|group| self.groups.contains(group))
+    }
+
+    pub fn subscribe(&mut self, targets: &SubscriptionTargets) {
+        if targets.all {
+            self.all = true;
+        } else if self.implicit_all {
+            self.all = false;
+        }
+        self.implicit_all = false;
+        self.devices.extend(targets.devices());
+        self.groups.extend(targets.groups().map(str::to_string));
+    }
+
+    pub fn set_options(&mut self, options: &StreamOptions) {
+        if let Some(fields) = &options.fields {
+            let mut fields = fields.clone();
+            fields.sort_by_key(|field| TELEMETRY_FIELDS.iter().position(|known| known == field));
+            fields.dedup();
+            self.fields = fields;
+        }
+        if let Some(max_rate) = options.max_rate {
+            self.max_rate = max_rate;
+        }
+    }
+
+    /// 设置了推送选项时改为推送合并后的遥测
+    pub fn wants_telemetry(&self) -> bool {
+        !self.fields.is_empty() || self.max_rate > 0.0
+    }
+
+    /// 同一设备两次推送的最小间隔
+    pub fn min_interval(&self) -> std::time::Duration {
+        if self.max_rate > 0.0 {
+            std::time::Duration::from_secs_f64(1.0 / self.max_rate)
+        } else {
+            std::time::Duration::ZERO
+        }
+    }
+
+    /// 从遥测快照中取出所选字段
+    pub fn select_fields(&self, telemetry: &TelemetrySnapshot) -> JsonValue {
+        let value = serde_json::to_value(telemetry).unwrap_or_default();
+        if self.fields.is_empty() {
+            return value;
+        }
+        let JsonValue::Object(mut all) = value else {
+            return value;
+        };
+        let selected = self
+            .fields
+            .iter()
+            .filter_map(|field| all.remove(field).map(|value| (field.clone(), value)))
+            .collect();
+        JsonValue::Object(selected)
+    }
+
+    pub fn unsubscribe(&mut self, targets: &SubscriptionTargets) {
+        self.implicit_all = false;
+        if targets.all {
+            self.all = false;
+            self.devices.clear();
+            self.groups.clear();
+            return;
+        }
+        for device_id in targets.devices() {
+            self.devices.remove(&device_id);
+        }
+        for group in targets.groups() {
+            self.groups.remove(group);
+        }
+    }
+
+    pub fn to_message(&self) -> ServerMessage {
+        let mut device_ids: Vec<i32> = self.devices.iter().copied().collect();
+        device_ids.sort_unstable();
+        let mut groups: Vec<String> = self.groups.iter().cloned().collect();
+        groups.sort();
+        ServerMessage::Subscriptions {
+            all: self.all,
+            device_ids,
+            groups,
+            fields: self.fields.clone(),
+            max_rate: (self.max_rate > 0.0).then_some(self.max_rate),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn round_trip_client(message: ClientMessage) {
+        let text = serde_json::to_string(&message).unwrap();
+        assert_eq!(ClientMessage::parse(&text), Ok(message), "{}", text);
+    }
+
+    fn round_trip_server(message: ServerMessage, kind: &str) {
+        let value = serde_json::to_value(&message).unwrap();
+        assert_eq!(value["type"], json!(kind));
+        let parsed: ServerMessage = serde_json::from_value(value).unwrap();
+        assert_eq!(parsed, message);
+    }
+
+    #[test]
+    fn client_messages_round_trip() {
+        round_trip_client(ClientMessage::Subscribe {
+            targets: SubscriptionTargets {
+                device_ids: vec![1, 2],
+                groups: vec!["巡检一队".to_string()],
+                ..Default::default()
+            },
+            options: StreamOptions {
+                fields: Some(vec!["position".to_string(), "battery".to_string()]),
+                max_rate: Some(2.0),
+            },
+        });
+        round_trip_client(ClientMessage::Subscribe {
+            targets: SubscriptionTargets {
+                all: true,
+                ..Default::default()
+            },
+            options: StreamOptions::default(),
+        });
+        round_trip_client(ClientMessage::Unsubscribe(SubscriptionTargets {
+            device_id: Some(3),
+            group: Some("巡检二队".to_string()),
+            ..Default::default()
+        }));
+        round_trip_client(ClientMessage::Ping {
+            id: Some(json!("42")),
+        });
+        round_trip_client(ClientMessage::Ping { id: None });
+    }
+
+    #[test]
+    fn parses_documented_and_legacy_messages() {
+        assert_eq!(
+            ClientMessage::parse(r#"{"type":"subscribe_device","device_id":1}"#),
+            Ok(ClientMessage::Subscribe {
+                targets: SubscriptionTargets {
+                    device_id: Some(1),
+                    ..Default::default()
+                },
+                options: StreamOptions::default(),
+            })
+        );
+        assert_eq!(
+            ClientMessage::parse(r#"{"type":"unsubscribe_device","device_id":1}"#),
+            Ok(ClientMessage::Unsubscribe(SubscriptionTargets {
+                device_id: Some(1),
+                ..Default::default()
+            }))
+        );
+        // 只修改推送选项时可以省略订阅目标
+        assert_eq!(
+            ClientMessage::parse(r#"{"type":"subscribe","fields":[],"max_rate":0}"#),
+            Ok(ClientMessage::Subscribe {
+                targets: SubscriptionTargets::default(),
+                options: StreamOptions {
+                    fields: Some(Vec::new()),
+                    max_rate: Some(0.0),
+                },
+            })
+        );
+        assert_eq!(
+            ClientMessage::parse(r#"{"type":"ping"}"#),
+            Ok(ClientMessage::Ping { id: None })
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_and_malformed_messages() {
+        let rejected = [
+            "not json",
+            "[]",
+            r#"{"device_id":1}"#,
+            r#"{"type":"shutdown"}"#,
+            r#"{"type":"subscribe"}"#,
+            r#"{"type":"unsubscribe","device_ids":[]}"#,
+            r#"{"type":"subscribe","group":"  "}"#,
+            r#"{"type":"subscribe","device_ids":"1"}"#,
+            r#"{"type":"subscribe","all":true,"fields":["position","password"]}"#,
+            r#"{"type":"subscribe","all":true,"max_rate":-1}"#,
+            r#"{"type":"subscribe","all":true,"max_rate":1000}"#,
+        ];
+        for text in rejected {
+            assert!(ClientMessage::parse(text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn server_messages_round_trip() {
+        round_trip_server(
+            ServerMessage::Welcome {
+                client_id: "client-1".to_string(),
+                device_id: Some(1),
+                message: "Connected".to_string(),
+            },
+            "welcome",
+        );
+        round_trip_server(
+            ServerMessage::Subscriptions {
+                all: false,
+                device_ids: vec![1, 2],
+                groups: vec!["巡检一队".to_string()],
+                fields: vec!["position".to_string()],
+                max_rate: Some(2.0),
+            },
+            "subscriptions",
+        );
+
+        let timestamp = chrono::DateTime::parse_from_rfc3339("2026-10-18T08:00:00+08:00").unwrap();
+        let mut telemetry = TelemetrySnapshot::default();
+        telemetry.apply_fields(
+            json!({ "latitude": 30.5, "longitude": 120.1, "battery_remaining": 80, "rssi": 90 })
+                .as_object()
+                .unwrap(),
+            timestamp,
+        );
+        round_trip_server(
+            ServerMessage::Snapshot {
+                device_id: 1,
+                group: Some("巡检一队".to_string()),
+                state: Some(json!({ "battery": "80" })),
+                telemetry: Some(Box::new(telemetry)),
+                timestamp: timestamp.to_rfc3339(),
+            },
+            "snapshot",
+        );
+        round_trip_server(
+            ServerMessage::Snapshot {
+                device_id: 2,
+                group: None,
+                state: None,
+                telemetry: None,
+                timestamp: timestamp.to_rfc3339(),
+            },
+            "snapshot",
+        );
+        round_trip_server(
+            ServerMessage::Telemetry {
+                device_id: 1,
+                telemetry: json!({ "position": { "value": { "latitude": 30.5, "longitude": 120.1 } } }),
+                timestamp: timestamp.to_rfc3339(),
+            },
+            "telemetry",
+        );
+        round_trip_server(
+            ServerMessage::Pong {
+                id: Some(json!(7)),
+                timestamp: timestamp.to_rfc3339(),
+            },
+            "pong",
+        );
+        round_trip_server(
+            ServerMessage::Error {
+                message: "无权访问该设备".to_string(),
+            },
+            "error",
+        );
+    }
+
+    #[test]
+    fn subscription_matching() {
+        let mut subscription = Subscription::for_device(None);
+        assert!(subscription.matches(1, None));
+        assert!(subscription.matches(2, Some("巡检一队")));
+
+        // 首次显式订阅后不再默认接收全部设备
+        subscription.subscribe(&SubscriptionTargets {
+            device_id: Some(1),
+            group: Some("巡检一队".to_string()),
+            ..Default::default()
+        });
+        assert!(subscription.matches(1, None));
+        assert!(subscription.matches(3, Some("巡检一队")));
+        assert!(!subscription.matches(2, None));
+        assert!(!subscription.matches(2, Some("巡检二队")));
+
+        subscription.unsubscribe(&SubscriptionTargets {
+            group: Some("巡检一队".to_string()),
+            ..Default::default()
+        });
+        assert!(!subscription.matches(3, Some("巡检一队")));
+        assert!(subscription.matches(1, None));
+
+        subscription.subscribe(&SubscriptionTargets {
+            all: true,
+            ..Default::default()
+        });
+        assert!(subscription.matches(5, None));
+        subscription.unsubscribe(&SubscriptionTargets {
+            all: true,
+            ..Default::default()
+        });
+        assert!(!subscription.matches(1, None));
+
+        let single = Subscription::for_device(Some(4));
+        assert!(single.matches(4, None));
+        assert!(!single.matches(5, None));
+    }
+
+    #[test]
+    fn stream_options_select_fields() {
+        let mut subscription = Subscription::for_device(None);
+        assert!(!subscription.wants_telemetry());
+
+        subscription.set_options(&StreamOptions {
+            fields: Some(vec![
+                "battery".to_string(),
+                "position".to_string(),
+                "battery".to_string(),
+            ]),
+            max_rate: Some(4.0),
+        });
+        assert!(subscription.wants_telemetry());
+        assert_eq!(subscription.fields, ["position", "battery"]);
+        assert_eq!(
+            subscription.min_interval(),
+            std::time::Duration::from_millis(250)
+        );
+
+        let mut telemetry = TelemetrySnapshot::default();
+        telemetry.apply_fields(
+            json!({ "latitude": 30.5, "longitude": 120.1, "roll": 1.0 })
+                .as_object()
+                .unwrap(),
+            chrono::Utc::now().fixed_offset(),
+        );
+        let selected = subscription.select_fields(&telemetry);
+        assert_eq!(selected["position"]["value"]["latitude"], json!(30.5));
+        assert!(selected["battery"].is_null());
+        assert!(selected.get("attitude").is_none());
+    }
+}
@@ -0,0 +1,393 @@
+//! 飞行回放
+//!
+//! 按记录时间把一个架次的原始数据重新推送给 WebSocket 客户端，数据消息与实时推送格式相同
+//! （`type: realtime_data`），前端地图无需区分实时和回放。客户端可发送控制消息：
+//! `{"type":"play"}`、`{"type":"pause"}`、`{"type":"seek","position_ms":60000}`、
+//! `{"type":"speed","speed":10}`，服务端以 `replay_status` 消息回报当前状态。
+
+use axum::extract::ws::{Message, WebSocket};
+use chrono::FixedOffset;
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value as JsonValue};
+use std::collections::VecDeque;
+use tokio::time::Instant;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::models::{device_realtime_data, flight};
+use crate::services::broadcast::realtime_message;
+use crate::services::realtime_data::UnifiedRealtimeMessage;
+use crate::services::telemetry::Timestamp;
+use sea_orm::{
+    ColumnTrait, Condition, DatabaseConnection, DbErr, EntityTrait, QueryFilter, QueryOrder,
+    QuerySelect,
+};
+
+/// 每次从数据库加载的记录数
+const BATCH_SIZE: u64 = 500;
+pub const MIN_SPEED: f64 = 0.1;
+pub const MAX_SPEED: f64 = 100.0;
+
+/// 回放的数据范围
+#[derive(Debug, Clone, Copy)]
+pub struct ReplayRange {
+    pub flight_id: Uuid,
+    pub device_id: i32,
+    pub start: Timestamp,
+    pub end: Timestamp,
+}
+
+impl ReplayRange {
+    /// 进行中的架次回放到当前时间
+    pub fn from_flight(flight: &flight::Model) -> Self {
+        Self {
+            flight_id: flight.uuid,
+            device_id: flight.device_id,
+            start: flight.start_time,
+            end: flight.end_time.unwrap_or_else(|| {
+                chrono::Utc::now().with_timezone(&FixedOffset::east_opt(8 * 3600).unwrap())
+            }),
+        }
+    }
+
+    fn duration_ms(&self) -> i64 {
+        (self.end - self.start).num_milliseconds().max(0)
+    }
+}
+
+/// 客户端控制消息
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplayControl {
+    Play,
+    Pause,
+    /// 相对架次开始的毫秒数
+    Seek(i64),
+    Speed(f64),
+    Ping,
+}
+
+impl ReplayControl {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let value: JsonValue =
+            serde_json::from_str(text).map_err(|e| format!("Invalid JSON: {}", e))?;
+        let kind = value
+            .get("type")
+            .and_then(JsonValue::as_str)
+            .ok_or("Missing message type")?;
+
+        match kind {
+            "play" => Ok(Self::Play),
+            "pause" => Ok(Self::Pause),
+            "ping" => Ok(Self::Ping),
+            "seek" => value
+                .get("position_ms")
+                .and_then(JsonValue::as_f64)
+                .map(|ms| Self::Seek(ms as i64))
+                .ok_or_else(|| "seek requires numeric position_ms".to_string()),
+            "speed" => value
+                .get("speed")
+                .and_then(JsonValue::as_f64)
+                .ok_or_else(|| "speed requires numeric speed".to_string())
+                .and_then(validate_speed)
+                .map(Self::Speed),
+            other => Err(format!("Unknown message type: {}", other)),
+        }
+    }
+}
+
+/// 回放倍速，常用 1、2、10，也支持其它值
+pub fn validate_speed(speed: f64) -> Result<f64, String> {
+    if speed.is_finite() && (MIN_SPEED..=MAX_SPEED).contains(&speed) {
+        Ok(speed)
+    } else {
+        Err(format!(
+            "Speed must be between {} and {}",
+            MIN_SPEED, MAX_SPEED
+        ))
+    }
+}
+
+/// 将存储的记录还原为实时消息，WebSocket 数据恢复为原始的 field:value 格式
+fn to_unified(row: device_realtime_data::Model) -> UnifiedRealtimeMessage {
+    let data = match (&row.data_type[..], &row.data_content) {
+        ("websocket", JsonValue::Object(fields)) if fields.len() == 1 => {
+            let (field, value) = fields.iter().next().unwrap();
+            let value = value
+                .as_str()
+                .map_or_else(|| value.to_string(), str::to_string);
+            json!(format!("{}:{}", field, value))
+        }
+        _ => row.data_content,
+    };
+    UnifiedRealtimeMessage {
+        device_id: row.device_id,
+        message_type: row.data_type,
+        data,
+        // 与实时推送一致使用东八区时间
+        timestamp: row
+            .received_at
+            .with_timezone(&FixedOffset::east_opt(8 * 3600).unwrap()),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Playing,
+    Paused,
+    Finished,
+}
+
+impl State {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Playing => "playing",
+            Self::Paused => "paused",
+            Self::Finished => "finished",
+        }
+    }
+}
+
+/// 单个客户端的回放状态
+struct Player {
+    db: DatabaseConnection,
+    range: ReplayRange,
+    speed: f64,
+    state: State,
+    /// `anchor` 时刻对应的回放位置（记录时间）
+    position: Timestamp,
+    anchor: Instant,
+    buffer: VecDeque<device_realtime_data::Model>,
+    /// 已加载的最后一条记录，为空时从 `position` 开始加载
+    cursor: Option<(Timestamp, i64)>,
+    exhausted: bool,
+}
+
+impl Player {
+    fn new(db: DatabaseConnection, range: ReplayRange, speed: f64) -> Self {
+        Self {
+            db,
+            range,
+            speed,
+            state: State::Playing,
+            position: range.start,
+            anchor: Instant::now(),
+            buffer: VecDeque::new(),
+            cursor: None,
+            exhausted: false,
+        }
+    }
+
+    fn current_position(&self) -> Timestamp {
+        if self.state != State::Playing {
+            return self.position;
+        }
+        let elapsed = self.anchor.elapsed().as_secs_f64() * 1000.0 * self.speed;
+        (self.position + chrono::Duration::milliseconds(elapsed as i64)).min(self.range.end)
+    }
+
+    /// 以当前位置为新的计时起点
+    fn rebase(&mut self) {
+        self.position = self.current_position();
+        self.anchor = Instant::now();
+    }
+
+    fn play(&mut self) {
+        match self.state {
+            State::Playing => {}
+            State::Paused => {
+                self.anchor = Instant::now();
+                self.state = State::Playing;
+            }
+            // 播放完毕后从头开始
+            State::Finished => {
+                self.seek(0);
+                self.state = State::Playing;
+            }
+        }
+    }
+
+    fn pause(&mut self) {
+        if self.state == State::Playing {
+            self.rebase();
+            self.state = State::Paused;
+        }
+    }
+
+    fn set_speed(&mut self, speed: f64) {
+        self.rebase();
+        self.speed = speed;
+    }
+
+    fn seek(&mut self, position_ms: i64) {
+        let offset = position_ms.clamp(0, self.range.duration_ms());
+        self.position = self.range.start + chrono::Duration::milliseconds(offset);
+        self.anchor = Instant::now();
+        self.buffer.clear();
+        self.cursor = None;
+        self.exhausted = false;
+        if self.state == State::Finished {
+            self.state = State::Paused;
+        }
+    }
+
+    fn finish(&mut self) {
+        self.position = self.range.end;
+        self.state = State::Finished;
+    }
+
+    /// 下一条记录的时间，必要时从数据库加载
+    async fn peek(&mut self) -> Result<Option<Timestamp>, DbErr> {
+        if self.buffer.is_empty() && !self.exhausted {
+            self.load().await?;
+        }
+        Ok(self.buffer.front().map(|row| row.received_at))
+    }
+
+    async fn load(&mut self) -> Result<(), DbErr> {
+        use device_realtime_data::Column;
+
+        let after = match self.cursor {
+            Some((received_at, id)) => Condition::any()
+                .add(Column::ReceivedAt.gt(received_at))
+                .add(
+                    Condition::all()
+                        .add(Column::ReceivedAt.eq(received_at))
+                        .add(Column::Id.gt(id)),
+                ),
+            None => Condition::all().add(Column::ReceivedAt.gte(self.position)),
+        };
+
+        let rows = device_realtime_data::Entity::find()
+            .filter(Column::DeviceId.eq(self.range.device_id))
+            .filter(Column::ReceivedAt.lte(self.range.end))
+            .filter(after)
+            .order_by_asc(Column::ReceivedAt)
+            .order_by_asc(Column::Id)
+            .limit(BATCH_SIZE)
+            .all(&self.db)
+            .await?;
+
+        self.exhausted = (rows.len() as u64) < BATCH_SIZE;
+        if let Some(last) = rows.last() {
+            self.cursor = Some((last.received_at, last.id));
+        }
+        self.buffer.extend(rows);
+        Ok(())
+    }
+
+    /// 记录时间对应的发送时刻
+    fn deadline(&self, at: Timestamp) -> Instant {
+        let wait_ms = (at - self.position).num_milliseconds().max(0) as f64 / self.speed;
+        self.anchor + std::time::Duration::from_millis(wait_ms as u64)
+    }
+
+    fn status(&self) -> JsonValue {
+        json!({
+            "type": "replay_status",
+            "flight_id": self.range.flight_id,
+            "state": self.state.as_str(),
+            "position_ms": (self.current_position() - self.range.start).num_milliseconds(),
+            "duration_ms": self.range.duration_ms(),
+            "speed": self.speed
+        })
+    }
+}
+
+async fn send_json(
+    sender: &mut futures_util::stream::SplitSink<WebSocket, Message>,
+    value: &JsonValue,
+) -> bool {
+    sender
+        .send(Message::Text(value.to_string().into()))
+        .await
+        .is_ok()
+}
+
+/// 处理一个回放连接，直到客户端断开
+pub async fn run(db: DatabaseConnection, websocket: WebSocket, range: ReplayRange, speed: f64) {
+    let (mut sender, mut receiver) = websocket.split();
+    let mut player = Player::new(db, range, speed);
+
+    let ready = json!({
+        "type": "replay_ready",
+        "flight_id": range.flight_id,
+        "device_id": range.device_id,
+        "start_time": range.start.to_rfc3339(),
+        "end_time": range.end.to_rfc3339(),
+        "duration_ms": range.duration_ms(),
+        "speed": speed
+    });
+    if !send_json(&mut sender, &ready).await {
+        return;
+    }
+    info!("Replay of flight {} started", range.flight_id);
+
+    loop {
+        let deadline = if player.state == State::Playing {
+            match player.peek().await {
+                Ok(Some(at)) => Some(player.deadline(at)),
+                Ok(None) => {
+                    player.finish();
+                    if !send_json(&mut sender, &player.status()).await {
+                        break;
+                    }
+                    None
+                }
+                Err(e) => {
+                    warn!("Replay of flight {} failed: {}", range.flight_id, e);
+                    let error =
+                        json!({ "type": "error", "message": format!("Database error: {}", e) });
+                    send_json(&mut sender, &error).await;
+                    break;
+                }
+            }
+        } else {
+            None
+        };
+
+        tokio::select! {
+            _ = async {
+                match deadline {
+                    Some(deadline) => tokio::time::sleep_until(deadline).await,
+                    None => std::future::pending().await,
+                }
+            } => {
+                if let Some(row) = player.buffer.pop_front() {
+                    let message = realtime_message(&to_unified(row));
+                    if !send_json(&mut sender, &message).await {
+                        break;
+                    }
+                }
+            }
+            message = receiver.next() => {
+                let text = match message {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => continue,
+                };
+                let reply = match ReplayControl::parse(&text) {
+                    Ok(ReplayControl::Ping) => json!({
+                        "type": "pong",
+                        "timestamp": chrono::Utc::now().to_rfc3339()
+                    }),
+                    Ok(control) => {
+                        match control {
+                            ReplayControl::Play => player.play(),
+                            ReplayControl::Pause => player.pause(),
+                            ReplayControl::Seek(position_ms) => player.seek(position_ms),
+                            ReplayControl::Speed(speed) => player.set_speed(speed),
+                            ReplayControl::Ping => {}
+                        }
+                        player.status()
+                    }
+                    Err(e) => json!({ "type": "error", "message": e }),
+                };
+                if !send_json(&mut sender, &reply).await {
+                    break;
+                }
+            }
+        }
+    }
+
+    info!("Replay of flight {} closed", range.flight_id);
+}
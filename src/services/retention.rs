@@ -0,0 +1,253 @@
+//! 历史数据保留策略
+//!
+//! 过期的原始实时数据按批导出到 gzip 压缩的 NDJSON 归档文件（每批一个文件，每行一条
+//! `device_realtime_data` 记录），归档文件完整写入磁盘后再删除该批数据；
+//! 遥测聚合按粒度直接删除。
+//! 归档文件的压缩和读写在阻塞线程池中进行，不占用异步运行时的工作线程。
+
+use chrono::FixedOffset;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::Serialize;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc;
+use tracing::info;
+
+use crate::models::{device_realtime_data, device_telemetry_rollup};
+use crate::services::rollup::Resolution;
+use crate::settings::RetentionSettings;
+use sea_orm::{
+    ColumnTrait, DatabaseConnection, EntityTrait, NotSet, PaginatorTrait, QueryFilter, QueryOrder,
+    QuerySelect, Set, TransactionTrait,
+};
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// 恢复时单条 INSERT 语句的最大行数
+const RESTORE_ROWS_PER_INSERT: usize = 1000;
+
+/// 在阻塞线程池中执行文件读写
+async fn blocking<T: Send + 'static>(
+    task: impl FnOnce() -> Result<T, BoxError> + Send + 'static,
+) -> Result<T, BoxError> {
+    tokio::task::spawn_blocking(task).await?
+}
+
+/// 一次保留策略执行的结果
+#[derive(Debug, Default, Serialize)]
+pub struct RetentionReport {
+    /// 已归档的原始数据行数
+    pub archived: u64,
+    /// 已删除（演练模式下为将删除）的原始数据行数
+    pub deleted_raw: u64,
+    /// 已删除（演练模式下为将删除）的聚合行数
+    pub deleted_rollups: u64,
+    /// 本次生成的归档文件，每批一个
+    pub archive_files: Vec<PathBuf>,
+}
+
+/// 将一批记录写入独立的归档文件，返回归档文件路径
+///
+/// 先写入临时文件，gzip 结束并同步到磁盘后再改名，返回后才能删除这些记录。
+/// 中途崩溃只会留下 `.partial` 临时文件，已完成的归档文件始终完整可恢复。
+fn write_archive(
+    dir: &Path,
+    now: chrono::DateTime<FixedOffset>,
+    sequence: usize,
+    rows: &[device_realtime_data::Model],
+) -> Result<PathBuf, BoxError> {
+    std::fs::create_dir_all(dir)?;
+    let path = dir.join(format!(
+        "device_realtime_data-{}-{:05}.ndjson.gz",
+        now.format("%Y%m%d%H%M%S"),
+        sequence
+    ));
+    let partial = path.with_extension("gz.partial");
+
+    let mut encoder = GzEncoder::new(
+        BufWriter::new(File::create(&partial)?),
+        Compression::default(),
+    );
+    for row in rows {
+        serde_json::to_writer(&mut encoder, row)?;
+        encoder.write_all(b"\n")?;
+    }
+    let file = encoder.finish()?.into_inner().map_err(|e| e.into_error())?;
+    file.sync_all()?;
+    std::fs::rename(&partial, &path)?;
+    Ok(path)
+}
+
+/// 执行保留策略
+///
+/// `dry_run` 为 true 时只统计将被删除的行数，不导出也不删除。
+pub async fn enforce(
+    db: &DatabaseConnection,
+    settings: &RetentionSettings,
+    dry_run: bool,
+) -> Result<RetentionReport, BoxError> {
+    let now = chrono::Utc::now().with_timezone(&FixedOffset::east_opt(8 * 3600).unwrap());
+    let mut report = RetentionReport::default();
+
+    // 按 (设备, 数据类型) 分别确定保留期限
+    let pairs: Vec<(i32, String)> = device_realtime_data::Entity::find()
+        .select_only()
+        .column(device_realtime_data::Column::DeviceId)
+        .column(device_realtime_data::Column::DataType)
+        .distinct()
+        .into_tuple()
+        .all(db)
+        .await?;
+
+    for (device_id, data_type) in pairs {
+        let days = settings.raw_days_for(device_id, &data_type);
+        if days == 0 {
+            continue;
+        }
+        let cutoff = now - chrono::Duration::days(i64::from(days));
+        let expired = device_realtime_data::Entity::find()
+            .filter(device_realtime_data::Column::DeviceId.eq(device_id))
+            .filter(device_realtime_data::Column::DataType.eq(data_type.as_str()))
+            .filter(device_realtime_data::Column::ReceivedAt.lt(cutoff));
+
+        if dry_run {
+            report.deleted_raw += expired.count(db).await?;
+            continue;
+        }
+
+        loop {
+            let rows = expired
+                .clone()
+                .order_by_asc(device_realtime_data::Column::Id)
+                .limit(settings.batch_size.max(1))
+                .all(db)
+                .await?;
+            if rows.is_empty() {
+                break;
+            }
+
+            let ids: Vec<i64> = rows.iter().map(|row| row.id).collect();
+            if settings.archive {
+                let dir = PathBuf::from(&settings.archive_dir);
+                let sequence = report.archive_files.len();
+                let archived = rows.len() as u64;
+                let path = blocking(move || write_archive(&dir, now, sequence, &rows)).await?;
+                report.archive_files.push(path);
+                report.archived += archived;
+            }
+
+            let result = device_realtime_data::Entity::delete_many()
+                .filter(device_realtime_data::Column::Id.is_in(ids))
+                .exec(db)
+                .await?;
+            report.deleted_raw += result.rows_affected;
+        }
+
+        info!(
+            "Retention applied to device {} {} data older than {} days",
+            device_id, data_type, days
+        );
+    }
+
+    // 遥测聚合不归档
+    let rollup_days = [
+        (Resolution::Second, settings.rollup_days.second),
+        (Resolution::Minute, settings.rollup_days.minute),
+        (Resolution::Hour, settings.rollup_days.hour),
+    ];
+    for (resolution, days) in rollup_days {
+        if days == 0 {
+            continue;
+        }
+        let cutoff = now - chrono::Duration::days(i64::from(days));
+        let filter = device_telemetry_rollup::Column::Resolution
+            .eq(resolution.as_str())
+            .and(device_telemetry_rollup::Column::BucketStart.lt(cutoff));
+
+        report.deleted_rollups += if dry_run {
+            device_telemetry_rollup::Entity::find()
+                .filter(filter)
+                .count(db)
+                .await?
+        } else {
+            device_telemetry_rollup::Entity::delete_many()
+                .filter(filter)
+                .exec(db)
+                .await?
+                .rows_affected
+        };
+    }
+
+    Ok(report)
+}
+
+/// 将归档文件中的记录重新写入数据库，返回恢复的行数
+///
+/// 支持 `.ndjson.gz` 和未压缩的 `.ndjson`。记录使用新的主键插入，整个文件在一个事务中完成。
+/// 注意：恢复的数据如果仍早于保留期限，会在下一次执行保留策略时再次被归档删除。
+pub async fn restore(db: &DatabaseConnection, path: &Path) -> Result<u64, BoxError> {
+    let file = File::open(path)?;
+    let gzip = path.extension().is_some_and(|ext| ext == "gz");
+
+    // 阻塞线程解压并解析归档，按批交给事务写入
+    let (chunk_tx, mut chunk_rx) = mpsc::channel(2);
+    let reader = tokio::task::spawn_blocking(move || read_archive(file, gzip, &chunk_tx));
+
+    let txn = db.begin().await?;
+    let mut restored = 0u64;
+    while let Some(chunk) = chunk_rx.recv().await {
+        restored += chunk.len() as u64;
+        device_realtime_data::Entity::insert_many(chunk)
+            .exec(&txn)
+            .await?;
+    }
+    reader.await??;
+
+    txn.commit().await?;
+    Ok(restored)
+}
+
+/// 逐行读取归档记录，每 `RESTORE_ROWS_PER_INSERT` 行发送一批，接收端关闭时提前结束
+fn read_archive(
+    file: File,
+    gzip: bool,
+    chunk_tx: &mpsc::Sender<Vec<device_realtime_data::ActiveModel>>,
+) -> Result<(), BoxError> {
+    let reader: Box<dyn Read> = if gzip {
+        Box::new(GzDecoder::new(file))
+    } else {
+        Box::new(file)
+    };
+
+    let mut chunk = Vec::with_capacity(RESTORE_ROWS_PER_INSERT);
+    for (index, line) in BufReader::new(reader).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let row: device_realtime_data::Model = serde_json::from_str(&line)
+            .map_err(|e| format!("Invalid archive entry at line {}: {}", index + 1, e))?;
+        chunk.push(device_realtime_data::ActiveModel {
+            id: NotSet,
+            device_id: Set(row.device_id),
+            data_type: Set(row.data_type),
+            data_content: Set(row.data_content),
+            received_at: Set(row.received_at),
+            created_at: Set(row.created_at),
+            updated_at: Set(row.updated_at),
+        });
+
+        if chunk.len() >= RESTORE_ROWS_PER_INSERT
+            && chunk_tx.blocking_send(std::mem::take(&mut chunk)).is_err()
+        {
+            return Ok(());
+        }
+    }
+    if !chunk.is_empty() {
+        let _ = chunk_tx.blocking_send(chunk);
+    }
+    Ok(())
+}
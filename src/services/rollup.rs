@@ -0,0 +1,480 @@
+//! 遥测降采样
+//!
+//! 各接入协议的数值字段按 1 秒、1 分钟、1 小时三种粒度聚合（最小、最大、平均、最新值），
+//! 写入 `device_telemetry_rollup`。聚合任务只在内存中累积增量，按固定间隔与数据库中已有的
+//! 时间桶合并，因此迟到的数据和进程重启都不会覆盖已经写入的统计。
+
+use chrono::{DateTime, FixedOffset, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value as JsonValue};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{error, warn};
+
+use crate::models::device_telemetry_rollup;
+use crate::services::telemetry::{self, Timestamp};
+use crate::settings::RollupSettings;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, Set,
+    TransactionTrait,
+};
+
+/// 自动选择时间桶时的目标点数
+const AUTO_SERIES_POINTS: i64 = 500;
+/// 单次查询允许返回的最大点数
+pub const MAX_SERIES_POINTS: i64 = 5000;
+/// 数据库持续不可用时内存中最多保留的时间桶数量
+const MAX_PENDING_BUCKETS: usize = 100_000;
+
+/// 聚合粒度
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Resolution {
+    Second,
+    Minute,
+    Hour,
+}
+
+impl Resolution {
+    /// 由细到粗
+    pub const ALL: [Self; 3] = [Self::Second, Self::Minute, Self::Hour];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Second => "1s",
+            Self::Minute => "1m",
+            Self::Hour => "1h",
+        }
+    }
+
+    pub fn seconds(self) -> i64 {
+        match self {
+            Self::Second => 1,
+            Self::Minute => 60,
+            Self::Hour => 3600,
+        }
+    }
+
+    /// 不超过给定时间桶长度的最粗粒度
+    pub fn for_bucket(bucket_seconds: i64) -> Self {
+        Self::ALL
+            .into_iter()
+            .rev()
+            .find(|resolution| resolution.seconds() <= bucket_seconds)
+            .unwrap_or(Self::Second)
+    }
+}
+
+/// 单个字段在一个时间桶内的统计
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldStats {
+    pub count: u64,
+    pub min: f64,
+    pub max: f64,
+    pub sum: f64,
+    pub last: f64,
+    /// 最新值的时间（毫秒时间戳），用于合并时判断先后
+    pub last_at: i64,
+}
+
+impl FieldStats {
+    fn new(value: f64, at: i64) -> Self {
+        Self {
+            count: 1,
+            min: value,
+            max: value,
+            sum: value,
+            last: value,
+            last_at: at,
+        }
+    }
+
+    pub fn merge(&mut self, other: &Self) {
+        self.count += other.count;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.sum += other.sum;
+        if other.last_at >= self.last_at {
+            self.last = other.last;
+            self.last_at = other.last_at;
+        }
+    }
+
+    pub fn avg(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.sum / self.count as f64
+        }
+    }
+}
+
+/// 一个时间桶的聚合结果
+#[derive(Debug, Clone, Default)]
+struct Bucket {
+    sample_count: u64,
+    fields: BTreeMap<String, FieldStats>,
+}
+
+impl Bucket {
+    fn merge(&mut self, other: &Self) {
+        self.sample_count += other.sample_count;
+        for (name, stats) in &other.fields {
+            match self.fields.get_mut(name) {
+                Some(existing) => existing.merge(stats),
+                None => {
+                    self.fields.insert(name.clone(), stats.clone());
+                }
+            }
+        }
+    }
+
+    fn from_model(model: &device_telemetry_rollup::Model) -> Self {
+        let fields = serde_json::from_value(model.fields.clone()).unwrap_or_else(|e| {
+            warn!("Invalid telemetry rollup fields in row {}: {}", model.id, e);
+            BTreeMap::new()
+        });
+        Self {
+            sample_count: model.sample_count.max(0) as u64,
+            fields,
+        }
+    }
+}
+
+/// (设备ID, 粒度, 时间桶起点秒级时间戳)
+type BucketKey = (i32, Resolution, i64);
+
+#[derive(Debug)]
+struct Sample {
+    device_id: i32,
+    fields: Vec<(String, f64)>,
+    timestamp: Timestamp,
+}
+
+/// 遥测聚合服务
+#[derive(Clone)]
+pub struct RollupService {
+    sender: mpsc::Sender<Sample>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl RollupService {
+    /// 创建服务并启动聚合任务
+    pub fn start(db: Arc<DatabaseConnection>, settings: RollupSettings) -> Self {
+        let (sender, receiver) = mpsc::channel(settings.queue_capacity.max(1));
+
+        let writer = RollupWriter {
+            db,
+            receiver,
+            pending: HashMap::new(),
+            flush_interval: Duration::from_millis(settings.flush_interval_ms.max(1)),
+        };
+        tokio::spawn(writer.run());
+
+        Self {
+            sender,
+            dropped: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// 记录一组统一字段，非数值字段忽略
+    pub fn record(&self, device_id: i32, fields: &Map<String, JsonValue>, timestamp: Timestamp) {
+        let fields: Vec<(String, f64)> = fields
+            .iter()
+            .filter_map(|(name, value)| telemetry::as_f64(value).map(|v| (name.clone(), v)))
+            .collect();
+        if fields.is_empty() {
+            return;
+        }
+
+        let sample = Sample {
+            device_id,
+            fields,
+            timestamp,
+        };
+        if self.sender.try_send(sample).is_err() {
+            let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+            // 避免日志刷屏
+            if dropped.is_power_of_two() {
+                warn!(
+                    "Telemetry rollup queue full, {} samples dropped so far",
+                    dropped
+                );
+            }
+        }
+    }
+}
+
+/// 单一聚合任务
+struct RollupWriter {
+    db: Arc<DatabaseConnection>,
+    receiver: mpsc::Receiver<Sample>,
+    /// 上次写入后累积的增量
+    pending: HashMap<BucketKey, Bucket>,
+    flush_interval: Duration,
+}
+
+impl RollupWriter {
+    async fn run(mut self) {
+        let mut ticker = tokio::time::interval(self.flush_interval);
+        loop {
+            tokio::select! {
+                sample = self.receiver.recv() => match sample {
+                    Some(sample) => self.add(sample),
+                    None => {
+                        self.flush().await;
+                        break;
+                    }
+                },
+                _ = ticker.tick() => self.flush().await,
+            }
+        }
+    }
+
+    fn add(&mut self, sample: Sample) {
+        let seconds = sample.timestamp.timestamp();
+        let at = sample.timestamp.timestamp_millis();
+
+        for resolution in Resolution::ALL {
+            let start = seconds.div_euclid(resolution.seconds()) * resolution.seconds();
+            let bucket = self
+                .pending
+                .entry((sample.device_id, resolution, start))
+                .or_default();
+            bucket.sample_count += 1;
+            for (name, value) in &sample.fields {
+                match bucket.fields.get_mut(name) {
+                    Some(stats) => stats.merge(&FieldStats::new(*value, at)),
+                    None => {
+                        bucket
+                            .fields
+                            .insert(name.clone(), FieldStats::new(*value, at));
+                    }
+                }
+            }
+        }
+    }
+
+    async fn flush(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+
+        let pending = std::mem::take(&mut self.pending);
+        if let Err(e) = merge_buckets(&self.db, &pending).await {
+            error!(
+                "Failed to write {} telemetry rollup buckets: {}",
+                pending.len(),
+                e
+            );
+            // 保留增量，下次一起重试
+            for (key, bucket) in pending {
+                if self.pending.len() >= MAX_PENDING_BUCKETS {
+                    warn!("Telemetry rollup backlog full, discarding buckets");
+                    break;
+                }
+                self.pending.entry(key).or_default().merge(&bucket);
+            }
+        }
+    }
+}
+
+fn bucket_time(seconds: i64) -> Timestamp {
+    Utc.timestamp_opt(seconds, 0)
+        .single()
+        .unwrap_or_default()
+        .with_timezone(&FixedOffset::east_opt(8 * 3600).unwrap())
+}
+
+/// 在一个事务中把增量合并到已有的时间桶
+async fn merge_buckets(
+    db: &DatabaseConnection,
+    pending: &HashMap<BucketKey, Bucket>,
+) -> Result<(), sea_orm::DbErr> {
+    let now = chrono::Utc::now().with_timezone(&FixedOffset::east_opt(8 * 3600).unwrap());
+    let txn = db.begin().await?;
+
+    for ((device_id, resolution, start), delta) in pending {
+        let bucket_start = bucket_time(*start);
+        let existing = device_telemetry_rollup::Entity::find()
+            .filter(device_telemetry_rollup::Column::DeviceId.eq(*device_id))
+            .filter(device_telemetry_rollup::Column::Resolution.eq(resolution.as_str()))
+            .filter(device_telemetry_rollup::Column::BucketStart.eq(bucket_start))
+            .one(&txn)
+            .await?;
+
+        match existing {
+            Some(model) => {
+                let mut bucket = Bucket::from_model(&model);
+                bucket.merge(delta);
+
+                let mut active: device_telemetry_rollup::ActiveModel = model.into();
+                active.sample_count = Set(bucket.sample_count as i64);
+                active.fields = Set(serde_json::to_value(&bucket.fields).unwrap_or_default());
+                active.updated_at = Set(now);
+                active.update(&txn).await?;
+            }
+            None => {
+                device_telemetry_rollup::ActiveModel {
+                    device_id: Set(*device_id),
+                    resolution: Set(resolution.as_str().to_string()),
+                    bucket_start: Set(bucket_start),
+                    sample_count: Set(delta.sample_count as i64),
+                    fields: Set(serde_json::to_value(&delta.fields).unwrap_or_default()),
+                    created_at: Set(now),
+                    updated_at: Set(now),
+                    ..Default::default()
+                }
+                .insert(&txn)
+                .await?;
+            }
+        }
+    }
+
+    txn.commit().await
+}
+
+/// 解析时间桶长度，如 `30s`、`5m`、`2h`、`1d`，纯数字按秒处理
+pub fn parse_bucket(value: &str) -> Option<i64> {
+    let value = value.trim();
+    let (number, unit) = match value.char_indices().last() {
+        Some((index, unit)) if unit.is_ascii_alphabetic() => (&value[..index], unit),
+        _ => (value, 's'),
+    };
+    let number: i64 = number.parse().ok().filter(|n| *n > 0)?;
+    let unit = match unit {
+        's' => 1,
+        'm' => 60,
+        'h' => 3600,
+        'd' => 86400,
+        _ => return None,
+    };
+    number.checked_mul(unit)
+}
+
+/// 确定查询使用的时间桶长度和存储粒度
+///
+/// 未指定时间桶时按目标点数自动选择，并向上取整到存储粒度的整数倍。
+pub fn choose_bucket(
+    from: Timestamp,
+    to: Timestamp,
+    bucket_seconds: Option<i64>,
+) -> Result<(Resolution, i64), String> {
+    let span = (to - from).num_seconds();
+    if span <= 0 {
+        return Err("`from` must be earlier than `to`".to_string());
+    }
+
+    let bucket =
+        bucket_seconds.unwrap_or_else(|| (span + AUTO_SERIES_POINTS - 1) / AUTO_SERIES_POINTS);
+    let resolution = Resolution::for_bucket(bucket);
+    let step = resolution.seconds();
+    let bucket = (bucket + step - 1) / step * step;
+
+    if span / bucket > MAX_SERIES_POINTS {
+        return Err(format!(
+            "Requested range would return more than {} points, use a larger bucket",
+            MAX_SERIES_POINTS
+        ));
+    }
+
+    Ok((resolution, bucket))
+}
+
+/// 查询结果中单个字段的统计
+#[derive(Debug, Clone, Serialize)]
+pub struct SeriesField {
+    pub min: f64,
+    pub max: f64,
+    pub avg: f64,
+    pub last: f64,
+    pub count: u64,
+}
+
+impl From<&FieldStats> for SeriesField {
+    fn from(stats: &FieldStats) -> Self {
+        Self {
+            min: stats.min,
+            max: stats.max,
+            avg: stats.avg(),
+            last: stats.last,
+            count: stats.count,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SeriesPoint {
+    /// 时间桶起点
+    pub t: Timestamp,
+    pub sample_count: u64,
+    pub fields: BTreeMap<String, SeriesField>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Series {
+    pub device_id: i32,
+    /// 读取的存储粒度
+    pub resolution: &'static str,
+    pub bucket_seconds: i64,
+    pub from: Timestamp,
+    pub to: Timestamp,
+    pub points: Vec<SeriesPoint>,
+}
+
+/// 读取聚合数据并按时间桶重新合并
+pub async fn query_series(
+    db: &DatabaseConnection,
+    device_id: i32,
+    from: DateTime<FixedOffset>,
+    to: DateTime<FixedOffset>,
+    resolution: Resolution,
+    bucket_seconds: i64,
+    fields: Option<&HashSet<String>>,
+) -> Result<Series, sea_orm::DbErr> {
+    // 起点对齐到时间桶边界，保证第一个点完整
+    let aligned_from = bucket_time(from.timestamp().div_euclid(bucket_seconds) * bucket_seconds);
+
+    let rows = device_telemetry_rollup::Entity::find()
+        .filter(device_telemetry_rollup::Column::DeviceId.eq(device_id))
+        .filter(device_telemetry_rollup::Column::Resolution.eq(resolution.as_str()))
+        .filter(device_telemetry_rollup::Column::BucketStart.gte(aligned_from))
+        .filter(device_telemetry_rollup::Column::BucketStart.lt(to))
+        .order_by_asc(device_telemetry_rollup::Column::BucketStart)
+        .all(db)
+        .await?;
+
+    let mut buckets: BTreeMap<i64, Bucket> = BTreeMap::new();
+    for row in &rows {
+        let mut bucket = Bucket::from_model(row);
+        if let Some(fields) = fields {
+            bucket.fields.retain(|name, _| fields.contains(name));
+        }
+        let start = row.bucket_start.timestamp().div_euclid(bucket_seconds) * bucket_seconds;
+        buckets.entry(start).or_default().merge(&bucket);
+    }
+
+    let points = buckets
+        .into_iter()
+        .map(|(start, bucket)| SeriesPoint {
+            t: bucket_time(start),
+            sample_count: bucket.sample_count,
+            fields: bucket
+                .fields
+                .iter()
+                .map(|(name, stats)| (name.clone(), SeriesField::from(stats)))
+                .collect(),
+        })
+        .collect();
+
+    Ok(Series {
+        device_id,
+        resolution: resolution.as_str(),
+        bucket_seconds,
+        from,
+        to,
+        points,
+    })
+}
@@ -1,33 +1,71 @@
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 use crate::services::{
-    broadcast::BroadcastService, device_websocket_proxy::DeviceWebSocketProxyService,
-    mqtt_service::MqttService, realtime_data::RealtimeDataService, websocket_proxy::WebSocketProxy,
+    access::{AccessControl, DeviceScope},
+    broadcast::{recv_lossy, BroadcastMetrics, BroadcastService},
+    client_queue::ClientQueue,
+    device_websocket_proxy::DeviceWebSocketProxyService,
+    fleet_mission::FleetCoordinator,
+    ingest::IngestMetrics,
+    mavlink_service::{MavlinkCommand, MavlinkCommandResult, MavlinkService},
+    mission::MissionPlanner,
+    mqtt_service::MqttService,
+    nmea_service::NmeaService,
+    realtime_data::RealtimeDataService,
+    sse::EventLog,
+    task_executor::{DeviceLink, TaskExecutor},
+    task_scheduler::TaskScheduler,
+    telemetry::TelemetrySnapshot,
+    websocket_proxy::WebSocketProxy,
 };
+use crate::settings::Settings;
 use sea_orm::DatabaseConnection;
 
 pub struct ServiceManager {
     pub mqtt_service: Arc<MqttService>,
+    pub mavlink_service: Arc<MavlinkService>,
+    pub nmea_service: Arc<NmeaService>,
     pub realtime_service: Arc<RealtimeDataService>,
     pub websocket_proxy: Arc<WebSocketProxy>,
     pub device_websocket_proxy: Arc<DeviceWebSocketProxyService>,
     pub broadcast_service: Arc<RwLock<BroadcastService>>,
+    pub access: Arc<AccessControl>,
+    pub task_executor: Arc<TaskExecutor>,
+    pub task_scheduler: Arc<TaskScheduler>,
+    pub fleet_coordinator: Arc<FleetCoordinator>,
     db: Arc<DatabaseConnection>,
 }
 
 impl ServiceManager {
     /// 创建新的服务管理器
-    pub async fn new(db: Arc<DatabaseConnection>) -> Self {
+    pub async fn new(
+        db: Arc<DatabaseConnection>,
+        settings: Settings,
+        jwt_secret: Option<String>,
+    ) -> Self {
         info!("Initializing service manager...");
 
+        // 实时连接的凭证校验
+        let access = Arc::new(AccessControl::new(Arc::clone(&db), jwt_secret));
+
         // 创建MQTT服务
         let (mqtt_service, mqtt_receiver) = MqttService::new(Arc::clone(&db));
         let mqtt_service = Arc::new(mqtt_service);
 
+        // 创建MAVLink服务
+        let (mavlink_service, mavlink_receiver) =
+            MavlinkService::new(Arc::clone(&db), settings.realtime.mavlink.clone());
+        let mavlink_service = Arc::new(mavlink_service);
+
+        // 创建NMEA服务
+        let (nmea_service, nmea_receiver) = NmeaService::new(Arc::clone(&db), settings.realtime.nmea.clone());
+        let nmea_service = Arc::new(nmea_service);
+
         // 创建实时数据服务
-        let (realtime_service, unified_receiver) = RealtimeDataService::new(Arc::clone(&db));
+        let (realtime_service, unified_receiver) =
+            RealtimeDataService::new(Arc::clone(&db), settings.realtime.clone());
         let realtime_service = Arc::new(realtime_service);
 
         // 创建WebSocket代理
@@ -38,22 +76,66 @@ impl ServiceManager {
             Arc::clone(&realtime_service),
             Arc::clone(&mqtt_service),
             realtime_service.subscribe_unified_messages(), // 订阅统一广播消息
+            Arc::clone(&access),
         ));
 
         // 创建广播服务
-        let broadcast_service = Arc::new(RwLock::new(BroadcastService::new(unified_receiver)));
+        let broadcast_service = Arc::new(RwLock::new(BroadcastService::new(
+            unified_receiver,
+            Arc::clone(&realtime_service),
+            settings.realtime.broadcast.clone(),
+        )));
+
+        // 创建任务执行服务
+        let task_executor = Arc::new(TaskExecutor::new(
+            Arc::clone(&db),
+            Arc::new(DeviceLink::new(
+                Arc::clone(&mavlink_service),
+                Arc::clone(&device_websocket_proxy),
+                Arc::clone(&realtime_service),
+            )),
+            Arc::new(MissionPlanner::new(settings.mission.clone())),
+        ));
+
+        // 创建任务调度服务
+        let task_scheduler = Arc::new(TaskScheduler::new(
+            Arc::clone(&db),
+            Arc::clone(&task_executor),
+            Arc::clone(&mavlink_service),
+            Arc::clone(&device_websocket_proxy),
+        ));
+
+        // 创建编队任务协调服务
+        let fleet_coordinator = Arc::new(FleetCoordinator::new(
+            Arc::clone(&db),
+            Arc::clone(&task_executor),
+        ));
 
         // 启动MQTT消息监听
         realtime_service.start_mqtt_listener(mqtt_receiver).await;
 
+        // 启动MAVLink消息监听
+        realtime_service
+            .start_mavlink_listener(mavlink_receiver)
+            .await;
+
+        // 启动NMEA消息监听
+        realtime_service.start_nmea_listener(nmea_receiver).await;
+
         info!("Service manager initialized successfully");
 
         Self {
             mqtt_service,
+            mavlink_service,
+            nmea_service,
             realtime_service,
             websocket_proxy,
             device_websocket_proxy,
             broadcast_service,
+            access,
+            task_executor,
+            task_scheduler,
+            fleet_coordinator,
             db,
         }
     }
@@ -67,6 +149,21 @@ impl ServiceManager {
             error!("Failed to load MQTT device configs: {}", e);
         }
 
+        // 启动MAVLink UDP监听
+        if let Err(e) = self.mavlink_service.start().await {
+            error!("Failed to start MAVLink listener: {}", e);
+        }
+
+        // 启动NMEA TCP监听
+        if let Err(e) = self.nmea_service.load_device_configs().await {
+            error!("Failed to load NMEA device configs: {}", e);
+        }
+
+        // 加载设备采集模式
+        if let Err(e) = self.realtime_service.load_collection_modes().await {
+            error!("Failed to load device collection modes: {}", e);
+        }
+
         if let Err(e) = self
             .websocket_proxy
             .load_and_connect_devices(&*self.db)
@@ -85,17 +182,67 @@ impl ServiceManager {
             error!("Failed to auto-connect devices: {}", e);
         }
 
-        // 启动广播服务
-        let broadcast_service = Arc::clone(&self.broadcast_service);
+        // 加载实时推送使用的设备归属和分组
+        self.reload_broadcast_devices().await;
+
+        // 重启前未执行完的任务标记为失败
+        match self.task_executor.fail_interrupted().await {
+            Ok(0) => {}
+            Ok(count) => info!("Marked {} interrupted tasks as failed", count),
+            Err(e) => error!("Failed to recover interrupted tasks: {}", e),
+        }
+        match self.fleet_coordinator.fail_interrupted().await {
+            Ok(0) => {}
+            Ok(count) => info!("Marked {} interrupted fleet missions as failed", count),
+            Err(e) => error!("Failed to recover interrupted fleet missions: {}", e),
+        }
+
+        // 处理编队子任务的结束事件
+        self.fleet_coordinator.start();
+
+        // 越出要求自动返航的电子围栏时返航
+        self.start_geofence_rtl();
+
+        // 启动任务调度（首次扫描处理停机期间错过的调度）
+        self.task_scheduler.start();
+
+        // 启动广播服务（在副本上运行，避免长期占用写锁导致客户端无法接入）
+        let mut broadcast_service = self.broadcast_service.read().await.clone();
         tokio::spawn(async move {
-            let mut service = broadcast_service.write().await;
-            service.start().await;
+            broadcast_service.start().await;
         });
 
         info!("All services started successfully");
         Ok(())
     }
 
+    /// 处理电子围栏越界事件，围栏设置了 `rtl_on_breach` 时取消设备的任务并返航
+    fn start_geofence_rtl(&self) {
+        let mut breaches = self.realtime_service.subscribe_geofence_breaches();
+        let task_executor = Arc::clone(&self.task_executor);
+        tokio::spawn(async move {
+            while let Some(breach) = recv_lossy(&mut breaches, "Geofence RTL").await {
+                if !breach.rtl {
+                    continue;
+                }
+                warn!(
+                    "Returning device {} to launch after geofence {} breach",
+                    breach.device_id, breach.geofence_id
+                );
+                let reason = format!("Geofence breach: {}", breach.message);
+                if let Err(e) = task_executor
+                    .return_to_launch(breach.device_id, reason)
+                    .await
+                {
+                    error!(
+                        "Failed to return device {} to launch: {}",
+                        breach.device_id, e
+                    );
+                }
+            }
+        });
+    }
+
     /// 自动连接已连接的设备
     async fn auto_connect_devices(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         use crate::models::device;
@@ -189,6 +336,21 @@ impl ServiceManager {
         self.realtime_service.get_device_state(device_id).await
     }
 
+    /// 获取设备遥测快照
+    pub async fn get_telemetry(&self, device_id: i32) -> Option<TelemetrySnapshot> {
+        self.realtime_service.get_telemetry(device_id).await
+    }
+
+    /// 获取所有设备遥测快照
+    pub async fn get_all_telemetry(&self) -> std::collections::HashMap<i32, TelemetrySnapshot> {
+        self.realtime_service.get_all_telemetry().await
+    }
+
+    /// 获取实时数据写入管道指标
+    pub fn get_ingest_metrics(&self) -> IngestMetrics {
+        self.realtime_service.ingest_metrics()
+    }
+
     /// 获取所有设备状态
     pub async fn get_all_device_states(&self) -> std::collections::HashMap<i32, serde_json::Value> {
         self.realtime_service.get_all_device_states().await
@@ -199,9 +361,12 @@ impl ServiceManager {
         &self,
         client_id: String,
         device_id: Option<i32>,
-    ) -> tokio::sync::mpsc::UnboundedReceiver<axum::extract::ws::Message> {
+        scope: DeviceScope,
+    ) -> ClientQueue {
         let broadcast_service = self.broadcast_service.read().await;
-        broadcast_service.add_client(client_id, device_id).await
+        broadcast_service
+            .add_client(client_id, device_id, scope)
+            .await
     }
 
     /// 移除WebSocket客户端
@@ -218,10 +383,11 @@ impl ServiceManager {
         websocket: axum::extract::ws::WebSocket,
         client_id: String,
         device_id: Option<i32>,
+        scope: DeviceScope,
     ) {
         let broadcast_service = self.broadcast_service.read().await;
         broadcast_service
-            .handle_websocket(websocket, client_id, device_id)
+            .handle_websocket(websocket, client_id, device_id, scope)
             .await
     }
 
@@ -235,6 +401,18 @@ impl ServiceManager {
         self.websocket_proxy.get_connected_devices().await.len()
     }
 
+    /// 获取SSE推送使用的事件缓冲
+    pub async fn get_event_log(&self) -> Arc<EventLog> {
+        let broadcast_service = self.broadcast_service.read().await;
+        broadcast_service.event_log()
+    }
+
+    /// 获取WebSocket推送指标
+    pub async fn get_broadcast_metrics(&self) -> BroadcastMetrics {
+        let broadcast_service = self.broadcast_service.read().await;
+        broadcast_service.metrics().await
+    }
+
     /// 获取WebSocket客户端数量
     pub async fn get_websocket_client_count(&self) -> usize {
         let broadcast_service = self.broadcast_service.read().await;
@@ -251,6 +429,68 @@ impl ServiceManager {
         self.mqtt_service.is_device_mqtt_running(device_id).await
     }
 
+    /// 检查设备是否通过MAVLink在线
+    pub async fn is_mavlink_online(&self, device_id: i32) -> bool {
+        self.mavlink_service.is_device_online(device_id).await
+    }
+
+    /// 重新加载MAVLink系统ID映射
+    pub async fn reload_mavlink_mappings(&self) {
+        if let Err(e) = self.mavlink_service.load_system_mappings().await {
+            error!("Failed to reload MAVLink mappings: {}", e);
+        }
+    }
+
+    /// 重新加载实时推送使用的设备归属和分组
+    pub async fn reload_broadcast_devices(&self) {
+        let broadcast_service = self.broadcast_service.read().await;
+        if let Err(e) = broadcast_service.load_devices(&self.db).await {
+            error!("Failed to load broadcast devices: {}", e);
+        }
+    }
+
+    /// 重新加载实时检查使用的电子围栏和设备归属
+    pub fn reload_geofences(&self) {
+        self.realtime_service.reload_geofences();
+    }
+
+    /// 根据设备配置同步NMEA监听和采集模式
+    pub async fn sync_device_nmea(&self, device: &crate::models::device::Model) {
+        match device.nmea_port.filter(|_| device.is_active) {
+            Some(port) => {
+                if let Err(e) = self
+                    .nmea_service
+                    .add_device_listener(device.id, port as u16)
+                    .await
+                {
+                    error!("{}", e);
+                }
+            }
+            None => self.nmea_service.remove_device_listener(device.id).await,
+        }
+
+        self.realtime_service
+            .set_collection_mode(device.id, device.collection_mode && device.is_active)
+            .await;
+    }
+
+    /// 移除设备的NMEA监听和采集模式
+    pub async fn remove_device_nmea(&self, device_id: i32) {
+        self.nmea_service.remove_device_listener(device_id).await;
+        self.realtime_service
+            .set_collection_mode(device_id, false)
+            .await;
+    }
+
+    /// 通过MAVLink向设备下发命令
+    pub async fn send_mavlink_command(
+        &self,
+        device_id: i32,
+        command: &MavlinkCommand,
+    ) -> Result<MavlinkCommandResult, Box<dyn std::error::Error + Send + Sync>> {
+        self.mavlink_service.send_command(device_id, command).await
+    }
+
     /// 创建设备WebSocket代理
     pub async fn create_device_websocket_proxy(
         &self,
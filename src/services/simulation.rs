@@ -0,0 +1,445 @@
+//! 任务模拟
+//!
+//! 按机型的运动参数（[`DroneProfile`]）推演展开后的任务步骤，不连接设备：
+//! - 起飞、降落和改变高度按爬升、下降速度，转向按转向速度，沿最短方向转动；
+//! - 飞往航点时先转向航点方向，再同时进行水平和垂直运动，耗时取两者较大值，到达后按航点悬停；
+//! - 悬停按指定时间，拍照不计时间；
+//! - 电量按续航时间线性消耗。
+//!
+//! 起点为 `origin`（设备当前位置），未知时从第一个航点开始，没有航点时轨迹不含经纬度。轨迹按
+//! `trajectory_interval_secs` 采样并包含每段运动的终点，据此检查最大高度、电子围栏，并按预留电量检查续航。
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::services::flight::haversine_distance;
+use crate::services::geofence::Geofence;
+use crate::services::mission::DroneProfile;
+use crate::services::task_executor::{DroneCommand, PlannedStep, StepAction};
+use crate::settings::MissionSettings;
+
+/// 轨迹最多包含的采样点数，超出时加大采样间隔
+const MAX_TRAJECTORY_POINTS: f64 = 5000.0;
+/// 与航点相距不超过该距离（米）时不转向
+const MIN_TURN_DISTANCE: f64 = 1.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ViolationKind {
+    Geofence,
+    MaxAltitude,
+    BatteryReserve,
+}
+
+/// 模拟中发现的违规，每次进入违规状态记录一次
+#[derive(Debug, Clone, Serialize)]
+pub struct Violation {
+    pub kind: ViolationKind,
+    /// 展开后步骤的序号
+    pub step: usize,
+    pub source_step: usize,
+    pub time_secs: f64,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub altitude: f64,
+    pub message: String,
+}
+
+/// 展开后每个步骤的预计开始和完成时间（相对任务开始，秒）
+#[derive(Debug, Clone, Serialize)]
+pub struct StepTimeline {
+    pub source_step: usize,
+    pub step_type: String,
+    pub start_secs: f64,
+    pub eta_secs: f64,
+    /// 完成时的剩余电量百分比
+    pub battery_percent: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TrajectoryPoint {
+    pub time_secs: f64,
+    /// 展开后步骤的序号
+    pub step: usize,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    /// 相对起飞点高度（米）
+    pub altitude: f64,
+    pub heading: f64,
+    pub battery_percent: f64,
+}
+
+/// 任务模拟结果
+#[derive(Debug, Clone, Serialize)]
+pub struct Simulation {
+    pub drone_model: Option<String>,
+    pub profile: DroneProfile,
+    /// 模拟的起点，`from_current_position` 为 false 时为第一个航点
+    pub origin: Option<(f64, f64)>,
+    pub from_current_position: bool,
+    pub duration_secs: f64,
+    /// 水平飞行距离（米）
+    pub distance_m: f64,
+    pub max_altitude_m: f64,
+    pub battery_remaining_percent: f64,
+    /// 飞行时间不超过扣除预留电量后的续航
+    pub within_endurance: bool,
+    pub steps: Vec<StepTimeline>,
+    pub trajectory: Vec<TrajectoryPoint>,
+    pub violations: Vec<Violation>,
+    pub simulated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct State {
+    position: Option<(f64, f64)>,
+    altitude: f64,
+    heading: f64,
+}
+
+impl State {
+    fn interpolate(&self, to: &State, fraction: f64) -> State {
+        let lerp = |a: f64, b: f64| a + (b - a) * fraction;
+        let turn = signed_angle(self.heading, to.heading);
+        State {
+            position: match (self.position, to.position) {
+                (Some(a), Some(b)) => Some((lerp(a.0, b.0), lerp(a.1, b.1))),
+                (_, position) => position,
+            },
+            altitude: lerp(self.altitude, to.altitude),
+            heading: (self.heading + turn * fraction).rem_euclid(360.0),
+        }
+    }
+}
+
+/// 一段匀速运动
+struct Segment {
+    step: usize,
+    from: State,
+    to: State,
+    duration: f64,
+}
+
+/// 从 `from` 转到 `to` 的最短角度（度），顺时针为正
+fn signed_angle(from: f64, to: f64) -> f64 {
+    let difference = (to - from).rem_euclid(360.0);
+    if difference > 180.0 {
+        difference - 360.0
+    } else {
+        difference
+    }
+}
+
+/// 从 `from` 到 `to` 的初始方位角（度）
+fn bearing(from: (f64, f64), to: (f64, f64)) -> f64 {
+    let (lat1, lat2) = (from.0.to_radians(), to.0.to_radians());
+    let delta = (to.1 - from.1).to_radians();
+    let y = delta.sin() * lat2.cos();
+    let x = lat1.cos() * lat2.sin() - lat1.sin() * lat2.cos() * delta.cos();
+    y.atan2(x).to_degrees().rem_euclid(360.0)
+}
+
+/// 把展开后的步骤转换为运动段
+struct Builder<'a> {
+    profile: &'a DroneProfile,
+    state: State,
+    segments: Vec<Segment>,
+    distance: f64,
+}
+
+impl Builder<'_> {
+    fn push(&mut self, step: usize, to: State, duration: f64) {
+        self.segments.push(Segment {
+            step,
+            from: self.state,
+            to,
+            duration: duration.max(0.0),
+        });
+        self.state = to;
+    }
+
+    fn hold(&mut self, step: usize, duration: f64) {
+        self.push(step, self.state, duration);
+    }
+
+    fn climb(&mut self, step: usize, altitude: f64) {
+        let duration = self.profile.climb_time(self.state.altitude, altitude);
+        self.push(
+            step,
+            State {
+                altitude,
+                ..self.state
+            },
+            duration,
+        );
+    }
+
+    fn turn(&mut self, step: usize, heading: f64) {
+        let duration = signed_angle(self.state.heading, heading).abs() / self.profile.yaw_rate;
+        let heading = heading.rem_euclid(360.0);
+        self.push(
+            step,
+            State {
+                heading,
+                ..self.state
+            },
+            duration,
+        );
+    }
+
+    fn fly_to(&mut self, step: usize, target: (f64, f64), altitude: f64, speed: Option<f64>) {
+        let leg = self
+            .state
+            .position
+            .map(|from| haversine_distance(from, target))
+            .unwrap_or(0.0);
+        if leg > MIN_TURN_DISTANCE {
+            if let Some(from) = self.state.position {
+                self.turn(step, bearing(from, target));
+            }
+        }
+        let speed = speed
+            .filter(|speed| *speed > 0.0)
+            .unwrap_or(self.profile.cruise_speed);
+        let duration = (leg / speed).max(self.profile.climb_time(self.state.altitude, altitude));
+        self.distance += leg;
+        self.push(
+            step,
+            State {
+                position: Some(target),
+                altitude,
+                ..self.state
+            },
+            duration,
+        );
+    }
+
+    fn step(&mut self, index: usize, action: &StepAction) {
+        match action {
+            StepAction::Command(DroneCommand::Takeoff { altitude })
+            | StepAction::Command(DroneCommand::MoveToHeight { height: altitude }) => {
+                self.climb(index, *altitude)
+            }
+            StepAction::Command(DroneCommand::Land | DroneCommand::ReturnToLaunch) => {
+                self.climb(index, 0.0)
+            }
+            StepAction::Command(DroneCommand::MoveToHeading { heading }) => {
+                self.turn(index, *heading)
+            }
+            StepAction::Command(DroneCommand::Photo) => self.hold(index, 0.0),
+            StepAction::Command(DroneCommand::GoTo {
+                latitude,
+                longitude,
+                altitude,
+                speed,
+            }) => self.fly_to(index, (*latitude, *longitude), *altitude, *speed),
+            StepAction::Wait(duration) => self.hold(index, duration.as_secs_f64()),
+            StepAction::Waypoint(waypoint) => {
+                self.fly_to(
+                    index,
+                    (waypoint.latitude, waypoint.longitude),
+                    waypoint.altitude,
+                    waypoint.speed,
+                );
+                if waypoint.loiter > 0.0 {
+                    self.hold(index, waypoint.loiter);
+                }
+            }
+        }
+    }
+}
+
+/// 模拟展开后的任务步骤
+pub fn simulate(
+    settings: &MissionSettings,
+    steps: &[PlannedStep],
+    origin: Option<(f64, f64)>,
+    drone_model: Option<&str>,
+    geofences: &[Geofence],
+) -> Simulation {
+    let profile = settings.profile_for(drone_model);
+    let start = origin.or_else(|| {
+        steps.iter().find_map(|step| match &step.action {
+            StepAction::Waypoint(waypoint) => Some((waypoint.latitude, waypoint.longitude)),
+            _ => None,
+        })
+    });
+
+    let mut builder = Builder {
+        profile: &profile,
+        state: State {
+            position: start,
+            altitude: 0.0,
+            heading: 0.0,
+        },
+        segments: Vec::new(),
+        distance: 0.0,
+    };
+    for (index, step) in steps.iter().enumerate() {
+        builder.step(index, &step.action);
+    }
+    let Builder {
+        segments, distance, ..
+    } = builder;
+
+    let endurance = f64::from(profile.endurance_secs).max(1.0);
+    let battery = |time: f64| (100.0 * (1.0 - time / endurance)).max(0.0);
+    let duration: f64 = segments.iter().map(|segment| segment.duration).sum();
+
+    // 每步的开始和完成时间
+    let mut timeline: Vec<StepTimeline> = steps
+        .iter()
+        .map(|step| StepTimeline {
+            source_step: step.source_step,
+            step_type: step.step_type.clone(),
+            start_secs: 0.0,
+            eta_secs: 0.0,
+            battery_percent: 100.0,
+        })
+        .collect();
+    let mut time = 0.0;
+    let mut started = vec![false; steps.len()];
+    for segment in &segments {
+        let entry = &mut timeline[segment.step];
+        if !started[segment.step] {
+            started[segment.step] = true;
+            entry.start_secs = time;
+        }
+        time += segment.duration;
+        entry.eta_secs = time;
+        entry.battery_percent = battery(time);
+    }
+
+    // 按间隔采样，并包含每段的终点
+    let interval = settings
+        .trajectory_interval_secs
+        .max(duration / MAX_TRAJECTORY_POINTS)
+        .max(0.01);
+    let point = |time: f64, step: usize, state: State| TrajectoryPoint {
+        time_secs: time,
+        step,
+        latitude: state.position.map(|position| position.0),
+        longitude: state.position.map(|position| position.1),
+        altitude: state.altitude,
+        heading: state.heading,
+        battery_percent: battery(time),
+    };
+    let mut trajectory = Vec::new();
+    if let Some(first) = segments.first() {
+        trajectory.push(point(0.0, first.step, first.from));
+    }
+    let mut segment_start = 0.0;
+    let mut next_sample = interval;
+    for segment in &segments {
+        let segment_end = segment_start + segment.duration;
+        while next_sample < segment_end {
+            let fraction = (next_sample - segment_start) / segment.duration;
+            trajectory.push(point(
+                next_sample,
+                segment.step,
+                segment.from.interpolate(&segment.to, fraction),
+            ));
+            next_sample += interval;
+        }
+        let is_duplicate = trajectory
+            .last()
+            .is_some_and(|last: &TrajectoryPoint| segment_end - last.time_secs < 1e-6);
+        if !is_duplicate {
+            trajectory.push(point(segment_end, segment.step, segment.to));
+        }
+        segment_start = segment_end;
+    }
+
+    let mut violations = Vec::new();
+    let violation = |kind: ViolationKind, point: &TrajectoryPoint, message: String| Violation {
+        kind,
+        step: point.step,
+        source_step: steps[point.step].source_step,
+        time_secs: point.time_secs,
+        latitude: point.latitude,
+        longitude: point.longitude,
+        altitude: point.altitude,
+        message,
+    };
+
+    // 超出最大高度或违反围栏，连续违规只记录开始的位置
+    let mut above_ceiling = false;
+    let mut breached = vec![false; geofences.len()];
+    for point in &trajectory {
+        let above = point.altitude > settings.max_altitude;
+        if above && !above_ceiling {
+            violations.push(violation(
+                ViolationKind::MaxAltitude,
+                point,
+                format!(
+                    "Altitude {:.1} m exceeds the limit of {:.1} m",
+                    point.altitude, settings.max_altitude
+                ),
+            ));
+        }
+        above_ceiling = above;
+
+        let (Some(latitude), Some(longitude)) = (point.latitude, point.longitude) else {
+            continue;
+        };
+        for (fence, breached) in geofences.iter().zip(breached.iter_mut()) {
+            let message = fence.violation(latitude, longitude, point.altitude);
+            if let (Some(message), false) = (&message, *breached) {
+                violations.push(violation(ViolationKind::Geofence, point, message.clone()));
+            }
+            *breached = message.is_some();
+        }
+    }
+
+    // 电量低于预留时的位置
+    let reserve = settings.reserve_percent.clamp(0.0, 100.0);
+    let usable = endurance * (1.0 - reserve / 100.0);
+    if duration > usable {
+        let mut segment_start = 0.0;
+        for segment in &segments {
+            let segment_end = segment_start + segment.duration;
+            if segment_end >= usable {
+                let fraction = if segment.duration > 0.0 {
+                    (usable - segment_start) / segment.duration
+                } else {
+                    1.0
+                };
+                let at = point(
+                    usable,
+                    segment.step,
+                    segment.from.interpolate(&segment.to, fraction),
+                );
+                violations.push(violation(
+                    ViolationKind::BatteryReserve,
+                    &at,
+                    format!(
+                        "Battery drops below the {:.0}% reserve; flight needs {:.0} s of {:.0} s usable",
+                        reserve, duration, usable
+                    ),
+                ));
+                break;
+            }
+            segment_start = segment_end;
+        }
+    }
+    violations.sort_by(|a, b| a.time_secs.total_cmp(&b.time_secs));
+
+    Simulation {
+        drone_model: drone_model.map(str::to_string),
+        max_altitude_m: trajectory
+            .iter()
+            .map(|point| point.altitude)
+            .fold(0.0, f64::max),
+        battery_remaining_percent: battery(duration),
+        within_endurance: duration <= usable,
+        profile,
+        origin: start,
+        from_current_position: origin.is_some(),
+        duration_secs: duration,
+        distance_m: distance,
+        steps: timeline,
+        trajectory,
+        violations,
+        simulated_at: Utc::now(),
+    }
+}
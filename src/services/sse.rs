@@ -0,0 +1,190 @@
+//! Server-Sent Events 推送
+//!
+//! 广播服务为每条实时数据分配递增序号，写入一个短的内存环形缓冲区后再分发给 SSE 连接。
+//! 事件格式与 WebSocket 推送相同（`event: realtime_data`，`data` 为 `realtime_data` 消息），
+//! `id` 为序号。客户端重连时带上 `Last-Event-ID`，服务端先补发缓冲区中之后的事件再继续实时推送；
+//! 缺失的事件已不在缓冲区（或服务重启后序号不连续）时先发送一条 `event: reset`，
+//! 客户端应重新拉取设备状态。
+
+use axum::response::sse::Event;
+use serde_json::json;
+use std::collections::{HashSet, VecDeque};
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+
+use crate::services::broadcast::DeviceId;
+
+/// 带序号的实时数据事件
+#[derive(Debug)]
+pub struct SequencedEvent {
+    pub seq: u64,
+    pub device_id: DeviceId,
+    /// 序列化后的 `realtime_data` 消息
+    pub payload: String,
+}
+
+#[derive(Debug)]
+struct Buffer {
+    events: VecDeque<Arc<SequencedEvent>>,
+    next_seq: u64,
+}
+
+/// 事件序号分配和环形缓冲区
+#[derive(Debug)]
+pub struct EventLog {
+    buffer: Mutex<Buffer>,
+    capacity: usize,
+    sender: broadcast::Sender<Arc<SequencedEvent>>,
+}
+
+impl EventLog {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        let (sender, _) = broadcast::channel(capacity);
+        Self {
+            buffer: Mutex::new(Buffer {
+                events: VecDeque::with_capacity(capacity),
+                next_seq: 1,
+            }),
+            capacity,
+            sender,
+        }
+    }
+
+    fn buffer(&self) -> std::sync::MutexGuard<'_, Buffer> {
+        self.buffer
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// 记录一条事件并分发给 SSE 连接
+    pub fn record(&self, device_id: DeviceId, payload: String) {
+        let mut buffer = self.buffer();
+        let event = Arc::new(SequencedEvent {
+            seq: buffer.next_seq,
+            device_id,
+            payload,
+        });
+        buffer.next_seq += 1;
+        if buffer.events.len() >= self.capacity {
+            buffer.events.pop_front();
+        }
+        buffer.events.push_back(Arc::clone(&event));
+        // 在锁内发送，保证接收顺序与序号一致
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<SequencedEvent>> {
+        self.sender.subscribe()
+    }
+
+    /// 最近分配的序号
+    pub fn last_seq(&self) -> u64 {
+        self.buffer().next_seq - 1
+    }
+
+    /// 序号大于 `after` 的缓冲事件；中间有事件已被淘汰或序号不属于本次运行时 `gap` 为真
+    fn since(&self, after: u64) -> (bool, Vec<Arc<SequencedEvent>>) {
+        let buffer = self.buffer();
+        let oldest = buffer
+            .events
+            .front()
+            .map_or(buffer.next_seq, |event| event.seq);
+        let gap = after + 1 < oldest || after >= buffer.next_seq;
+        let events = buffer
+            .events
+            .iter()
+            .filter(|event| gap || event.seq > after)
+            .cloned()
+            .collect();
+        (gap, events)
+    }
+}
+
+/// 单个 SSE 连接的推送状态
+struct SseStream {
+    log: Arc<EventLog>,
+    receiver: broadcast::Receiver<Arc<SequencedEvent>>,
+    /// 允许推送的设备，`None` 表示全部
+    devices: Option<HashSet<DeviceId>>,
+    last_seq: u64,
+    pending: VecDeque<Event>,
+}
+
+impl SseStream {
+    fn enqueue(&mut self, event: &SequencedEvent) {
+        if event.seq <= self.last_seq {
+            return;
+        }
+        self.last_seq = event.seq;
+        if self
+            .devices
+            .as_ref()
+            .is_some_and(|devices| !devices.contains(&event.device_id))
+        {
+            return;
+        }
+        self.pending.push_back(
+            Event::default()
+                .id(event.seq.to_string())
+                .event("realtime_data")
+                .data(&event.payload),
+        );
+    }
+
+    /// 从缓冲区补发 `last_seq` 之后的事件
+    fn catch_up(&mut self) {
+        let (gap, events) = self.log.since(self.last_seq);
+        if gap {
+            let reset = json!({
+                "last_event_id": self.last_seq,
+                "oldest_event_id": events.first().map(|event| event.seq)
+            });
+            self.pending
+                .push_back(Event::default().event("reset").data(reset.to_string()));
+            self.last_seq = 0;
+        }
+        for event in &events {
+            self.enqueue(event);
+        }
+    }
+
+    async fn next(&mut self) -> Option<Event> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Some(event);
+            }
+            match self.receiver.recv().await {
+                Ok(event) => self.enqueue(&event),
+                // 落后时从缓冲区补齐
+                Err(broadcast::error::RecvError::Lagged(_)) => self.catch_up(),
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+/// 创建 SSE 事件流，`last_event_id` 为空时只推送之后的新事件
+pub fn event_stream(
+    log: Arc<EventLog>,
+    devices: Option<HashSet<DeviceId>>,
+    last_event_id: Option<u64>,
+) -> impl futures_util::Stream<Item = Result<Event, Infallible>> {
+    // 先订阅再读取缓冲区，两者重叠的事件按序号去重
+    let receiver = log.subscribe();
+    let mut stream = SseStream {
+        last_seq: last_event_id.unwrap_or_else(|| log.last_seq()),
+        log,
+        receiver,
+        devices,
+        pending: VecDeque::new(),
+    };
+    if last_event_id.is_some() {
+        stream.catch_up();
+    }
+
+    futures_util::stream::unfold(stream, |mut stream| async move {
+        stream.next().await.map(|event| (Ok(event), stream))
+    })
+}
@@ -0,0 +1,1427 @@
+//! 任务执行
+//!
+//! 按 [`TaskParameters`] 中的步骤依次下发命令，并以命令下发之后更新的遥测确认完成：
+//! - `takeoff`：设备上报正在飞行，或相对高度达到 `altitude`（默认 1 米）；
+//! - `landing`：设备上报已落地、已上锁，或相对高度低于 0.3 米；
+//! - `move_to_height`：相对高度与 `height` 相差不超过 0.3 米；
+//! - `move_to_heading`：机头朝向与 `heading` 相差不超过 5 度；
+//! - `photo`：命令下发成功即完成；
+//! - `wait` / `hover`：等待 `duration` 秒；
+//! - `waypoint` / `survey` / `orbit`：按 [`mission`] 展开为航点，逐个飞往航点，位置与航点相距不超过 3 米、
+//!   相对高度相差不超过 1 米时视为到达，之后按航点拍照和悬停。
+//!
+//! 每一步在步骤 `timeout`（默认 30 秒，航点默认 120 秒）内未确认时按任务的 `retry_count` 重新下发，重试用尽
+//! 或超过任务总超时 `timeout` 时任务失败。MAVLink 在线的设备下发 COMMAND_LONG，其它设备通过 WebSocket
+//! 代理发送文本命令（`up`、`down`、`height:<米>`、`heading:<度>`、`photo`、`goto:<纬度>,<经度>,<米>`、
+//! `rtl`）。执行进度写入 `task.progress`，展开后的每个航点单独记录进度。开始执行前按设备机型的电池续航
+//! 和设备的电子围栏校验航线。
+//!
+//! 暂停后正在执行的步骤仍会完成，之后等待恢复；中止（aborted）与取消一样停止执行，并额外下发降落命令。
+//! 任务状态均通过 [`task_state::transition`] 变更并记录时间线。
+
+use async_trait::async_trait;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, DatabaseConnection, DbErr, EntityTrait,
+    QueryFilter,
+};
+use serde_json::Value as JsonValue;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{broadcast, watch, Mutex};
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+
+use crate::models::device;
+use crate::models::task::{
+    self, StepProgress, TaskParameters, TaskProgress, TaskStatus, TaskStep, STEP_CANCELLED,
+    STEP_COMPLETED, STEP_FAILED, STEP_RUNNING,
+};
+use crate::services::device_websocket_proxy::DeviceWebSocketProxyService;
+use crate::services::flight::haversine_distance;
+use crate::services::geofence::{self, Geofence};
+use crate::services::mavlink_service::{MavlinkCommand, MavlinkService};
+use crate::services::mission::{self, CameraAction, MissionEstimate, MissionPlanner, Waypoint};
+use crate::services::realtime_data::RealtimeDataService;
+use crate::services::simulation::{self, Simulation, ViolationKind};
+use crate::services::task_state::{self, Actor, TransitionError};
+use crate::services::telemetry::{as_bool, as_f64, TelemetrySnapshot, Timestamp, Timestamped};
+
+/// 步骤未指定超时时的默认值（秒）
+const DEFAULT_STEP_TIMEOUT_SECS: u32 = 30;
+/// 航点步骤未指定超时时飞往单个航点的默认超时（秒）
+const DEFAULT_WAYPOINT_TIMEOUT_SECS: u32 = 120;
+/// 检查遥测的间隔
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+/// 起飞步骤未指定高度时的确认高度（米）
+const DEFAULT_TAKEOFF_ALTITUDE: f64 = 1.0;
+const ALTITUDE_TOLERANCE: f64 = 0.3;
+const LANDED_ALTITUDE: f64 = 0.3;
+const HEADING_TOLERANCE: f64 = 5.0;
+const WAYPOINT_TOLERANCE: f64 = 3.0;
+const WAYPOINT_ALTITUDE_TOLERANCE: f64 = 1.0;
+const INTERRUPTED_BY_RESTART: &str = "Interrupted by server restart";
+
+/// 下发给设备的命令
+#[derive(Debug, Clone, PartialEq)]
+pub enum DroneCommand {
+    Takeoff {
+        altitude: f64,
+    },
+    Land,
+    /// 返航并降落
+    ReturnToLaunch,
+    MoveToHeight {
+        height: f64,
+    },
+    MoveToHeading {
+        heading: f64,
+    },
+    Photo,
+    /// 飞往指定位置，`altitude` 为相对起飞点高度
+    GoTo {
+        latitude: f64,
+        longitude: f64,
+        altitude: f64,
+        speed: Option<f64>,
+    },
+}
+
+impl DroneCommand {
+    /// WebSocket 设备使用的文本命令
+    pub fn to_text(&self) -> String {
+        match self {
+            Self::Takeoff { .. } => "up".to_string(),
+            Self::Land => "down".to_string(),
+            Self::ReturnToLaunch => "rtl".to_string(),
+            Self::MoveToHeight { height } => format!("height:{}", height),
+            Self::MoveToHeading { heading } => format!("heading:{}", heading),
+            Self::Photo => "photo".to_string(),
+            Self::GoTo {
+                latitude,
+                longitude,
+                altitude,
+                ..
+            } => format!("goto:{},{},{}", latitude, longitude, altitude),
+        }
+    }
+
+    /// 转换为 MAVLink 命令，调整高度需要当前位置和高度
+    fn to_mavlink(
+        &self,
+        telemetry: Option<&TelemetrySnapshot>,
+    ) -> Result<Vec<MavlinkCommand>, String> {
+        Ok(match self {
+            Self::Takeoff { altitude } => vec![
+                MavlinkCommand::Arm,
+                MavlinkCommand::Takeoff {
+                    altitude: *altitude as f32,
+                },
+            ],
+            Self::Land => vec![MavlinkCommand::Land],
+            Self::ReturnToLaunch => vec![MavlinkCommand::Rtl],
+            Self::MoveToHeight { height } => {
+                let current = telemetry.and_then(|t| {
+                    let position = &t.position.as_ref()?.value;
+                    let altitude = &t.altitude.as_ref()?.value;
+                    Some((position, altitude.amsl?, altitude.relative?))
+                });
+                let Some((position, amsl, relative)) = current else {
+                    return Err("Position and altitude telemetry are required".to_string());
+                };
+                // 在当前位置改变高度，相对高度换算为海拔高度
+                vec![MavlinkCommand::Reposition {
+                    latitude: position.latitude,
+                    longitude: position.longitude,
+                    altitude: (amsl + height - relative) as f32,
+                    speed: None,
+                }]
+            }
+            Self::MoveToHeading { heading } => vec![MavlinkCommand::Yaw {
+                heading: *heading as f32,
+            }],
+            Self::Photo => vec![MavlinkCommand::Photo],
+            Self::GoTo {
+                latitude,
+                longitude,
+                altitude,
+                speed,
+            } => {
+                let current = telemetry
+                    .and_then(|t| t.altitude.as_ref())
+                    .and_then(|a| Some((a.value.amsl?, a.value.relative?)));
+                let Some((amsl, relative)) = current else {
+                    return Err("Altitude telemetry is required".to_string());
+                };
+                vec![MavlinkCommand::Reposition {
+                    latitude: *latitude,
+                    longitude: *longitude,
+                    altitude: (amsl + altitude - relative) as f32,
+                    speed: speed.map(|speed| speed as f32),
+                }]
+            }
+        })
+    }
+
+    /// `since` 之后更新的遥测是否表明命令已完成
+    pub fn is_complete(&self, telemetry: &TelemetrySnapshot, since: Timestamp) -> bool {
+        let relative = fresh(&telemetry.altitude, since).and_then(|a| a.relative);
+        let flying = telemetry
+            .extensions
+            .get("flying")
+            .filter(|value| value.updated_at >= since)
+            .and_then(|value| as_bool(&value.value));
+        let armed = fresh(&telemetry.armed, since).copied();
+
+        match self {
+            Self::Takeoff { altitude } => {
+                flying == Some(true)
+                    || relative.is_some_and(|relative| relative >= altitude - ALTITUDE_TOLERANCE)
+            }
+            Self::Land | Self::ReturnToLaunch => {
+                flying == Some(false)
+                    || armed == Some(false)
+                    || relative.is_some_and(|relative| relative <= LANDED_ALTITUDE)
+            }
+            Self::MoveToHeight { height } => {
+                relative.is_some_and(|relative| (relative - height).abs() <= ALTITUDE_TOLERANCE)
+            }
+            Self::MoveToHeading { heading } => fresh(&telemetry.attitude, since)
+                .and_then(|a| a.yaw)
+                .or_else(|| fresh(&telemetry.velocity, since).and_then(|v| v.heading))
+                .is_some_and(|current| angle_difference(current, *heading) <= HEADING_TOLERANCE),
+            Self::Photo => true,
+            Self::GoTo {
+                latitude,
+                longitude,
+                altitude,
+                ..
+            } => {
+                fresh(&telemetry.position, since).is_some_and(|position| {
+                    haversine_distance(
+                        (position.latitude, position.longitude),
+                        (*latitude, *longitude),
+                    ) <= WAYPOINT_TOLERANCE
+                }) && relative.is_none_or(|relative| {
+                    (relative - altitude).abs() <= WAYPOINT_ALTITUDE_TOLERANCE
+                })
+            }
+        }
+    }
+}
+
+/// `since` 之后更新的遥测值
+fn fresh<T>(value: &Option<Timestamped<T>>, since: Timestamp) -> Option<&T> {
+    value
+        .as_ref()
+        .filter(|value| value.updated_at >= since)
+        .map(|value| &value.value)
+}
+
+/// 两个方向的夹角（度）
+fn angle_difference(a: f64, b: f64) -> f64 {
+    let difference = (a - b).rem_euclid(360.0);
+    difference.min(360.0 - difference)
+}
+
+/// 单个步骤要执行的动作
+#[derive(Debug, Clone, PartialEq)]
+pub enum StepAction {
+    Command(DroneCommand),
+    Wait(Duration),
+    /// 飞往航点，到达后按航点拍照和悬停
+    Waypoint(Waypoint),
+}
+
+impl StepAction {
+    pub fn from_step(step: &TaskStep) -> Result<Self, String> {
+        let number = |key: &str| step.parameters.get(key).and_then(as_f64);
+        let required = |key: &str| {
+            number(key).ok_or_else(|| format!("{} requires numeric {}", step.step_type, key))
+        };
+
+        Ok(match step.step_type.as_str() {
+            "takeoff" => Self::Command(DroneCommand::Takeoff {
+                altitude: number("altitude").unwrap_or(DEFAULT_TAKEOFF_ALTITUDE),
+            }),
+            "landing" => Self::Command(DroneCommand::Land),
+            "move_to_height" => Self::Command(DroneCommand::MoveToHeight {
+                height: required("height")?,
+            }),
+            "move_to_heading" => Self::Command(DroneCommand::MoveToHeading {
+                heading: required("heading")?,
+            }),
+            "photo" => Self::Command(DroneCommand::Photo),
+            "wait" | "hover" => {
+                let duration = required("duration")?;
+                if duration < 0.0 {
+                    return Err(format!("{} duration must not be negative", step.step_type));
+                }
+                Self::Wait(Duration::from_secs_f64(duration))
+            }
+            other => return Err(format!("Unsupported step type: {}", other)),
+        })
+    }
+}
+
+/// 任务参数中的步骤展开后实际执行的步骤，地理步骤展开为多个航点
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlannedStep {
+    /// 所属任务参数步骤的序号
+    pub source_step: usize,
+    pub step_type: String,
+    pub action: StepAction,
+    pub timeout: Duration,
+}
+
+/// 解析任务参数中的步骤
+pub fn parse_parameters(
+    parameters: Option<&JsonValue>,
+) -> Result<(TaskParameters, Vec<PlannedStep>), String> {
+    let parameters = parameters.ok_or("Task has no parameters")?;
+    let parameters: TaskParameters = serde_json::from_value(parameters.clone())
+        .map_err(|e| format!("Invalid task parameters: {}", e))?;
+    if parameters.steps.is_empty() {
+        return Err("Task has no steps".to_string());
+    }
+
+    let mut planned = Vec::new();
+    for (index, step) in parameters.steps.iter().enumerate() {
+        let error = |e: String| format!("Step {}: {}", index + 1, e);
+        let (step_type, actions, default_timeout) = if mission::is_geographic(&step.step_type) {
+            let waypoints = mission::waypoints(step).map_err(error)?;
+            let actions = waypoints.into_iter().map(StepAction::Waypoint).collect();
+            ("waypoint", actions, DEFAULT_WAYPOINT_TIMEOUT_SECS)
+        } else {
+            let action = StepAction::from_step(step).map_err(error)?;
+            (
+                step.step_type.as_str(),
+                vec![action],
+                DEFAULT_STEP_TIMEOUT_SECS,
+            )
+        };
+        let timeout = Duration::from_secs(step.timeout.unwrap_or(default_timeout).into());
+        planned.extend(actions.into_iter().map(|action| PlannedStep {
+            source_step: index,
+            step_type: step_type.to_string(),
+            action,
+            timeout,
+        }));
+    }
+    Ok((parameters, planned))
+}
+
+/// 解析并估算后的航线
+pub struct MissionPlan {
+    pub parameters: TaskParameters,
+    pub steps: Vec<PlannedStep>,
+    /// 设备最近上报的位置
+    pub origin: Option<(f64, f64)>,
+    pub estimate: MissionEstimate,
+}
+
+/// 设备命令通道
+#[async_trait]
+pub trait DroneLink: Send + Sync {
+    /// 下发命令，设备拒绝或无法送达时返回错误
+    async fn send(&self, device_id: i32, command: &DroneCommand) -> Result<(), String>;
+
+    /// 设备最新遥测
+    async fn telemetry(&self, device_id: i32) -> Option<TelemetrySnapshot>;
+}
+
+/// 通过 MAVLink 或设备 WebSocket 代理下发命令
+pub struct DeviceLink {
+    mavlink: Arc<MavlinkService>,
+    proxy: Arc<DeviceWebSocketProxyService>,
+    realtime: Arc<RealtimeDataService>,
+}
+
+impl DeviceLink {
+    pub fn new(
+        mavlink: Arc<MavlinkService>,
+        proxy: Arc<DeviceWebSocketProxyService>,
+        realtime: Arc<RealtimeDataService>,
+    ) -> Self {
+        Self {
+            mavlink,
+            proxy,
+            realtime,
+        }
+    }
+}
+
+#[async_trait]
+impl DroneLink for DeviceLink {
+    async fn send(&self, device_id: i32, command: &DroneCommand) -> Result<(), String> {
+        if !self.mavlink.is_device_online(device_id).await {
+            return self
+                .proxy
+                .send_device_command(device_id, &command.to_text())
+                .await
+                .map_err(|e| e.to_string());
+        }
+
+        let telemetry = self.realtime.get_telemetry(device_id).await;
+        for mavlink_command in command.to_mavlink(telemetry.as_ref())? {
+            let result = self
+                .mavlink
+                .send_command(device_id, &mavlink_command)
+                .await
+                .map_err(|e| e.to_string())?;
+            if !result.accepted {
+                return Err(format!(
+                    "MAVLink command {} rejected: {}",
+                    result.command, result.result_name
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    async fn telemetry(&self, device_id: i32) -> Option<TelemetrySnapshot> {
+        self.realtime.get_telemetry(device_id).await
+    }
+}
+
+fn now() -> chrono::DateTime<chrono::FixedOffset> {
+    chrono::Utc::now().into()
+}
+
+/// 停止执行的请求
+#[derive(Debug, Clone)]
+struct StopRequest {
+    status: TaskStatus, // cancelled 或 aborted
+    actor: Actor,
+    reason: Option<String>,
+}
+
+/// 任务执行结束
+#[derive(Debug, Clone)]
+pub struct TaskFinished {
+    pub task_id: i32,
+    pub device_id: i32,
+    pub status: TaskStatus,
+}
+
+/// 执行中的任务
+struct RunningTask {
+    device_id: i32,
+    cancel: CancellationToken,
+    pause: watch::Sender<bool>,
+    stop: Arc<Mutex<Option<StopRequest>>>,
+}
+
+/// 任务执行服务
+pub struct TaskExecutor {
+    db: Arc<DatabaseConnection>,
+    link: Arc<dyn DroneLink>,
+    planner: Arc<MissionPlanner>,
+    /// 任务ID到执行状态的映射
+    running: Arc<Mutex<HashMap<i32, RunningTask>>>,
+    finished: broadcast::Sender<TaskFinished>,
+}
+
+impl TaskExecutor {
+    pub fn new(
+        db: Arc<DatabaseConnection>,
+        link: Arc<dyn DroneLink>,
+        planner: Arc<MissionPlanner>,
+    ) -> Self {
+        Self {
+            db,
+            link,
+            planner,
+            running: Arc::new(Mutex::new(HashMap::new())),
+            finished: broadcast::channel(256).0,
+        }
+    }
+
+    /// 订阅任务执行结束事件
+    pub fn subscribe_finished(&self) -> broadcast::Receiver<TaskFinished> {
+        self.finished.subscribe()
+    }
+
+    /// 解析任务步骤，按设备机型和最近上报的位置估算航线
+    pub async fn plan(
+        &self,
+        device_id: i32,
+        parameters: Option<&JsonValue>,
+    ) -> Result<MissionPlan, String> {
+        let (parameters, steps) = parse_parameters(parameters)?;
+        let drone_model = device::Entity::find_by_id(device_id)
+            .one(&*self.db)
+            .await
+            .map_err(|e| e.to_string())?
+            .and_then(|device| device.drone_model);
+        let origin = self
+            .link
+            .telemetry(device_id)
+            .await
+            .and_then(|telemetry| telemetry.position)
+            .map(|position| (position.value.latitude, position.value.longitude));
+        let estimate = self
+            .planner
+            .estimate(&steps, origin, drone_model.as_deref());
+        Ok(MissionPlan {
+            parameters,
+            steps,
+            origin,
+            estimate,
+        })
+    }
+
+    /// 按设备机型模拟任务，`origin` 为空时从设备最近上报的位置开始，`geofences` 与设备的电子围栏一起检查
+    pub async fn simulate(
+        &self,
+        device_id: i32,
+        parameters: Option<&JsonValue>,
+        origin: Option<(f64, f64)>,
+        geofences: &[Geofence],
+    ) -> Result<Simulation, String> {
+        let plan = self.plan(device_id, parameters).await?;
+        let mut all_geofences = geofence::for_device(&self.db, device_id)
+            .await
+            .map_err(|e| e.to_string())?;
+        all_geofences.extend_from_slice(geofences);
+        Ok(simulation::simulate(
+            self.planner.settings(),
+            &plan.steps,
+            origin.or(plan.origin),
+            plan.estimate.drone_model.as_deref(),
+            &all_geofences,
+        ))
+    }
+
+    /// 按模拟航线检查设备的电子围栏，返回第一处违规
+    pub async fn check_geofences(&self, device_id: i32, plan: &MissionPlan) -> Result<(), String> {
+        let geofences = geofence::for_device(&self.db, device_id)
+            .await
+            .map_err(|e| e.to_string())?;
+        if geofences.is_empty() {
+            return Ok(());
+        }
+        let simulation = simulation::simulate(
+            self.planner.settings(),
+            &plan.steps,
+            plan.origin,
+            plan.estimate.drone_model.as_deref(),
+            &geofences,
+        );
+        match simulation
+            .violations
+            .into_iter()
+            .find(|violation| violation.kind == ViolationKind::Geofence)
+        {
+            Some(violation) => Err(format!(
+                "Step {}: {}",
+                violation.source_step + 1,
+                violation.message
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// 服务重启前未执行完的任务已中断，标记为失败
+    pub async fn fail_interrupted(&self) -> Result<u64, DbErr> {
+        let interrupted = task::Entity::find()
+            .filter(task::Column::Status.is_in([
+                TaskStatus::Running.to_string(),
+                TaskStatus::Paused.to_string(),
+            ]))
+            .filter(task::Column::Progress.is_not_null())
+            .all(&*self.db)
+            .await?;
+
+        let mut count = 0;
+        for task in interrupted {
+            let mut progress: TaskProgress = task
+                .progress
+                .clone()
+                .and_then(|value| serde_json::from_value(value).ok())
+                .unwrap_or_default();
+            progress.error = Some(INTERRUPTED_BY_RESTART.to_string());
+            if let Some(step) = progress
+                .current_step
+                .and_then(|index| progress.steps.get_mut(index))
+                .filter(|step| step.status == STEP_RUNNING)
+            {
+                step.status = STEP_FAILED.to_string();
+                step.finished_at = Some(now());
+            }
+
+            let changes = task::ActiveModel {
+                progress: Set(serde_json::to_value(&progress).ok()),
+                end_time: Set(Some(now())),
+                ..Default::default()
+            };
+            match task_state::transition(
+                &self.db,
+                task.id,
+                TaskStatus::Failed,
+                Actor::System,
+                Some(INTERRUPTED_BY_RESTART.to_string()),
+                changes,
+            )
+            .await
+            {
+                Ok(_) => count += 1,
+                Err(TransitionError::Db(e)) => return Err(e),
+                Err(e) => warn!("Failed to mark task {} as failed: {}", task.id, e),
+            }
+        }
+        Ok(count)
+    }
+
+    /// 开始执行任务，参数无效、航线超出续航或违反电子围栏、状态不允许或设备正在执行其它任务时返回错误
+    pub async fn start(&self, task: &task::Model, actor: Actor) -> Result<(), String> {
+        let plan = self.plan(task.device_id, task.parameters.as_ref()).await?;
+        plan.estimate.check()?;
+        self.check_geofences(task.device_id, &plan).await?;
+        let MissionPlan {
+            parameters, steps, ..
+        } = plan;
+        let progress = TaskProgress::new(
+            steps
+                .iter()
+                .map(|step| StepProgress::pending(&step.step_type, step.source_step))
+                .collect(),
+        );
+
+        let cancel = CancellationToken::new();
+        let (pause, paused) = watch::channel(false);
+        let stop = Arc::new(Mutex::new(None));
+        let mut running = self.running.lock().await;
+        if running.contains_key(&task.id) {
+            return Err("Task is already running".to_string());
+        }
+        if running
+            .values()
+            .any(|running| running.device_id == task.device_id)
+        {
+            return Err(format!(
+                "Device {} is already running another task",
+                task.device_id
+            ));
+        }
+
+        let changes = task::ActiveModel {
+            progress: Set(serde_json::to_value(&progress).ok()),
+            start_time: Set(Some(now())),
+            end_time: Set(None),
+            ..Default::default()
+        };
+        task_state::transition(&self.db, task.id, TaskStatus::Running, actor, None, changes)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        running.insert(
+            task.id,
+            RunningTask {
+                device_id: task.device_id,
+                cancel: cancel.clone(),
+                pause,
+                stop: Arc::clone(&stop),
+            },
+        );
+        drop(running);
+
+        let execution = Execution {
+            db: Arc::clone(&self.db),
+            link: Arc::clone(&self.link),
+            task_id: task.id,
+            device_id: task.device_id,
+            progress,
+            parameters,
+            steps,
+            cancel,
+            paused,
+            stop,
+        };
+        let running = Arc::clone(&self.running);
+        let finished = self.finished.clone();
+        let (task_id, device_id) = (task.id, task.device_id);
+        tokio::spawn(async move {
+            let status = execution.run().await;
+            running.lock().await.remove(&task_id);
+            // 没有订阅者时发送失败，无需处理
+            let _ = finished.send(TaskFinished {
+                task_id,
+                device_id,
+                status,
+            });
+        });
+        Ok(())
+    }
+
+    /// 暂停执行中的任务，正在执行的步骤完成后不再继续
+    pub async fn pause(
+        &self,
+        task_id: i32,
+        actor: Actor,
+        reason: Option<String>,
+    ) -> Result<(), String> {
+        self.set_paused(task_id, TaskStatus::Paused, actor, reason)
+            .await
+    }
+
+    /// 恢复已暂停的任务
+    pub async fn resume(
+        &self,
+        task_id: i32,
+        actor: Actor,
+        reason: Option<String>,
+    ) -> Result<(), String> {
+        self.set_paused(task_id, TaskStatus::Running, actor, reason)
+            .await
+    }
+
+    async fn set_paused(
+        &self,
+        task_id: i32,
+        status: TaskStatus,
+        actor: Actor,
+        reason: Option<String>,
+    ) -> Result<(), String> {
+        let running = self.running.lock().await;
+        let Some(task) = running.get(&task_id) else {
+            return Err("Task is not running".to_string());
+        };
+        task_state::transition(
+            &self.db,
+            task_id,
+            status,
+            actor,
+            reason,
+            task::ActiveModel {
+                ..Default::default()
+            },
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+        task.pause.send_replace(status == TaskStatus::Paused);
+        Ok(())
+    }
+
+    /// 停止执行中的任务，`status` 为 aborted 时同时下发降落命令；任务未在执行时返回 false
+    pub async fn stop(
+        &self,
+        task_id: i32,
+        status: TaskStatus,
+        actor: Actor,
+        reason: Option<String>,
+    ) -> bool {
+        match self.running.lock().await.get(&task_id) {
+            Some(running) => {
+                *running.stop.lock().await = Some(StopRequest {
+                    status,
+                    actor,
+                    reason,
+                });
+                running.cancel.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 取消设备正在执行的任务并下发返航命令
+    pub async fn return_to_launch(&self, device_id: i32, reason: String) -> Result<(), String> {
+        let task_id = self
+            .running
+            .lock()
+            .await
+            .iter()
+            .find(|(_, running)| running.device_id == device_id)
+            .map(|(task_id, _)| *task_id);
+        if let Some(task_id) = task_id {
+            self.stop(task_id, TaskStatus::Cancelled, Actor::System, Some(reason))
+                .await;
+        }
+        self.link
+            .send(device_id, &DroneCommand::ReturnToLaunch)
+            .await
+    }
+
+    pub async fn is_running(&self, task_id: i32) -> bool {
+        self.running.lock().await.contains_key(&task_id)
+    }
+
+    /// 设备是否正在执行任务
+    pub async fn is_device_busy(&self, device_id: i32) -> bool {
+        self.running
+            .lock()
+            .await
+            .values()
+            .any(|running| running.device_id == device_id)
+    }
+}
+
+/// 步骤失败或任务被取消
+enum Interrupted {
+    Failed(String),
+    Cancelled,
+}
+
+fn timed_out(limit: Instant, step_timeout: Duration, deadline: Option<Instant>) -> String {
+    if deadline == Some(limit) {
+        "Task timeout exceeded".to_string()
+    } else {
+        format!("Not confirmed within {} seconds", step_timeout.as_secs())
+    }
+}
+
+/// 等待指定时长，超过任务总超时时返回错误
+async fn wait(
+    duration: Duration,
+    step_timeout: Duration,
+    deadline: Option<Instant>,
+) -> Result<(), String> {
+    let end = Instant::now() + duration;
+    let limit = deadline.map_or(end, |deadline| deadline.min(end));
+    tokio::time::sleep_until(limit).await;
+    if limit < end {
+        return Err(timed_out(limit, step_timeout, deadline));
+    }
+    Ok(())
+}
+
+/// 单个任务的执行过程
+struct Execution {
+    db: Arc<DatabaseConnection>,
+    link: Arc<dyn DroneLink>,
+    task_id: i32,
+    device_id: i32,
+    parameters: TaskParameters,
+    steps: Vec<PlannedStep>,
+    progress: TaskProgress,
+    cancel: CancellationToken,
+    paused: watch::Receiver<bool>,
+    stop: Arc<Mutex<Option<StopRequest>>>,
+}
+
+impl Execution {
+    /// 执行全部步骤并记录结果，返回任务的最终状态
+    async fn run(mut self) -> TaskStatus {
+        info!(
+            "Task {} started on device {} ({} steps)",
+            self.task_id,
+            self.device_id,
+            self.steps.len()
+        );
+
+        let deadline = self
+            .parameters
+            .timeout
+            .map(|secs| Instant::now() + Duration::from_secs(secs.into()));
+        let (status, actor, reason) = match self.run_steps(deadline).await {
+            Ok(()) => (TaskStatus::Completed, Actor::Executor, None),
+            Err(Interrupted::Cancelled) => {
+                let stop = self.stop.lock().await.clone().unwrap_or(StopRequest {
+                    status: TaskStatus::Cancelled,
+                    actor: Actor::System,
+                    reason: None,
+                });
+                if stop.status == TaskStatus::Aborted {
+                    self.emergency_land().await;
+                }
+                (stop.status, stop.actor, stop.reason)
+            }
+            Err(Interrupted::Failed(message)) => {
+                warn!("Task {} failed: {}", self.task_id, message);
+                self.progress.error = Some(message.clone());
+                (TaskStatus::Failed, Actor::Executor, Some(message))
+            }
+        };
+
+        info!("Task {} finished: {}", self.task_id, status.to_string());
+        let changes = task::ActiveModel {
+            progress: Set(serde_json::to_value(&self.progress).ok()),
+            end_time: Set(Some(now())),
+            ..Default::default()
+        };
+        if let Err(e) =
+            task_state::transition(&self.db, self.task_id, status, actor, reason, changes).await
+        {
+            error!("Failed to finish task {}: {}", self.task_id, e);
+        }
+        status
+    }
+
+    /// 中止任务时下发降落命令
+    async fn emergency_land(&mut self) {
+        warn!(
+            "Task {} aborted, landing device {}",
+            self.task_id, self.device_id
+        );
+        if let Err(e) = self.link.send(self.device_id, &DroneCommand::Land).await {
+            error!(
+                "Failed to land device {} after abort: {}",
+                self.device_id, e
+            );
+            self.progress.error = Some(format!("Emergency landing failed: {}", e));
+        }
+    }
+
+    /// 任务暂停时等待恢复或取消
+    async fn wait_while_paused(&mut self) -> Result<(), Interrupted> {
+        if !*self.paused.borrow_and_update() {
+            return Ok(());
+        }
+        // 保存暂停前已完成步骤的进度
+        self.save_progress().await;
+        while *self.paused.borrow_and_update() {
+            tokio::select! {
+                _ = self.cancel.cancelled() => return Err(Interrupted::Cancelled),
+                changed = self.paused.changed() => {
+                    if changed.is_err() {
+                        return Err(Interrupted::Cancelled);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn run_steps(&mut self, deadline: Option<Instant>) -> Result<(), Interrupted> {
+        let attempts = 1 + self.parameters.retry_count.unwrap_or(0);
+
+        for index in 0..self.steps.len() {
+            let action = self.steps[index].action.clone();
+            let step_timeout = self.steps[index].timeout;
+            // 暂停时下一步骤保持待执行
+            self.wait_while_paused().await?;
+            self.progress.current_step = Some(index);
+            self.progress.steps[index].status = STEP_RUNNING.to_string();
+            self.progress.steps[index].started_at = Some(now());
+
+            let mut attempt = 0;
+            let result = loop {
+                if let Err(interrupted) = self.wait_while_paused().await {
+                    break Err(interrupted);
+                }
+                attempt += 1;
+                self.progress.steps[index].attempts = attempt;
+                self.save_progress().await;
+
+                let result = tokio::select! {
+                    _ = self.cancel.cancelled() => Err(Interrupted::Cancelled),
+                    result = self.attempt(&action, step_timeout, deadline) => {
+                        result.map_err(Interrupted::Failed)
+                    }
+                };
+                let deadline_passed = deadline.is_some_and(|deadline| Instant::now() >= deadline);
+                match result {
+                    Err(Interrupted::Failed(message))
+                        if attempt < attempts
+                            && !deadline_passed
+                            && !matches!(action, StepAction::Wait(_)) =>
+                    {
+                        warn!(
+                            "Task {} step {} attempt {} failed: {}",
+                            self.task_id,
+                            index + 1,
+                            attempt,
+                            message
+                        );
+                        self.progress.steps[index].error = Some(message);
+                    }
+                    result => break result,
+                }
+            };
+
+            let step = &mut self.progress.steps[index];
+            step.finished_at = Some(now());
+            match result {
+                Ok(()) => {
+                    step.status = STEP_COMPLETED.to_string();
+                    step.error = None;
+                }
+                Err(Interrupted::Cancelled) => {
+                    step.status = STEP_CANCELLED.to_string();
+                    return Err(Interrupted::Cancelled);
+                }
+                Err(Interrupted::Failed(message)) => {
+                    step.status = STEP_FAILED.to_string();
+                    step.error = Some(message.clone());
+                    return Err(Interrupted::Failed(format!(
+                        "Step {} ({}) failed: {}",
+                        index + 1,
+                        step.step_type,
+                        message
+                    )));
+                }
+            }
+        }
+
+        self.progress.current_step = None;
+        Ok(())
+    }
+
+    /// 执行一次步骤，在步骤超时和任务总超时之前完成
+    async fn attempt(
+        &self,
+        action: &StepAction,
+        step_timeout: Duration,
+        deadline: Option<Instant>,
+    ) -> Result<(), String> {
+        match action {
+            StepAction::Wait(duration) => wait(*duration, step_timeout, deadline).await,
+            StepAction::Command(command) => self.command(command, step_timeout, deadline).await,
+            StepAction::Waypoint(waypoint) => {
+                let goto = DroneCommand::GoTo {
+                    latitude: waypoint.latitude,
+                    longitude: waypoint.longitude,
+                    altitude: waypoint.altitude,
+                    speed: waypoint.speed,
+                };
+                self.command(&goto, step_timeout, deadline).await?;
+                if waypoint.camera_action == CameraAction::Photo {
+                    self.command(&DroneCommand::Photo, step_timeout, deadline)
+                        .await?;
+                }
+                if waypoint.loiter > 0.0 {
+                    wait(
+                        Duration::from_secs_f64(waypoint.loiter),
+                        step_timeout,
+                        deadline,
+                    )
+                    .await?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// 下发命令并等待遥测确认
+    async fn command(
+        &self,
+        command: &DroneCommand,
+        step_timeout: Duration,
+        deadline: Option<Instant>,
+    ) -> Result<(), String> {
+        let end = Instant::now() + step_timeout;
+        let limit = deadline.map_or(end, |deadline| deadline.min(end));
+        let since: Timestamp = now();
+        tokio::time::timeout_at(limit, async {
+            self.link.send(self.device_id, command).await?;
+            loop {
+                if let Some(telemetry) = self.link.telemetry(self.device_id).await {
+                    if command.is_complete(&telemetry, since) {
+                        return Ok(());
+                    }
+                }
+                tokio::time::sleep(POLL_INTERVAL).await;
+            }
+        })
+        .await
+        .unwrap_or_else(|_| Err(timed_out(limit, step_timeout, deadline)))
+    }
+
+    /// 保存执行进度
+    async fn save_progress(&self) {
+        let active = task::ActiveModel {
+            id: Set(self.task_id),
+            progress: Set(serde_json::to_value(&self.progress).ok()),
+            updated_at: Set(now()),
+            ..Default::default()
+        };
+        if let Err(e) = active.update(&*self.db).await {
+            error!("Failed to save progress of task {}: {}", self.task_id, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{task::TaskStatus, task_event};
+    use crate::services::telemetry::{Altitude, Attitude, Position};
+    use crate::settings::MissionSettings;
+    use sea_orm::{ConnectOptions, ConnectionTrait, Database, Schema};
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const DEVICE_ID: i32 = 3;
+
+    /// 模拟设备：命令下发后立即更新对应的遥测
+    #[derive(Default)]
+    struct FakeLink {
+        telemetry: std::sync::Mutex<TelemetrySnapshot>,
+        sent: std::sync::Mutex<Vec<DroneCommand>>,
+        /// 接下来拒绝的命令数
+        rejections: AtomicUsize,
+        /// 接受命令但不更新遥测
+        unresponsive: bool,
+    }
+
+    impl FakeLink {
+        fn sent(&self) -> Vec<DroneCommand> {
+            self.sent.lock().unwrap().clone()
+        }
+    }
+
+    #[async_trait]
+    impl DroneLink for FakeLink {
+        async fn send(&self, _device_id: i32, command: &DroneCommand) -> Result<(), String> {
+            self.sent.lock().unwrap().push(command.clone());
+            if self
+                .rejections
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                .is_ok()
+            {
+                return Err("Command rejected".to_string());
+            }
+            if self.unresponsive {
+                return Ok(());
+            }
+
+            let updated_at = now();
+            let mut telemetry = self.telemetry.lock().unwrap();
+            let mut set_relative = |relative: f64| {
+                telemetry.altitude = Some(Timestamped {
+                    value: Altitude {
+                        amsl: Some(100.0 + relative),
+                        relative: Some(relative),
+                    },
+                    updated_at,
+                });
+            };
+            match command {
+                DroneCommand::Takeoff { altitude } => set_relative(*altitude),
+                DroneCommand::Land | DroneCommand::ReturnToLaunch => set_relative(0.0),
+                DroneCommand::MoveToHeight { height } => set_relative(*height),
+                DroneCommand::MoveToHeading { heading } => {
+                    telemetry.attitude = Some(Timestamped {
+                        value: Attitude {
+                            yaw: Some(*heading),
+                            ..Default::default()
+                        },
+                        updated_at,
+                    });
+                }
+                DroneCommand::Photo => {}
+                DroneCommand::GoTo {
+                    latitude,
+                    longitude,
+                    altitude,
+                    ..
+                } => {
+                    set_relative(*altitude);
+                    telemetry.position = Some(Timestamped {
+                        value: Position {
+                            latitude: *latitude,
+                            longitude: *longitude,
+                        },
+                        updated_at,
+                    });
+                }
+            }
+            telemetry.updated_at = Some(updated_at);
+            Ok(())
+        }
+
+        async fn telemetry(&self, _device_id: i32) -> Option<TelemetrySnapshot> {
+            Some(self.telemetry.lock().unwrap().clone())
+        }
+    }
+
+    /// 内存 SQLite，只建执行器用到的表
+    async fn database() -> DatabaseConnection {
+        let mut options = ConnectOptions::new("sqlite::memory:");
+        options
+            .max_connections(1)
+            .min_connections(1)
+            .sqlx_logging(false);
+        let db = Database::connect(options).await.unwrap();
+        let backend = db.get_database_backend();
+        let schema = Schema::new(backend);
+        for statement in [
+            schema.create_table_from_entity(device::Entity),
+            schema.create_table_from_entity(task::Entity),
+            schema.create_table_from_entity(task_event::Entity),
+        ] {
+            db.execute(backend.build(&statement)).await.unwrap();
+        }
+        // 任务引用的设备和用户不需要存在
+        db.execute_unprepared("PRAGMA foreign_keys = OFF")
+            .await
+            .unwrap();
+        db
+    }
+
+    async fn start(
+        link: FakeLink,
+        parameters: JsonValue,
+    ) -> (
+        TaskExecutor,
+        Arc<FakeLink>,
+        task::Model,
+        broadcast::Receiver<TaskFinished>,
+    ) {
+        let db = Arc::new(database().await);
+        let task = task::ActiveModel {
+            uuid: Set(uuid::Uuid::new_v4()),
+            name: Set("test".to_string()),
+            task_type: Set("manual".to_string()),
+            status: Set(TaskStatus::Pending.to_string()),
+            device_id: Set(DEVICE_ID),
+            user_id: Set(1),
+            parameters: Set(Some(parameters)),
+            created_at: Set(now()),
+            updated_at: Set(now()),
+            ..Default::default()
+        }
+        .insert(&*db)
+        .await
+        .unwrap();
+
+        let link = Arc::new(link);
+        let executor = TaskExecutor::new(
+            db,
+            link.clone(),
+            Arc::new(MissionPlanner::new(MissionSettings::default())),
+        );
+        let finished = executor.subscribe_finished();
+        executor.start(&task, Actor::System).await.unwrap();
+        (executor, link, task, finished)
+    }
+
+    async fn finished(receiver: &mut broadcast::Receiver<TaskFinished>) -> TaskStatus {
+        tokio::time::timeout(Duration::from_secs(10), receiver.recv())
+            .await
+            .expect("task did not finish")
+            .unwrap()
+            .status
+    }
+
+    async fn stored(executor: &TaskExecutor, task_id: i32) -> (String, TaskProgress) {
+        let task = task::Entity::find_by_id(task_id)
+            .one(&*executor.db)
+            .await
+            .unwrap()
+            .unwrap();
+        let progress = serde_json::from_value(task.progress.unwrap()).unwrap();
+        (task.status, progress)
+    }
+
+    fn step(step_type: &str, parameters: JsonValue, timeout: Option<u32>) -> JsonValue {
+        json!({ "step_type": step_type, "parameters": parameters, "timeout": timeout })
+    }
+
+    #[tokio::test]
+    async fn completes_steps_confirmed_by_telemetry() {
+        let parameters = json!({
+            "steps": [
+                step("takeoff", json!({ "altitude": 5 }), None),
+                step("move_to_height", json!({ "height": 12 }), None),
+                step("move_to_heading", json!({ "heading": 90 }), None),
+                step("photo", json!({}), None),
+                step("landing", json!({}), None),
+            ]
+        });
+        let (executor, link, task, mut receiver) = start(FakeLink::default(), parameters).await;
+
+        assert_eq!(finished(&mut receiver).await, TaskStatus::Completed);
+        assert_eq!(
+            link.sent(),
+            vec![
+                DroneCommand::Takeoff { altitude: 5.0 },
+                DroneCommand::MoveToHeight { height: 12.0 },
+                DroneCommand::MoveToHeading { heading: 90.0 },
+                DroneCommand::Photo,
+                DroneCommand::Land,
+            ]
+        );
+        let (status, progress) = stored(&executor, task.id).await;
+        assert_eq!(status, "completed");
+        assert_eq!(progress.current_step, None);
+        assert!(progress
+            .steps
+            .iter()
+            .all(|step| step.status == STEP_COMPLETED && step.attempts == 1));
+        assert!(!executor.is_running(task.id).await);
+    }
+
+    #[tokio::test]
+    async fn retries_rejected_command() {
+        let link = FakeLink {
+            rejections: AtomicUsize::new(1),
+            ..Default::default()
+        };
+        let parameters = json!({
+            "steps": [step("takeoff", json!({ "altitude": 5 }), None)],
+            "retry_count": 1
+        });
+        let (executor, link, task, mut receiver) = start(link, parameters).await;
+
+        assert_eq!(finished(&mut receiver).await, TaskStatus::Completed);
+        assert_eq!(link.sent().len(), 2);
+        let (_, progress) = stored(&executor, task.id).await;
+        assert_eq!(progress.steps[0].status, STEP_COMPLETED);
+        assert_eq!(progress.steps[0].attempts, 2);
+        assert_eq!(progress.steps[0].error, None);
+    }
+
+    #[tokio::test]
+    async fn fails_when_retries_are_exhausted() {
+        let link = FakeLink {
+            rejections: AtomicUsize::new(2),
+            ..Default::default()
+        };
+        let parameters = json!({
+            "steps": [
+                step("takeoff", json!({ "altitude": 5 }), None),
+                step("landing", json!({}), None),
+            ],
+            "retry_count": 1
+        });
+        let (executor, link, task, mut receiver) = start(link, parameters).await;
+
+        assert_eq!(finished(&mut receiver).await, TaskStatus::Failed);
+        assert_eq!(link.sent().len(), 2);
+        let (status, progress) = stored(&executor, task.id).await;
+        assert_eq!(status, "failed");
+        assert_eq!(progress.steps[0].status, STEP_FAILED);
+        assert_eq!(progress.steps[0].attempts, 2);
+        assert_eq!(progress.steps[0].error.as_deref(), Some("Command rejected"));
+        assert_eq!(progress.steps[1].status, task::STEP_PENDING);
+        assert_eq!(
+            progress.error.as_deref(),
+            Some("Step 1 (takeoff) failed: Command rejected")
+        );
+    }
+
+    #[tokio::test]
+    async fn step_timeout_is_retried_then_fails() {
+        let link = FakeLink {
+            unresponsive: true,
+            ..Default::default()
+        };
+        let parameters = json!({
+            "steps": [step("move_to_height", json!({ "height": 12 }), Some(1))],
+            "retry_count": 1
+        });
+        let (executor, link, task, mut receiver) = start(link, parameters).await;
+
+        assert_eq!(finished(&mut receiver).await, TaskStatus::Failed);
+        assert_eq!(link.sent().len(), 2);
+        let (_, progress) = stored(&executor, task.id).await;
+        assert_eq!(progress.steps[0].attempts, 2);
+        assert_eq!(
+            progress.steps[0].error.as_deref(),
+            Some("Not confirmed within 1 seconds")
+        );
+    }
+
+    #[tokio::test]
+    async fn task_timeout_stops_execution() {
+        let link = FakeLink {
+            unresponsive: true,
+            ..Default::default()
+        };
+        let parameters = json!({
+            "steps": [
+                step("takeoff", json!({ "altitude": 5 }), Some(30)),
+                step("landing", json!({}), None),
+            ],
+            "timeout": 1,
+            "retry_count": 3
+        });
+        let started = Instant::now();
+        let (executor, link, task, mut receiver) = start(link, parameters).await;
+
+        assert_eq!(finished(&mut receiver).await, TaskStatus::Failed);
+        assert!(started.elapsed() < Duration::from_secs(5));
+        // 超过任务总超时后不再重试
+        assert_eq!(link.sent(), vec![DroneCommand::Takeoff { altitude: 5.0 }]);
+        let (_, progress) = stored(&executor, task.id).await;
+        assert_eq!(
+            progress.steps[0].error.as_deref(),
+            Some("Task timeout exceeded")
+        );
+    }
+
+    #[tokio::test]
+    async fn pause_holds_next_step_until_resumed() {
+        let parameters = json!({
+            "steps": [
+                step("wait", json!({ "duration": 0.5 }), None),
+                step("landing", json!({}), None),
+            ]
+        });
+        let (executor, link, task, mut receiver) = start(FakeLink::default(), parameters).await;
+        // 在等待步骤执行中暂停
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        executor.pause(task.id, Actor::System, None).await.unwrap();
+
+        // 正在执行的等待步骤仍会完成，之后不再下发命令
+        tokio::time::sleep(Duration::from_millis(1000)).await;
+        assert!(link.sent().is_empty());
+        let (status, progress) = stored(&executor, task.id).await;
+        assert_eq!(status, "paused");
+        assert_eq!(progress.steps[0].status, STEP_COMPLETED);
+        assert_eq!(progress.steps[1].status, task::STEP_PENDING);
+
+        executor.resume(task.id, Actor::System, None).await.unwrap();
+        assert_eq!(finished(&mut receiver).await, TaskStatus::Completed);
+        assert_eq!(link.sent(), vec![DroneCommand::Land]);
+    }
+
+    #[tokio::test]
+    async fn cancel_stops_without_landing() {
+        let parameters = json!({
+            "steps": [
+                step("wait", json!({ "duration": 30 }), None),
+                step("landing", json!({}), None),
+            ]
+        });
+        let (executor, link, task, mut receiver) = start(FakeLink::default(), parameters).await;
+        assert!(
+            executor
+                .stop(task.id, TaskStatus::Cancelled, Actor::System, None)
+                .await
+        );
+
+        assert_eq!(finished(&mut receiver).await, TaskStatus::Cancelled);
+        assert!(link.sent().is_empty());
+        let (status, progress) = stored(&executor, task.id).await;
+        assert_eq!(status, "cancelled");
+        assert_eq!(progress.steps[0].status, STEP_CANCELLED);
+    }
+
+    #[tokio::test]
+    async fn abort_lands_the_device() {
+        let parameters = json!({
+            "steps": [
+                step("takeoff", json!({ "altitude": 5 }), None),
+                step("wait", json!({ "duration": 30 }), None),
+                step("landing", json!({}), None),
+            ]
+        });
+        let (executor, link, task, mut receiver) = start(FakeLink::default(), parameters).await;
+        // 等待起飞完成后中止
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(
+            executor
+                .stop(
+                    task.id,
+                    TaskStatus::Aborted,
+                    Actor::System,
+                    Some("test".to_string())
+                )
+                .await
+        );
+
+        assert_eq!(finished(&mut receiver).await, TaskStatus::Aborted);
+        assert_eq!(
+            link.sent(),
+            vec![DroneCommand::Takeoff { altitude: 5.0 }, DroneCommand::Land]
+        );
+        let (status, progress) = stored(&executor, task.id).await;
+        assert_eq!(status, "aborted");
+        assert_eq!(progress.steps[1].status, STEP_CANCELLED);
+        assert_eq!(progress.steps[2].status, task::STEP_PENDING);
+    }
+}
@@ -0,0 +1,601 @@
+//! 任务调度
+//!
+//! 调度分两种：
+//! - 单次调度：在 `run_at` 执行任务本身，执行后调度自动停用；
+//! - 周期调度：按 cron 表达式在指定时区计算执行时间，支持标准 crontab 的 5 段写法和带秒的 6/7 段写法
+//!   （6/7 段写法中数字星期 1 为周日，建议使用 MON-FRI 等名称），
+//!   每次以任务为模板复制出一条新任务执行，模板任务保持不变。
+//!
+//! 调度器每 5 秒扫描到期的调度。执行前检查设备：需处于启用状态、通过 MAVLink 或 WebSocket 代理在线，
+//! 且没有正在执行的任务。设备不可用时在宽限期（`misfire_grace_secs`）内持续重试，超过宽限期记为错过；
+//! 停机期间错过的调度从服务启动时开始计算宽限期，以便设备重新上线。
+//! 服务停机等原因超过宽限期仍未执行的调度按 `misfire_policy` 处理：`skip` 直接跳到下一次执行时间，
+//! `run_once` 立即补执行一次（多次错过也只补一次）。
+
+use chrono::{DateTime, FixedOffset, TimeZone};
+use chrono_tz::Tz;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, DatabaseConnection, DbErr, EntityTrait,
+    QueryFilter,
+};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::models::task::{self, TaskStatus, TaskType};
+use crate::models::{device, task_schedule};
+use crate::services::device_websocket_proxy::DeviceWebSocketProxyService;
+use crate::services::mavlink_service::MavlinkService;
+use crate::services::task_executor::{parse_parameters, TaskExecutor};
+use crate::services::task_state::{self, Actor};
+
+/// 扫描到期调度的间隔
+const TICK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// 解析后的调度规则
+pub struct ScheduleSpec {
+    cron: Option<cron::Schedule>,
+    run_at: Option<DateTime<FixedOffset>>,
+    timezone: Tz,
+}
+
+impl ScheduleSpec {
+    /// 解析调度规则，`cron` 为空时为 `run_at` 单次调度
+    pub fn parse(
+        cron: Option<&str>,
+        run_at: Option<DateTime<FixedOffset>>,
+        timezone: &str,
+    ) -> Result<Self, String> {
+        let timezone =
+            Tz::from_str(timezone).map_err(|_| format!("Unknown time zone: {}", timezone))?;
+
+        let cron = match cron.map(str::trim) {
+            Some(expression) => {
+                let fields: Vec<&str> = expression.split_whitespace().collect();
+                // 5 段为标准 crontab 写法：补 0 秒，星期按 0/7 为周日转换为名称
+                let expression = if fields.len() == 5 {
+                    format!(
+                        "0 {} {}",
+                        fields[..4].join(" "),
+                        crontab_day_of_week(fields[4])
+                    )
+                } else {
+                    expression.to_string()
+                };
+                Some(
+                    cron::Schedule::from_str(&expression)
+                        .map_err(|e| format!("Invalid cron expression: {}", e))?,
+                )
+            }
+            None => None,
+        };
+
+        if cron.is_none() && run_at.is_none() {
+            return Err("Either cron or run_at is required".to_string());
+        }
+
+        Ok(Self {
+            cron,
+            run_at,
+            timezone,
+        })
+    }
+
+    pub fn from_model(schedule: &task_schedule::Model) -> Result<Self, String> {
+        Self::parse(
+            schedule.cron.as_deref(),
+            schedule.run_at,
+            &schedule.timezone,
+        )
+    }
+
+    pub fn is_recurring(&self) -> bool {
+        self.cron.is_some()
+    }
+
+    /// `after` 之后的第一次执行时间，以调度时区表示
+    pub fn next_after(&self, after: DateTime<FixedOffset>) -> Option<DateTime<FixedOffset>> {
+        match &self.cron {
+            Some(cron) => cron
+                .after(&after.with_timezone(&self.timezone))
+                .next()
+                .map(|at| at.fixed_offset()),
+            None => self
+                .run_at
+                .filter(|run_at| *run_at > after)
+                .map(|run_at| self.localize(run_at)),
+        }
+    }
+
+    /// 从下一次执行时间 `next_run_at` 开始的最多 `count` 次执行时间
+    pub fn upcoming(
+        &self,
+        next_run_at: DateTime<FixedOffset>,
+        count: usize,
+    ) -> Vec<DateTime<FixedOffset>> {
+        let first = self.localize(next_run_at);
+        match &self.cron {
+            Some(cron) => std::iter::once(first)
+                .chain(
+                    cron.after(&first.with_timezone(&self.timezone))
+                        .map(|at| at.fixed_offset()),
+                )
+                .take(count)
+                .collect(),
+            None => std::iter::once(first).take(count).collect(),
+        }
+    }
+
+    /// 转换为调度时区的时间
+    pub fn localize(&self, at: DateTime<FixedOffset>) -> DateTime<FixedOffset> {
+        self.timezone
+            .from_utc_datetime(&at.naive_utc())
+            .fixed_offset()
+    }
+}
+
+/// 将标准 crontab 的数字星期（0 或 7 为周日）转换为名称，步长数字保持不变
+fn crontab_day_of_week(field: &str) -> String {
+    const NAMES: [&str; 8] = ["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT", "SUN"];
+
+    let mut result = String::new();
+    let mut number = String::new();
+    let mut after_step = false;
+    for c in field.chars().chain(std::iter::once(',')) {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        if !number.is_empty() {
+            match number.parse::<usize>().ok().filter(|_| !after_step) {
+                Some(day) if day < NAMES.len() => result.push_str(NAMES[day]),
+                _ => result.push_str(&number),
+            }
+            number.clear();
+        }
+        after_step = c == '/';
+        result.push(c);
+    }
+    result.pop();
+    result
+}
+
+fn now() -> DateTime<FixedOffset> {
+    chrono::Utc::now().into()
+}
+
+/// 到期调度按宽限期的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MisfireDecision {
+    /// 超过宽限期且策略为 `skip`，跳到下一次执行时间
+    Skip,
+    /// 检查设备后执行；`wait_expired` 为 true 时设备不可用记为错过，否则继续等待
+    Run { wait_expired: bool },
+}
+
+/// 判断在 `scheduled_at` 到期的调度在 `now` 时如何处理，停机期间错过的调度从 `started_at` 开始等待设备
+fn misfire_decision(
+    policy: &str,
+    grace_secs: i32,
+    scheduled_at: DateTime<FixedOffset>,
+    started_at: DateTime<FixedOffset>,
+    now: DateTime<FixedOffset>,
+) -> MisfireDecision {
+    let grace = chrono::Duration::seconds(i64::from(grace_secs.max(0)));
+    if now - scheduled_at > grace && policy == task_schedule::MISFIRE_SKIP {
+        return MisfireDecision::Skip;
+    }
+    MisfireDecision::Run {
+        wait_expired: now - scheduled_at.max(started_at) > grace,
+    }
+}
+
+/// 一次到期调度的处理结果
+enum Outcome {
+    Launched(i32),
+    Missed(String),
+    Failed(String),
+}
+
+/// 任务调度服务
+pub struct TaskScheduler {
+    db: Arc<DatabaseConnection>,
+    executor: Arc<TaskExecutor>,
+    mavlink: Arc<MavlinkService>,
+    proxy: Arc<DeviceWebSocketProxyService>,
+    /// 服务启动时间，停机期间错过的调度从此时开始等待设备上线
+    started_at: DateTime<FixedOffset>,
+}
+
+impl TaskScheduler {
+    pub fn new(
+        db: Arc<DatabaseConnection>,
+        executor: Arc<TaskExecutor>,
+        mavlink: Arc<MavlinkService>,
+        proxy: Arc<DeviceWebSocketProxyService>,
+    ) -> Self {
+        Self {
+            db,
+            executor,
+            mavlink,
+            proxy,
+            started_at: now(),
+        }
+    }
+
+    /// 启动调度循环，首次扫描立即执行以处理停机期间错过的调度
+    pub fn start(self: &Arc<Self>) {
+        let scheduler = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(TICK_INTERVAL);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = scheduler.tick().await {
+                    error!("Task scheduler tick failed: {}", e);
+                }
+            }
+        });
+    }
+
+    /// 处理所有到期的调度
+    pub async fn tick(&self) -> Result<(), DbErr> {
+        let now = now();
+        let due = task_schedule::Entity::find()
+            .filter(task_schedule::Column::Enabled.eq(true))
+            .filter(task_schedule::Column::NextRunAt.lte(now))
+            .find_also_related(task::Entity)
+            .all(&*self.db)
+            .await?;
+
+        for (schedule, task) in due {
+            let Some(task) = task else {
+                continue;
+            };
+            let schedule_id = schedule.id;
+            if let Err(e) = self.fire(schedule, task, now).await {
+                error!("Failed to run schedule {}: {}", schedule_id, e);
+            }
+        }
+        Ok(())
+    }
+
+    async fn fire(
+        &self,
+        schedule: task_schedule::Model,
+        task: task::Model,
+        now: DateTime<FixedOffset>,
+    ) -> Result<(), DbErr> {
+        let Some(scheduled_at) = schedule.next_run_at else {
+            return Ok(());
+        };
+        let spec = match ScheduleSpec::from_model(&schedule) {
+            Ok(spec) => spec,
+            Err(e) => {
+                warn!("Disabling schedule {}: {}", schedule.id, e);
+                return self.finish(schedule, None, Outcome::Failed(e), now).await;
+            }
+        };
+
+        let decision = misfire_decision(
+            &schedule.misfire_policy,
+            schedule.misfire_grace_secs,
+            scheduled_at,
+            self.started_at,
+            now,
+        );
+
+        let outcome = if decision == MisfireDecision::Skip {
+            info!(
+                "Schedule {} missed run at {}, skipping to next run",
+                schedule.id, scheduled_at
+            );
+            let missed = format!("Missed run at {}", spec.localize(scheduled_at));
+            // 到期后记录的错误是宽限期内等待设备的原因
+            match schedule
+                .last_error
+                .as_ref()
+                .filter(|_| schedule.updated_at >= scheduled_at)
+            {
+                Some(reason) => Outcome::Missed(format!("{}: {}", missed, reason)),
+                None => Outcome::Missed(missed),
+            }
+        } else {
+            match self.check_device(task.device_id).await {
+                Ok(()) => match self.launch(&spec, &task).await {
+                    Ok(task_id) => {
+                        info!(
+                            "Schedule {} launched task {} (scheduled at {})",
+                            schedule.id, task_id, scheduled_at
+                        );
+                        Outcome::Launched(task_id)
+                    }
+                    Err(e) => {
+                        warn!("Schedule {} failed to launch: {}", schedule.id, e);
+                        Outcome::Failed(e)
+                    }
+                },
+                Err(reason) if decision == MisfireDecision::Run { wait_expired: true } => {
+                    info!("Schedule {} missed run: {}", schedule.id, reason);
+                    Outcome::Missed(reason)
+                }
+                // 宽限期内等待设备可用
+                Err(reason) => {
+                    if schedule.last_error.as_deref() != Some(reason.as_str()) {
+                        let mut active: task_schedule::ActiveModel = schedule.into();
+                        active.last_error = Set(Some(reason));
+                        active.updated_at = Set(now);
+                        active.update(&*self.db).await?;
+                    }
+                    return Ok(());
+                }
+            }
+        };
+
+        let next_run_at = spec.next_after(now);
+        self.finish(schedule, next_run_at, outcome, now).await
+    }
+
+    /// 记录调度结果并推进到下一次执行时间
+    async fn finish(
+        &self,
+        schedule: task_schedule::Model,
+        next_run_at: Option<DateTime<FixedOffset>>,
+        outcome: Outcome,
+        now: DateTime<FixedOffset>,
+    ) -> Result<(), DbErr> {
+        let mut active: task_schedule::ActiveModel = schedule.into();
+        let (status, error) = match outcome {
+            Outcome::Launched(task_id) => {
+                active.last_task_id = Set(Some(task_id));
+                (task_schedule::RUN_LAUNCHED, None)
+            }
+            Outcome::Missed(reason) => (task_schedule::RUN_MISSED, Some(reason)),
+            Outcome::Failed(reason) => (task_schedule::RUN_FAILED, Some(reason)),
+        };
+        active.last_status = Set(Some(status.to_string()));
+        active.last_error = Set(error);
+        active.last_run_at = Set(Some(now));
+        active.enabled = Set(next_run_at.is_some());
+        active.next_run_at = Set(next_run_at);
+        active.updated_at = Set(now);
+        active.update(&*self.db).await?;
+        Ok(())
+    }
+
+    /// 检查设备能否执行任务
+    async fn check_device(&self, device_id: i32) -> Result<(), String> {
+        let device = device::Entity::find_by_id(device_id)
+            .one(&*self.db)
+            .await
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| "Device not found".to_string())?;
+        if !device.is_active {
+            return Err("Device is inactive".to_string());
+        }
+        if self.executor.is_device_busy(device_id).await {
+            return Err("Device is running another task".to_string());
+        }
+        if !self.mavlink.is_device_online(device_id).await
+            && !self.proxy.is_device_connected(device_id).await
+        {
+            return Err("Device is offline".to_string());
+        }
+        Ok(())
+    }
+
+    /// 单次调度执行任务本身，周期调度复制任务后执行，返回执行的任务ID
+    async fn launch(&self, spec: &ScheduleSpec, task: &task::Model) -> Result<i32, String> {
+        if !spec.is_recurring() {
+            self.executor.start(task, Actor::Scheduler).await?;
+            return Ok(task.id);
+        }
+
+        parse_parameters(task.parameters.as_ref())?;
+        let run = task::ActiveModel {
+            uuid: Set(Uuid::new_v4()),
+            name: Set(task.name.clone()),
+            description: Set(task.description.clone()),
+            task_type: Set(TaskType::Scheduled.to_string()),
+            status: Set(TaskStatus::Pending.to_string()),
+            device_id: Set(task.device_id),
+            user_id: Set(task.user_id),
+            parameters: Set(task.parameters.clone()),
+            ..Default::default()
+        };
+        let run = task::Entity::insert(run)
+            .exec_with_returning(&*self.db)
+            .await
+            .map_err(|e| e.to_string())?;
+        if let Err(e) = task_state::record_created(
+            &*self.db,
+            &run,
+            Actor::Scheduler,
+            Some(format!("Scheduled run of task {}", task.id)),
+        )
+        .await
+        {
+            error!("Failed to record creation of task {}: {}", run.id, e);
+        }
+
+        if let Err(e) = self.executor.start(&run, Actor::Scheduler).await {
+            let changes = task::ActiveModel {
+                end_time: Set(Some(now())),
+                ..Default::default()
+            };
+            if let Err(transition_err) = task_state::transition(
+                &self.db,
+                run.id,
+                TaskStatus::Failed,
+                Actor::Scheduler,
+                Some(e.clone()),
+                changes,
+            )
+            .await
+            {
+                error!(
+                    "Failed to mark scheduled task {} as failed: {}",
+                    run.id, transition_err
+                );
+            }
+            return Err(e);
+        }
+        Ok(run.id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shanghai(day: u32, hour: u32, min: u32, sec: u32) -> DateTime<FixedOffset> {
+        chrono_tz::Asia::Shanghai
+            .with_ymd_and_hms(2026, 10, day, hour, min, sec)
+            .unwrap()
+            .fixed_offset()
+    }
+
+    #[test]
+    fn crontab_day_of_week_converts_numbers_to_names() {
+        let cases = [
+            ("0", "SUN"),
+            ("7", "SUN"),
+            ("1-5", "MON-FRI"),
+            ("0,6", "SUN,SAT"),
+            ("5-7", "FRI-SUN"),
+            ("1,3-5", "MON,WED-FRI"),
+            ("*/2", "*/2"),
+            ("1-5/2", "MON-FRI/2"),
+            ("MON-FRI", "MON-FRI"),
+            ("*", "*"),
+        ];
+        for (field, expected) in cases {
+            assert_eq!(crontab_day_of_week(field), expected, "{}", field);
+        }
+    }
+
+    #[test]
+    fn five_field_cron_runs_in_schedule_time_zone() {
+        // 2026-10-16 是周五
+        let friday = shanghai(16, 10, 0, 0);
+        for sunday in ["0", "7"] {
+            let spec =
+                ScheduleSpec::parse(Some(&format!("0 9 * * {}", sunday)), None, "Asia/Shanghai")
+                    .unwrap();
+            assert_eq!(spec.next_after(friday), Some(shanghai(18, 9, 0, 0)));
+        }
+
+        let weekdays = ScheduleSpec::parse(Some("0 9 * * 1-5"), None, "Asia/Shanghai").unwrap();
+        // 按 UTC 传入的时间也按调度时区计算，结果带 +08:00 偏移
+        let next = weekdays
+            .next_after(friday.with_timezone(&FixedOffset::east_opt(0).unwrap()))
+            .unwrap();
+        assert_eq!(next, shanghai(19, 9, 0, 0));
+        assert_eq!(next.offset().local_minus_utc(), 8 * 3600);
+        assert_eq!(
+            weekdays.upcoming(next, 3),
+            vec![
+                shanghai(19, 9, 0, 0),
+                shanghai(20, 9, 0, 0),
+                shanghai(21, 9, 0, 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn misfire_within_grace_waits_for_device() {
+        let scheduled_at = shanghai(19, 9, 0, 0);
+        let started_at = shanghai(1, 0, 0, 0);
+        for policy in [task_schedule::MISFIRE_SKIP, task_schedule::MISFIRE_RUN_ONCE] {
+            assert_eq!(
+                misfire_decision(policy, 60, scheduled_at, started_at, shanghai(19, 9, 0, 59)),
+                MisfireDecision::Run {
+                    wait_expired: false
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn misfire_beyond_grace_follows_policy() {
+        let scheduled_at = shanghai(19, 9, 0, 0);
+        let started_at = shanghai(1, 0, 0, 0);
+        // 同一时刻用其它时区表示，判断不受偏移影响
+        let now = chrono_tz::America::New_York
+            .from_utc_datetime(&shanghai(19, 9, 2, 0).naive_utc())
+            .fixed_offset();
+
+        assert_eq!(
+            misfire_decision(
+                task_schedule::MISFIRE_SKIP,
+                60,
+                scheduled_at,
+                started_at,
+                now
+            ),
+            MisfireDecision::Skip
+        );
+        assert_eq!(
+            misfire_decision(
+                task_schedule::MISFIRE_RUN_ONCE,
+                60,
+                scheduled_at,
+                started_at,
+                now
+            ),
+            MisfireDecision::Run { wait_expired: true }
+        );
+        // 负数宽限期按 0 处理
+        assert_eq!(
+            misfire_decision(
+                task_schedule::MISFIRE_SKIP,
+                -30,
+                scheduled_at,
+                started_at,
+                shanghai(19, 9, 0, 1)
+            ),
+            MisfireDecision::Skip
+        );
+    }
+
+    #[test]
+    fn run_once_after_restart_waits_from_start_time() {
+        // 停机期间错过 9:00 的调度，服务 9:05 启动
+        let scheduled_at = shanghai(19, 9, 0, 0);
+        let started_at = shanghai(19, 9, 5, 0);
+
+        assert_eq!(
+            misfire_decision(
+                task_schedule::MISFIRE_RUN_ONCE,
+                60,
+                scheduled_at,
+                started_at,
+                shanghai(19, 9, 5, 30)
+            ),
+            MisfireDecision::Run {
+                wait_expired: false
+            }
+        );
+        assert_eq!(
+            misfire_decision(
+                task_schedule::MISFIRE_RUN_ONCE,
+                60,
+                scheduled_at,
+                started_at,
+                shanghai(19, 9, 6, 30)
+            ),
+            MisfireDecision::Run { wait_expired: true }
+        );
+        assert_eq!(
+            misfire_decision(
+                task_schedule::MISFIRE_SKIP,
+                60,
+                scheduled_at,
+                started_at,
+                shanghai(19, 9, 5, 30)
+            ),
+            MisfireDecision::Skip
+        );
+    }
+}
@@ -0,0 +1,171 @@
+//! 任务状态变更
+//!
+//! 任务状态只能按 [`TaskStatus::can_transition_to`] 定义的状态机变更。更新以“状态未被并发修改”为条件，
+//! 并在同一事务中写入 `task_event`，记录操作方、时间和原因，作为任务的时间线。
+
+use sea_orm::{
+    ActiveValue::Set, ColumnTrait, ConnectionTrait, DatabaseConnection, DbErr, EntityTrait,
+    QueryFilter, TransactionTrait,
+};
+use std::fmt;
+
+use crate::models::task::{self, TaskStatus};
+use crate::models::task_event;
+
+/// 状态并发变更时的最大重试次数
+const MAX_ATTEMPTS: usize = 3;
+
+/// 状态变更的操作方
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Actor {
+    User(i32),
+    Executor,
+    Scheduler,
+    System,
+}
+
+impl Actor {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Actor::User(_) => "user",
+            Actor::Executor => "executor",
+            Actor::Scheduler => "scheduler",
+            Actor::System => "system",
+        }
+    }
+
+    pub fn user_id(&self) -> Option<i32> {
+        match self {
+            Actor::User(user_id) => Some(*user_id),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum TransitionError {
+    NotFound,
+    /// 状态机不允许的变更
+    Illegal {
+        from: String,
+        to: TaskStatus,
+    },
+    /// 多次重试后状态仍被并发修改
+    Conflict,
+    Db(DbErr),
+}
+
+impl fmt::Display for TransitionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound => write!(f, "Task not found"),
+            Self::Illegal { from, to } => write!(
+                f,
+                "Cannot change task status from {} to {}",
+                from,
+                to.to_string()
+            ),
+            Self::Conflict => write!(f, "Task status was changed concurrently"),
+            Self::Db(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for TransitionError {}
+
+impl From<DbErr> for TransitionError {
+    fn from(e: DbErr) -> Self {
+        Self::Db(e)
+    }
+}
+
+fn now() -> chrono::DateTime<chrono::FixedOffset> {
+    chrono::Utc::now().into()
+}
+
+/// 变更任务状态，`changes` 中已设置的字段随状态一同更新，返回更新后的任务
+pub async fn transition(
+    db: &DatabaseConnection,
+    task_id: i32,
+    to: TaskStatus,
+    actor: Actor,
+    reason: Option<String>,
+    changes: task::ActiveModel,
+) -> Result<task::Model, TransitionError> {
+    for _ in 0..MAX_ATTEMPTS {
+        let task = task::Entity::find_by_id(task_id)
+            .one(db)
+            .await?
+            .ok_or(TransitionError::NotFound)?;
+        let allowed = task
+            .status
+            .parse::<TaskStatus>()
+            .is_ok_and(|from| from.can_transition_to(&to));
+        if !allowed {
+            return Err(TransitionError::Illegal {
+                from: task.status,
+                to,
+            });
+        }
+
+        let now = now();
+        let mut changes = changes.clone();
+        changes.status = Set(to.to_string());
+        changes.updated_at = Set(now);
+
+        let txn = db.begin().await?;
+        let result = task::Entity::update_many()
+            .set(changes)
+            .filter(task::Column::Id.eq(task_id))
+            .filter(task::Column::Status.eq(&task.status))
+            .exec(&txn)
+            .await?;
+        if result.rows_affected == 0 {
+            // 读取之后状态已被修改，按新状态重新校验
+            txn.rollback().await?;
+            continue;
+        }
+        insert_event(&txn, task_id, Some(task.status), to, actor, reason, now).await?;
+        txn.commit().await?;
+
+        return task::Entity::find_by_id(task_id)
+            .one(db)
+            .await?
+            .ok_or(TransitionError::NotFound);
+    }
+    Err(TransitionError::Conflict)
+}
+
+/// 记录任务创建
+pub async fn record_created<C: ConnectionTrait>(
+    db: &C,
+    task: &task::Model,
+    actor: Actor,
+    reason: Option<String>,
+) -> Result<(), DbErr> {
+    let status = task.status.parse().unwrap_or(TaskStatus::Pending);
+    insert_event(db, task.id, None, status, actor, reason, now()).await
+}
+
+async fn insert_event<C: ConnectionTrait>(
+    db: &C,
+    task_id: i32,
+    from: Option<String>,
+    to: TaskStatus,
+    actor: Actor,
+    reason: Option<String>,
+    created_at: chrono::DateTime<chrono::FixedOffset>,
+) -> Result<(), DbErr> {
+    let event = task_event::ActiveModel {
+        task_id: Set(task_id),
+        from_status: Set(from),
+        to_status: Set(to.to_string()),
+        actor: Set(actor.name().to_string()),
+        user_id: Set(actor.user_id()),
+        reason: Set(reason),
+        created_at: Set(created_at),
+        ..Default::default()
+    };
+    task_event::Entity::insert(event).exec(db).await?;
+    Ok(())
+}
@@ -0,0 +1,460 @@
+//! 统一遥测模型
+//!
+//! 各接入协议（WebSocket、MQTT、MAVLink、NMEA）产生的字段统一归并到 `TelemetrySnapshot`，
+//! 单位固定为：度、米、米/秒、伏、安、百分比。无法识别的字段保存在 `extensions` 中。
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value as JsonValue};
+use std::collections::BTreeMap;
+
+pub type Timestamp = chrono::DateTime<chrono::FixedOffset>;
+
+/// 带更新时间的遥测值
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Timestamped<T> {
+    pub value: T,
+    pub updated_at: Timestamp,
+}
+
+/// 位置（WGS84 十进制度）
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Position {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+/// 高度（米）
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Altitude {
+    /// 海拔高度
+    pub amsl: Option<f64>,
+    /// 相对起飞点高度
+    pub relative: Option<f64>,
+}
+
+/// 姿态（度）
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Attitude {
+    pub roll: Option<f64>,
+    pub pitch: Option<f64>,
+    pub yaw: Option<f64>,
+}
+
+/// 速度
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Velocity {
+    /// 地速（米/秒）
+    pub ground_speed: Option<f64>,
+    /// 爬升率（米/秒，向上为正）
+    pub climb_rate: Option<f64>,
+    /// 机头朝向（度）
+    pub heading: Option<f64>,
+    /// 航迹方向（度）
+    pub course: Option<f64>,
+}
+
+/// 电池
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Battery {
+    /// 电压（伏）
+    pub voltage: Option<f64>,
+    /// 电流（安）
+    pub current: Option<f64>,
+    /// 剩余电量（百分比）
+    pub remaining: Option<f64>,
+    /// 温度（摄氏度）
+    pub temperature: Option<f64>,
+}
+
+/// 定位类型，按定位质量从低到高排列
+///
+/// MAVLink `GPS_RAW_INT.fix_type` 和 NMEA GGA 定位质量的取值含义不同，统一换算到这里：
+///
+/// | 类型 | MAVLink | NMEA GGA |
+/// |------|---------|----------|
+/// | `no_fix` | 0、1 | 0 |
+/// | `estimated` | - | 6 |
+/// | `fix_2d` | 2 | - |
+/// | `fix_3d` | 3、7 | 1 |
+/// | `dgps` | 4、8 | 2、3 |
+/// | `rtk_float` | 5 | 5 |
+/// | `rtk_fixed` | 6 | 4 |
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GpsFixType {
+    NoFix,
+    /// 航位推算
+    Estimated,
+    Fix2d,
+    /// GGA 带有海拔，NMEA 的普通定位按三维定位处理
+    Fix3d,
+    /// 差分定位，包括 PPP 和 PPS
+    Dgps,
+    RtkFloat,
+    RtkFixed,
+}
+
+impl GpsFixType {
+    /// MAVLink `GPS_FIX_TYPE`
+    pub fn from_mavlink(fix_type: u8) -> Option<Self> {
+        match fix_type {
+            0 | 1 => Some(Self::NoFix),
+            2 => Some(Self::Fix2d),
+            // 7 为静态定位（基站）
+            3 | 7 => Some(Self::Fix3d),
+            // 8 为 PPP
+            4 | 8 => Some(Self::Dgps),
+            5 => Some(Self::RtkFloat),
+            6 => Some(Self::RtkFixed),
+            _ => None,
+        }
+    }
+
+    /// NMEA GGA 定位质量，手动输入（7）和模拟（8）不是真实定位，不做换算
+    pub fn from_nmea_quality(quality: u8) -> Option<Self> {
+        match quality {
+            0 => Some(Self::NoFix),
+            1 => Some(Self::Fix3d),
+            2 | 3 => Some(Self::Dgps),
+            4 => Some(Self::RtkFixed),
+            5 => Some(Self::RtkFloat),
+            6 => Some(Self::Estimated),
+            _ => None,
+        }
+    }
+}
+
+/// GPS 定位状态
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GpsFix {
+    pub fix_type: Option<GpsFixType>,
+    pub satellites: Option<u8>,
+    pub hdop: Option<f64>,
+    pub valid: Option<bool>,
+}
+
+/// 设备遥测快照
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TelemetrySnapshot {
+    pub position: Option<Timestamped<Position>>,
+    pub altitude: Option<Timestamped<Altitude>>,
+    pub attitude: Option<Timestamped<Attitude>>,
+    pub velocity: Option<Timestamped<Velocity>>,
+    pub battery: Option<Timestamped<Battery>>,
+    pub gps: Option<Timestamped<GpsFix>>,
+    pub flight_mode: Option<Timestamped<String>>,
+    pub armed: Option<Timestamped<bool>>,
+    /// 链路信号强度（百分比）
+    pub signal_strength: Option<Timestamped<f64>>,
+    pub extensions: BTreeMap<String, Timestamped<JsonValue>>,
+    pub updated_at: Option<Timestamp>,
+}
+
+/// 更新分组中的字段，保留分组里其它字段的旧值
+fn update_group<T: Default>(
+    slot: &mut Option<Timestamped<T>>,
+    timestamp: Timestamp,
+    update: impl FnOnce(&mut T),
+) {
+    let entry = slot.get_or_insert_with(|| Timestamped {
+        value: T::default(),
+        updated_at: timestamp,
+    });
+    update(&mut entry.value);
+    entry.updated_at = timestamp;
+}
+
+/// 数值字段兼容 JSON 数字和字符串
+pub fn as_f64(value: &JsonValue) -> Option<f64> {
+    match value {
+        JsonValue::Number(n) => n.as_f64(),
+        JsonValue::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+    .filter(|v: &f64| v.is_finite())
+}
+
+fn as_u8(value: &JsonValue) -> Option<u8> {
+    as_f64(value).and_then(|v| {
+        if (0.0..=255.0).contains(&v) {
+            Some(v as u8)
+        } else {
+            None
+        }
+    })
+}
+
+/// 布尔字段兼容 JSON 布尔值、数字和常见字符串
+pub fn as_bool(value: &JsonValue) -> Option<bool> {
+    match value {
+        JsonValue::Bool(b) => Some(*b),
+        JsonValue::Number(n) => n.as_f64().map(|v| v != 0.0),
+        JsonValue::String(s) => match s.trim().to_ascii_lowercase().as_str() {
+            "true" | "1" | "yes" | "on" => Some(true),
+            "false" | "0" | "no" | "off" => Some(false),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// 将 WebSocket 设备上报的 field:value 转换为统一字段
+///
+/// 大疆桥接程序的 `location` 为 "纬度 经度 高度"，`attitude` 为 "俯仰 横滚 偏航"。
+pub fn websocket_fields(field: &str, value: &str) -> Map<String, JsonValue> {
+    let parts: Vec<f64> = value
+        .split_whitespace()
+        .filter_map(|part| part.parse().ok())
+        .collect();
+
+    let mut fields = Map::new();
+    match (field, parts.as_slice()) {
+        ("location", [latitude, longitude, rest @ ..]) => {
+            fields.insert("latitude".into(), (*latitude).into());
+            fields.insert("longitude".into(), (*longitude).into());
+            if let Some(altitude) = rest.first() {
+                fields.insert("relative_altitude".into(), (*altitude).into());
+            }
+        }
+        ("attitude", [pitch, roll, yaw, ..]) => {
+            fields.insert("pitch".into(), (*pitch).into());
+            fields.insert("roll".into(), (*roll).into());
+            fields.insert("yaw".into(), (*yaw).into());
+        }
+        ("battery", _) => {
+            fields.insert("battery_remaining".into(), value.into());
+        }
+        ("isfly", _) => {
+            fields.insert("flying".into(), value.into());
+        }
+        _ => {
+            fields.insert(field.into(), value.into());
+        }
+    }
+    fields
+}
+
+impl TelemetrySnapshot {
+    /// 合并一组扁平字段（MAVLink、NMEA、MQTT 以及 WebSocket 的 field:value）
+    pub fn apply_fields(&mut self, fields: &Map<String, JsonValue>, timestamp: Timestamp) {
+        // 经纬度需要成对出现
+        if let (Some(latitude), Some(longitude)) = (
+            fields.get("latitude").and_then(as_f64),
+            fields.get("longitude").and_then(as_f64),
+        ) {
+            self.position = Some(Timestamped {
+                value: Position {
+                    latitude,
+                    longitude,
+                },
+                updated_at: timestamp,
+            });
+        }
+
+        for (key, value) in fields {
+            if !self.apply_field(key, value, timestamp) {
+                self.extensions.insert(
+                    key.clone(),
+                    Timestamped {
+                        value: value.clone(),
+                        updated_at: timestamp,
+                    },
+                );
+            }
+        }
+
+        self.updated_at = Some(timestamp);
+    }
+
+    /// 合并 WebSocket 设备上报的单个字段
+    pub fn apply_websocket_field(&mut self, field: &str, value: &str, timestamp: Timestamp) {
+        self.apply_fields(&websocket_fields(field, value), timestamp);
+    }
+
+    /// 按字段名写入已知字段，未识别时返回 false
+    fn apply_field(&mut self, key: &str, value: &JsonValue, timestamp: Timestamp) -> bool {
+        match key {
+            // 位置已在 apply_fields 中成对处理
+            "latitude" | "longitude" => as_f64(value).is_some(),
+            "altitude" => Self::set_f64(&mut self.altitude, value, timestamp, |a, v| {
+                a.amsl = Some(v)
+            }),
+            "relative_altitude" => Self::set_f64(&mut self.altitude, value, timestamp, |a, v| {
+                a.relative = Some(v)
+            }),
+            "roll" => Self::set_f64(&mut self.attitude, value, timestamp, |a, v| {
+                a.roll = Some(v)
+            }),
+            "pitch" => Self::set_f64(&mut self.attitude, value, timestamp, |a, v| {
+                a.pitch = Some(v)
+            }),
+            "yaw" => Self::set_f64(&mut self.attitude, value, timestamp, |a, v| a.yaw = Some(v)),
+            "ground_speed" | "speed" => {
+                Self::set_f64(&mut self.velocity, value, timestamp, |v, x| {
+                    v.ground_speed = Some(x)
+                })
+            }
+            "climb_rate" | "vertical_speed" => {
+                Self::set_f64(&mut self.velocity, value, timestamp, |v, x| {
+                    v.climb_rate = Some(x)
+                })
+            }
+            "heading" => Self::set_f64(&mut self.velocity, value, timestamp, |v, x| {
+                v.heading = Some(x)
+            }),
+            "course" => Self::set_f64(&mut self.velocity, value, timestamp, |v, x| {
+                v.course = Some(x)
+            }),
+            "battery_voltage" => Self::set_f64(&mut self.battery, value, timestamp, |b, v| {
+                b.voltage = Some(v)
+            }),
+            "battery_current" => Self::set_f64(&mut self.battery, value, timestamp, |b, v| {
+                b.current = Some(v)
+            }),
+            "battery_remaining" => Self::set_f64(&mut self.battery, value, timestamp, |b, v| {
+                b.remaining = Some(v)
+            }),
+            "battery_temperature" => Self::set_f64(&mut self.battery, value, timestamp, |b, v| {
+                b.temperature = Some(v)
+            }),
+            "gps_fix_type" => {
+                Self::set_fix_type(&mut self.gps, value, timestamp, GpsFixType::from_mavlink)
+            }
+            "fix_quality" => Self::set_fix_type(
+                &mut self.gps,
+                value,
+                timestamp,
+                GpsFixType::from_nmea_quality,
+            ),
+            "satellites" => match as_u8(value) {
+                Some(satellites) => {
+                    update_group(&mut self.gps, timestamp, |g| {
+                        g.satellites = Some(satellites)
+                    });
+                    true
+                }
+                None => false,
+            },
+            "hdop" => Self::set_f64(&mut self.gps, value, timestamp, |g, v| g.hdop = Some(v)),
+            "gps_valid" => match as_bool(value) {
+                Some(valid) => {
+                    update_group(&mut self.gps, timestamp, |g| g.valid = Some(valid));
+                    true
+                }
+                None => false,
+            },
+            "flight_mode" => match value.as_str() {
+                Some(mode) => {
+                    self.flight_mode = Some(Timestamped {
+                        value: mode.to_string(),
+                        updated_at: timestamp,
+                    });
+                    true
+                }
+                None => false,
+            },
+            "armed" => match as_bool(value) {
+                Some(armed) => {
+                    self.armed = Some(Timestamped {
+                        value: armed,
+                        updated_at: timestamp,
+                    });
+                    true
+                }
+                None => false,
+            },
+            "signal_strength" | "rssi" => match as_f64(value) {
+                Some(strength) => {
+                    self.signal_strength = Some(Timestamped {
+                        value: strength,
+                        updated_at: timestamp,
+                    });
+                    true
+                }
+                None => false,
+            },
+            _ => false,
+        }
+    }
+
+    fn set_f64<T: Default>(
+        slot: &mut Option<Timestamped<T>>,
+        value: &JsonValue,
+        timestamp: Timestamp,
+        update: impl FnOnce(&mut T, f64),
+    ) -> bool {
+        match as_f64(value) {
+            Some(v) => {
+                update_group(slot, timestamp, |group| update(group, v));
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn set_fix_type(
+        slot: &mut Option<Timestamped<GpsFix>>,
+        value: &JsonValue,
+        timestamp: Timestamp,
+        convert: fn(u8) -> Option<GpsFixType>,
+    ) -> bool {
+        match as_u8(value).and_then(convert) {
+            Some(fix_type) => {
+                update_group(slot, timestamp, |g| g.fix_type = Some(fix_type));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 读取扩展字段中的数值
+    pub fn extension_f64(&self, key: &str) -> Option<f64> {
+        self.extensions.get(key).and_then(|v| as_f64(&v.value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn fix_type(fields: JsonValue) -> Option<GpsFixType> {
+        let mut snapshot = TelemetrySnapshot::default();
+        snapshot.apply_fields(
+            fields.as_object().unwrap(),
+            chrono::Utc::now().fixed_offset(),
+        );
+        snapshot.gps.and_then(|gps| gps.value.fix_type)
+    }
+
+    #[test]
+    fn fix_type_is_normalized_per_protocol() {
+        assert_eq!(
+            fix_type(json!({ "gps_fix_type": 1 })),
+            Some(GpsFixType::NoFix)
+        );
+        assert_eq!(
+            fix_type(json!({ "fix_quality": 1 })),
+            Some(GpsFixType::Fix3d)
+        );
+        assert_eq!(
+            fix_type(json!({ "gps_fix_type": 3 })),
+            Some(GpsFixType::Fix3d)
+        );
+        assert_eq!(
+            fix_type(json!({ "gps_fix_type": 6 })),
+            Some(GpsFixType::RtkFixed)
+        );
+        assert_eq!(
+            fix_type(json!({ "fix_quality": 4 })),
+            Some(GpsFixType::RtkFixed)
+        );
+        assert_eq!(
+            fix_type(json!({ "fix_quality": 5 })),
+            Some(GpsFixType::RtkFloat)
+        );
+        assert_eq!(fix_type(json!({ "fix_quality": 8 })), None);
+        assert!(GpsFixType::Fix2d < GpsFixType::Fix3d);
+        assert_eq!(json!(GpsFixType::RtkFloat), json!("rtk_float"));
+    }
+}
@@ -97,7 +97,16 @@ impl WebSocketProxy {
                         );
 
                         // 解析消息
-                        if let Some(ws_msg) =
+                        if crate::services::nmea::is_nmea(&text) {
+                            if let Err(e) =
+                                realtime_service.process_nmea_text(device_id, &text).await
+                            {
+                                error!(
+                                    "Failed to process NMEA data from device {}: {}",
+                                    device_id, e
+                                );
+                            }
+                        } else if let Some(ws_msg) =
                             RealtimeDataService::parse_websocket_message(device_id, &text)
                         {
                             if let Err(e) = realtime_service.process_websocket_message(ws_msg).await
@@ -0,0 +1,539 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+
+use loco_rs::config::Config;
+
+use crate::services::client_queue::OverflowPolicy;
+use crate::services::mission::DroneProfile;
+
+/// 应用自定义配置（对应配置文件中的 `settings` 节点）
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Settings {
+    /// 实时数据相关配置
+    #[serde(default)]
+    pub realtime: RealtimeSettings,
+    /// 历史数据保留与归档配置
+    #[serde(default)]
+    pub retention: RetentionSettings,
+    /// 航线规划配置
+    #[serde(default)]
+    pub mission: MissionSettings,
+}
+
+/// 实时数据配置
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RealtimeSettings {
+    /// MAVLink UDP 接入配置
+    #[serde(default)]
+    pub mavlink: MavlinkSettings,
+    /// NMEA 接入配置
+    #[serde(default)]
+    pub nmea: NmeaSettings,
+    /// 实时数据写入管道配置
+    #[serde(default)]
+    pub ingest: IngestSettings,
+    /// 遥测聚合配置
+    #[serde(default)]
+    pub rollup: RollupSettings,
+    /// 飞行架次识别配置
+    #[serde(default)]
+    pub flight: FlightSettings,
+    /// 电子围栏实时检查配置
+    #[serde(default)]
+    pub geofence: GeofenceSettings,
+    /// WebSocket 推送配置
+    #[serde(default)]
+    pub broadcast: BroadcastSettings,
+}
+
+/// MAVLink UDP 接入配置
+#[derive(Debug, Clone, Deserialize)]
+pub struct MavlinkSettings {
+    /// 是否启用 MAVLink 监听
+    #[serde(default = "default_mavlink_enable")]
+    pub enable: bool,
+    /// 监听地址
+    #[serde(default = "default_mavlink_binding")]
+    pub binding: String,
+    /// 监听端口
+    #[serde(default = "default_mavlink_port")]
+    pub port: u16,
+}
+
+impl Default for MavlinkSettings {
+    fn default() -> Self {
+        Self {
+            enable: default_mavlink_enable(),
+            binding: default_mavlink_binding(),
+            port: default_mavlink_port(),
+        }
+    }
+}
+
+fn default_mavlink_enable() -> bool {
+    true
+}
+
+fn default_mavlink_binding() -> String {
+    "0.0.0.0".to_string()
+}
+
+fn default_mavlink_port() -> u16 {
+    14550
+}
+
+/// NMEA 接入配置
+#[derive(Debug, Clone, Deserialize)]
+pub struct NmeaSettings {
+    /// 设备 TCP 监听地址，NMEA 流没有认证，默认只接受本机转发
+    #[serde(default = "default_nmea_binding")]
+    pub binding: String,
+    /// 采集模式下自动生成采集数据的最小间隔（秒）
+    #[serde(default = "default_nmea_collection_interval")]
+    pub collection_interval: u64,
+}
+
+impl Default for NmeaSettings {
+    fn default() -> Self {
+        Self {
+            binding: default_nmea_binding(),
+            collection_interval: default_nmea_collection_interval(),
+        }
+    }
+}
+
+fn default_nmea_binding() -> String {
+    "127.0.0.1".to_string()
+}
+
+fn default_nmea_collection_interval() -> u64 {
+    5
+}
+
+/// 实时数据写入管道配置
+#[derive(Debug, Clone, Deserialize)]
+pub struct IngestSettings {
+    /// 写入队列容量
+    #[serde(default = "default_ingest_queue_capacity")]
+    pub queue_capacity: usize,
+    /// 队列满时入队的最长等待时间（毫秒），超时即丢弃
+    #[serde(default = "default_ingest_enqueue_timeout_ms")]
+    pub enqueue_timeout_ms: u64,
+    /// 每批最大写入行数
+    #[serde(default = "default_ingest_batch_size")]
+    pub batch_size: usize,
+    /// 批量写入间隔（毫秒）
+    #[serde(default = "default_ingest_flush_interval_ms")]
+    pub flush_interval_ms: u64,
+    /// 预写文件路径
+    #[serde(default = "default_ingest_spill_path")]
+    pub spill_path: String,
+    /// 预写文件最大字节数，超过后新数据直接丢弃
+    #[serde(default = "default_ingest_spill_max_bytes")]
+    pub spill_max_bytes: u64,
+}
+
+impl Default for IngestSettings {
+    fn default() -> Self {
+        Self {
+            queue_capacity: default_ingest_queue_capacity(),
+            enqueue_timeout_ms: default_ingest_enqueue_timeout_ms(),
+            batch_size: default_ingest_batch_size(),
+            flush_interval_ms: default_ingest_flush_interval_ms(),
+            spill_path: default_ingest_spill_path(),
+            spill_max_bytes: default_ingest_spill_max_bytes(),
+        }
+    }
+}
+
+fn default_ingest_queue_capacity() -> usize {
+    10_000
+}
+
+fn default_ingest_enqueue_timeout_ms() -> u64 {
+    100
+}
+
+fn default_ingest_batch_size() -> usize {
+    500
+}
+
+fn default_ingest_flush_interval_ms() -> u64 {
+    1000
+}
+
+fn default_ingest_spill_path() -> String {
+    "data/realtime_ingest.wal".to_string()
+}
+
+fn default_ingest_spill_max_bytes() -> u64 {
+    64 * 1024 * 1024
+}
+
+/// 遥测聚合配置
+#[derive(Debug, Clone, Deserialize)]
+pub struct RollupSettings {
+    /// 待聚合样本队列容量，队列满时丢弃样本
+    #[serde(default = "default_rollup_queue_capacity")]
+    pub queue_capacity: usize,
+    /// 聚合结果写入数据库的间隔（毫秒）
+    #[serde(default = "default_rollup_flush_interval_ms")]
+    pub flush_interval_ms: u64,
+}
+
+impl Default for RollupSettings {
+    fn default() -> Self {
+        Self {
+            queue_capacity: default_rollup_queue_capacity(),
+            flush_interval_ms: default_rollup_flush_interval_ms(),
+        }
+    }
+}
+
+fn default_rollup_queue_capacity() -> usize {
+    10_000
+}
+
+fn default_rollup_flush_interval_ms() -> u64 {
+    5000
+}
+
+/// WebSocket 推送配置
+#[derive(Debug, Clone, Deserialize)]
+pub struct BroadcastSettings {
+    /// 每个客户端发送队列的容量
+    #[serde(default = "default_broadcast_client_queue_capacity")]
+    pub client_queue_capacity: usize,
+    /// 队列写满时的处理策略：drop_oldest 或 coalesce_latest
+    #[serde(default)]
+    pub overflow_policy: OverflowPolicy,
+    /// SSE 断线续传保留的最近事件数
+    #[serde(default = "default_broadcast_event_buffer_size")]
+    pub event_buffer_size: usize,
+}
+
+impl Default for BroadcastSettings {
+    fn default() -> Self {
+        Self {
+            client_queue_capacity: default_broadcast_client_queue_capacity(),
+            overflow_policy: OverflowPolicy::default(),
+            event_buffer_size: default_broadcast_event_buffer_size(),
+        }
+    }
+}
+
+fn default_broadcast_client_queue_capacity() -> usize {
+    256
+}
+
+fn default_broadcast_event_buffer_size() -> usize {
+    1024
+}
+
+/// 飞行架次识别配置
+#[derive(Debug, Clone, Deserialize)]
+pub struct FlightSettings {
+    /// 相对高度超过该值（米）视为已起飞
+    #[serde(default = "default_flight_takeoff_altitude")]
+    pub takeoff_altitude: f64,
+    /// 未上报解锁状态的设备，高度持续低于起飞阈值超过该时长（秒）视为已降落
+    #[serde(default = "default_flight_landed_timeout_secs")]
+    pub landed_timeout_secs: u64,
+    /// 超过该时长（秒）没有收到遥测时结束当前架次
+    #[serde(default = "default_flight_gap_timeout_secs")]
+    pub gap_timeout_secs: u64,
+    /// 架次统计写入数据库的间隔（毫秒）
+    #[serde(default = "default_flight_update_interval_ms")]
+    pub update_interval_ms: u64,
+}
+
+impl Default for FlightSettings {
+    fn default() -> Self {
+        Self {
+            takeoff_altitude: default_flight_takeoff_altitude(),
+            landed_timeout_secs: default_flight_landed_timeout_secs(),
+            gap_timeout_secs: default_flight_gap_timeout_secs(),
+            update_interval_ms: default_flight_update_interval_ms(),
+        }
+    }
+}
+
+fn default_flight_takeoff_altitude() -> f64 {
+    2.0
+}
+
+fn default_flight_landed_timeout_secs() -> u64 {
+    30
+}
+
+fn default_flight_gap_timeout_secs() -> u64 {
+    60
+}
+
+fn default_flight_update_interval_ms() -> u64 {
+    5000
+}
+
+/// 电子围栏实时检查配置
+#[derive(Debug, Clone, Deserialize)]
+pub struct GeofenceSettings {
+    /// 是否检查实时遥测
+    #[serde(default = "default_geofence_enable")]
+    pub enable: bool,
+    /// 重新加载围栏和设备归属的间隔（秒），围栏通过接口修改时会立即重新加载
+    #[serde(default = "default_geofence_refresh_secs")]
+    pub refresh_secs: u64,
+}
+
+impl Default for GeofenceSettings {
+    fn default() -> Self {
+        Self {
+            enable: default_geofence_enable(),
+            refresh_secs: default_geofence_refresh_secs(),
+        }
+    }
+}
+
+fn default_geofence_enable() -> bool {
+    true
+}
+
+fn default_geofence_refresh_secs() -> u64 {
+    60
+}
+
+/// 历史数据保留与归档配置
+///
+/// 天数为 0 表示永久保留。原始数据的保留天数按 设备 > 数据类型 > 默认值 的优先级确定。
+#[derive(Debug, Clone, Deserialize)]
+pub struct RetentionSettings {
+    /// 原始实时数据默认保留天数
+    #[serde(default = "default_retention_raw_days")]
+    pub raw_days: u32,
+    /// 按数据类型（mqtt、websocket、mavlink、nmea）覆盖保留天数
+    #[serde(default)]
+    pub data_types: HashMap<String, u32>,
+    /// 按设备ID覆盖保留天数
+    #[serde(default)]
+    pub devices: HashMap<i32, u32>,
+    /// 遥测聚合各粒度的保留天数
+    #[serde(default)]
+    pub rollup_days: RollupRetention,
+    /// 删除前是否将过期原始数据导出为归档文件
+    #[serde(default = "default_retention_archive")]
+    pub archive: bool,
+    /// 归档文件目录
+    #[serde(default = "default_retention_archive_dir")]
+    pub archive_dir: String,
+    /// 每批导出和删除的行数
+    #[serde(default = "default_retention_batch_size")]
+    pub batch_size: u64,
+}
+
+impl Default for RetentionSettings {
+    fn default() -> Self {
+        Self {
+            raw_days: default_retention_raw_days(),
+            data_types: HashMap::new(),
+            devices: HashMap::new(),
+            rollup_days: RollupRetention::default(),
+            archive: default_retention_archive(),
+            archive_dir: default_retention_archive_dir(),
+            batch_size: default_retention_batch_size(),
+        }
+    }
+}
+
+impl RetentionSettings {
+    /// 设备某类原始数据的保留天数
+    pub fn raw_days_for(&self, device_id: i32, data_type: &str) -> u32 {
+        self.devices
+            .get(&device_id)
+            .or_else(|| self.data_types.get(data_type))
+            .copied()
+            .unwrap_or(self.raw_days)
+    }
+}
+
+/// 遥测聚合保留天数
+#[derive(Debug, Clone, Deserialize)]
+pub struct RollupRetention {
+    #[serde(rename = "1s", default = "default_rollup_retention_second")]
+    pub second: u32,
+    #[serde(rename = "1m", default = "default_rollup_retention_minute")]
+    pub minute: u32,
+    #[serde(rename = "1h", default = "default_rollup_retention_hour")]
+    pub hour: u32,
+}
+
+impl Default for RollupRetention {
+    fn default() -> Self {
+        Self {
+            second: default_rollup_retention_second(),
+            minute: default_rollup_retention_minute(),
+            hour: default_rollup_retention_hour(),
+        }
+    }
+}
+
+fn default_retention_raw_days() -> u32 {
+    7
+}
+
+fn default_retention_archive() -> bool {
+    true
+}
+
+fn default_retention_archive_dir() -> String {
+    "data/archive".to_string()
+}
+
+fn default_retention_batch_size() -> u64 {
+    5000
+}
+
+fn default_rollup_retention_second() -> u32 {
+    7
+}
+
+fn default_rollup_retention_minute() -> u32 {
+    90
+}
+
+fn default_rollup_retention_hour() -> u32 {
+    365
+}
+
+/// 航线规划配置
+///
+/// 任务执行前按机型的电池续航校验航线的预计飞行时间，未配置的机型使用默认续航。
+/// 任务模拟按机型的运动参数（`drone_models`）推演，未配置的项使用默认值。
+#[derive(Debug, Clone, Deserialize)]
+pub struct MissionSettings {
+    /// 航点未指定速度时的水平飞行速度（米/秒）
+    #[serde(default = "default_mission_cruise_speed")]
+    pub cruise_speed: f64,
+    /// 起降和改变高度的垂直速度（米/秒）
+    #[serde(default = "default_mission_vertical_speed")]
+    pub vertical_speed: f64,
+    /// 预留电量百分比，预计飞行时间不能超过扣除预留后的续航
+    #[serde(default = "default_mission_reserve_percent")]
+    pub reserve_percent: f64,
+    /// 默认电池续航（秒）
+    #[serde(default = "default_mission_endurance_secs")]
+    pub default_endurance_secs: u32,
+    /// 按机型（设备的 drone_model）覆盖电池续航（秒）
+    #[serde(default)]
+    pub endurance_secs: HashMap<String, u32>,
+    /// 转向速度（度/秒）
+    #[serde(default = "default_mission_yaw_rate")]
+    pub yaw_rate: f64,
+    /// 相对起飞点的最大飞行高度（米），任务模拟超出时报告违规
+    #[serde(default = "default_mission_max_altitude")]
+    pub max_altitude: f64,
+    /// 任务模拟轨迹的采样间隔（秒）
+    #[serde(default = "default_mission_trajectory_interval_secs")]
+    pub trajectory_interval_secs: f64,
+    /// 按机型覆盖运动参数
+    #[serde(default)]
+    pub drone_models: HashMap<String, DroneModelSettings>,
+}
+
+/// 机型的运动参数，未配置的项使用 [`MissionSettings`] 中的默认值
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct DroneModelSettings {
+    /// 水平飞行速度（米/秒）
+    pub cruise_speed: Option<f64>,
+    /// 爬升速度（米/秒）
+    pub climb_rate: Option<f64>,
+    /// 下降速度（米/秒）
+    pub descent_rate: Option<f64>,
+    /// 转向速度（度/秒）
+    pub yaw_rate: Option<f64>,
+    /// 电池续航（秒）
+    pub endurance_secs: Option<u32>,
+}
+
+impl Default for MissionSettings {
+    fn default() -> Self {
+        Self {
+            cruise_speed: default_mission_cruise_speed(),
+            vertical_speed: default_mission_vertical_speed(),
+            reserve_percent: default_mission_reserve_percent(),
+            default_endurance_secs: default_mission_endurance_secs(),
+            endurance_secs: HashMap::new(),
+            yaw_rate: default_mission_yaw_rate(),
+            max_altitude: default_mission_max_altitude(),
+            trajectory_interval_secs: default_mission_trajectory_interval_secs(),
+            drone_models: HashMap::new(),
+        }
+    }
+}
+
+impl MissionSettings {
+    /// 机型的运动参数，速度不小于 0.1
+    pub fn profile_for(&self, drone_model: Option<&str>) -> DroneProfile {
+        let overrides = drone_model
+            .and_then(|model| self.drone_models.get(model))
+            .cloned()
+            .unwrap_or_default();
+        let endurance = drone_model
+            .and_then(|model| self.endurance_secs.get(model))
+            .copied()
+            .unwrap_or(self.default_endurance_secs);
+        DroneProfile {
+            cruise_speed: overrides.cruise_speed.unwrap_or(self.cruise_speed).max(0.1),
+            climb_rate: overrides
+                .climb_rate
+                .unwrap_or(self.vertical_speed)
+                .max(0.1),
+            descent_rate: overrides
+                .descent_rate
+                .unwrap_or(self.vertical_speed)
+                .max(0.1),
+            yaw_rate: overrides.yaw_rate.unwrap_or(self.yaw_rate).max(0.1),
+            endurance_secs: overrides.endurance_secs.unwrap_or(endurance),
+        }
+    }
+}
+
+fn default_mission_cruise_speed() -> f64 {
+    5.0
+}
+
+fn default_mission_vertical_speed() -> f64 {
+    2.0
+}
+
+fn default_mission_reserve_percent() -> f64 {
+    20.0
+}
+
+fn default_mission_endurance_secs() -> u32 {
+    1200
+}
+
+fn default_mission_yaw_rate() -> f64 {
+    45.0
+}
+
+fn default_mission_max_altitude() -> f64 {
+    120.0
+}
+
+fn default_mission_trajectory_interval_secs() -> f64 {
+    1.0
+}
+
+impl Settings {
+    /// 从 loco 配置中读取自定义配置，缺失或格式错误时使用默认值
+    pub fn from_config(config: &Config) -> Self {
+        match &config.settings {
+            Some(value) => serde_json::from_value(value.clone()).unwrap_or_else(|e| {
+                tracing::error!("Invalid application settings, using defaults: {}", e);
+                Self::default()
+            }),
+            None => Self::default(),
+        }
+    }
+}
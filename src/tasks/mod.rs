@@ -1 +1,3 @@
+pub mod restore_archive;
+pub mod retention;
 pub mod seed;
@@ -0,0 +1,36 @@
+//! 将保留策略导出的归档文件重新导入 `device_realtime_data`，每次导入一个文件。
+//!
+//! ```sh
+//! cargo loco task restore_archive file:data/archive/device_realtime_data-20250915033000-00000.ndjson.gz
+//! ```
+//!
+//! 恢复的数据如果仍早于保留期限，会在下一次执行 `retention` 时再次被归档删除，
+//! 需要长期保留时请先调整 `settings.retention`。
+
+use std::path::Path;
+
+use loco_rs::prelude::*;
+
+use crate::services::retention;
+
+pub struct RestoreArchive;
+#[async_trait]
+impl Task for RestoreArchive {
+    fn task(&self) -> TaskInfo {
+        TaskInfo {
+            name: "restore_archive".to_string(),
+            detail: "Load an archived realtime data file back into the database".to_string(),
+        }
+    }
+
+    async fn run(&self, app_context: &AppContext, vars: &task::Vars) -> Result<()> {
+        let file = vars.cli_arg("file")?;
+
+        let restored = retention::restore(&app_context.db, Path::new(file))
+            .await
+            .map_err(|e| Error::string(&format!("Failed to restore {}: {}", file, e)))?;
+
+        println!("Restored {} realtime rows from {}", restored, file);
+        Ok(())
+    }
+}
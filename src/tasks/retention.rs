@@ -0,0 +1,54 @@
+//! 执行历史数据保留策略：归档并删除过期的原始实时数据，清理过期的遥测聚合。
+//!
+//! 保留期限在配置文件的 `settings.retention` 中设置，通常由 loco 调度器定时执行。
+//! ```sh
+//! cargo loco task retention
+//! ```
+//!
+//! 只统计将被删除的数据：
+//! ```sh
+//! cargo loco task retention dry_run:true
+//! ```
+
+use loco_rs::prelude::*;
+
+use crate::{services::retention, settings::Settings};
+
+pub struct EnforceRetention;
+#[async_trait]
+impl Task for EnforceRetention {
+    fn task(&self) -> TaskInfo {
+        TaskInfo {
+            name: "retention".to_string(),
+            detail: "Archive and delete expired realtime data and telemetry rollups".to_string(),
+        }
+    }
+
+    async fn run(&self, app_context: &AppContext, vars: &task::Vars) -> Result<()> {
+        let dry_run = vars
+            .cli_arg("dry_run")
+            .is_ok_and(|dry_run| dry_run == "true");
+        let settings = Settings::from_config(&app_context.config).retention;
+
+        let report = retention::enforce(&app_context.db, &settings, dry_run)
+            .await
+            .map_err(|e| Error::string(&format!("Retention failed: {}", e)))?;
+
+        if dry_run {
+            println!(
+                "Dry run: {} raw rows and {} rollup rows would be deleted",
+                report.deleted_raw, report.deleted_rollups
+            );
+        } else {
+            println!(
+                "Archived {} raw rows, deleted {} raw rows and {} rollup rows",
+                report.archived, report.deleted_raw, report.deleted_rollups
+            );
+            for path in &report.archive_files {
+                println!("Archive written to {}", path.display());
+            }
+        }
+
+        Ok(())
+    }
+}